@@ -0,0 +1,68 @@
+//! Throughput of the register decoders and the RTU frame codec.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use truebner_smt100::core::{
+    modbus::{rtu::*, *},
+    Measurements, RawCounts, RelativePermittivity, Temperature, VolumetricWaterContent,
+};
+
+fn measurements() -> Measurements {
+    Measurements {
+        temperature: Temperature::from_degree_celsius(21.5),
+        water_content: VolumetricWaterContent::from_percent(34.4),
+        permittivity: RelativePermittivity::from_ratio(15.2),
+        raw_counts: RawCounts::from(11345),
+    }
+}
+
+/// The response frame of reading all measurement registers.
+fn response_frame() -> Vec<u8> {
+    let regs = encode_measurements_to_u16s(&measurements());
+    let mut frame = vec![1, FN_READ_HOLDING_REGISTERS, (2 * regs.len()) as u8];
+    for reg in &regs {
+        frame.extend_from_slice(&reg.to_be_bytes());
+    }
+    let crc = crc16(&frame);
+    frame.extend_from_slice(&crc.to_le_bytes());
+    frame
+}
+
+fn decode_registers(c: &mut Criterion) {
+    let regs = encode_measurements_to_u16s(&measurements());
+    let bytes: Vec<_> = regs.iter().flat_map(|reg| reg.to_be_bytes()).collect();
+    c.bench_function("decode_measurements_from_u16s", |b| {
+        b.iter(|| decode_measurements_from_u16s(black_box(&regs)))
+    });
+    c.bench_function("decode_measurements_from_bytes", |b| {
+        b.iter(|| decode_measurements_from_bytes(black_box(&bytes)))
+    });
+    c.bench_function("decode_temperature_from_u16", |b| {
+        b.iter(|| decode_temperature_from_u16(black_box(regs[0])))
+    });
+}
+
+fn rtu_frames(c: &mut Criterion) {
+    let frame = response_frame();
+    c.bench_function("crc16", |b| b.iter(|| crc16(black_box(&frame))));
+    c.bench_function("encode_read_holding_registers", |b| {
+        let mut buf = [0; 8];
+        b.iter(|| {
+            encode_read_holding_registers(
+                black_box(1),
+                MEASUREMENTS_REG_START,
+                MEASUREMENTS_REG_COUNT,
+                &mut buf,
+            )
+        })
+    });
+    c.bench_function("decode_response_frame", |b| {
+        let mut regs = [0; MEASUREMENTS_REG_COUNT as usize];
+        b.iter(|| {
+            let (frame, _) = decode_response_frame(black_box(&frame)).unwrap();
+            frame.decode_registers(&mut regs)
+        })
+    });
+}
+
+criterion_group!(benches, decode_registers, rtu_frames);
+criterion_main!(benches);
@@ -0,0 +1,109 @@
+//! End-to-end poll cycles against emulated sensors on an in-process
+//! bus, including the number of heap allocations per read.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+use tokio::runtime::Runtime;
+use tokio_modbus::prelude::{Slave, SlaveContext as _};
+use truebner_smt100::{
+    mock::{server::Faults, Bus},
+    modbus::{self, BusManager},
+};
+
+/// Counts all allocations of the process.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+const SLAVE_COUNT: u8 = 4;
+
+const TIMEOUT: Duration = Duration::from_millis(10);
+
+fn runtime() -> Runtime {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .build()
+        .unwrap()
+}
+
+/// A responding slave 1 and a dead slave 2.
+fn bus() -> Bus {
+    let bus = Bus::new();
+    bus.add_slave(Slave(1));
+    let dead = bus.add_slave(Slave(2));
+    dead.set_faults(Some(Faults {
+        timeout: 1.0,
+        ..Default::default()
+    }));
+    bus
+}
+
+fn read_measurements(c: &mut Criterion) {
+    let runtime = runtime();
+    let bus = bus();
+    let mut context = runtime.block_on(async { bus.connect() });
+    context.set_slave(Slave(1));
+    c.bench_function("read_measurements", |b| {
+        b.iter(|| runtime.block_on(modbus::read_measurements(&mut context)).unwrap())
+    });
+}
+
+fn poll_cycle(c: &mut Criterion) {
+    let runtime = runtime();
+    let bus = Bus::new();
+    let mut manager = runtime.block_on(async { BusManager::new(bus.shared_context()) });
+    for slave in 1..=SLAVE_COUNT {
+        bus.add_slave(Slave(slave));
+        manager.register(Slave(slave));
+    }
+    runtime.block_on(manager.reconnect()).unwrap();
+    c.bench_function("poll_all", |b| {
+        b.iter(|| runtime.block_on(manager.poll_all(Some(TIMEOUT))).unwrap())
+    });
+}
+
+/// Not a timing benchmark: Prints the average number of allocations
+/// of successful and timed out reads.
+fn allocations_per_read(_: &mut Criterion) {
+    const READS: usize = 100;
+    let runtime = runtime();
+    let bus = bus();
+    let mut context = runtime.block_on(async { bus.connect() });
+    for (name, slave) in [("ok", Slave(1)), ("timeout", Slave(2))] {
+        context.set_slave(slave);
+        // Warm up
+        let _ = runtime.block_on(modbus::read_measurements_with_timeout(&mut context, TIMEOUT));
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        for _ in 0..READS {
+            let _ = runtime.block_on(modbus::read_measurements_with_timeout(&mut context, TIMEOUT));
+        }
+        let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+        println!("allocations per read ({}): {}", name, allocations as f64 / READS as f64);
+    }
+}
+
+criterion_group!(benches, read_measurements, poll_cycle, allocations_per_read);
+criterion_main!(benches);
@@ -1,15 +1,16 @@
-#[cfg(feature = "modbus-rtu")]
+#[cfg(feature = "tokio-modbus-rtu")]
 pub fn main() {
     use chrono::{DateTime, Utc};
     use env_logger::Builder as LoggerBuilder;
-    use futures::{future::Either, Future, Stream};
-    use std::{cell::RefCell, env, io::Error, rc::Rc, time::Duration};
-    use stream_cancel::{StreamExt, Tripwire};
-    use tokio::timer::Interval;
-    use tokio_core::reactor::{Core, Handle};
-    use tokio_modbus::prelude::{*, client::util::*};
-
-    use truebner_smt100::{modbus, *};
+    use futures::{future::BoxFuture, FutureExt};
+    use std::{env, io, sync::Arc, time::Duration};
+    use tokio::time::{interval_at, Instant};
+    use tokio_modbus::prelude::{client, Slave};
+
+    use truebner_smt100::{
+        modbus::{self, NewContext, SharedContext},
+        *,
+    };
 
     let mut logger_builder = LoggerBuilder::new();
     logger_builder.filter_level(log::LevelFilter::Info);
@@ -20,17 +21,19 @@ pub fn main() {
     }
     logger_builder.init();
 
-    let mut core = Core::new().unwrap();
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
 
     #[derive(Debug, Clone)]
     struct ContextConfig {
-        handle: Handle,
         tty_path: String,
-    };
+    }
 
     impl NewContext for ContextConfig {
-        fn new_context(&self) -> Box<dyn Future<Item = client::Context, Error = Error>> {
-            Box::new(modbus::rtu::connect_path(&self.handle, &self.tty_path))
+        fn new_context(&self) -> BoxFuture<'_, io::Result<client::Context>> {
+            async move { modbus::rtu::connect_path(&self.tty_path) }.boxed()
         }
     }
 
@@ -39,11 +42,10 @@ pub fn main() {
         slave: Slave,
         cycle_time: Duration,
         timeout: Duration,
-    };
+    }
 
     // TODO: Parse parameters and options from command-line arguments
     let context_config = ContextConfig {
-        handle: core.handle(),
         tty_path: "/dev/ttyUSB0".to_owned(),
     };
     let slave_config = SlaveConfig {
@@ -83,17 +85,17 @@ pub fn main() {
         // Only shared with the single proxy and otherwise unused within the
         // control loop. Just to demonstrate how to share the Modbus context
         // and how to recover from communication errors by reconnecting.
-        _shared_context: Rc<RefCell<SharedContext>>,
+        _shared_context: Arc<SharedContext>,
 
         config: SlaveConfig,
         proxy: modbus::SlaveProxy,
         measurements: Measurements,
-    };
+    }
 
     impl ControlLoop {
         pub fn new(config: SlaveConfig, new_context: Box<dyn NewContext>) -> Self {
-            let shared_context = Rc::new(RefCell::new(SharedContext::new(None, new_context)));
-            let proxy = modbus::SlaveProxy::new(config.slave, Rc::clone(&shared_context));
+            let shared_context = Arc::new(SharedContext::new(None, new_context));
+            let proxy = modbus::SlaveProxy::new(config.slave, Arc::clone(&shared_context));
             Self {
                 _shared_context: shared_context,
                 config,
@@ -102,114 +104,78 @@ pub fn main() {
             }
         }
 
-        fn reconnect(&self) -> impl Future<Item = (), Error = Error> {
-            self.proxy.reconnect()
+        async fn reconnect(&self) -> Result<()> {
+            self.proxy.reconnect().await
         }
 
-        pub fn measure_temperature(mut self) -> impl Future<Item = Self, Error = (Error, Self)> {
-            self
-                .proxy
-                .read_temperature(self.config.timeout)
-                .then(move |res| match res {
-                    Ok(val) => {
-                        self.measurements.temperature = Some(Measurement::new(val));
-                        Ok(self)
-                    }
-                    Err(err) => Err((err, self)),
-                })
+        pub async fn measure_temperature(&mut self) -> Result<()> {
+            let val = self.proxy.read_temperature(Some(self.config.timeout)).await?;
+            self.measurements.temperature = Some(Measurement::new(val));
+            Ok(())
         }
 
-        pub fn measure_water_content(mut self) -> impl Future<Item = Self, Error = (Error, Self)> {
-            self
-                .proxy
-                .read_water_content(self.config.timeout)
-                .then(move |res| match res {
-                    Ok(val) => {
-                        self.measurements.water_content = Some(Measurement::new(val));
-                        Ok(self)
-                    }
-                    Err(err) => Err((err, self)),
-                })
+        pub async fn measure_water_content(&mut self) -> Result<()> {
+            let val = self.proxy.read_water_content(Some(self.config.timeout)).await?;
+            self.measurements.water_content = Some(Measurement::new(val));
+            Ok(())
         }
 
-        pub fn measure_permittivity(mut self) -> impl Future<Item = Self, Error = (Error, Self)> {
-            self
-                .proxy
-                .read_permittivity(self.config.timeout)
-                .then(move |res| match res {
-                    Ok(val) => {
-                        self.measurements.permittivity = Some(Measurement::new(val));
-                        Ok(self)
-                    }
-                    Err(err) => Err((err, self)),
-                })
+        pub async fn measure_permittivity(&mut self) -> Result<()> {
+            let val = self.proxy.read_permittivity(Some(self.config.timeout)).await?;
+            self.measurements.permittivity = Some(Measurement::new(val));
+            Ok(())
         }
 
-        pub fn recover_after_error(&self, err: &Error) -> impl Future<Item = (), Error = ()> {
-            log::warn!(
-                "Reconnecting after error: {}",
-                err
-            );
-            self.reconnect().or_else(|err| {
-                log::error!(
-                    "Failed to reconnect: {}",
-                    err
-                );
+        pub async fn recover_after_error(&self, err: &Error) {
+            log::warn!("Reconnecting after error: {}", err);
+            if let Err(err) = self.reconnect().await {
+                log::error!("Failed to reconnect: {}", err);
                 // Continue and don't leave/terminate the control loop!
-                Ok(())
-            })
+            }
         }
 
-        pub fn broadcast_slave(&self) -> impl Future<Item = (), Error = Error> {
-            self.proxy.broadcast_slave()
+        pub async fn broadcast_slave(&self) -> Result<()> {
+            modbus::CommissioningSession::enter(Arc::clone(&self._shared_context))
+                .broadcast_slave(self.proxy.slave())
+                .await
         }
     }
 
     log::info!("Connecting: {:?}", context_config);
-    let ctrl_loop = ControlLoop::new(slave_config, Box::new(context_config));
-    core.run(ctrl_loop.reconnect()).unwrap();
-
-    let broadcast_slave = false;
-    if broadcast_slave {
-        log::info!(
-            "Resetting Modbus slave address to {:?}",
-            ctrl_loop.proxy.slave()
-        );
-        core.run(ctrl_loop.broadcast_slave()).unwrap();
-    }
+    let mut ctrl_loop = ControlLoop::new(slave_config, Box::new(context_config));
+    runtime.block_on(async move {
+        ctrl_loop.reconnect().await.unwrap();
+
+        let broadcast_slave = false;
+        if broadcast_slave {
+            log::info!(
+                "Resetting Modbus slave address to {:?}",
+                ctrl_loop.proxy.slave()
+            );
+            ctrl_loop.broadcast_slave().await.unwrap();
+        }
 
-    let (_trigger, tripwire) = Tripwire::new();
-    let cycle_interval = Interval::new_interval(ctrl_loop.config.cycle_time);
-    let ctrl_loop_task = cycle_interval
-        .map_err(|err| {
-            log::error!("Aborting control loop after timer error: {:?}", err);
-        })
-        .take_until(tripwire)
-        .fold(ctrl_loop, |ctrl_loop, _event| {
-            // Asynchronous chain of measurements. The control loop
-            // is consumed and returned upon each step to update the
-            // measurement after reading a new value asynchronously.
-            futures::future::ok(ctrl_loop)
-                .and_then(ControlLoop::measure_temperature)
-                .and_then(ControlLoop::measure_water_content)
-                .and_then(ControlLoop::measure_permittivity)
-                .then(|res| match res {
-                    Ok(ctrl_loop) => {
-                        log::info!("{:?}", ctrl_loop.measurements);
-                        Either::A(futures::future::ok(ctrl_loop))
-                    }
-                    Err((err, ctrl_loop)) => {
-                        log::info!("{:?}", ctrl_loop.measurements);
-                        Either::B(ctrl_loop.recover_after_error(&err).map(|()| ctrl_loop))
-                    }
-                })
-        });
-
-    core.run(ctrl_loop_task).unwrap();
+        let cycle_time = ctrl_loop.config.cycle_time;
+        let mut cycle_interval = interval_at(Instant::now() + cycle_time, cycle_time);
+        loop {
+            cycle_interval.tick().await;
+            // Sequential chain of measurements that stops at the first error
+            let res = async {
+                ctrl_loop.measure_temperature().await?;
+                ctrl_loop.measure_water_content().await?;
+                ctrl_loop.measure_permittivity().await
+            }
+            .await;
+            log::info!("{:?}", ctrl_loop.measurements);
+            if let Err(err) = res {
+                ctrl_loop.recover_after_error(&err).await;
+            }
+        }
+    });
 }
 
-#[cfg(not(feature = "modbus-rtu"))]
+#[cfg(not(feature = "tokio-modbus-rtu"))]
 pub fn main() {
-    println!("feature `modbus-rtu` is required to run this example");
+    println!("feature `tokio-modbus-rtu` is required to run this example");
     std::process::exit(1);
 }
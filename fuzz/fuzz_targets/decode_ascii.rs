@@ -0,0 +1,12 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use truebner_smt100::ascii::*;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = decode_response_line(data);
+    let _ = decode_temperature_from_bytes(data);
+    let _ = decode_water_content_from_bytes(data);
+    let _ = decode_permittivity_from_bytes(data);
+    let _ = decode_raw_counts_from_bytes(data);
+});
@@ -0,0 +1,15 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use truebner_smt100::modbus::*;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = decode_temperature_from_bytes(data);
+    let _ = decode_water_content_from_bytes(data);
+    let _ = decode_permittivity_from_bytes(data);
+    let _ = decode_raw_counts_from_bytes(data);
+    if let Ok((measurements, _)) = decode_measurements_from_bytes(data) {
+        let regs = encode_measurements_to_u16s(&measurements);
+        assert_eq!(Ok(measurements), decode_measurements_from_u16s(&regs).map(|(m, _)| m));
+    }
+});
@@ -0,0 +1,16 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use truebner_smt100::modbus::rtu::*;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = response_frame_len(data);
+    if let Ok((frame, _)) = decode_response_frame(data) {
+        let _ = frame.exception_code();
+        let mut regs = [0; MAX_REG_COUNT as usize];
+        for len in 0..=regs.len() {
+            let _ = frame.decode_registers(&mut regs[..len]);
+        }
+        let _ = frame.decode_write_single_register();
+    }
+});
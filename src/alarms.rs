@@ -0,0 +1,362 @@
+//! Threshold alarms, e.g. for triggering irrigation.
+//!
+//! Rules compare a single quantity against a threshold. An alarm is
+//! raised after the condition has been met for a number of consecutive
+//! measurements and cleared as soon as the value has recovered beyond
+//! the hysteresis band around the threshold.
+//!
+//! ```no_run
+//! # use tokio::sync::mpsc;
+//! # use truebner_smt100::{alarms::{Alarms, Rule}, poller::Poller, Capabilities, Quantity};
+//! # fn add_alarms(poller: &mut Poller<impl Capabilities>) {
+//! let rules = vec![
+//!     Rule::below("dry", Quantity::WaterContent, 20.0).with_cycles(3).with_hysteresis(2.0),
+//!     Rule::above("hot", Quantity::Temperature, 50.0),
+//! ];
+//! let (sender, receiver) = mpsc::channel(16);
+//! poller.add_sink(Box::new(Alarms::new(rules, sender)));
+//! # drop(receiver);
+//! # }
+//! ```
+
+use super::*;
+
+use crate::{
+    poller::{Measurement, TimestampedMeasurements},
+    sink::MeasurementSink,
+};
+
+use futures::{future::BoxFuture, FutureExt};
+use std::{collections::HashMap, io, time::SystemTime};
+use tokio::sync::mpsc;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Condition {
+    /// The value is less than the threshold.
+    Below(f64),
+
+    /// The value is greater than the threshold.
+    Above(f64),
+}
+
+impl Condition {
+    fn is_met(self, value: f64) -> bool {
+        match self {
+            Condition::Below(threshold) => value < threshold,
+            Condition::Above(threshold) => value > threshold,
+        }
+    }
+
+    fn is_recovered(self, value: f64, hysteresis: f64) -> bool {
+        match self {
+            Condition::Below(threshold) => value >= threshold + hysteresis,
+            Condition::Above(threshold) => value <= threshold - hysteresis,
+        }
+    }
+}
+
+/// A threshold rule for a single quantity.
+///
+/// Values are compared in the units of the accessors, i.e. °C for
+/// the temperature and % for the water content.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+    /// Identifies the rule in alarm events.
+    pub name: String,
+
+    pub quantity: Quantity,
+
+    pub condition: Condition,
+
+    /// The number of consecutive measurements that must meet the
+    /// condition before an alarm is raised.
+    pub cycles: u32,
+
+    /// The distance from the threshold that a value must recover
+    /// before the alarm is cleared.
+    pub hysteresis: f64,
+}
+
+impl Rule {
+    pub fn new(name: impl Into<String>, quantity: Quantity, condition: Condition) -> Self {
+        Self {
+            name: name.into(),
+            quantity,
+            condition,
+            cycles: 1,
+            hysteresis: 0.0,
+        }
+    }
+
+    pub fn below(name: impl Into<String>, quantity: Quantity, threshold: f64) -> Self {
+        Self::new(name, quantity, Condition::Below(threshold))
+    }
+
+    pub fn above(name: impl Into<String>, quantity: Quantity, threshold: f64) -> Self {
+        Self::new(name, quantity, Condition::Above(threshold))
+    }
+
+    pub fn with_cycles(self, cycles: u32) -> Self {
+        Self { cycles, ..self }
+    }
+
+    pub fn with_hysteresis(self, hysteresis: f64) -> Self {
+        Self { hysteresis, ..self }
+    }
+}
+
+/// The details of a raised or cleared alarm.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Alarm {
+    /// The name of the rule.
+    pub rule: String,
+
+    pub slave: Option<u8>,
+
+    /// The measured value that triggered the event.
+    pub value: f64,
+
+    /// The time of the measurement that triggered the event.
+    pub ts: SystemTime,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AlarmEvent {
+    Raised(Alarm),
+    Cleared(Alarm),
+}
+
+fn latest_value(quantity: Quantity, measurements: &TimestampedMeasurements) -> Option<(SystemTime, f64)> {
+    fn split<T>(measurement: Option<Measurement<T>>, to_f64: impl FnOnce(T) -> f64) -> Option<(SystemTime, f64)> {
+        measurement.map(|Measurement { ts, val }| (ts, to_f64(val)))
+    }
+    match quantity {
+        Quantity::Temperature => split(measurements.temperature, Temperature::to_degree_celsius),
+        Quantity::WaterContent => split(measurements.water_content, VolumetricWaterContent::to_percent),
+        Quantity::Permittivity => split(measurements.permittivity, RelativePermittivity::to_ratio),
+        Quantity::RawCounts => split(measurements.raw_counts, |val| f64::from(u16::from(val))),
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct RuleState {
+    last_ts: Option<SystemTime>,
+    consecutive: u32,
+    raised: bool,
+}
+
+/// Evaluates rules for the measurements of one or more slaves.
+#[derive(Debug, Clone, Default)]
+pub struct AlarmEngine {
+    rules: Vec<Rule>,
+    states: HashMap<(Option<u8>, usize), RuleState>,
+}
+
+impl AlarmEngine {
+    pub fn new(rules: Vec<Rule>) -> Self {
+        Self {
+            rules,
+            states: HashMap::new(),
+        }
+    }
+
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    /// Check if an alarm of a rule is currently raised for `slave`.
+    pub fn is_raised(&self, slave: Option<u8>, rule: &str) -> bool {
+        self.rules.iter().enumerate().any(|(index, r)| {
+            r.name == rule && self.states.get(&(slave, index)).map(|state| state.raised).unwrap_or(false)
+        })
+    }
+
+    /// Evaluate all rules for the latest measurements of `slave`.
+    ///
+    /// Measurements that have already been evaluated, i.e. the last
+    /// known good values after a failed poll cycle, are ignored.
+    pub fn evaluate(&mut self, slave: Option<u8>, measurements: &TimestampedMeasurements) -> Vec<AlarmEvent> {
+        let mut events = Vec::new();
+        for (index, rule) in self.rules.iter().enumerate() {
+            let (ts, value) = match latest_value(rule.quantity, measurements) {
+                Some(latest) => latest,
+                None => continue,
+            };
+            let state = self.states.entry((slave, index)).or_default();
+            if state.last_ts.map(|last_ts| last_ts >= ts).unwrap_or(false) {
+                continue;
+            }
+            state.last_ts = Some(ts);
+            let alarm = || Alarm {
+                rule: rule.name.clone(),
+                slave,
+                value,
+                ts,
+            };
+            if state.raised {
+                if rule.condition.is_recovered(value, rule.hysteresis) {
+                    state.raised = false;
+                    state.consecutive = 0;
+                    events.push(AlarmEvent::Cleared(alarm()));
+                }
+                continue;
+            }
+            if rule.condition.is_met(value) {
+                state.consecutive += 1;
+                if state.consecutive >= rule.cycles {
+                    state.raised = true;
+                    events.push(AlarmEvent::Raised(alarm()));
+                }
+            } else {
+                state.consecutive = 0;
+            }
+        }
+        events
+    }
+}
+
+/// Evaluates rules after each poll cycle and emits alarm events
+/// on a channel.
+#[derive(Debug)]
+pub struct Alarms {
+    engine: AlarmEngine,
+    sender: mpsc::Sender<AlarmEvent>,
+}
+
+impl Alarms {
+    pub fn new(rules: Vec<Rule>, sender: mpsc::Sender<AlarmEvent>) -> Self {
+        Self {
+            engine: AlarmEngine::new(rules),
+            sender,
+        }
+    }
+
+    pub fn engine(&self) -> &AlarmEngine {
+        &self.engine
+    }
+}
+
+/// Fails if the receiver of alarm events has been dropped.
+impl MeasurementSink for Alarms {
+    fn record(&mut self, slave: Option<u8>, measurements: TimestampedMeasurements) -> BoxFuture<'_, io::Result<()>> {
+        async move {
+            for event in self.engine.evaluate(slave, &measurements) {
+                log::info!("{:?}", event);
+                self.sender
+                    .send(event)
+                    .await
+                    .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "receiver dropped"))?;
+            }
+            Ok(())
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn engine(rule: Rule) -> AlarmEngine {
+        AlarmEngine::new(vec![rule])
+    }
+
+    fn dry(cycles: u32) -> AlarmEngine {
+        engine(Rule::below("dry", Quantity::WaterContent, 20.0).with_cycles(cycles))
+    }
+
+    /// Evaluate the water content of consecutive poll cycles.
+    fn evaluate(engine: &mut AlarmEngine, values: &[f64]) -> Vec<Option<AlarmEvent>> {
+        let mut ts = UNIX_EPOCH;
+        values
+            .iter()
+            .map(|value| {
+                ts += Duration::from_secs(1);
+                let measurements = TimestampedMeasurements {
+                    water_content: Some(Measurement::at(ts, VolumetricWaterContent::from_percent(*value))),
+                    ..Default::default()
+                };
+                let mut events = engine.evaluate(None, &measurements);
+                assert!(events.len() <= 1);
+                events.pop()
+            })
+            .collect()
+    }
+
+    fn raised(events: &[Option<AlarmEvent>]) -> Vec<bool> {
+        events
+            .iter()
+            .map(|event| matches!(event, Some(AlarmEvent::Raised(_))))
+            .collect()
+    }
+
+    fn cleared(events: &[Option<AlarmEvent>]) -> Vec<bool> {
+        events
+            .iter()
+            .map(|event| matches!(event, Some(AlarmEvent::Cleared(_))))
+            .collect()
+    }
+
+    #[test]
+    fn raise_after_consecutive_cycles() {
+        let mut engine = dry(3);
+        let events = evaluate(&mut engine, &[19.0, 18.0, 17.0, 16.0]);
+        assert_eq!(vec![false, false, true, false], raised(&events));
+        assert!(engine.is_raised(None, "dry"));
+        match &events[2] {
+            Some(AlarmEvent::Raised(alarm)) => {
+                assert_eq!("dry", alarm.rule);
+                assert_eq!(None, alarm.slave);
+                assert_eq!(17.0, alarm.value);
+                assert_eq!(UNIX_EPOCH + Duration::from_secs(3), alarm.ts);
+            }
+            event => panic!("Unexpected event: {:?}", event),
+        }
+    }
+
+    #[test]
+    fn reset_consecutive_cycles_on_miss() {
+        let mut engine = dry(2);
+        let events = evaluate(&mut engine, &[19.0, 20.0, 19.0, 21.0, 19.0, 19.0]);
+        assert_eq!(vec![false, false, false, false, false, true], raised(&events));
+    }
+
+    #[test]
+    fn clear_beyond_hysteresis() {
+        let mut engine = engine(Rule::below("dry", Quantity::WaterContent, 20.0).with_hysteresis(2.0));
+        let events = evaluate(&mut engine, &[19.0, 20.0, 21.9, 19.0, 22.0]);
+        assert_eq!(vec![true, false, false, false, false], raised(&events));
+        assert_eq!(vec![false, false, false, false, true], cleared(&events));
+        assert!(!engine.is_raised(None, "dry"));
+    }
+
+    #[test]
+    fn clear_above_threshold_beyond_hysteresis() {
+        let mut engine = engine(Rule::above("wet", Quantity::WaterContent, 40.0).with_hysteresis(5.0));
+        let events = evaluate(&mut engine, &[41.0, 35.1, 35.0, 40.0, 41.0]);
+        assert_eq!(vec![true, false, false, false, true], raised(&events));
+        assert_eq!(vec![false, false, true, false, false], cleared(&events));
+    }
+
+    #[test]
+    fn raise_immediately_without_cycles() {
+        let mut engine = dry(0);
+        let events = evaluate(&mut engine, &[21.0, 19.0]);
+        assert_eq!(vec![false, true], raised(&events));
+    }
+
+    #[test]
+    fn ignore_measurements_that_have_already_been_evaluated() {
+        let mut engine = dry(2);
+        let measurements = TimestampedMeasurements {
+            water_content: Some(Measurement::at(UNIX_EPOCH, VolumetricWaterContent::from_percent(10.0))),
+            ..Default::default()
+        };
+        assert!(engine.evaluate(None, &measurements).is_empty());
+        assert!(engine.evaluate(None, &measurements).is_empty());
+        assert!(!engine.is_raised(None, "dry"));
+        assert!(engine.evaluate(Some(1), &measurements).is_empty());
+    }
+}
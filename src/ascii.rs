@@ -0,0 +1,142 @@
+use super::*;
+
+use crate::core::ascii::*;
+
+use futures::{future::BoxFuture, FutureExt};
+use std::{
+    io,
+    path::Path,
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::Duration,
+};
+use tokio::io::{
+    split, AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, ReadHalf, WriteHalf,
+};
+use tokio_serial::{DataBits, FlowControl, Parity, SerialPortBuilder, SerialStream, StopBits};
+
+/// The serial line settings are the same as for Modbus RTU.
+pub fn serial_port_builder(path: impl AsRef<Path>) -> SerialPortBuilder {
+    tokio_serial::new(path.as_ref().to_string_lossy(), 9600)
+        .data_bits(DataBits::Eight)
+        .stop_bits(StopBits::One)
+        .parity(Parity::Even)
+        .flow_control(FlowControl::None)
+}
+
+struct Transport<T> {
+    reader: BufReader<ReadHalf<T>>,
+    writer: WriteHalf<T>,
+}
+
+impl<T: AsyncRead + AsyncWrite> Transport<T> {
+    fn new(transport: T) -> Self {
+        let (reader, writer) = split(transport);
+        Self {
+            reader: BufReader::new(reader),
+            writer,
+        }
+    }
+}
+
+/// Proxy for a single sensor that has been configured for the
+/// ASCII text command protocol.
+///
+/// The transport is consumed by a pending request and only returned
+/// after the response has been received. If a request fails or times
+/// out the transport is dropped and all subsequent requests fail until
+/// a new transport has been provided by `reconnect()`.
+pub struct Proxy<T> {
+    transport: Mutex<Option<Transport<T>>>,
+}
+
+impl<T: AsyncRead + AsyncWrite + Send> Proxy<T> {
+    pub fn new(transport: T) -> Self {
+        Self {
+            transport: Mutex::new(Some(Transport::new(transport))),
+        }
+    }
+
+    fn transport(&self) -> MutexGuard<'_, Option<Transport<T>>> {
+        self.transport.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Check if the transport is available for the next request.
+    pub fn is_connected(&self) -> bool {
+        self.transport().is_some()
+    }
+
+    /// Replace the transport to recover from communication errors.
+    pub fn reconnect(&self, transport: T) {
+        *self.transport() = Some(Transport::new(transport));
+    }
+
+    async fn request(&self, command: Command) -> Result<Vec<u8>> {
+        let taken = self.transport().take();
+        let mut transport = taken.ok_or(Error::NotConnected)?;
+        transport.writer.write_all(command.as_bytes()).await?;
+        let mut rsp = Vec::new();
+        transport.reader.read_until(RESPONSE_TERMINATOR, &mut rsp).await?;
+        *self.transport() = Some(transport);
+        Ok(rsp)
+    }
+
+    async fn request_with_timeout(
+        &self,
+        command: Command,
+        quantity: Quantity,
+        timeout: Option<Duration>,
+    ) -> Result<Vec<u8>> {
+        with_timeout(timeout, self.request(command))
+            .await
+            .map_err(|err| err.with_timeout_quantity(quantity))
+    }
+
+    pub async fn read_temperature(&self, timeout: Option<Duration>) -> Result<Temperature> {
+        let rsp = self.request_with_timeout(Command::GetTemperature, Quantity::Temperature, timeout).await?;
+        Ok(decode_temperature_from_bytes(&rsp)?.0)
+    }
+
+    pub async fn read_water_content(&self, timeout: Option<Duration>) -> Result<VolumetricWaterContent> {
+        let rsp = self.request_with_timeout(Command::GetWaterContent, Quantity::WaterContent, timeout).await?;
+        Ok(decode_water_content_from_bytes(&rsp)?.0)
+    }
+
+    pub async fn read_permittivity(&self, timeout: Option<Duration>) -> Result<RelativePermittivity> {
+        let rsp = self.request_with_timeout(Command::GetPermittivity, Quantity::Permittivity, timeout).await?;
+        Ok(decode_permittivity_from_bytes(&rsp)?.0)
+    }
+
+    pub async fn read_raw_counts(&self, timeout: Option<Duration>) -> Result<RawCounts> {
+        let rsp = self.request_with_timeout(Command::GetCounts, Quantity::RawCounts, timeout).await?;
+        Ok(decode_raw_counts_from_bytes(&rsp)?.0)
+    }
+}
+
+impl<T: AsyncRead + AsyncWrite + Send> Capabilities for Proxy<T> {
+    fn read_temperature(&self, timeout: Option<Duration>) -> BoxFuture<'_, Result<Temperature>> {
+        self.read_temperature(timeout).boxed()
+    }
+
+    fn read_water_content(&self, timeout: Option<Duration>) -> BoxFuture<'_, Result<VolumetricWaterContent>> {
+        self.read_water_content(timeout).boxed()
+    }
+
+    fn read_permittivity(&self, timeout: Option<Duration>) -> BoxFuture<'_, Result<RelativePermittivity>> {
+        self.read_permittivity(timeout).boxed()
+    }
+
+    fn read_raw_counts(&self, timeout: Option<Duration>) -> BoxFuture<'_, Result<RawCounts>> {
+        self.read_raw_counts(timeout).boxed()
+    }
+}
+
+/// Must be called within the context of a _tokio_ runtime.
+pub fn open_path(path: impl AsRef<Path>) -> io::Result<SerialStream> {
+    log::info!("Opening serial port {}", path.as_ref().display());
+    Ok(SerialStream::open(&serial_port_builder(path))?)
+}
+
+/// Must be called within the context of a _tokio_ runtime.
+pub fn connect_path(path: impl AsRef<Path>) -> io::Result<Proxy<SerialStream>> {
+    open_path(path).map(Proxy::new)
+}
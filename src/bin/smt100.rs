@@ -0,0 +1,285 @@
+//! Command-line tool for reading and commissioning sensors.
+
+use futures::{future::BoxFuture, FutureExt};
+use std::{env, error, fmt, io, process, sync::Arc, time::Duration};
+use tokio::time::{interval_at, Instant};
+use tokio_modbus::prelude::{client, Slave, SlaveId};
+use tokio_serial::Parity;
+
+use truebner_smt100::{
+    core::modbus::Smt100Address,
+    modbus::{self, NewContext, SharedContext},
+    watch, Measurements,
+};
+
+const USAGE: &str = "Usage: smt100 <COMMAND> [OPTIONS]
+
+Commands:
+    read                    Read all measurements once
+    watch                   Read all measurements periodically
+    set-address <ADDRESS>   Change the Modbus slave address
+    scan [FIRST] [LAST]     Search for slaves on the bus (default: 1 247)
+
+Options:
+    --device <PATH>         Serial port (default: /dev/ttyUSB0)
+    --baud-rate <RATE>      Baud rate of the serial port (default: 9600)
+    --parity <PARITY>       Parity: none, odd, or even (default: even)
+    --slave <ADDRESS>       Modbus slave address (default: 1)
+    --timeout <DURATION>    Request timeout (default: 500ms)
+    --cycle <DURATION>      Cycle time for watch (default: 1s)
+    --format <FORMAT>       Output format: table or json (default: table)
+    --broadcast             Use the broadcast address for set-address
+    --no-verify             Don't read back the address after set-address
+    --help                  Print this message";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Table,
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Command {
+    Read,
+    Watch,
+    SetAddress(Slave),
+    Scan(Slave, Slave),
+}
+
+#[derive(Debug, Clone)]
+struct Options {
+    command: Command,
+    device: String,
+    serial_config: modbus::rtu::SerialConfig,
+    slave: Slave,
+    timeout: Duration,
+    cycle: Duration,
+    format: Format,
+    broadcast: bool,
+    verify: bool,
+}
+
+#[derive(Debug)]
+struct UsageError(String);
+
+impl fmt::Display for UsageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl error::Error for UsageError {}
+
+type Result<T> = std::result::Result<T, Box<dyn error::Error>>;
+
+fn usage_error<T>(msg: impl Into<String>) -> Result<T> {
+    Err(Box::new(UsageError(msg.into())))
+}
+
+fn parse_slave(arg: &str) -> Result<Slave> {
+    match arg.parse::<SlaveId>().ok().and_then(Smt100Address::new) {
+        Some(address) => Ok(address.into()),
+        None => usage_error(format!("invalid slave address: {}", arg)),
+    }
+}
+
+fn parse_duration(arg: &str) -> Result<Duration> {
+    humantime::parse_duration(arg).or_else(|_| usage_error(format!("invalid duration: {}", arg)))
+}
+
+fn parse_options(mut args: impl Iterator<Item = String>) -> Result<Options> {
+    let mut command = None;
+    let mut params = Vec::new();
+    let mut options = Options {
+        command: Command::Read,
+        device: "/dev/ttyUSB0".to_owned(),
+        serial_config: Default::default(),
+        slave: Slave::min_device(),
+        timeout: Duration::from_millis(500),
+        cycle: Duration::from_secs(1),
+        format: Format::Table,
+        broadcast: false,
+        verify: true,
+    };
+    while let Some(arg) = args.next() {
+        let mut value = || match args.next() {
+            Some(value) => Ok(value),
+            None => usage_error(format!("missing value for {}", arg)),
+        };
+        match arg.as_str() {
+            "--device" => options.device = value()?,
+            "--baud-rate" => {
+                let arg = value()?;
+                let baud_rate = match arg.parse() {
+                    Ok(baud_rate) if baud_rate > 0 => baud_rate,
+                    _ => return usage_error(format!("invalid baud rate: {}", arg)),
+                };
+                options.serial_config = options.serial_config.with_baud_rate(baud_rate);
+            }
+            "--parity" => {
+                let parity = match value()?.as_str() {
+                    "none" => Parity::None,
+                    "odd" => Parity::Odd,
+                    "even" => Parity::Even,
+                    parity => return usage_error(format!("invalid parity: {}", parity)),
+                };
+                options.serial_config = options.serial_config.with_parity(parity);
+            }
+            "--slave" => options.slave = parse_slave(&value()?)?,
+            "--timeout" => options.timeout = parse_duration(&value()?)?,
+            "--cycle" => {
+                let arg = value()?;
+                options.cycle = match parse_duration(&arg)? {
+                    cycle if cycle > Duration::ZERO => cycle,
+                    _ => return usage_error(format!("invalid cycle time: {}", arg)),
+                };
+            }
+            "--format" => {
+                options.format = match value()?.as_str() {
+                    "table" => Format::Table,
+                    "json" => Format::Json,
+                    format => return usage_error(format!("invalid format: {}", format)),
+                }
+            }
+            "--broadcast" => options.broadcast = true,
+            "--no-verify" => options.verify = false,
+            "-h" | "--help" => return usage_error(""),
+            _ if arg.starts_with('-') => return usage_error(format!("unknown option: {}", arg)),
+            _ if command.is_none() => command = Some(arg),
+            _ => params.push(arg),
+        }
+    }
+    options.command = match (command.as_deref(), params.as_slice()) {
+        (Some("read"), []) => Command::Read,
+        (Some("watch"), []) => Command::Watch,
+        (Some("set-address"), [address]) => Command::SetAddress(parse_slave(address)?),
+        (Some("scan"), []) => Command::Scan(Slave::min_device(), Slave::max_device()),
+        (Some("scan"), [first]) => Command::Scan(parse_slave(first)?, Slave::max_device()),
+        (Some("scan"), [first, last]) => Command::Scan(parse_slave(first)?, parse_slave(last)?),
+        (Some(command), _) => return usage_error(format!("invalid command: {}", command)),
+        (None, _) => return usage_error("missing command"),
+    };
+    Ok(options)
+}
+
+fn print_measurements(format: Format, slave: Slave, measurements: &Measurements) {
+    let Measurements {
+        temperature,
+        water_content,
+        permittivity,
+        raw_counts,
+    } = measurements;
+    match format {
+        Format::Table => {
+            println!("Slave          {}", slave.0);
+            println!("Temperature    {:.2} °C", temperature.to_degree_celsius());
+            println!("Water content  {:.2} %", water_content.to_percent());
+            println!("Permittivity   {:.2}", permittivity.to_ratio());
+            println!("Raw counts     {}", raw_counts);
+        }
+        Format::Json => println!("{}", watch::Record::new(Some(slave.0), *measurements)),
+    }
+}
+
+fn print_slaves(format: Format, slaves: &[Slave]) {
+    let slave_ids: Vec<_> = slaves.iter().map(|slave| slave.0.to_string()).collect();
+    match format {
+        Format::Table => {
+            if slave_ids.is_empty() {
+                println!("No slaves found");
+            } else {
+                println!("Found slaves: {}", slave_ids.join(", "));
+            }
+        }
+        Format::Json => println!("{{\"slaves\":[{}]}}", slave_ids.join(",")),
+    }
+}
+
+#[derive(Debug, Clone)]
+struct ContextConfig {
+    device: String,
+    serial_config: modbus::rtu::SerialConfig,
+}
+
+impl NewContext for ContextConfig {
+    fn new_context(&self) -> BoxFuture<'_, io::Result<client::Context>> {
+        async move { modbus::rtu::connect_path_with_config(&self.device, self.serial_config) }.boxed()
+    }
+}
+
+async fn run(options: Options) -> Result<()> {
+    let context_config = ContextConfig {
+        device: options.device.clone(),
+        serial_config: options.serial_config,
+    };
+    let shared_context = Arc::new(SharedContext::new(None, Box::new(context_config)));
+    shared_context.reconnect().await?;
+    let proxy = modbus::SlaveProxy::new(options.slave, Arc::clone(&shared_context));
+    let timeout = Some(options.timeout);
+    match options.command {
+        Command::Read => {
+            let measurements = proxy.read_all(timeout).await?;
+            print_measurements(options.format, options.slave, &measurements);
+        }
+        Command::Watch if options.format == Format::Json => {
+            let proxy = modbus::ReconnectingProxy::new(proxy);
+            let slave = Some(options.slave.0);
+            watch::watch(&proxy, slave, options.cycle, timeout, io::stdout()).await?;
+        }
+        Command::Watch => {
+            let mut ticks = interval_at(Instant::now() + options.cycle, options.cycle);
+            loop {
+                ticks.tick().await;
+                match proxy.read_all(timeout).await {
+                    Ok(measurements) => print_measurements(options.format, options.slave, &measurements),
+                    Err(err) => {
+                        log::warn!("Reconnecting after error: {}", err);
+                        if let Err(err) = proxy.reconnect().await {
+                            log::error!("Failed to reconnect: {}", err);
+                        }
+                    }
+                }
+            }
+        }
+        Command::SetAddress(new_slave) => {
+            let mut session = modbus::CommissioningSession::enter(shared_context);
+            session.set_verify_writes(options.verify);
+            if options.broadcast {
+                session.broadcast_slave(new_slave).await?;
+            } else {
+                session.set_slave_address(options.slave, new_slave).await?;
+            }
+            println!("Changed slave address to {}", new_slave.0);
+        }
+        Command::Scan(first, last) => {
+            let slaves = (first.0..=last.0).map(Slave);
+            let found = modbus::scan_bus(&shared_context, slaves, options.timeout).await?;
+            print_slaves(options.format, &found);
+        }
+    }
+    Ok(())
+}
+
+pub fn main() {
+    env_logger::init();
+    let options = match parse_options(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            let msg = err.to_string();
+            if !msg.is_empty() {
+                eprintln!("{}\n", msg);
+            }
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+    let res = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(Into::into)
+        .and_then(|runtime| runtime.block_on(run(options)));
+    if let Err(err) = res {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
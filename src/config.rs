@@ -0,0 +1,269 @@
+//! Bus and slave setup from configuration files.
+//!
+//! A bus with multiple sensors is configured in TOML like this:
+//!
+//! ```toml
+//! tty = "/dev/ttyUSB0"
+//! baud = 9600
+//! inter_request_delay = "10ms"
+//!
+//! [[slaves]]
+//! address = 1
+//! name = "bed-A"
+//! cycle = "1min"
+//! timeout = "500ms"
+//!
+//! [[slaves]]
+//! address = 2
+//! name = "greenhouse-3"
+//! ```
+//!
+//! Durations are parsed by _humantime_. All fields except `tty` and
+//! the slave `address` are optional.
+
+use crate::{
+    core::modbus::Smt100Address,
+    modbus::{
+        rtu::{connect_path_with_config, SerialConfig},
+        BusManager, NewContext, SharedContext, SlaveProxy,
+    },
+    poller::{PollConfig, Poller},
+    registry::{SensorMetadata, SensorRegistry},
+};
+
+use futures::{future::BoxFuture, FutureExt};
+use serde::Deserialize;
+use std::{io, sync::Arc, time::Duration};
+use tokio_modbus::prelude::{client, Slave};
+
+/// A serial port with multiple sensors.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct BusConfig {
+    /// The path of the serial port, e.g. `/dev/ttyUSB0`.
+    pub tty: String,
+
+    /// Defaults to the factory settings of the sensors.
+    #[serde(default = "default_baud")]
+    pub baud: u32,
+
+    /// See also: `SharedContext::set_inter_request_delay()`
+    #[serde(default, with = "humantime_serde")]
+    pub inter_request_delay: Duration,
+
+    #[serde(default)]
+    pub slaves: Vec<SlaveConfig>,
+}
+
+/// A single sensor on the bus.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct SlaveConfig {
+    /// The Modbus slave address.
+    ///
+    /// Addresses outside of the unicast range are rejected when parsing.
+    pub address: Smt100Address,
+
+    /// A unique name for referencing the sensor, e.g. `bed-A`.
+    #[serde(default)]
+    pub name: Option<String>,
+
+    /// The poll cycle.
+    #[serde(default = "default_cycle", with = "humantime_serde")]
+    pub cycle: Duration,
+
+    /// The timeout for each single measurement.
+    #[serde(default = "default_timeout", with = "humantime_serde")]
+    pub timeout: Duration,
+}
+
+fn default_baud() -> u32 {
+    SerialConfig::default().baud_rate()
+}
+
+fn default_cycle() -> Duration {
+    PollConfig::default().cycle
+}
+
+fn default_timeout() -> Duration {
+    PollConfig::default().timeout
+}
+
+impl SlaveConfig {
+    pub fn slave(&self) -> Slave {
+        self.address.into()
+    }
+
+    /// The name of the sensor, i.e. `slave-<address>` if no name
+    /// has been configured.
+    pub fn name(&self) -> String {
+        self.name
+            .clone()
+            .unwrap_or_else(|| format!("slave-{}", self.address))
+    }
+
+    /// Poll all quantities with the configured cycle and timeout.
+    pub fn poll_config(&self) -> PollConfig {
+        PollConfig {
+            cycle: self.cycle,
+            timeout: self.timeout,
+            slave: Some(self.address.get()),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct ContextConfig {
+    tty: String,
+    serial_config: SerialConfig,
+}
+
+impl NewContext for ContextConfig {
+    fn new_context(&self) -> BoxFuture<'_, io::Result<client::Context>> {
+        async move { connect_path_with_config(&self.tty, self.serial_config) }.boxed()
+    }
+}
+
+impl BusConfig {
+    /// Parse the configuration from a TOML document.
+    pub fn from_toml(input: &str) -> io::Result<Self> {
+        toml::from_str(input).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    /// The serial port settings with the configured baud rate.
+    pub fn serial_config(&self) -> SerialConfig {
+        SerialConfig::default().with_baud_rate(self.baud)
+    }
+
+    /// Create a disconnected context for the serial port.
+    ///
+    /// The serial port is opened on the first `reconnect()`.
+    pub fn shared_context(&self) -> SharedContext {
+        let new_context = ContextConfig {
+            tty: self.tty.clone(),
+            serial_config: self.serial_config(),
+        };
+        let shared_context = SharedContext::new(None, Box::new(new_context));
+        shared_context.set_inter_request_delay(self.inter_request_delay);
+        shared_context
+    }
+
+    /// Register all configured slaves in order.
+    ///
+    /// The bus manager is disconnected until `BusManager::reconnect()`.
+    pub fn bus_manager(&self) -> BusManager {
+        let mut bus_manager = BusManager::new(Arc::new(self.shared_context()));
+        for slave in &self.slaves {
+            bus_manager.register(slave.slave());
+        }
+        bus_manager
+    }
+
+    /// A poller for each configured slave.
+    pub fn pollers(&self, bus_manager: &BusManager) -> Vec<Poller<SlaveProxy>> {
+        self.slaves
+            .iter()
+            .map(|slave| Poller::new(bus_manager.slave_proxy(slave.slave()), slave.poll_config()))
+            .collect()
+    }
+
+    /// Register all configured slaves by their names.
+    ///
+    /// Fails if the names are not unique.
+    pub fn sensor_registry(&self, bus_manager: &BusManager) -> io::Result<SensorRegistry> {
+        let mut registry = SensorRegistry::new();
+        for slave in &self.slaves {
+            let name = slave.name();
+            if registry.contains(&name) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Duplicate sensor name: {}", name),
+                ));
+            }
+            let proxy = bus_manager.slave_proxy(slave.slave());
+            registry.insert(name, Box::new(proxy), SensorMetadata::default());
+        }
+        Ok(registry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::Error;
+
+    const EXAMPLE: &str = r#"
+tty = "/dev/ttyUSB0"
+baud = 19200
+inter_request_delay = "10ms"
+
+[[slaves]]
+address = 1
+name = "bed-A"
+cycle = "1min"
+timeout = "250ms"
+
+[[slaves]]
+address = 2
+"#;
+
+    #[test]
+    fn parse_example() {
+        let config = BusConfig::from_toml(EXAMPLE).unwrap();
+        assert_eq!("/dev/ttyUSB0", config.tty);
+        assert_eq!(19_200, config.serial_config().baud_rate());
+        assert_eq!(Duration::from_millis(10), config.inter_request_delay);
+        assert_eq!(2, config.slaves.len());
+        let first = &config.slaves[0];
+        assert_eq!(Slave(1), first.slave());
+        assert_eq!("bed-A", first.name());
+        let poll_config = first.poll_config();
+        assert_eq!(Duration::from_secs(60), poll_config.cycle);
+        assert_eq!(Duration::from_millis(250), poll_config.timeout);
+        assert_eq!(Some(1), poll_config.slave);
+        let second = &config.slaves[1];
+        assert_eq!("slave-2", second.name());
+        assert_eq!(PollConfig::default().cycle, second.cycle);
+        assert_eq!(PollConfig::default().timeout, second.timeout);
+    }
+
+    #[test]
+    fn defaults() {
+        let config = BusConfig::from_toml(r#"tty = "COM3""#).unwrap();
+        assert_eq!(SerialConfig::default().baud_rate(), config.baud);
+        assert_eq!(Duration::default(), config.inter_request_delay);
+        assert!(config.slaves.is_empty());
+    }
+
+    #[test]
+    fn reject_invalid_config() {
+        let invalid = |input| BusConfig::from_toml(input).unwrap_err().kind();
+        assert_eq!(io::ErrorKind::InvalidData, invalid("baud = 9600"));
+        assert_eq!(io::ErrorKind::InvalidData, invalid("tty = \"COM3\"\n[[slaves]]\naddress = 0"));
+        assert_eq!(io::ErrorKind::InvalidData, invalid("tty = \"COM3\"\n[[slaves]]\naddress = 248"));
+        assert_eq!(io::ErrorKind::InvalidData, invalid("tty = \"COM3\"\n[[slaves]]\nname = \"bed-A\""));
+    }
+
+    #[tokio::test]
+    async fn disconnected_bus_manager() {
+        let config = BusConfig::from_toml(EXAMPLE).unwrap();
+        let bus_manager = config.bus_manager();
+        assert_eq!(&[Slave(1), Slave(2)], bus_manager.slaves());
+        assert_eq!(Duration::from_millis(10), bus_manager.inter_request_delay());
+        let pollers = config.pollers(&bus_manager);
+        assert_eq!(2, pollers.len());
+        assert_eq!(&config.slaves[1].poll_config(), pollers[1].config());
+        let registry = config.sensor_registry(&bus_manager).unwrap();
+        assert_eq!(vec!["bed-A", "slave-2"], registry.names().collect::<Vec<_>>());
+        let res = registry.capabilities("bed-A").unwrap().read_temperature(None).await;
+        assert!(matches!(res, Err(Error::NotConnected)));
+    }
+
+    #[test]
+    fn reject_duplicate_names() {
+        let mut config = BusConfig::from_toml(EXAMPLE).unwrap();
+        config.slaves[1].name = Some("bed-A".to_owned());
+        let err = config.sensor_registry(&config.bus_manager()).err().unwrap();
+        assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+    }
+}
@@ -0,0 +1,208 @@
+use super::*;
+
+use core::str;
+
+/// Every command is terminated by an exclamation mark.
+pub const COMMAND_TERMINATOR: u8 = b'!';
+
+/// Every response is a single line of text.
+pub const RESPONSE_TERMINATOR: u8 = b'\n';
+
+/// Text commands for reading measurements in ASCII mode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+    GetTemperature,
+    GetWaterContent,
+    GetPermittivity,
+    GetCounts,
+}
+
+impl Command {
+    /// The complete request frame including the terminator.
+    pub fn as_bytes(self) -> &'static [u8] {
+        use Command::*;
+        match self {
+            GetTemperature => b"GetTemperature!",
+            GetWaterContent => b"GetWaterContent!",
+            GetPermittivity => b"GetPermittivity!",
+            GetCounts => b"GetCounts!",
+        }
+    }
+}
+
+/// Split off the next response line and strip any surrounding whitespace,
+/// including the terminating line break.
+pub fn decode_response_line(input: &[u8]) -> DecodeResult<(&str, &[u8])> {
+    if let Some(pos) = input.iter().position(|b| *b == RESPONSE_TERMINATOR) {
+        let (line, rest) = input.split_at(pos + 1);
+        str::from_utf8(line)
+            .map(|line| (line.trim(), rest))
+            .map_err(|_| DecodeError::InvalidInput)
+    } else {
+        Err(DecodeError::InsufficientInput)
+    }
+}
+
+fn decode_f64_from_str(input: &str) -> DecodeResult<f64> {
+    input.parse().map_err(|_| DecodeError::InvalidInput)
+}
+
+pub fn decode_temperature_from_str(input: &str) -> DecodeResult<Temperature> {
+    let degree_celsius = decode_f64_from_str(input)?;
+    Ok(Temperature::from_degree_celsius(degree_celsius))
+}
+
+/// Decode the temperature and verify the range of the sensor variant.
+pub fn decode_temperature_from_str_for_variant(
+    input: &str,
+    variant: SensorVariant,
+) -> DecodeResult<Temperature> {
+    let res = decode_temperature_from_str(input)?;
+    if variant.is_valid_temperature(res) {
+        Ok(res)
+    } else {
+        Err(DecodeError::InvalidData)
+    }
+}
+
+pub fn decode_temperature_from_bytes(input: &[u8]) -> DecodeResult<(Temperature, &[u8])> {
+    decode_response_line(input).and_then(|(line, rest)| Ok((decode_temperature_from_str(line)?, rest)))
+}
+
+pub fn decode_water_content_from_str(input: &str) -> DecodeResult<VolumetricWaterContent> {
+    let percent = decode_f64_from_str(input)?;
+    let res = VolumetricWaterContent::from_percent(percent);
+    if res.is_valid() {
+        Ok(res)
+    } else {
+        Err(DecodeError::InvalidData)
+    }
+}
+
+pub fn decode_water_content_from_bytes(input: &[u8]) -> DecodeResult<(VolumetricWaterContent, &[u8])> {
+    decode_response_line(input).and_then(|(line, rest)| Ok((decode_water_content_from_str(line)?, rest)))
+}
+
+pub fn decode_permittivity_from_str(input: &str) -> DecodeResult<RelativePermittivity> {
+    let ratio = decode_f64_from_str(input)?;
+    let res = RelativePermittivity::from_ratio(ratio);
+    if res.is_valid() {
+        Ok(res)
+    } else {
+        Err(DecodeError::InvalidData)
+    }
+}
+
+pub fn decode_permittivity_from_bytes(input: &[u8]) -> DecodeResult<(RelativePermittivity, &[u8])> {
+    decode_response_line(input).and_then(|(line, rest)| Ok((decode_permittivity_from_str(line)?, rest)))
+}
+
+pub fn decode_raw_counts_from_str(input: &str) -> DecodeResult<RawCounts> {
+    input
+        .parse::<u16>()
+        .map(Into::into)
+        .map_err(|_| DecodeError::InvalidInput)
+}
+
+pub fn decode_raw_counts_from_bytes(input: &[u8]) -> DecodeResult<(RawCounts, &[u8])> {
+    decode_response_line(input).and_then(|(line, rest)| Ok((decode_raw_counts_from_str(line)?, rest)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_commands() {
+        for cmd in &[
+            Command::GetTemperature,
+            Command::GetWaterContent,
+            Command::GetPermittivity,
+            Command::GetCounts,
+        ] {
+            assert_eq!(Some(&COMMAND_TERMINATOR), cmd.as_bytes().last());
+        }
+    }
+
+    #[test]
+    fn decode_line() {
+        assert_eq!(Err(DecodeError::InsufficientInput), decode_response_line(b"21.5"));
+        assert_eq!(Ok(("21.5", &b""[..])), decode_response_line(b"21.5\r\n"));
+        assert_eq!(Ok(("21.5", &b"34.4\r\n"[..])), decode_response_line(b" 21.5\r\n34.4\r\n"));
+        assert_eq!(Err(DecodeError::InvalidInput), decode_response_line(b"\xFF\r\n"));
+    }
+
+    #[test]
+    fn decode_temperature() {
+        assert_eq!(
+            Temperature::from_degree_celsius(-40.0),
+            decode_temperature_from_bytes(b"-40.00\r\n").unwrap().0
+        );
+        assert_eq!(
+            Temperature::from_degree_celsius(27.97),
+            decode_temperature_from_bytes(b"27.97\r\n").unwrap().0
+        );
+        assert!(decode_temperature_from_bytes(b"Error\r\n").is_err());
+    }
+
+    #[test]
+    fn decode_water_content() {
+        // Valid range
+        assert_eq!(
+            VolumetricWaterContent::from_percent(0.0),
+            decode_water_content_from_bytes(b"0.00\r\n").unwrap().0
+        );
+        assert_eq!(
+            VolumetricWaterContent::from_percent(34.4),
+            decode_water_content_from_bytes(b"34.40\r\n").unwrap().0
+        );
+        // Invalid range
+        assert_eq!(
+            Err(DecodeError::InvalidData),
+            decode_water_content_from_bytes(b"100.01\r\n")
+        );
+    }
+
+    #[test]
+    fn decode_permittivity() {
+        // Valid range
+        assert_eq!(
+            RelativePermittivity::from_ratio(15.2),
+            decode_permittivity_from_bytes(b"15.20\r\n").unwrap().0
+        );
+        // Invalid range
+        assert_eq!(
+            Err(DecodeError::InvalidData),
+            decode_permittivity_from_bytes(b"0.99\r\n")
+        );
+    }
+
+    #[test]
+    fn decode_raw_counts() {
+        assert_eq!(
+            RawCounts::from(11345),
+            decode_raw_counts_from_bytes(b"11345\r\n").unwrap().0
+        );
+        assert!(decode_raw_counts_from_bytes(b"-1\r\n").is_err());
+        assert!(decode_raw_counts_from_bytes(b"65536\r\n").is_err());
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn decode_arbitrary_bytes(input in proptest::collection::vec(proptest::num::u8::ANY, 0..16)) {
+            let _ = decode_response_line(&input);
+            let _ = decode_temperature_from_bytes(&input);
+            let _ = decode_water_content_from_bytes(&input);
+            let _ = decode_permittivity_from_bytes(&input);
+            let _ = decode_raw_counts_from_bytes(&input);
+        }
+
+        #[test]
+        fn decode_arbitrary_str(input in "\\PC*") {
+            let _ = decode_temperature_from_str(&input);
+            let _ = decode_water_content_from_str(&input);
+            let _ = decode_permittivity_from_str(&input);
+            let _ = decode_raw_counts_from_str(&input);
+        }
+    }
+}
@@ -0,0 +1,286 @@
+//! Conversion of permittivity into volumetric water content.
+//!
+//! The water content that is measured by the sensor is derived from
+//! the permittivity using a factory calibration for mineral soils.
+//! Other media require different calibration curves that could be
+//! applied to the measured permittivity.
+//!
+//! The permittivity itself could be recalibrated from the raw counts
+//! of a particular sensor with a `TwoPointCalibration`.
+
+use super::*;
+
+/// Derives the volumetric water content of a medium from its permittivity.
+pub trait CalibrationCurve {
+    /// The results might be invalid if the permittivity is outside
+    /// of the range for which the curve has been determined.
+    fn water_content(&self, permittivity: RelativePermittivity) -> VolumetricWaterContent;
+}
+
+/// Polynomial calibration curve θ(ε) = c₀ + c₁·ε + c₂·ε² + …
+///
+/// The coefficients are given in ascending order and the result θ is
+/// a volume ratio (m³/m³), i.e. not in percent.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Polynomial<'a> {
+    coefficients: &'a [f64],
+}
+
+impl<'a> Polynomial<'a> {
+    pub const fn new(coefficients: &'a [f64]) -> Self {
+        Self { coefficients }
+    }
+
+    pub const fn coefficients(&self) -> &'a [f64] {
+        self.coefficients
+    }
+
+    /// Evaluate the polynomial using Horner's method.
+    pub fn eval(&self, x: f64) -> f64 {
+        self.coefficients.iter().rev().fold(0.0, |acc, c| acc * x + c)
+    }
+}
+
+impl<'a> CalibrationCurve for Polynomial<'a> {
+    fn water_content(&self, permittivity: RelativePermittivity) -> VolumetricWaterContent {
+        VolumetricWaterContent::from_percent(self.eval(permittivity.to_ratio()) * 100.0)
+    }
+}
+
+/// Mineral soils according to Roth et al. (1992).
+pub const MINERAL_SOIL: Polynomial<'static> =
+    Polynomial::new(&[-7.28e-2, 4.48e-2, -1.95e-3, 3.61e-5]);
+
+/// Organic soils and substrates like peat or potting soil
+/// according to Roth et al. (1992).
+pub const ORGANIC_SOIL: Polynomial<'static> =
+    Polynomial::new(&[-2.33e-2, 2.85e-2, -4.31e-4, 3.04e-6]);
+
+/// Universal equation for mineral soils according to Topp et al. (1980).
+pub const TOPP: Polynomial<'static> = Polynomial::new(&[-5.3e-2, 2.92e-2, -5.5e-4, 4.3e-6]);
+
+/// Derive the volumetric water content from a permittivity reading
+/// using the given calibration curve.
+pub fn calibrated_water_content(
+    permittivity: RelativePermittivity,
+    curve: &impl CalibrationCurve,
+) -> VolumetricWaterContent {
+    curve.water_content(permittivity)
+}
+
+/// Linear compensation of the temperature dependency of the water
+/// content θ' = θ·(1 + α·(T − T₀)) + β·(T − T₀).
+///
+/// See also: `VolumetricWaterContent::temperature_compensated()`
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TemperatureCoefficients {
+    /// The temperature T₀ in °C that doesn't need any compensation.
+    pub reference_degree_celsius: f64,
+
+    /// The relative coefficient α in 1/K.
+    pub relative: f64,
+
+    /// The absolute coefficient β in percentage points per K.
+    pub absolute: f64,
+}
+
+impl TemperatureCoefficients {
+    /// Compensate the water content that has been measured at the
+    /// given temperature.
+    pub fn compensate(
+        &self,
+        water_content: VolumetricWaterContent,
+        temperature: Temperature,
+    ) -> VolumetricWaterContent {
+        let delta = temperature.to_degree_celsius() - self.reference_degree_celsius;
+        let percent = water_content.to_percent() * (1.0 + self.relative * delta) + self.absolute * delta;
+        VolumetricWaterContent::from_percent(percent)
+    }
+}
+
+impl Default for TemperatureCoefficients {
+    fn default() -> Self {
+        FREE_WATER_TEMPERATURE_COEFFICIENTS
+    }
+}
+
+/// Compensates the decrease of the permittivity of free water by
+/// about 0.46 % per K around 20°C according to Kaatze (1989).
+///
+/// Bound water in fine-textured or organic soils shows a different
+/// behavior that needs to be determined empirically.
+pub const FREE_WATER_TEMPERATURE_COEFFICIENTS: TemperatureCoefficients = TemperatureCoefficients {
+    reference_degree_celsius: 20.0,
+    relative: 0.0046,
+    absolute: 0.0,
+};
+
+/// The permittivity of water at 20°C.
+pub const WATER_PERMITTIVITY: f64 = 80.1;
+
+/// User calibration of the permittivity from raw counts that have
+/// been recorded in air and in water.
+///
+/// The raw counts are assumed to be linear in the refractive index √ε,
+/// i.e. the propagation time of the signal along the sensor.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TwoPointCalibration {
+    /// The raw counts in air with a permittivity of 1.
+    pub air_counts: u16,
+
+    /// The raw counts in water.
+    pub water_counts: u16,
+
+    /// The permittivity of the water during the calibration, which
+    /// depends on its temperature.
+    pub water_permittivity: f64,
+}
+
+impl TwoPointCalibration {
+    /// Calibrate with water at 20°C.
+    pub fn new(air_counts: RawCounts, water_counts: RawCounts) -> Self {
+        Self {
+            air_counts: air_counts.into(),
+            water_counts: water_counts.into(),
+            water_permittivity: WATER_PERMITTIVITY,
+        }
+    }
+
+    pub const fn with_water_permittivity(mut self, water_permittivity: f64) -> Self {
+        self.water_permittivity = water_permittivity;
+        self
+    }
+
+    /// Both reference points must be distinguishable.
+    pub fn is_valid(&self) -> bool {
+        self.air_counts != self.water_counts && self.water_permittivity > RelativePermittivity::min_ratio()
+    }
+
+    /// Convert raw counts into permittivity.
+    ///
+    /// The result is invalid if the calibration is invalid or if the
+    /// raw counts are beyond the reading in air.
+    pub fn calibrated_permittivity(&self, raw_counts: RawCounts) -> RelativePermittivity {
+        if !self.is_valid() {
+            return RelativePermittivity::from_ratio(f64::NAN);
+        }
+        let air_counts = f64::from(self.air_counts);
+        let slope = (sqrt(self.water_permittivity) - 1.0) / (f64::from(self.water_counts) - air_counts);
+        let refractive_index = (1.0 + (f64::from(u16::from(raw_counts)) - air_counts) * slope).max(0.0);
+        RelativePermittivity::from_ratio(refractive_index * refractive_index)
+    }
+}
+
+/// Newton's method, because `f64::sqrt()` is not available without `std`.
+fn sqrt(x: f64) -> f64 {
+    if !(x > 0.0 && x.is_finite()) {
+        return if x == 0.0 { 0.0 } else { f64::NAN };
+    }
+    // Start above the root to decrease monotonically
+    let mut y = x.max(1.0);
+    loop {
+        let next = 0.5 * (y + x / y);
+        if next >= y {
+            return y;
+        }
+        y = next;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_approx_eq(expected: f64, actual: f64) {
+        assert!((expected - actual).abs() < 1e-9, "{} != {}", expected, actual);
+    }
+
+    #[test]
+    fn eval_polynomial() {
+        assert_approx_eq(0.0, Polynomial::new(&[]).eval(2.0));
+        assert_approx_eq(1.5, Polynomial::new(&[1.5]).eval(2.0));
+        // 1 + 2x + 3x²
+        assert_approx_eq(17.0, Polynomial::new(&[1.0, 2.0, 3.0]).eval(2.0));
+        assert_approx_eq(2.0, Polynomial::new(&[1.0, 2.0, 3.0]).eval(-1.0));
+    }
+
+    #[test]
+    fn custom_curve() {
+        let linear = Polynomial::new(&[-0.1, 0.02]);
+        assert_approx_eq(
+            30.0,
+            calibrated_water_content(RelativePermittivity::from_ratio(20.0), &linear).to_percent(),
+        );
+    }
+
+    #[test]
+    fn builtin_curves_are_increasing() {
+        for curve in &[MINERAL_SOIL, ORGANIC_SOIL, TOPP] {
+            let mut last = calibrated_water_content(RelativePermittivity::from_ratio(3.0), curve);
+            assert!(last.is_valid());
+            for ratio in 4..=40 {
+                let next =
+                    calibrated_water_content(RelativePermittivity::from_ratio(f64::from(ratio)), curve);
+                assert!(next.is_valid());
+                assert!(next > last);
+                last = next;
+            }
+        }
+    }
+
+    #[test]
+    fn square_root() {
+        assert_approx_eq(0.0, sqrt(0.0));
+        assert_approx_eq(0.5, sqrt(0.25));
+        assert_approx_eq(9.0, sqrt(81.0));
+        assert!(sqrt(-1.0).is_nan());
+    }
+
+    #[test]
+    fn two_point_calibration() {
+        let calib = TwoPointCalibration::new(RawCounts::from(2000), RawCounts::from(10000))
+            .with_water_permittivity(81.0);
+        assert!(calib.is_valid());
+        assert_approx_eq(1.0, calib.calibrated_permittivity(RawCounts::from(2000)).to_ratio());
+        assert_approx_eq(81.0, calib.calibrated_permittivity(RawCounts::from(10000)).to_ratio());
+        // Halfway between the refractive indices 1 and 9
+        assert_approx_eq(25.0, calib.calibrated_permittivity(RawCounts::from(6000)).to_ratio());
+        assert!(!calib.calibrated_permittivity(RawCounts::from(1000)).is_valid());
+    }
+
+    #[test]
+    fn invalid_two_point_calibration() {
+        let calib = TwoPointCalibration::new(RawCounts::from(2000), RawCounts::from(2000));
+        assert!(!calib.is_valid());
+        assert!(!calib.calibrated_permittivity(RawCounts::from(2000)).is_valid());
+    }
+
+    #[test]
+    fn temperature_compensation() {
+        let water_content = VolumetricWaterContent::from_percent(30.0);
+        let coefficients = TemperatureCoefficients::default();
+        assert_eq!(
+            water_content,
+            water_content.temperature_compensated(Temperature::from_degree_celsius(20.0), &coefficients)
+        );
+        assert_approx_eq(
+            31.38,
+            water_content
+                .temperature_compensated(Temperature::from_degree_celsius(30.0), &coefficients)
+                .to_percent(),
+        );
+        let custom = TemperatureCoefficients {
+            reference_degree_celsius: 25.0,
+            relative: 0.0,
+            absolute: -0.1,
+        };
+        assert_approx_eq(
+            30.5,
+            water_content
+                .temperature_compensated(Temperature::from_degree_celsius(20.0), &custom)
+                .to_percent(),
+        );
+    }
+}
@@ -0,0 +1,155 @@
+//! Fixed-point representations for targets without an FPU.
+//!
+//! All types losslessly represent the raw register values of the
+//! sensor and could be converted into the floating-point types
+//! if needed.
+
+use super::*;
+
+/// Temperature in 0.01 °C.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TemperatureCentiDegrees(pub i16);
+
+impl TemperatureCentiDegrees {
+    /// The register value is offset by +100 °C.
+    const REGISTER_OFFSET: i32 = 10_000;
+
+    pub fn from_register(input: u16) -> DecodeResult<Self> {
+        let centi_degrees = i32::from(input) - Self::REGISTER_OFFSET;
+        if centi_degrees > i32::from(i16::MAX) {
+            return Err(DecodeError::InvalidData);
+        }
+        Ok(Self(centi_degrees as i16))
+    }
+
+    /// Values below -100 °C are not representable and saturate.
+    pub fn to_register(self) -> u16 {
+        (i32::from(self.0) + Self::REGISTER_OFFSET).max(0) as u16
+    }
+
+    pub fn is_valid(self) -> bool {
+        self.0 >= -4_000 && self.0 <= 8_000
+    }
+}
+
+impl From<TemperatureCentiDegrees> for Temperature {
+    fn from(from: TemperatureCentiDegrees) -> Self {
+        Self::from_degree_celsius(f64::from(from.0) / 100.0)
+    }
+}
+
+/// Volumetric water content in basis points, i.e. 0.01 %.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct WaterContentBasisPoints(pub u16);
+
+impl WaterContentBasisPoints {
+    pub fn from_register(input: u16) -> DecodeResult<Self> {
+        let res = Self(input);
+        if res.is_valid() {
+            Ok(res)
+        } else {
+            Err(DecodeError::InvalidData)
+        }
+    }
+
+    pub fn to_register(self) -> u16 {
+        self.0
+    }
+
+    pub fn is_valid(self) -> bool {
+        self.0 <= 10_000
+    }
+}
+
+impl From<WaterContentBasisPoints> for VolumetricWaterContent {
+    fn from(from: WaterContentBasisPoints) -> Self {
+        Self::from_percent(f64::from(from.0) / 100.0)
+    }
+}
+
+/// Relative permittivity in 1/100.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PermittivityCentis(pub u16);
+
+impl PermittivityCentis {
+    pub fn from_register(input: u16) -> DecodeResult<Self> {
+        let res = Self(input);
+        if res.is_valid() {
+            Ok(res)
+        } else {
+            Err(DecodeError::InvalidData)
+        }
+    }
+
+    pub fn to_register(self) -> u16 {
+        self.0
+    }
+
+    pub fn is_valid(self) -> bool {
+        self.0 >= 100
+    }
+}
+
+impl From<PermittivityCentis> for RelativePermittivity {
+    fn from(from: PermittivityCentis) -> Self {
+        Self::from_ratio(f64::from(from.0) / 100.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn temperature_register_roundtrip() {
+        for &(reg, centi_degrees) in &[(0x1770, -4_000), (0x2710, 0), (0x31FD, 2_797), (0x4650, 8_000)] {
+            let temperature = TemperatureCentiDegrees::from_register(reg).unwrap();
+            assert_eq!(TemperatureCentiDegrees(centi_degrees), temperature);
+            assert!(temperature.is_valid());
+            assert_eq!(reg, temperature.to_register());
+        }
+        assert_eq!(
+            TemperatureCentiDegrees(-10_000),
+            TemperatureCentiDegrees::from_register(0).unwrap()
+        );
+        assert_eq!(0, TemperatureCentiDegrees(i16::MIN).to_register());
+        assert!(TemperatureCentiDegrees::from_register(0xFFFF).is_err());
+    }
+
+    #[test]
+    fn temperature_into_float() {
+        assert_eq!(
+            Temperature::from_degree_celsius(27.97),
+            TemperatureCentiDegrees(2_797).into()
+        );
+    }
+
+    #[test]
+    fn water_content_register() {
+        assert_eq!(
+            Ok(WaterContentBasisPoints(3_440)),
+            WaterContentBasisPoints::from_register(0x0D70)
+        );
+        assert_eq!(
+            Err(DecodeError::InvalidData),
+            WaterContentBasisPoints::from_register(10_001)
+        );
+        assert_eq!(
+            VolumetricWaterContent::from_percent(34.4),
+            WaterContentBasisPoints(3_440).into()
+        );
+    }
+
+    #[test]
+    fn permittivity_register() {
+        assert_eq!(Ok(PermittivityCentis(1_520)), PermittivityCentis::from_register(1_520));
+        assert_eq!(Err(DecodeError::InvalidData), PermittivityCentis::from_register(99));
+        assert_eq!(
+            RelativePermittivity::from_ratio(15.2),
+            PermittivityCentis(1_520).into()
+        );
+    }
+}
@@ -1,10 +1,35 @@
+#[cfg(feature = "ascii")]
+pub mod ascii;
+
+pub mod calib;
+
+#[cfg(feature = "fixed-point")]
+pub mod fixed;
+
 #[cfg(feature = "modbus")]
 pub mod modbus;
 
-use core::{fmt, time::Duration, result::Result};
+#[cfg(feature = "modbus")]
+pub mod profiles;
+
+pub mod trend;
+
+#[cfg(feature = "uom")]
+mod uom;
+
+use core::{
+    convert::TryFrom,
+    fmt,
+    future::Future,
+    ops::{Add, Div, Mul, RangeInclusive, Sub},
+    time::Duration,
+    result::Result,
+};
 
 /// (Thermodynamic) Temperature.
 #[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(transparent))]
 #[repr(transparent)]
 pub struct Temperature(f64);
 
@@ -13,19 +38,62 @@ impl Temperature {
         Self(degree_celsius)
     }
 
+    /// Create a valid temperature for the digital sensor variant.
+    pub fn try_from_degree_celsius(degree_celsius: f64) -> Result<Self, RangeError> {
+        let res = Self::from_degree_celsius(degree_celsius);
+        if res.is_valid() {
+            Ok(res)
+        } else {
+            Err(RangeError(Quantity::Temperature))
+        }
+    }
+
     pub const fn to_degree_celsius(self) -> f64 {
         self.0
     }
+
+    pub const fn min_degree_celsius() -> f64 {
+        -40.0
+    }
+
+    pub const fn max_degree_celsius() -> f64 {
+        80.0
+    }
+
+    pub const fn min() -> Self {
+        Self::from_degree_celsius(Self::min_degree_celsius())
+    }
+
+    pub const fn max() -> Self {
+        Self::from_degree_celsius(Self::max_degree_celsius())
+    }
+
+    /// Check the range of the digital sensor variant.
+    ///
+    /// See also: `SensorVariant::is_valid_temperature()`
+    pub fn is_valid(self) -> bool {
+        SensorVariant::Digital.is_valid_temperature(self)
+    }
+}
+
+impl TryFrom<f64> for Temperature {
+    type Error = RangeError;
+
+    fn try_from(degree_celsius: f64) -> Result<Self, Self::Error> {
+        Self::try_from_degree_celsius(degree_celsius)
+    }
 }
 
 impl fmt::Display for Temperature {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{} °C", self.to_degree_celsius())
+        fmt_value(f, self.to_degree_celsius(), " °C")
     }
 }
 
 /// Volumetric water content (VWC).
 #[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(transparent))]
 #[repr(transparent)]
 pub struct VolumetricWaterContent(f64);
 
@@ -34,6 +102,15 @@ impl VolumetricWaterContent {
         Self(percent)
     }
 
+    pub fn try_from_percent(percent: f64) -> Result<Self, RangeError> {
+        let res = Self::from_percent(percent);
+        if res.is_valid() {
+            Ok(res)
+        } else {
+            Err(RangeError(Quantity::WaterContent))
+        }
+    }
+
     pub const fn to_percent(self) -> f64 {
         self.0
     }
@@ -57,16 +134,53 @@ impl VolumetricWaterContent {
     pub fn is_valid(self) -> bool {
         self >= Self::min() && self <= Self::max()
     }
+
+    /// Derive the water content from the permittivity using the
+    /// empirical equation of Topp et al. (1980) instead of the
+    /// sensor's built-in calibration.
+    pub fn from_permittivity_topp(permittivity: RelativePermittivity) -> Self {
+        Self::from_permittivity(permittivity, &calib::TOPP)
+    }
+
+    /// Derive the water content from the permittivity using a
+    /// custom calibration curve, e.g. a `calib::Polynomial`.
+    pub fn from_permittivity(
+        permittivity: RelativePermittivity,
+        curve: &impl calib::CalibrationCurve,
+    ) -> Self {
+        curve.water_content(permittivity)
+    }
+
+    /// Compensate the temperature dependency of a water content that
+    /// has been measured at the given temperature, e.g. with the default
+    /// `calib::TemperatureCoefficients`.
+    pub fn temperature_compensated(
+        self,
+        temperature: Temperature,
+        coefficients: &calib::TemperatureCoefficients,
+    ) -> Self {
+        coefficients.compensate(self, temperature)
+    }
+}
+
+impl TryFrom<f64> for VolumetricWaterContent {
+    type Error = RangeError;
+
+    fn try_from(percent: f64) -> Result<Self, Self::Error> {
+        Self::try_from_percent(percent)
+    }
 }
 
 impl fmt::Display for VolumetricWaterContent {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{} %", self.to_percent())
+        fmt_value(f, self.to_percent(), " %")
     }
 }
 
 /// Relative permittivity or dielectric constant (DK).
 #[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(transparent))]
 #[repr(transparent)]
 pub struct RelativePermittivity(f64);
 
@@ -75,6 +189,15 @@ impl RelativePermittivity {
         Self(percent)
     }
 
+    pub fn try_from_ratio(ratio: f64) -> Result<Self, RangeError> {
+        let res = Self::from_ratio(ratio);
+        if res.is_valid() {
+            Ok(res)
+        } else {
+            Err(RangeError(Quantity::Permittivity))
+        }
+    }
+
     pub const fn to_ratio(self) -> f64 {
         self.0
     }
@@ -92,13 +215,125 @@ impl RelativePermittivity {
     }
 }
 
+impl TryFrom<f64> for RelativePermittivity {
+    type Error = RangeError;
+
+    fn try_from(ratio: f64) -> Result<Self, Self::Error> {
+        Self::try_from_ratio(ratio)
+    }
+}
+
 impl fmt::Display for RelativePermittivity {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{} %", self.to_ratio())
+        // The ratio has no unit
+        fmt_value(f, self.to_ratio(), "")
     }
 }
 
+/// Arithmetic on the inner values, e.g. for filters and statistics.
+///
+/// Differences are represented by the same type, i.e. they might
+/// be out of range.
+macro_rules! impl_value_ops {
+    ($($ty:ident),*) => {
+        $(
+            impl $ty {
+                /// The absolute difference between both values.
+                pub fn abs_diff(self, other: Self) -> Self {
+                    if self > other {
+                        self - other
+                    } else {
+                        other - self
+                    }
+                }
+            }
+
+            impl Add for $ty {
+                type Output = Self;
+
+                fn add(self, rhs: Self) -> Self {
+                    Self(self.0 + rhs.0)
+                }
+            }
+
+            impl Sub for $ty {
+                type Output = Self;
+
+                fn sub(self, rhs: Self) -> Self {
+                    Self(self.0 - rhs.0)
+                }
+            }
+
+            impl Mul<f64> for $ty {
+                type Output = Self;
+
+                fn mul(self, rhs: f64) -> Self {
+                    Self(self.0 * rhs)
+                }
+            }
+
+            impl Div<f64> for $ty {
+                type Output = Self;
+
+                fn div(self, rhs: f64) -> Self {
+                    Self(self.0 / rhs)
+                }
+            }
+        )*
+    };
+}
+
+impl_value_ops!(Temperature, VolumetricWaterContent, RelativePermittivity);
+
+/// Format the value with the width and precision of the formatter
+/// followed by the unit.
+fn fmt_value(f: &mut fmt::Formatter<'_>, value: f64, unit: &str) -> fmt::Result {
+    match (f.width(), f.precision()) {
+        (Some(width), Some(precision)) => {
+            write!(f, "{:width$.precision$}", value, width = width, precision = precision)
+        }
+        (Some(width), None) => write!(f, "{:width$}", value, width = width),
+        (None, Some(precision)) => write!(f, "{:.*}", precision, value),
+        (None, None) => write!(f, "{}", value),
+    }?;
+    f.write_str(unit)
+}
+
+/// Displays a value without its unit.
+#[derive(Clone, Copy, Debug)]
+pub struct Unitless(f64);
+
+impl fmt::Display for Unitless {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt_value(f, self.0, "")
+    }
+}
+
+macro_rules! impl_value_fmt {
+    ($($ty:ident),*) => {
+        $(
+            impl $ty {
+                /// Display the value without its unit.
+                pub fn unitless(self) -> Unitless {
+                    Unitless(self.0)
+                }
+
+                /// Format the value with a fixed number of decimals,
+                /// e.g. `27.97 °C` instead of `27.970000000000002 °C`.
+                #[cfg(feature = "std")]
+                pub fn to_string_fixed(self, decimals: usize) -> String {
+                    format!("{:.*}", decimals, self)
+                }
+            }
+        )*
+    };
+}
+
+impl_value_fmt!(Temperature, VolumetricWaterContent, RelativePermittivity);
+
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(transparent))]
 pub struct RawCounts(u16);
 
 impl From<u16> for RawCounts {
@@ -119,6 +354,223 @@ impl fmt::Display for RawCounts {
     }
 }
 
+/// The quantities that are measured by the sensor.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Quantity {
+    Temperature,
+    WaterContent,
+    Permittivity,
+    RawCounts,
+}
+
+impl Quantity {
+    /// All quantities in register order.
+    pub const ALL: [Quantity; 4] = [
+        Quantity::Temperature,
+        Quantity::WaterContent,
+        Quantity::Permittivity,
+        Quantity::RawCounts,
+    ];
+
+    /// The name in snake case, e.g. for topics, columns, or field keys.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Quantity::Temperature => "temperature",
+            Quantity::WaterContent => "water_content",
+            Quantity::Permittivity => "permittivity",
+            Quantity::RawCounts => "raw_counts",
+        }
+    }
+}
+
+bitflags::bitflags! {
+    /// A set of quantities that are measured together.
+    ///
+    /// Skipping unused quantities saves bus time.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct MeasurementSelection: u8 {
+        const TEMPERATURE = 0b0001;
+        const WATER_CONTENT = 0b0010;
+        const PERMITTIVITY = 0b0100;
+        const RAW_COUNTS = 0b1000;
+    }
+}
+
+impl Default for MeasurementSelection {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+impl From<Quantity> for MeasurementSelection {
+    fn from(from: Quantity) -> Self {
+        match from {
+            Quantity::Temperature => Self::TEMPERATURE,
+            Quantity::WaterContent => Self::WATER_CONTENT,
+            Quantity::Permittivity => Self::PERMITTIVITY,
+            Quantity::RawCounts => Self::RAW_COUNTS,
+        }
+    }
+}
+
+impl MeasurementSelection {
+    pub fn contains_quantity(self, quantity: Quantity) -> bool {
+        self.contains(quantity.into())
+    }
+
+    /// The selected quantities in register order.
+    pub fn quantities(self) -> impl Iterator<Item = Quantity> {
+        Quantity::ALL
+            .iter()
+            .copied()
+            .filter(move |quantity| self.contains_quantity(*quantity))
+    }
+}
+
+/// Hardware variants of the sensor with different measurement ranges.
+///
+/// The variants only differ in their temperature range.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SensorVariant {
+    /// Digital interfaces, i.e. Modbus RTU, SDI-12, or ASCII text commands
+    #[default]
+    Digital,
+
+    /// Analog voltage or current outputs
+    Analog,
+}
+
+impl SensorVariant {
+    pub fn temperature_range(self) -> RangeInclusive<Temperature> {
+        match self {
+            SensorVariant::Digital => Temperature::min()..=Temperature::max(),
+            SensorVariant::Analog => Temperature::min()..=Temperature::from_degree_celsius(60.0),
+        }
+    }
+
+    pub fn water_content_range(self) -> RangeInclusive<VolumetricWaterContent> {
+        VolumetricWaterContent::min()..=VolumetricWaterContent::max()
+    }
+
+    pub fn is_valid_temperature(self, temperature: Temperature) -> bool {
+        self.temperature_range().contains(&temperature)
+    }
+
+    pub fn is_valid_water_content(self, water_content: VolumetricWaterContent) -> bool {
+        self.water_content_range().contains(&water_content)
+    }
+
+    pub fn is_valid_permittivity(self, permittivity: RelativePermittivity) -> bool {
+        permittivity.is_valid()
+    }
+
+    /// Check that all measurements are within the valid ranges.
+    pub fn validate(self, measurements: &Measurements) -> DecodeResult<()> {
+        if self.is_valid_temperature(measurements.temperature)
+            && self.is_valid_water_content(measurements.water_content)
+            && self.is_valid_permittivity(measurements.permittivity)
+        {
+            Ok(())
+        } else {
+            Err(DecodeError::InvalidData)
+        }
+    }
+}
+
+/// All measurements of a sensor at a single point in time.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Measurements {
+    pub temperature: Temperature,
+    pub water_content: VolumetricWaterContent,
+    pub permittivity: RelativePermittivity,
+    pub raw_counts: RawCounts,
+}
+
+/// The measurements of selected quantities at a single point in time.
+///
+/// Quantities that have not been selected are `None`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SelectedMeasurements {
+    pub temperature: Option<Temperature>,
+    pub water_content: Option<VolumetricWaterContent>,
+    pub permittivity: Option<RelativePermittivity>,
+    pub raw_counts: Option<RawCounts>,
+}
+
+impl SelectedMeasurements {
+    /// The quantities that are present.
+    pub fn selection(&self) -> MeasurementSelection {
+        let mut selection = MeasurementSelection::empty();
+        selection.set(MeasurementSelection::TEMPERATURE, self.temperature.is_some());
+        selection.set(MeasurementSelection::WATER_CONTENT, self.water_content.is_some());
+        selection.set(MeasurementSelection::PERMITTIVITY, self.permittivity.is_some());
+        selection.set(MeasurementSelection::RAW_COUNTS, self.raw_counts.is_some());
+        selection
+    }
+}
+
+impl From<Measurements> for SelectedMeasurements {
+    fn from(from: Measurements) -> Self {
+        let Measurements {
+            temperature,
+            water_content,
+            permittivity,
+            raw_counts,
+        } = from;
+        Self {
+            temperature: Some(temperature),
+            water_content: Some(water_content),
+            permittivity: Some(permittivity),
+            raw_counts: Some(raw_counts),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DecodeError {
+    InsufficientInput,
+    InvalidInput,
+    InvalidData,
+    InvalidChecksum,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use DecodeError::*;
+        match self {
+            InsufficientInput => write!(f, "Insufficient input"),
+            InvalidInput => write!(f, "Invalid input"),
+            InvalidData => write!(f, "Invalid data"),
+            InvalidChecksum => write!(f, "Invalid checksum"),
+        }
+
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DecodeError {}
+
+pub type DecodeResult<T> = Result<T, DecodeError>;
+
+/// A value is outside of the valid range of the quantity.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RangeError(pub Quantity);
+
+impl fmt::Display for RangeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} out of range", self.0)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for RangeError {}
+
 /// Blocking interface that exposes the generic capabilities of the
 /// TRUEBNER SMT100 Soil Moisture Sensor.
 pub trait Capabilities {
@@ -145,10 +597,48 @@ pub trait Capabilities {
     fn read_raw_counts(&self, timeout: Option<Duration>) -> Result<RawCounts, Self::ReadError>;
 }
 
+/// Asynchronous interface that exposes the generic capabilities of the
+/// TRUEBNER SMT100 Soil Moisture Sensor without an allocator, e.g. for
+/// firmware based on _embassy_.
+///
+/// The returned futures are not required to be `Send`.
+pub trait AsyncCapabilities {
+    type ReadError;
+
+    /// Measure the current temperature in the range from -40°C to +80°C
+    /// (analog version from -40°C to +60°C).
+    fn read_temperature(
+        &self,
+        timeout: Option<Duration>,
+    ) -> impl Future<Output = Result<Temperature, Self::ReadError>>;
+
+    /// Measure the current water content of the medium (soil) around the sensor
+    /// in the range from 0% to 60% (up to 100% with limited accuracy).
+    fn read_water_content(
+        &self,
+        timeout: Option<Duration>,
+    ) -> impl Future<Output = Result<VolumetricWaterContent, Self::ReadError>>;
+
+    /// Measure the current (relative) permittivity of the medium around the sensor.
+    fn read_permittivity(
+        &self,
+        timeout: Option<Duration>,
+    ) -> impl Future<Output = Result<RelativePermittivity, Self::ReadError>>;
+
+    /// Retrieve the current raw and uncalibrated signal of the sensor.
+    fn read_raw_counts(&self, timeout: Option<Duration>) -> impl Future<Output = Result<RawCounts, Self::ReadError>>;
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn quantity_names() {
+        assert_eq!("water_content", Quantity::WaterContent.name());
+        assert!(MeasurementSelection::all().quantities().eq(Quantity::ALL.iter().copied()));
+    }
+
     #[test]
     #[allow(clippy::float_cmp)]
     fn water_content_percent() {
@@ -160,4 +650,146 @@ mod tests {
         assert!(!VolumetricWaterContent::from_percent(-0.5).is_valid());
         assert!(!VolumetricWaterContent::from_percent(100.01).is_valid());
     }
+
+    #[test]
+    fn checked_constructors() {
+        assert!(Temperature::try_from_degree_celsius(21.5).is_ok());
+        assert_eq!(
+            Err(RangeError(Quantity::Temperature)),
+            Temperature::try_from(-41.0)
+        );
+        assert!(VolumetricWaterContent::try_from_percent(34.4).is_ok());
+        assert_eq!(
+            Err(RangeError(Quantity::WaterContent)),
+            VolumetricWaterContent::try_from(150.0)
+        );
+        assert!(RelativePermittivity::try_from_ratio(15.2).is_ok());
+        assert_eq!(
+            Err(RangeError(Quantity::Permittivity)),
+            RelativePermittivity::try_from(0.5)
+        );
+        assert!(VolumetricWaterContent::try_from(f64::NAN).is_err());
+    }
+
+    #[test]
+    fn temperature_range() {
+        assert!(Temperature::from_degree_celsius(-40.0).is_valid());
+        assert!(Temperature::from_degree_celsius(80.0).is_valid());
+        assert!(!Temperature::from_degree_celsius(-40.01).is_valid());
+        assert!(!Temperature::from_degree_celsius(80.01).is_valid());
+        let analog = SensorVariant::Analog;
+        assert!(analog.is_valid_temperature(Temperature::from_degree_celsius(60.0)));
+        assert!(!analog.is_valid_temperature(Temperature::from_degree_celsius(60.01)));
+    }
+
+    #[test]
+    fn value_ops() {
+        let a = Temperature::from_degree_celsius(20.5);
+        let b = Temperature::from_degree_celsius(22.0);
+        assert_eq!(Temperature::from_degree_celsius(42.5), a + b);
+        assert_eq!(Temperature::from_degree_celsius(-1.5), a - b);
+        assert_eq!(Temperature::from_degree_celsius(1.5), a.abs_diff(b));
+        assert_eq!(b.abs_diff(a), a.abs_diff(b));
+        assert_eq!(Temperature::from_degree_celsius(21.25), (a + b) / 2.0);
+        assert!(a < b);
+        assert_eq!(
+            VolumetricWaterContent::from_percent(30.0),
+            VolumetricWaterContent::from_percent(15.0) * 2.0
+        );
+        assert_eq!(
+            RelativePermittivity::from_ratio(2.0),
+            RelativePermittivity::from_ratio(3.0).abs_diff(RelativePermittivity::from_ratio(5.0))
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn display_precision() {
+        let temperature = Temperature::from_degree_celsius(27.970000000000002);
+        assert_eq!("27.970000000000002 °C", format!("{}", temperature));
+        assert_eq!("28.0 °C", format!("{:.1}", temperature));
+        assert_eq!(" 27.97 °C", format!("{:6.2}", temperature));
+        assert_eq!("27.97", format!("{:.2}", temperature.unitless()));
+        assert_eq!("27.97 °C", temperature.to_string_fixed(2));
+        assert_eq!("35.5 %", format!("{:.1}", VolumetricWaterContent::from_percent(35.48)));
+        assert_eq!("12.25", format!("{}", RelativePermittivity::from_ratio(12.25)));
+    }
+
+    #[test]
+    fn validate_measurements() {
+        let measurements = Measurements {
+            temperature: Temperature::from_degree_celsius(70.0),
+            water_content: VolumetricWaterContent::from_percent(30.0),
+            permittivity: RelativePermittivity::from_ratio(12.0),
+            raw_counts: RawCounts::from(10000),
+        };
+        assert_eq!(Ok(()), SensorVariant::Digital.validate(&measurements));
+        assert_eq!(
+            Err(DecodeError::InvalidData),
+            SensorVariant::Analog.validate(&measurements)
+        );
+    }
+
+    #[test]
+    fn water_content_from_permittivity_topp() {
+        let vwc = VolumetricWaterContent::from_permittivity_topp(RelativePermittivity::from_ratio(10.0));
+        assert!((vwc.to_percent() - 18.83).abs() < 1e-9);
+        // Air
+        assert!(!VolumetricWaterContent::from_permittivity_topp(RelativePermittivity::min()).is_valid());
+        // Water
+        assert!(VolumetricWaterContent::from_permittivity_topp(RelativePermittivity::from_ratio(80.0)).is_valid());
+    }
+
+    #[test]
+    fn water_content_from_permittivity() {
+        let curve = calib::Polynomial::new(&[0.0, 0.01]);
+        let vwc = VolumetricWaterContent::from_permittivity(RelativePermittivity::from_ratio(25.0), &curve);
+        assert!((vwc.to_percent() - 25.0).abs() < 1e-9);
+    }
+
+    struct Constant(Measurements);
+
+    impl AsyncCapabilities for Constant {
+        type ReadError = ();
+
+        async fn read_temperature(&self, _: Option<Duration>) -> Result<Temperature, ()> {
+            Ok(self.0.temperature)
+        }
+
+        async fn read_water_content(&self, _: Option<Duration>) -> Result<VolumetricWaterContent, ()> {
+            Ok(self.0.water_content)
+        }
+
+        async fn read_permittivity(&self, _: Option<Duration>) -> Result<RelativePermittivity, ()> {
+            Ok(self.0.permittivity)
+        }
+
+        async fn read_raw_counts(&self, _: Option<Duration>) -> Result<RawCounts, ()> {
+            Ok(self.0.raw_counts)
+        }
+    }
+
+    async fn read_all<C: AsyncCapabilities>(capabilities: &C) -> Result<Measurements, C::ReadError> {
+        Ok(Measurements {
+            temperature: capabilities.read_temperature(None).await?,
+            water_content: capabilities.read_water_content(None).await?,
+            permittivity: capabilities.read_permittivity(None).await?,
+            raw_counts: capabilities.read_raw_counts(None).await?,
+        })
+    }
+
+    #[test]
+    fn async_capabilities() {
+        use core::{pin::pin, task::{Context, Poll, Waker}};
+        let measurements = Measurements {
+            temperature: Temperature::from_degree_celsius(21.5),
+            water_content: VolumetricWaterContent::from_percent(34.4),
+            permittivity: RelativePermittivity::from_ratio(15.2),
+            raw_counts: RawCounts::from(11345),
+        };
+        let capabilities = Constant(measurements);
+        let mut future = pin!(read_all(&capabilities));
+        let mut cx = Context::from_waker(Waker::noop());
+        assert_eq!(Poll::Ready(Ok(measurements)), future.as_mut().poll(&mut cx));
+    }
 }
@@ -0,0 +1,426 @@
+//! Async Modbus RTU client for firmware based on _embassy_.
+//!
+//! Frames are transferred over any serial port that implements the
+//! async `embedded-io` traits, e.g. the buffered UARTs of _embassy-rp_
+//! or _embassy-stm32_. The serial port must be configured with the
+//! settings in `rtu`. The direction of the RS-485 transceiver is
+//! switched by `rtu::DirectionControl`, e.g. by a `DriverEnablePin`
+//! that is connected to both DE and RE.
+//!
+//! Multiple sensors on the same bus share the client through a mutex:
+//!
+//! ```ignore
+//! use embassy_executor::Spawner;
+//! use embassy_rp::{bind_interrupts, gpio, peripherals::UART0, uart};
+//! use embassy_sync::{blocking_mutex::raw::NoopRawMutex, mutex::Mutex};
+//! use static_cell::StaticCell;
+//! use truebner_smt100::{
+//!     modbus::{embassy::{Client, Sensor}, rtu::DriverEnablePin},
+//!     AsyncCapabilities,
+//! };
+//!
+//! bind_interrupts!(struct Irqs {
+//!     UART0_IRQ => uart::BufferedInterruptHandler<UART0>;
+//! });
+//!
+//! #[embassy_executor::main]
+//! async fn main(_spawner: Spawner) {
+//!     let p = embassy_rp::init(Default::default());
+//!     static TX_BUF: StaticCell<[u8; 64]> = StaticCell::new();
+//!     static RX_BUF: StaticCell<[u8; 64]> = StaticCell::new();
+//!     let mut config = uart::Config::default();
+//!     config.baudrate = 9600;
+//!     config.parity = uart::Parity::ParityEven;
+//!     let uart = uart::BufferedUart::new(
+//!         p.UART0, Irqs, p.PIN_0, p.PIN_1,
+//!         TX_BUF.init([0; 64]), RX_BUF.init([0; 64]), config,
+//!     );
+//!     let de = DriverEnablePin(gpio::Output::new(p.PIN_2, gpio::Level::Low));
+//!     let bus = Mutex::<NoopRawMutex, _>::new(Client::new(uart, de, 1));
+//!     let sensor = Sensor::new(&bus, 1);
+//!     loop {
+//!         let timeout = Some(core::time::Duration::from_millis(500));
+//!         if let Ok(temperature) = sensor.read_temperature(timeout).await {
+//!             defmt::info!("{}", temperature);
+//!         }
+//!         embassy_time::Timer::after_secs(10).await;
+//!     }
+//! }
+//! ```
+
+use super::{rtu::*, *};
+
+use embassy_sync::{blocking_mutex::raw::RawMutex, mutex::Mutex};
+use embedded_io_async::{Read, ReadExactError, Write};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<E> {
+    /// Reading from or writing to the serial port failed.
+    Serial(E),
+
+    /// Switching the direction of the RS-485 transceiver failed.
+    Direction,
+
+    /// No response has been received in time.
+    Timeout,
+
+    /// The sensor responded with a Modbus exception code.
+    Exception(u8),
+
+    /// The response is incomplete or doesn't match the request.
+    Decode(DecodeError),
+}
+
+impl<E> From<DecodeError> for Error<E> {
+    fn from(from: DecodeError) -> Self {
+        Error::Decode(from)
+    }
+}
+
+impl<E> From<ReadExactError<E>> for Error<E> {
+    fn from(from: ReadExactError<E>) -> Self {
+        match from {
+            ReadExactError::UnexpectedEof => Error::Decode(DecodeError::InsufficientInput),
+            ReadExactError::Other(err) => Error::Serial(err),
+        }
+    }
+}
+
+impl<E: fmt::Debug> fmt::Display for Error<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Serial(err) => write!(f, "Serial port error: {:?}", err),
+            Error::Direction => write!(f, "Direction control failed"),
+            Error::Timeout => write!(f, "Timed out"),
+            Error::Exception(code) => write!(f, "Modbus exception: 0x{:02X}", code),
+            Error::Decode(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+pub type Result<T, E> = core::result::Result<T, Error<E>>;
+
+/// Fail with `Error::Timeout` if the request doesn't finish in time.
+async fn with_timeout<T, E>(
+    timeout: Option<Duration>,
+    request: impl Future<Output = Result<T, E>>,
+) -> Result<T, E> {
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return request.await,
+    };
+    let timeout = embassy_time::Duration::from_micros(timeout.as_micros().try_into().unwrap_or(u64::MAX));
+    embassy_time::with_timeout(timeout, request)
+        .await
+        .unwrap_or(Err(Error::Timeout))
+}
+
+/// Async Modbus RTU client for a half-duplex RS-485 bus.
+#[derive(Debug)]
+pub struct Client<S, D> {
+    serial: S,
+    direction: D,
+    slave: u8,
+}
+
+impl<S, D> Client<S, D>
+where
+    S: Read + Write,
+    D: DirectionControl,
+{
+    /// Use `()` as `direction` for transceivers with automatic
+    /// direction control.
+    pub fn new(serial: S, direction: D, slave: u8) -> Self {
+        Self {
+            serial,
+            direction,
+            slave,
+        }
+    }
+
+    pub fn slave(&self) -> u8 {
+        self.slave
+    }
+
+    pub fn set_slave(&mut self, slave: u8) {
+        self.slave = slave;
+    }
+
+    /// Release the serial port and the direction control.
+    pub fn into_inner(self) -> (S, D) {
+        (self.serial, self.direction)
+    }
+
+    pub async fn read_temperature(&mut self, timeout: Option<Duration>) -> Result<Temperature, S::Error> {
+        self.read_block(timeout).await
+    }
+
+    pub async fn read_water_content(&mut self, timeout: Option<Duration>) -> Result<VolumetricWaterContent, S::Error> {
+        self.read_block(timeout).await
+    }
+
+    pub async fn read_permittivity(&mut self, timeout: Option<Duration>) -> Result<RelativePermittivity, S::Error> {
+        self.read_block(timeout).await
+    }
+
+    pub async fn read_raw_counts(&mut self, timeout: Option<Duration>) -> Result<RawCounts, S::Error> {
+        self.read_block(timeout).await
+    }
+
+    /// Read all measurements with a single request.
+    pub async fn read_measurements(&mut self, timeout: Option<Duration>) -> Result<Measurements, S::Error> {
+        self.read_block(timeout).await
+    }
+
+    /// Read and decode a block of consecutive holding registers.
+    pub async fn read_block<T, const START: u16, const COUNT: u16>(
+        &mut self,
+        timeout: Option<Duration>,
+    ) -> Result<T, S::Error>
+    where
+        T: FromRegisterBlock<START, COUNT>,
+    {
+        let mut regs = [0u16; MAX_REG_COUNT as usize];
+        let regs = regs.get_mut(..usize::from(COUNT)).ok_or(DecodeError::InvalidInput)?;
+        self.read_holding_registers(START, regs, timeout).await?;
+        Ok(T::from_registers(regs)?)
+    }
+
+    /// Read consecutive holding registers into `regs`.
+    pub async fn read_holding_registers(
+        &mut self,
+        start: u16,
+        regs: &mut [u16],
+        timeout: Option<Duration>,
+    ) -> Result<(), S::Error> {
+        let mut buf = [0u8; MAX_FRAME_LEN];
+        let count = u16::try_from(regs.len()).map_err(|_| DecodeError::InvalidInput)?;
+        let len = encode_read_holding_registers(self.slave, start, count, &mut buf)?;
+        self.send_request(&buf[..len]).await?;
+        with_timeout(timeout, self.recv_response(FN_READ_HOLDING_REGISTERS, &mut buf))
+            .await?
+            .decode_registers(regs)?;
+        Ok(())
+    }
+
+    /// Write a single holding register.
+    ///
+    /// No response is expected for the Modbus broadcast address 0.
+    pub async fn write_single_register(
+        &mut self,
+        addr: u16,
+        value: u16,
+        timeout: Option<Duration>,
+    ) -> Result<(), S::Error> {
+        let mut buf = [0u8; MAX_FRAME_LEN];
+        let len = encode_write_single_register(self.slave, addr, value, &mut buf)?;
+        self.send_request(&buf[..len]).await?;
+        if self.slave == 0 {
+            return Ok(());
+        }
+        let echo = with_timeout(timeout, self.recv_response(FN_WRITE_SINGLE_REGISTER, &mut buf))
+            .await?
+            .decode_write_single_register()?;
+        if echo != (addr, value) {
+            return Err(DecodeError::InvalidInput.into());
+        }
+        Ok(())
+    }
+
+    async fn send_request(&mut self, frame: &[u8]) -> Result<(), S::Error> {
+        self.direction.before_send().map_err(|_| Error::Direction)?;
+        let res = async {
+            self.serial.write_all(frame).await.map_err(Error::Serial)?;
+            // Wait until the last byte has been transmitted
+            self.serial.flush().await.map_err(Error::Serial)
+        }
+        .await;
+        // Always release the bus, even if sending failed
+        self.direction.after_send().map_err(|_| Error::Direction)?;
+        res
+    }
+
+    async fn recv_response<'a>(
+        &mut self,
+        function: u8,
+        buf: &'a mut [u8; MAX_FRAME_LEN],
+    ) -> Result<ResponseFrame<'a>, S::Error> {
+        self.serial.read_exact(&mut buf[..3]).await?;
+        let len = response_frame_len(&buf[..3])?;
+        self.serial.read_exact(&mut buf[3..len]).await?;
+        let (frame, _) = decode_response_frame(&buf[..len])?;
+        if frame.slave != self.slave || frame.function & !EXCEPTION_FLAG != function {
+            return Err(DecodeError::InvalidInput.into());
+        }
+        if let Some(code) = frame.exception_code() {
+            return Err(Error::Exception(code));
+        }
+        Ok(frame)
+    }
+}
+
+/// A single sensor on a bus that is shared through a mutex.
+#[derive(Debug)]
+pub struct Sensor<'a, M: RawMutex, S, D> {
+    bus: &'a Mutex<M, Client<S, D>>,
+    slave: u8,
+}
+
+impl<'a, M: RawMutex, S, D> Sensor<'a, M, S, D> {
+    pub fn new(bus: &'a Mutex<M, Client<S, D>>, slave: u8) -> Self {
+        Self { bus, slave }
+    }
+
+    pub fn slave(&self) -> u8 {
+        self.slave
+    }
+}
+
+impl<'a, M, S, D> AsyncCapabilities for Sensor<'a, M, S, D>
+where
+    M: RawMutex,
+    S: Read + Write,
+    D: DirectionControl,
+{
+    type ReadError = Error<S::Error>;
+
+    async fn read_temperature(&self, timeout: Option<Duration>) -> Result<Temperature, S::Error> {
+        let mut client = self.bus.lock().await;
+        client.set_slave(self.slave);
+        client.read_temperature(timeout).await
+    }
+
+    async fn read_water_content(&self, timeout: Option<Duration>) -> Result<VolumetricWaterContent, S::Error> {
+        let mut client = self.bus.lock().await;
+        client.set_slave(self.slave);
+        client.read_water_content(timeout).await
+    }
+
+    async fn read_permittivity(&self, timeout: Option<Duration>) -> Result<RelativePermittivity, S::Error> {
+        let mut client = self.bus.lock().await;
+        client.set_slave(self.slave);
+        client.read_permittivity(timeout).await
+    }
+
+    async fn read_raw_counts(&self, timeout: Option<Duration>) -> Result<RawCounts, S::Error> {
+        let mut client = self.bus.lock().await;
+        client.set_slave(self.slave);
+        client.read_raw_counts(timeout).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use core::{
+        cell::Cell,
+        convert::Infallible,
+        pin::pin,
+        task::{Context, Poll, Waker},
+    };
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+
+    struct Serial {
+        rx: &'static [u8],
+        tx: [u8; 16],
+        tx_len: usize,
+    }
+
+    impl Serial {
+        fn new(rx: &'static [u8]) -> Self {
+            Self {
+                rx,
+                tx: [0; 16],
+                tx_len: 0,
+            }
+        }
+
+        fn sent(&self) -> &[u8] {
+            &self.tx[..self.tx_len]
+        }
+    }
+
+    impl embedded_io_async::ErrorType for Serial {
+        type Error = Infallible;
+    }
+
+    impl Read for Serial {
+        async fn read(&mut self, buf: &mut [u8]) -> core::result::Result<usize, Infallible> {
+            let len = buf.len().min(self.rx.len());
+            buf[..len].copy_from_slice(&self.rx[..len]);
+            self.rx = &self.rx[len..];
+            Ok(len)
+        }
+    }
+
+    impl Write for Serial {
+        async fn write(&mut self, buf: &[u8]) -> core::result::Result<usize, Infallible> {
+            let len = buf.len().min(self.tx.len() - self.tx_len);
+            self.tx[self.tx_len..self.tx_len + len].copy_from_slice(&buf[..len]);
+            self.tx_len += len;
+            Ok(len)
+        }
+    }
+
+    /// Counts the transitions to high.
+    #[derive(Default)]
+    struct Pin {
+        high: Cell<bool>,
+        toggles: usize,
+    }
+
+    impl embedded_hal::digital::ErrorType for Pin {
+        type Error = Infallible;
+    }
+
+    impl embedded_hal::digital::OutputPin for Pin {
+        fn set_low(&mut self) -> core::result::Result<(), Infallible> {
+            self.high.set(false);
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> core::result::Result<(), Infallible> {
+            self.high.set(true);
+            self.toggles += 1;
+            Ok(())
+        }
+    }
+
+    fn block_on<T>(future: impl Future<Output = T>) -> T {
+        let mut future = pin!(future);
+        let mut cx = Context::from_waker(Waker::noop());
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => output,
+            Poll::Pending => panic!("pending"),
+        }
+    }
+
+    #[test]
+    fn read_temperature() {
+        let serial = Serial::new(&[0x01, 0x03, 0x02, 0x31, 0xFD, 0x6C, 0x55]);
+        let mut client = Client::new(serial, DriverEnablePin(Pin::default()), 1);
+        assert_eq!(
+            Temperature::from_degree_celsius(27.97),
+            block_on(client.read_temperature(None)).unwrap()
+        );
+        let (serial, DriverEnablePin(pin)) = client.into_inner();
+        assert_eq!([0x01, 0x03, 0x00, 0x00, 0x00, 0x01, 0x84, 0x0A], serial.sent());
+        assert_eq!(1, pin.toggles);
+        assert!(!pin.high.get());
+    }
+
+    #[test]
+    fn shared_bus() {
+        let serial = Serial::new(&[0x02, 0x83, 0x02, 0xC0, 0xF1]);
+        let bus = Mutex::<NoopRawMutex, _>::new(Client::new(serial, (), 1));
+        let sensor = Sensor::new(&bus, 2);
+        assert_eq!(
+            Err(Error::Decode(DecodeError::InvalidChecksum)),
+            block_on(sensor.read_temperature(None))
+        );
+        let (serial, _) = bus.into_inner().into_inner();
+        assert_eq!(0x02, serial.sent()[0]);
+    }
+}
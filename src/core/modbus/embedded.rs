@@ -0,0 +1,315 @@
+//! Blocking Modbus RTU client for bare-metal targets.
+//!
+//! Frames are transferred over any serial port that implements the
+//! blocking `embedded-io` traits. The serial port must be configured
+//! with the settings in `rtu` and is responsible for aborting reads
+//! that don't receive a response in time, i.e. by returning an error.
+//! The direction of RS-485 transceivers without automatic direction
+//! control is switched by `rtu::DirectionControl`.
+
+use super::{rtu::*, *};
+
+use embedded_io::{Read, ReadExactError, Write};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<E> {
+    /// Reading from or writing to the serial port failed.
+    Serial(E),
+
+    /// Switching the direction of the RS-485 transceiver failed.
+    Direction,
+
+    /// The sensor responded with a Modbus exception code.
+    Exception(u8),
+
+    /// The response is incomplete or doesn't match the request.
+    Decode(DecodeError),
+}
+
+impl<E> From<DecodeError> for Error<E> {
+    fn from(from: DecodeError) -> Self {
+        Error::Decode(from)
+    }
+}
+
+impl<E> From<ReadExactError<E>> for Error<E> {
+    fn from(from: ReadExactError<E>) -> Self {
+        match from {
+            ReadExactError::UnexpectedEof => Error::Decode(DecodeError::InsufficientInput),
+            ReadExactError::Other(err) => Error::Serial(err),
+        }
+    }
+}
+
+impl<E: fmt::Debug> fmt::Display for Error<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Serial(err) => write!(f, "Serial port error: {:?}", err),
+            Error::Direction => write!(f, "Direction control failed"),
+            Error::Exception(code) => write!(f, "Modbus exception: 0x{:02X}", code),
+            Error::Decode(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+pub type Result<T, E> = core::result::Result<T, Error<E>>;
+
+/// Modbus RTU client for a single sensor.
+#[derive(Debug)]
+pub struct Client<S, D = ()> {
+    serial: S,
+    direction: D,
+    slave: u8,
+}
+
+impl<S> Client<S>
+where
+    S: Read + Write,
+{
+    pub fn new(serial: S, slave: u8) -> Self {
+        Self::with_direction_control(serial, (), slave)
+    }
+}
+
+impl<S, D> Client<S, D>
+where
+    S: Read + Write,
+    D: DirectionControl,
+{
+    pub fn with_direction_control(serial: S, direction: D, slave: u8) -> Self {
+        Self {
+            serial,
+            direction,
+            slave,
+        }
+    }
+
+    pub fn slave(&self) -> u8 {
+        self.slave
+    }
+
+    pub fn set_slave(&mut self, slave: u8) {
+        self.slave = slave;
+    }
+
+    /// Release the serial port.
+    pub fn into_inner(self) -> S {
+        self.serial
+    }
+
+    pub fn read_temperature(&mut self) -> Result<Temperature, S::Error> {
+        self.read_block()
+    }
+
+    pub fn read_water_content(&mut self) -> Result<VolumetricWaterContent, S::Error> {
+        self.read_block()
+    }
+
+    pub fn read_permittivity(&mut self) -> Result<RelativePermittivity, S::Error> {
+        self.read_block()
+    }
+
+    pub fn read_raw_counts(&mut self) -> Result<RawCounts, S::Error> {
+        self.read_block()
+    }
+
+    /// Read all measurements with a single request.
+    pub fn read_measurements(&mut self) -> Result<Measurements, S::Error> {
+        self.read_block()
+    }
+
+    /// Read and decode a block of consecutive holding registers.
+    pub fn read_block<T, const START: u16, const COUNT: u16>(&mut self) -> Result<T, S::Error>
+    where
+        T: FromRegisterBlock<START, COUNT>,
+    {
+        let mut regs = [0u16; MAX_REG_COUNT as usize];
+        let regs = regs.get_mut(..usize::from(COUNT)).ok_or(DecodeError::InvalidInput)?;
+        self.read_holding_registers(START, regs)?;
+        Ok(T::from_registers(regs)?)
+    }
+
+    /// Read consecutive holding registers into `regs`.
+    pub fn read_holding_registers(&mut self, start: u16, regs: &mut [u16]) -> Result<(), S::Error> {
+        let mut buf = [0u8; MAX_FRAME_LEN];
+        let count = u16::try_from(regs.len()).map_err(|_| DecodeError::InvalidInput)?;
+        let len = encode_read_holding_registers(self.slave, start, count, &mut buf)?;
+        self.send_request(&buf[..len])?;
+        self.recv_response(FN_READ_HOLDING_REGISTERS, &mut buf)?
+            .decode_registers(regs)?;
+        Ok(())
+    }
+
+    /// Write a single holding register.
+    ///
+    /// No response is expected for the Modbus broadcast address 0.
+    pub fn write_single_register(&mut self, addr: u16, value: u16) -> Result<(), S::Error> {
+        let mut buf = [0u8; MAX_FRAME_LEN];
+        let len = encode_write_single_register(self.slave, addr, value, &mut buf)?;
+        self.send_request(&buf[..len])?;
+        if self.slave == 0 {
+            return Ok(());
+        }
+        let echo = self
+            .recv_response(FN_WRITE_SINGLE_REGISTER, &mut buf)?
+            .decode_write_single_register()?;
+        if echo != (addr, value) {
+            return Err(DecodeError::InvalidInput.into());
+        }
+        Ok(())
+    }
+
+    fn send_request(&mut self, frame: &[u8]) -> Result<(), S::Error> {
+        self.direction.before_send().map_err(|_| Error::Direction)?;
+        let res = self
+            .serial
+            .write_all(frame)
+            .and_then(|()| self.serial.flush())
+            .map_err(Error::Serial);
+        // Always release the bus, even if sending failed
+        self.direction.after_send().map_err(|_| Error::Direction)?;
+        res
+    }
+
+    fn recv_response<'a>(
+        &mut self,
+        function: u8,
+        buf: &'a mut [u8; MAX_FRAME_LEN],
+    ) -> Result<ResponseFrame<'a>, S::Error> {
+        self.serial.read_exact(&mut buf[..3])?;
+        let len = response_frame_len(&buf[..3])?;
+        self.serial.read_exact(&mut buf[3..len])?;
+        let (frame, _) = decode_response_frame(&buf[..len])?;
+        if frame.slave != self.slave || frame.function & !EXCEPTION_FLAG != function {
+            return Err(DecodeError::InvalidInput.into());
+        }
+        if let Some(code) = frame.exception_code() {
+            return Err(Error::Exception(code));
+        }
+        Ok(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use core::convert::Infallible;
+
+    struct Serial {
+        rx: &'static [u8],
+        tx: [u8; 16],
+        tx_len: usize,
+    }
+
+    impl Serial {
+        fn new(rx: &'static [u8]) -> Self {
+            Self {
+                rx,
+                tx: [0; 16],
+                tx_len: 0,
+            }
+        }
+
+        fn sent(&self) -> &[u8] {
+            &self.tx[..self.tx_len]
+        }
+    }
+
+    impl embedded_io::ErrorType for Serial {
+        type Error = Infallible;
+    }
+
+    impl Read for Serial {
+        fn read(&mut self, buf: &mut [u8]) -> core::result::Result<usize, Infallible> {
+            let len = buf.len().min(self.rx.len());
+            buf[..len].copy_from_slice(&self.rx[..len]);
+            self.rx = &self.rx[len..];
+            Ok(len)
+        }
+    }
+
+    impl Write for Serial {
+        fn write(&mut self, buf: &[u8]) -> core::result::Result<usize, Infallible> {
+            let len = buf.len().min(self.tx.len() - self.tx_len);
+            self.tx[self.tx_len..self.tx_len + len].copy_from_slice(&buf[..len]);
+            self.tx_len += len;
+            Ok(len)
+        }
+
+        fn flush(&mut self) -> core::result::Result<(), Infallible> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn read_temperature() {
+        let mut client = Client::new(Serial::new(&[0x01, 0x03, 0x02, 0x31, 0xFD, 0x6C, 0x55]), 1);
+        assert_eq!(
+            Temperature::from_degree_celsius(27.97),
+            client.read_temperature().unwrap()
+        );
+        assert_eq!(
+            [0x01, 0x03, 0x00, 0x00, 0x00, 0x01, 0x84, 0x0A],
+            client.into_inner().sent()
+        );
+    }
+
+    #[test]
+    fn exception_response() {
+        let mut client = Client::new(Serial::new(&[0x01, 0x83, 0x02, 0xC0, 0xF1]), 1);
+        assert_eq!(Err(Error::Exception(0x02)), client.read_temperature());
+    }
+
+    #[test]
+    fn invalid_checksum() {
+        let mut client = Client::new(Serial::new(&[0x01, 0x03, 0x02, 0x31, 0xFD, 0x6C, 0x56]), 1);
+        assert_eq!(
+            Err(Error::Decode(DecodeError::InvalidChecksum)),
+            client.read_temperature()
+        );
+    }
+
+    #[test]
+    fn incomplete_response() {
+        let mut client = Client::new(Serial::new(&[0x01, 0x03, 0x02, 0x31]), 1);
+        assert_eq!(
+            Err(Error::Decode(DecodeError::InsufficientInput)),
+            client.read_temperature()
+        );
+    }
+
+    #[derive(Default)]
+    struct Direction {
+        sending: bool,
+        switches: usize,
+    }
+
+    impl DirectionControl for Direction {
+        type Error = Infallible;
+
+        fn before_send(&mut self) -> core::result::Result<(), Infallible> {
+            assert!(!self.sending);
+            self.sending = true;
+            self.switches += 1;
+            Ok(())
+        }
+
+        fn after_send(&mut self) -> core::result::Result<(), Infallible> {
+            assert!(self.sending);
+            self.sending = false;
+            self.switches += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn direction_control() {
+        let serial = Serial::new(&[0x01, 0x03, 0x02, 0x31, 0xFD, 0x6C, 0x55]);
+        let mut client = Client::with_direction_control(serial, Direction::default(), 1);
+        client.read_temperature().unwrap();
+        assert!(!client.direction.sending);
+        assert_eq!(2, client.direction.switches);
+    }
+}
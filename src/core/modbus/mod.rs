@@ -1,33 +1,14 @@
 use super::*;
 
-#[cfg(feature = "rtu")]
-pub mod rtu;
-
-use core::{fmt, mem, convert::TryInto};
-
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
-pub enum DecodeError {
-    InsufficientInput,
-    InvalidInput,
-    InvalidData,
-}
-
-impl fmt::Display for DecodeError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        use DecodeError::*;
-        match self {
-            InsufficientInput => write!(f, "Insufficient input"),
-            InvalidInput => write!(f, "Invalid input"),
-            InvalidData => write!(f, "Invalid data"),
-        }
+#[cfg(feature = "embedded-rtu")]
+pub mod embedded;
 
-    }
-}
+#[cfg(feature = "embassy")]
+pub mod embassy;
 
-#[cfg(feature = "std")]
-impl std::error::Error for DecodeError {}
+pub mod rtu;
 
-pub type DecodeResult<T> = Result<T, DecodeError>;
+use core::{mem, convert::TryInto, ops::Range};
 
 fn decode_be_u16_from_bytes(input: &[u8]) -> DecodeResult<(u16, &[u8])> {
     if input.len() < mem::size_of::<u16>() {
@@ -41,14 +22,24 @@ fn decode_be_u16_from_bytes(input: &[u8]) -> DecodeResult<(u16, &[u8])> {
     }
 }
 
-pub const TEMPERATURE_REG_START: u16 = 0x0000;
-pub const TEMPERATURE_REG_COUNT: u16 = 0x0001;
-
 pub fn decode_temperature_from_u16(input: u16) -> DecodeResult<Temperature> {
     let degree_celsius = f64::from(i32::from(input) - 10000i32) / 100f64;
     Ok(Temperature::from_degree_celsius(degree_celsius))
 }
 
+/// Decode the temperature and verify the range of the sensor variant.
+pub fn decode_temperature_from_u16_for_variant(
+    input: u16,
+    variant: SensorVariant,
+) -> DecodeResult<Temperature> {
+    let res = decode_temperature_from_u16(input)?;
+    if variant.is_valid_temperature(res) {
+        Ok(res)
+    } else {
+        Err(DecodeError::InvalidData)
+    }
+}
+
 pub fn decode_temperature_from_bytes(input: &[u8]) -> DecodeResult<(Temperature, &[u8])> {
     decode_be_u16_from_bytes(input).and_then(|(val, rest)| Ok((decode_temperature_from_u16(val)?, rest)))
 }
@@ -63,9 +54,6 @@ impl From<VolumetricWaterContentRaw> for VolumetricWaterContent {
     }
 }
 
-pub const WATER_CONTENT_REG_START: u16 = 0x0001;
-pub const WATER_CONTENT_REG_COUNT: u16 = 0x0001;
-
 pub fn decode_water_content_from_u16(input: u16) -> DecodeResult<VolumetricWaterContent> {
     let percent = f64::from(input) / 100f64;
     let res = VolumetricWaterContent::from_percent(percent);
@@ -90,9 +78,6 @@ impl From<RelativePermittivityRaw> for RelativePermittivity {
     }
 }
 
-pub const PERMITTIVITY_REG_START: u16 = 0x0002;
-pub const PERMITTIVITY_REG_COUNT: u16 = 0x0001;
-
 pub fn decode_permittivity_from_u16(input: u16) -> DecodeResult<RelativePermittivity> {
     let ratio = f64::from(input) / 100f64;
     let res = RelativePermittivity::from_ratio(ratio);
@@ -107,9 +92,6 @@ pub fn decode_permittivity_from_bytes(input: &[u8]) -> DecodeResult<(RelativePer
     decode_be_u16_from_bytes(input).and_then(|(val, rest)| Ok((decode_permittivity_from_u16(val)?, rest)))
 }
 
-pub const RAW_COUNTS_REG_START: u16 = 0x0003;
-pub const RAW_COUNTS_REG_COUNT: u16 = 0x0001;
-
 #[inline]
 pub fn decode_raw_counts_from_u16(input: u16) -> DecodeResult<RawCounts> {
     Ok(input.into())
@@ -120,8 +102,454 @@ pub fn decode_raw_counts_from_bytes(input: &[u8]) -> DecodeResult<(RawCounts, &[
     decode_be_u16_from_bytes(input).and_then(|(val, rest)| Ok((decode_raw_counts_from_u16(val)?, rest)))
 }
 
+/// The holding registers of the sensor.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Register {
+    Temperature,
+    WaterContent,
+    Permittivity,
+    RawCounts,
+
+    /// The Modbus slave address of the device.
+    SlaveAddress,
+}
+
+/// The decoded contents of a `Register`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RegisterValue {
+    Temperature(Temperature),
+    WaterContent(VolumetricWaterContent),
+    Permittivity(RelativePermittivity),
+    RawCounts(RawCounts),
+    SlaveAddress(u8),
+}
+
+impl Register {
+    /// All registers ordered by address.
+    pub const ALL: [Register; 5] = [
+        Register::Temperature,
+        Register::WaterContent,
+        Register::Permittivity,
+        Register::RawCounts,
+        Register::SlaveAddress,
+    ];
+
+    /// The address of the first register.
+    pub const fn address(self) -> u16 {
+        match self {
+            Register::Temperature => 0x0000,
+            Register::WaterContent => 0x0001,
+            Register::Permittivity => 0x0002,
+            Register::RawCounts => 0x0003,
+            Register::SlaveAddress => 0x0004,
+        }
+    }
+
+    /// The number of consecutive registers.
+    pub const fn count(self) -> u16 {
+        0x0001
+    }
+
+    /// The addresses of all consecutive registers.
+    pub fn range(self) -> Range<u16> {
+        self.address()..self.address() + self.count()
+    }
+
+    /// The register that starts at `address`.
+    pub fn from_address(address: u16) -> Option<Self> {
+        Self::ALL.iter().copied().find(|reg| reg.address() == address)
+    }
+
+    /// The machine-readable description.
+    pub fn descriptor(self) -> &'static RegisterDescriptor {
+        let index = Self::ALL
+            .iter()
+            .position(|reg| *reg == self)
+            .expect("all registers are mapped");
+        &REGISTER_MAP[index]
+    }
+
+    /// Decode the contents of exactly `count()` registers.
+    pub fn decode(self, input: &[u16]) -> DecodeResult<RegisterValue> {
+        if input.len() < usize::from(self.count()) {
+            return Err(DecodeError::InsufficientInput);
+        }
+        if input.len() > usize::from(self.count()) {
+            return Err(DecodeError::InvalidInput);
+        }
+        let res = match self {
+            Register::Temperature => RegisterValue::Temperature(decode_temperature_from_u16(input[0])?),
+            Register::WaterContent => RegisterValue::WaterContent(decode_water_content_from_u16(input[0])?),
+            Register::Permittivity => RegisterValue::Permittivity(decode_permittivity_from_u16(input[0])?),
+            Register::RawCounts => RegisterValue::RawCounts(decode_raw_counts_from_u16(input[0])?),
+            Register::SlaveAddress => RegisterValue::SlaveAddress(decode_slave_address_from_u16(input[0])?),
+        };
+        Ok(res)
+    }
+}
+
+impl From<Quantity> for Register {
+    fn from(from: Quantity) -> Self {
+        match from {
+            Quantity::Temperature => Register::Temperature,
+            Quantity::WaterContent => Register::WaterContent,
+            Quantity::Permittivity => Register::Permittivity,
+            Quantity::RawCounts => Register::RawCounts,
+        }
+    }
+}
+
+/// Whether a register could be written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Access {
+    ReadOnly,
+    ReadWrite,
+}
+
+/// Machine-readable description of a `Register`, e.g. for generating
+/// the mappings of SCADA systems or gateways.
+///
+/// The value is calculated from the unsigned register contents
+/// as `raw * scale + offset`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct RegisterDescriptor {
+    pub register: Register,
+    pub name: &'static str,
+    pub address: u16,
+    pub count: u16,
+    pub scale: f64,
+    pub offset: f64,
+
+    /// The unit of the scaled value, `None` for dimensionless
+    /// values.
+    pub unit: Option<&'static str>,
+
+    pub access: Access,
+}
+
+impl RegisterDescriptor {
+    const fn new(register: Register, name: &'static str, access: Access) -> Self {
+        Self {
+            register,
+            name,
+            address: register.address(),
+            count: register.count(),
+            scale: 1.0,
+            offset: 0.0,
+            unit: None,
+            access,
+        }
+    }
+
+    const fn scaled(self, scale: f64, offset: f64, unit: Option<&'static str>) -> Self {
+        Self {
+            scale,
+            offset,
+            unit,
+            ..self
+        }
+    }
+
+    /// Convert the contents of a single register into the scaled value.
+    pub fn scale_value(&self, raw: u16) -> f64 {
+        f64::from(raw) * self.scale + self.offset
+    }
+}
+
+const REGISTER_MAP: [RegisterDescriptor; Register::ALL.len()] = [
+    RegisterDescriptor::new(Register::Temperature, "temperature", Access::ReadOnly)
+        .scaled(0.01, -100.0, Some("°C")),
+    RegisterDescriptor::new(Register::WaterContent, "water_content", Access::ReadOnly)
+        .scaled(0.01, 0.0, Some("%")),
+    RegisterDescriptor::new(Register::Permittivity, "permittivity", Access::ReadOnly)
+        .scaled(0.01, 0.0, None),
+    RegisterDescriptor::new(Register::RawCounts, "raw_counts", Access::ReadOnly),
+    RegisterDescriptor::new(Register::SlaveAddress, "slave_address", Access::ReadWrite),
+];
+
+/// The descriptions of all registers ordered by address.
+pub fn register_map() -> &'static [RegisterDescriptor] {
+    &REGISTER_MAP
+}
+
+/// All measurements are stored in consecutive registers and
+/// can be read at once.
+pub const MEASUREMENTS_REG_START: u16 = Register::Temperature.address();
+pub const MEASUREMENTS_REG_COUNT: u16 = Register::Temperature.count()
+    + Register::WaterContent.count()
+    + Register::Permittivity.count()
+    + Register::RawCounts.count();
+
+/// Consecutive holding registers that are read with a single request.
+///
+/// The block is only a type without any runtime representation.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RegisterBlock<const START: u16, const COUNT: u16>;
+
+impl<const START: u16, const COUNT: u16> RegisterBlock<START, COUNT> {
+    pub const START: u16 = START;
+    pub const COUNT: u16 = COUNT;
+
+    /// Check if the register is part of the block.
+    pub fn contains(register: Register) -> bool {
+        register.address() >= START && register.range().end <= START + COUNT
+    }
+
+    /// Split the registers of this block from the remaining input.
+    pub fn split(input: &[u16]) -> DecodeResult<(&[u16], &[u16])> {
+        if input.len() < usize::from(COUNT) {
+            return Err(DecodeError::InsufficientInput);
+        }
+        Ok(input.split_at(usize::from(COUNT)))
+    }
+
+    /// Decode the leading registers of the input.
+    pub fn decode<T>(input: &[u16]) -> DecodeResult<(T, &[u16])>
+    where
+        T: FromRegisterBlock<START, COUNT>,
+    {
+        let (head, rest) = Self::split(input)?;
+        Ok((T::from_registers(head)?, rest))
+    }
+}
+
+/// Values that are decoded from a `RegisterBlock`.
+pub trait FromRegisterBlock<const START: u16, const COUNT: u16>: Sized {
+    /// Decode exactly `COUNT` registers.
+    fn from_registers(input: &[u16]) -> DecodeResult<Self>;
+}
+
+macro_rules! impl_from_single_register {
+    ($($ty:ty => $register:expr, $decode:ident;)*) => {
+        $(
+            impl FromRegisterBlock<{ $register.address() }, 1> for $ty {
+                fn from_registers(input: &[u16]) -> DecodeResult<Self> {
+                    match *input {
+                        [reg] => $decode(reg),
+                        _ => Err(DecodeError::InvalidInput),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_from_single_register! {
+    Temperature => Register::Temperature, decode_temperature_from_u16;
+    VolumetricWaterContent => Register::WaterContent, decode_water_content_from_u16;
+    RelativePermittivity => Register::Permittivity, decode_permittivity_from_u16;
+    RawCounts => Register::RawCounts, decode_raw_counts_from_u16;
+}
+
+pub type TemperatureBlock = RegisterBlock<{ Register::Temperature.address() }, 1>;
+pub type WaterContentBlock = RegisterBlock<{ Register::WaterContent.address() }, 1>;
+pub type PermittivityBlock = RegisterBlock<{ Register::Permittivity.address() }, 1>;
+pub type RawCountsBlock = RegisterBlock<{ Register::RawCounts.address() }, 1>;
+pub type MeasurementsBlock = RegisterBlock<MEASUREMENTS_REG_START, MEASUREMENTS_REG_COUNT>;
+
+impl FromRegisterBlock<MEASUREMENTS_REG_START, MEASUREMENTS_REG_COUNT> for Measurements {
+    fn from_registers(input: &[u16]) -> DecodeResult<Self> {
+        if let [temperature, water_content, permittivity, raw_counts] = *input {
+            Ok(Measurements {
+                temperature: decode_temperature_from_u16(temperature)?,
+                water_content: decode_water_content_from_u16(water_content)?,
+                permittivity: decode_permittivity_from_u16(permittivity)?,
+                raw_counts: decode_raw_counts_from_u16(raw_counts)?,
+            })
+        } else {
+            Err(DecodeError::InvalidInput)
+        }
+    }
+}
+
+pub fn decode_measurements_from_u16s(input: &[u16]) -> DecodeResult<(Measurements, &[u16])> {
+    MeasurementsBlock::decode(input)
+}
+
+/// Decode all measurements and verify the ranges of the sensor variant.
+pub fn decode_measurements_from_u16s_for_variant(
+    input: &[u16],
+    variant: SensorVariant,
+) -> DecodeResult<(Measurements, &[u16])> {
+    let (measurements, rest) = decode_measurements_from_u16s(input)?;
+    variant.validate(&measurements)?;
+    Ok((measurements, rest))
+}
+
+/// The consecutive registers that contain all selected quantities
+/// as `(start, count)`.
+///
+/// Returns `None` if nothing has been selected.
+pub fn selected_measurements_reg_range(selection: MeasurementSelection) -> Option<(u16, u16)> {
+    let mut regs = selection.quantities().map(|quantity| Register::from(quantity).address());
+    let start = regs.next()?;
+    let end = regs.last().unwrap_or(start);
+    Some((start, end - start + 1))
+}
+
+/// Decode the selected quantities from the registers in
+/// `selected_measurements_reg_range()`.
+pub fn decode_selected_measurements_from_u16s(
+    selection: MeasurementSelection,
+    input: &[u16],
+) -> DecodeResult<SelectedMeasurements> {
+    let mut measurements = SelectedMeasurements::default();
+    let (start, count) = match selected_measurements_reg_range(selection) {
+        Some(range) => range,
+        None => return Ok(measurements),
+    };
+    if input.len() != usize::from(count) {
+        return Err(DecodeError::InvalidInput);
+    }
+    for quantity in selection.quantities() {
+        let reg = input[usize::from(Register::from(quantity).address() - start)];
+        match quantity {
+            Quantity::Temperature => measurements.temperature = Some(decode_temperature_from_u16(reg)?),
+            Quantity::WaterContent => measurements.water_content = Some(decode_water_content_from_u16(reg)?),
+            Quantity::Permittivity => measurements.permittivity = Some(decode_permittivity_from_u16(reg)?),
+            Quantity::RawCounts => measurements.raw_counts = Some(decode_raw_counts_from_u16(reg)?),
+        }
+    }
+    Ok(measurements)
+}
+
+pub fn decode_measurements_from_bytes(input: &[u8]) -> DecodeResult<(Measurements, &[u8])> {
+    let (temperature, input) = decode_temperature_from_bytes(input)?;
+    let (water_content, input) = decode_water_content_from_bytes(input)?;
+    let (permittivity, input) = decode_permittivity_from_bytes(input)?;
+    let (raw_counts, rest) = decode_raw_counts_from_bytes(input)?;
+    let measurements = Measurements {
+        temperature,
+        water_content,
+        permittivity,
+        raw_counts,
+    };
+    Ok((measurements, rest))
+}
+
+pub fn decode_slave_address_from_u16(input: u16) -> DecodeResult<u8> {
+    u8::try_from(input).map_err(|_| DecodeError::InvalidData)
+}
+
 pub const BROADCAST_SLAVE_ADDR: u8 = 0xFD;
-pub const BROADCAST_REG_ADDR: u16 = 0x0004;
+pub const BROADCAST_REG_ADDR: u16 = Register::SlaveAddress.address();
+
+/// The Modbus slave address of a single sensor in the unicast range
+/// from 1 to 247.
+///
+/// Excludes both the Modbus broadcast address 0 and the sensor-specific
+/// `BROADCAST_SLAVE_ADDR`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "u8", into = "u8")
+)]
+pub struct Smt100Address(u8);
+
+impl Smt100Address {
+    pub const MIN: Self = Self(1);
+    pub const MAX: Self = Self(247);
+
+    /// Returns `None` outside of the unicast range.
+    pub const fn new(addr: u8) -> Option<Self> {
+        if addr >= Self::MIN.0 && addr <= Self::MAX.0 {
+            Some(Self(addr))
+        } else {
+            None
+        }
+    }
+
+    pub const fn get(self) -> u8 {
+        self.0
+    }
+}
+
+impl TryFrom<u8> for Smt100Address {
+    type Error = InvalidAddressError;
+
+    fn try_from(from: u8) -> Result<Self, Self::Error> {
+        Self::new(from).ok_or(InvalidAddressError(from))
+    }
+}
+
+impl From<Smt100Address> for u8 {
+    fn from(from: Smt100Address) -> Self {
+        from.0
+    }
+}
+
+impl fmt::Display for Smt100Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// A slave address is outside of the unicast range.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct InvalidAddressError(pub u8);
+
+impl fmt::Display for InvalidAddressError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Invalid slave address {}, expected {} to {}",
+            self.0,
+            Smt100Address::MIN,
+            Smt100Address::MAX
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for InvalidAddressError {}
+
+/// Round to the nearest register value with ties rounded upwards.
+///
+/// Values outside of the register range saturate and NaN is encoded as 0.
+fn encode_u16_rounded(input: f64) -> u16 {
+    if input > 0.0 {
+        // The conversion saturates
+        (input + 0.5) as u16
+    } else {
+        0
+    }
+}
+
+pub fn encode_temperature_to_u16(input: Temperature) -> u16 {
+    encode_u16_rounded(input.to_degree_celsius() * 100f64 + 10000f64)
+}
+
+pub fn encode_water_content_to_u16(input: VolumetricWaterContent) -> u16 {
+    encode_u16_rounded(input.to_percent() * 100f64)
+}
+
+pub fn encode_permittivity_to_u16(input: RelativePermittivity) -> u16 {
+    encode_u16_rounded(input.to_ratio() * 100f64)
+}
+
+#[inline]
+pub fn encode_raw_counts_to_u16(input: RawCounts) -> u16 {
+    input.into()
+}
+
+/// Encode all measurements in register order.
+pub fn encode_measurements_to_u16s(input: &Measurements) -> [u16; MEASUREMENTS_REG_COUNT as usize] {
+    [
+        encode_temperature_to_u16(input.temperature),
+        encode_water_content_to_u16(input.water_content),
+        encode_permittivity_to_u16(input.permittivity),
+        encode_raw_counts_to_u16(input.raw_counts),
+    ]
+}
 
 #[cfg(test)]
 mod tests {
@@ -151,6 +579,21 @@ mod tests {
         );
     }
 
+    #[test]
+    fn decode_temperature_for_variant() {
+        // 70.0 °C
+        assert!(decode_temperature_from_u16_for_variant(0x4268, SensorVariant::Digital).is_ok());
+        assert_eq!(
+            Err(DecodeError::InvalidData),
+            decode_temperature_from_u16_for_variant(0x4268, SensorVariant::Analog)
+        );
+        // -40.01 °C
+        assert_eq!(
+            Err(DecodeError::InvalidData),
+            decode_temperature_from_u16_for_variant(0x176F, SensorVariant::Digital)
+        );
+    }
+
     #[test]
     fn decode_water_content() {
         // Valid range
@@ -186,4 +629,189 @@ mod tests {
         assert!(decode_permittivity_from_bytes(&[0x00, 0x00]).is_err());
         assert!(decode_permittivity_from_bytes(&[0x00, 0x63]).is_err());
     }
+
+    #[test]
+    fn decode_measurements() {
+        let expected = Measurements {
+            temperature: Temperature::from_degree_celsius(27.97),
+            water_content: VolumetricWaterContent::from_percent(34.4),
+            permittivity: RelativePermittivity::from_ratio(15.2),
+            raw_counts: RawCounts::from(0x1234),
+        };
+        assert_eq!(
+            Ok((expected, &[][..])),
+            decode_measurements_from_u16s(&[0x31FD, 0x0D70, 0x05F0, 0x1234])
+        );
+        assert_eq!(
+            Ok((expected, &[0x00][..])),
+            decode_measurements_from_bytes(&[0x31, 0xFD, 0x0D, 0x70, 0x05, 0xF0, 0x12, 0x34, 0x00])
+        );
+        // Insufficient input
+        assert_eq!(
+            Err(DecodeError::InsufficientInput),
+            decode_measurements_from_u16s(&[0x31FD, 0x0D70, 0x05F0])
+        );
+        assert_eq!(
+            Err(DecodeError::InsufficientInput),
+            decode_measurements_from_bytes(&[0x31, 0xFD, 0x0D, 0x70, 0x05, 0xF0, 0x12])
+        );
+        // Invalid data
+        assert_eq!(
+            Err(DecodeError::InvalidData),
+            decode_measurements_from_u16s(&[0x31FD, 0xFFFF, 0x05F0, 0x1234])
+        );
+    }
+
+    #[test]
+    fn encode_temperature() {
+        assert_eq!(0x1770, encode_temperature_to_u16(Temperature::from_degree_celsius(-40.0)));
+        assert_eq!(0x2710, encode_temperature_to_u16(Temperature::from_degree_celsius(0.0)));
+        assert_eq!(0x31FD, encode_temperature_to_u16(Temperature::from_degree_celsius(27.97)));
+        assert_eq!(0x4650, encode_temperature_to_u16(Temperature::from_degree_celsius(80.0)));
+        // Rounding
+        assert_eq!(0x2710, encode_temperature_to_u16(Temperature::from_degree_celsius(-0.004)));
+        assert_eq!(0x2711, encode_temperature_to_u16(Temperature::from_degree_celsius(0.005)));
+        // Saturation
+        assert_eq!(0x0000, encode_temperature_to_u16(Temperature::from_degree_celsius(-200.0)));
+        assert_eq!(0xFFFF, encode_temperature_to_u16(Temperature::from_degree_celsius(1000.0)));
+    }
+
+    #[test]
+    fn encode_water_content() {
+        assert_eq!(0x0000, encode_water_content_to_u16(VolumetricWaterContent::from_percent(0.0)));
+        assert_eq!(0x0D70, encode_water_content_to_u16(VolumetricWaterContent::from_percent(34.4)));
+        assert_eq!(0x2710, encode_water_content_to_u16(VolumetricWaterContent::from_percent(100.0)));
+        assert_eq!(0x0000, encode_water_content_to_u16(VolumetricWaterContent::from_percent(-1.0)));
+        assert_eq!(0x0000, encode_water_content_to_u16(VolumetricWaterContent::from_percent(f64::NAN)));
+    }
+
+    #[test]
+    fn encode_decode_roundtrip() {
+        let measurements = Measurements {
+            temperature: Temperature::from_degree_celsius(-12.34),
+            water_content: VolumetricWaterContent::from_percent(56.78),
+            permittivity: RelativePermittivity::from_ratio(23.45),
+            raw_counts: RawCounts::from(12345),
+        };
+        let registers = encode_measurements_to_u16s(&measurements);
+        assert_eq!(
+            measurements,
+            decode_measurements_from_u16s(&registers).unwrap().0
+        );
+    }
+
+    #[test]
+    fn decode_selected_measurements() {
+        let selection = MeasurementSelection::TEMPERATURE | MeasurementSelection::PERMITTIVITY;
+        assert_eq!(Some((0x0000, 3)), selected_measurements_reg_range(selection));
+        assert_eq!(
+            Some((0x0001, 1)),
+            selected_measurements_reg_range(MeasurementSelection::WATER_CONTENT)
+        );
+        assert_eq!(None, selected_measurements_reg_range(MeasurementSelection::empty()));
+        let measurements = decode_selected_measurements_from_u16s(selection, &[0x31FD, 0x0D71, 0x05F0]).unwrap();
+        assert_eq!(Some(Temperature::from_degree_celsius(27.97)), measurements.temperature);
+        assert!(measurements.water_content.is_none());
+        assert!(measurements.permittivity.is_some());
+        assert!(measurements.raw_counts.is_none());
+        assert_eq!(selection, measurements.selection());
+        assert_eq!(
+            Err(DecodeError::InvalidInput),
+            decode_selected_measurements_from_u16s(selection, &[0x31FD])
+        );
+    }
+
+    #[test]
+    fn register_addresses() {
+        for (prev, next) in Register::ALL.iter().zip(Register::ALL.iter().skip(1)) {
+            assert_eq!(prev.range().end, next.address());
+        }
+        for reg in Register::ALL.iter().copied() {
+            assert_eq!(Some(reg), Register::from_address(reg.address()));
+        }
+        assert_eq!(None, Register::from_address(0x0005));
+        assert_eq!(None, Register::from_address(0x0009));
+        assert_eq!(Register::Permittivity, Quantity::Permittivity.into());
+    }
+
+    #[test]
+    fn register_descriptors() {
+        assert_eq!(Register::ALL.len(), register_map().len());
+        for (reg, descriptor) in Register::ALL.iter().copied().zip(register_map()) {
+            assert_eq!(reg, descriptor.register);
+            assert_eq!(reg.range(), descriptor.address..descriptor.address + descriptor.count);
+            assert_eq!(descriptor, reg.descriptor());
+        }
+        let temperature = Register::Temperature.descriptor();
+        assert!((27.97 - temperature.scale_value(0x31FD)).abs() < 1e-9);
+        assert_eq!(Access::ReadWrite, Register::SlaveAddress.descriptor().access);
+        assert_eq!(Access::ReadOnly, Register::RawCounts.descriptor().access);
+        for quantity in Quantity::ALL.iter().copied() {
+            assert_eq!(quantity.name(), Register::from(quantity).descriptor().name);
+        }
+    }
+
+    #[test]
+    fn register_blocks() {
+        assert!(MeasurementsBlock::contains(Register::RawCounts));
+        assert!(!MeasurementsBlock::contains(Register::SlaveAddress));
+        assert_eq!(1, RawCountsBlock::COUNT);
+        let (temperature, rest) = TemperatureBlock::decode::<Temperature>(&[0x31FD, 0x0D71]).unwrap();
+        assert_eq!(Temperature::from_degree_celsius(27.97), temperature);
+        assert_eq!([0x0D71], rest);
+        assert_eq!(
+            Err(DecodeError::InsufficientInput),
+            MeasurementsBlock::decode::<Measurements>(&[0x31FD, 0x0D71, 0x05F0])
+        );
+    }
+
+    #[test]
+    fn decode_register() {
+        assert_eq!(
+            Ok(RegisterValue::Temperature(Temperature::from_degree_celsius(27.97))),
+            Register::Temperature.decode(&[0x31FD])
+        );
+        assert_eq!(Ok(RegisterValue::SlaveAddress(7)), Register::SlaveAddress.decode(&[7]));
+        assert_eq!(Err(DecodeError::InvalidData), Register::SlaveAddress.decode(&[0x0100]));
+        assert_eq!(Err(DecodeError::InvalidInput), Register::RawCounts.decode(&[1, 2]));
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn decode_arbitrary_bytes(input in proptest::collection::vec(proptest::num::u8::ANY, 0..16)) {
+            let _ = decode_temperature_from_bytes(&input);
+            let _ = decode_water_content_from_bytes(&input);
+            let _ = decode_permittivity_from_bytes(&input);
+            let _ = decode_raw_counts_from_bytes(&input);
+            let _ = decode_measurements_from_bytes(&input);
+        }
+
+        #[test]
+        fn decode_encode_registers(reg: u16) {
+            if let Ok(temperature) = decode_temperature_from_u16(reg) {
+                proptest::prop_assert_eq!(reg, encode_temperature_to_u16(temperature));
+            }
+            if let Ok(water_content) = decode_water_content_from_u16(reg) {
+                proptest::prop_assert_eq!(reg, encode_water_content_to_u16(water_content));
+            }
+            if let Ok(permittivity) = decode_permittivity_from_u16(reg) {
+                proptest::prop_assert_eq!(reg, encode_permittivity_to_u16(permittivity));
+            }
+            let raw_counts = decode_raw_counts_from_u16(reg).unwrap();
+            proptest::prop_assert_eq!(reg, encode_raw_counts_to_u16(raw_counts));
+        }
+    }
+
+    #[test]
+    fn smt100_address_range() {
+        assert_eq!(None, Smt100Address::new(0));
+        assert_eq!(Some(Smt100Address::MIN), Smt100Address::new(1));
+        assert_eq!(Some(Smt100Address::MAX), Smt100Address::new(247));
+        assert_eq!(None, Smt100Address::new(248));
+        assert_eq!(
+            Err(InvalidAddressError(BROADCAST_SLAVE_ADDR)),
+            Smt100Address::try_from(BROADCAST_SLAVE_ADDR)
+        );
+        assert_eq!(42, u8::from(Smt100Address::try_from(42).unwrap()));
+    }
 }
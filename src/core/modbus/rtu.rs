@@ -1,7 +1,321 @@
+//! Modbus RTU framing.
+//!
+//! All functions operate on raw frames including the slave address
+//! and the checksum.
+
+use super::*;
+
+#[cfg(feature = "rtu")]
 use serialport::{DataBits, FlowControl, Parity, StopBits};
 
 pub const BAUD_RATE: u32 = 9600;
+#[cfg(feature = "rtu")]
 pub const DATA_BITS: DataBits = DataBits::Eight;
+#[cfg(feature = "rtu")]
 pub const STOP_BITS: StopBits = StopBits::One;
+#[cfg(feature = "rtu")]
 pub const PARITY: Parity = Parity::Even;
+#[cfg(feature = "rtu")]
 pub const FLOW_CONTROL: FlowControl = FlowControl::None;
+
+/// Checksum of a Modbus RTU frame (CRC-16/MODBUS).
+///
+/// The checksum is appended to the frame in little-endian byte order.
+pub fn crc16(input: &[u8]) -> u16 {
+    input.iter().fold(0xFFFF, |crc, byte| {
+        (0..8).fold(crc ^ u16::from(*byte), |crc, _| {
+            if crc & 0x0001 != 0 {
+                (crc >> 1) ^ 0xA001
+            } else {
+                crc >> 1
+            }
+        })
+    })
+}
+
+pub const FN_READ_HOLDING_REGISTERS: u8 = 0x03;
+pub const FN_WRITE_SINGLE_REGISTER: u8 = 0x06;
+pub const FN_WRITE_MULTIPLE_REGISTERS: u8 = 0x10;
+
+/// The function code of exception responses is flagged by the MSB.
+pub const EXCEPTION_FLAG: u8 = 0x80;
+
+/// The maximum size of a Modbus RTU frame.
+pub const MAX_FRAME_LEN: usize = 256;
+
+/// The maximum number of registers that fit into a single response.
+pub const MAX_REG_COUNT: u16 = 125;
+
+const CRC_LEN: usize = 2;
+const REQUEST_FRAME_LEN: usize = 8;
+
+/// Switches the direction of a half-duplex RS-485 transceiver.
+///
+/// Transceivers without automatic direction control need the driver
+/// enable (DE) and inverted receiver enable (RE) inputs to be toggled
+/// explicitly, e.g. by a GPIO pin.
+pub trait DirectionControl {
+    type Error;
+
+    /// Enable the driver before sending a request.
+    fn before_send(&mut self) -> Result<(), Self::Error>;
+
+    /// Enable the receiver after the request has been sent completely.
+    fn after_send(&mut self) -> Result<(), Self::Error>;
+}
+
+/// Transceivers with automatic direction control.
+impl DirectionControl for () {
+    type Error = core::convert::Infallible;
+
+    fn before_send(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn after_send(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// An output pin that is connected to both DE and RE and
+/// is set high while sending.
+#[cfg(feature = "embedded-hal")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DriverEnablePin<P>(pub P);
+
+#[cfg(feature = "embedded-hal")]
+impl<P> DirectionControl for DriverEnablePin<P>
+where
+    P: embedded_hal::digital::OutputPin,
+{
+    type Error = P::Error;
+
+    fn before_send(&mut self) -> Result<(), Self::Error> {
+        self.0.set_high()
+    }
+
+    fn after_send(&mut self) -> Result<(), Self::Error> {
+        self.0.set_low()
+    }
+}
+
+fn encode_request(slave: u8, function: u8, addr: u16, value: u16, buf: &mut [u8]) -> DecodeResult<usize> {
+    if buf.len() < REQUEST_FRAME_LEN {
+        return Err(DecodeError::InsufficientInput);
+    }
+    buf[0] = slave;
+    buf[1] = function;
+    buf[2..4].copy_from_slice(&addr.to_be_bytes());
+    buf[4..6].copy_from_slice(&value.to_be_bytes());
+    let crc = crc16(&buf[..6]);
+    buf[6..REQUEST_FRAME_LEN].copy_from_slice(&crc.to_le_bytes());
+    Ok(REQUEST_FRAME_LEN)
+}
+
+/// Encode a request frame into `buf` and return its length.
+pub fn encode_read_holding_registers(slave: u8, start: u16, count: u16, buf: &mut [u8]) -> DecodeResult<usize> {
+    if count == 0 || count > MAX_REG_COUNT {
+        return Err(DecodeError::InvalidInput);
+    }
+    encode_request(slave, FN_READ_HOLDING_REGISTERS, start, count, buf)
+}
+
+/// Encode a request frame into `buf` and return its length.
+pub fn encode_write_single_register(slave: u8, addr: u16, value: u16, buf: &mut [u8]) -> DecodeResult<usize> {
+    encode_request(slave, FN_WRITE_SINGLE_REGISTER, addr, value, buf)
+}
+
+/// Determine the total length of a response frame from its first
+/// 3 bytes.
+pub fn response_frame_len(input: &[u8]) -> DecodeResult<usize> {
+    if input.len() < 3 {
+        return Err(DecodeError::InsufficientInput);
+    }
+    match input[1] {
+        function if function & EXCEPTION_FLAG != 0 => Ok(3 + CRC_LEN),
+        FN_READ_HOLDING_REGISTERS => Ok(3 + usize::from(input[2]) + CRC_LEN),
+        FN_WRITE_SINGLE_REGISTER => Ok(6 + CRC_LEN),
+        _ => Err(DecodeError::InvalidInput),
+    }
+}
+
+/// A response frame with a valid checksum.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ResponseFrame<'a> {
+    pub slave: u8,
+
+    /// The function code including the exception flag.
+    pub function: u8,
+
+    /// The payload following the function code.
+    pub data: &'a [u8],
+}
+
+impl<'a> ResponseFrame<'a> {
+    pub fn exception_code(&self) -> Option<u8> {
+        if self.function & EXCEPTION_FLAG != 0 {
+            self.data.first().copied()
+        } else {
+            None
+        }
+    }
+
+    /// Decode the response of a read request for `regs.len()` registers.
+    pub fn decode_registers(&self, regs: &mut [u16]) -> DecodeResult<()> {
+        if self.function != FN_READ_HOLDING_REGISTERS {
+            return Err(DecodeError::InvalidInput);
+        }
+        let (byte_count, mut input) = self.data.split_first().ok_or(DecodeError::InsufficientInput)?;
+        if usize::from(*byte_count) != regs.len() * 2 || input.len() != regs.len() * 2 {
+            return Err(DecodeError::InvalidInput);
+        }
+        for reg in regs {
+            let (val, rest) = decode_be_u16_from_bytes(input)?;
+            *reg = val;
+            input = rest;
+        }
+        Ok(())
+    }
+
+    /// Decode the echoed address and value of a write request.
+    pub fn decode_write_single_register(&self) -> DecodeResult<(u16, u16)> {
+        if self.function != FN_WRITE_SINGLE_REGISTER {
+            return Err(DecodeError::InvalidInput);
+        }
+        let (addr, input) = decode_be_u16_from_bytes(self.data)?;
+        let (value, _) = decode_be_u16_from_bytes(input)?;
+        Ok((addr, value))
+    }
+}
+
+/// Validate and split off the next response frame from `input`.
+pub fn decode_response_frame(input: &[u8]) -> DecodeResult<(ResponseFrame<'_>, &[u8])> {
+    let len = response_frame_len(input)?;
+    if input.len() < len {
+        return Err(DecodeError::InsufficientInput);
+    }
+    let (frame, rest) = input.split_at(len);
+    let (adu, crc) = frame.split_at(len - CRC_LEN);
+    if crc16(adu).to_le_bytes() != crc {
+        return Err(DecodeError::InvalidChecksum);
+    }
+    let frame = ResponseFrame {
+        slave: adu[0],
+        function: adu[1],
+        data: &adu[2..],
+    };
+    Ok((frame, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksum() {
+        assert_eq!(0x0A84, crc16(&[0x01, 0x03, 0x00, 0x00, 0x00, 0x01]));
+        assert_eq!([0x84, 0x0A], crc16(&[0x01, 0x03, 0x00, 0x00, 0x00, 0x01]).to_le_bytes());
+        assert_eq!(0xFFFF, crc16(&[]));
+    }
+
+    #[test]
+    fn encode_requests() {
+        let mut buf = [0; 8];
+        assert_eq!(Ok(8), encode_read_holding_registers(1, 0x0000, 1, &mut buf));
+        assert_eq!([0x01, 0x03, 0x00, 0x00, 0x00, 0x01, 0x84, 0x0A], buf);
+        assert_eq!(Ok(8), encode_write_single_register(0xFD, 0x0004, 0x0002, &mut buf));
+        assert_eq!(&[0xFD, 0x06, 0x00, 0x04, 0x00, 0x02], &buf[..6]);
+        assert_eq!(
+            Err(DecodeError::InvalidInput),
+            encode_read_holding_registers(1, 0x0000, 0, &mut buf)
+        );
+        assert_eq!(
+            Err(DecodeError::InsufficientInput),
+            encode_read_holding_registers(1, 0x0000, 1, &mut buf[..7])
+        );
+    }
+
+    #[test]
+    fn decode_read_response() {
+        let input = [0x01, 0x03, 0x02, 0x31, 0xFD, 0x6C, 0x55, 0xFF];
+        assert_eq!(Ok(7), response_frame_len(&input));
+        let (frame, rest) = decode_response_frame(&input).unwrap();
+        assert_eq!(1, frame.slave);
+        assert_eq!(None, frame.exception_code());
+        assert_eq!([0xFF], rest);
+        let mut regs = [0; 1];
+        frame.decode_registers(&mut regs).unwrap();
+        assert_eq!([0x31FD], regs);
+        assert_eq!(Err(DecodeError::InvalidInput), frame.decode_registers(&mut [0; 2]));
+    }
+
+    #[test]
+    fn decode_exception_response() {
+        let (frame, rest) = decode_response_frame(&[0x01, 0x83, 0x02, 0xC0, 0xF1]).unwrap();
+        assert_eq!(Some(0x02), frame.exception_code());
+        assert!(rest.is_empty());
+    }
+
+    #[test]
+    fn decode_invalid_response() {
+        assert_eq!(
+            Err(DecodeError::InsufficientInput),
+            decode_response_frame(&[0x01, 0x03])
+        );
+        assert_eq!(
+            Err(DecodeError::InsufficientInput),
+            decode_response_frame(&[0x01, 0x03, 0x02, 0x31, 0xFD, 0x6C])
+        );
+        assert_eq!(
+            Err(DecodeError::InvalidChecksum),
+            decode_response_frame(&[0x01, 0x03, 0x02, 0x31, 0xFD, 0x6C, 0x56])
+        );
+        assert_eq!(
+            Err(DecodeError::InvalidInput),
+            decode_response_frame(&[0x01, 0x10, 0x00, 0x00, 0x00])
+        );
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn decode_arbitrary_frames(input in proptest::collection::vec(proptest::num::u8::ANY, 0..32)) {
+            let _ = response_frame_len(&input);
+            if let Ok((frame, _)) = decode_response_frame(&input) {
+                let _ = frame.exception_code();
+                let _ = frame.decode_registers(&mut [0; 4]);
+                let _ = frame.decode_write_single_register();
+            }
+        }
+
+        #[test]
+        fn encode_request_checksum(slave: u8, start: u16, count in 1..=MAX_REG_COUNT) {
+            let mut buf = [0; REQUEST_FRAME_LEN];
+            proptest::prop_assert_eq!(Ok(REQUEST_FRAME_LEN), encode_read_holding_registers(slave, start, count, &mut buf));
+            // The checksum over a frame including its checksum is 0
+            proptest::prop_assert_eq!(0, crc16(&buf));
+        }
+
+        #[test]
+        fn decode_encoded_response(
+            slave: u8,
+            regs in proptest::collection::vec(proptest::num::u16::ANY, 1..=usize::from(MAX_REG_COUNT)),
+        ) {
+            let mut buf = [0; MAX_FRAME_LEN];
+            let byte_count = regs.len() * 2;
+            buf[..3].copy_from_slice(&[slave, FN_READ_HOLDING_REGISTERS, byte_count as u8]);
+            for (i, reg) in regs.iter().enumerate() {
+                buf[3 + i * 2..5 + i * 2].copy_from_slice(&reg.to_be_bytes());
+            }
+            let len = 3 + byte_count;
+            let crc = crc16(&buf[..len]).to_le_bytes();
+            buf[len..len + CRC_LEN].copy_from_slice(&crc);
+            proptest::prop_assert_eq!(Ok(len + CRC_LEN), response_frame_len(&buf));
+            let (frame, rest) = decode_response_frame(&buf[..len + CRC_LEN]).unwrap();
+            proptest::prop_assert!(rest.is_empty());
+            proptest::prop_assert_eq!(slave, frame.slave);
+            let mut decoded = [0; MAX_REG_COUNT as usize];
+            frame.decode_registers(&mut decoded[..regs.len()]).unwrap();
+            proptest::prop_assert_eq!(&regs[..], &decoded[..regs.len()]);
+        }
+    }
+}
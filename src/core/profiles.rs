@@ -0,0 +1,226 @@
+//! Register layouts and scaling of TRUEBNER soil sensors.
+//!
+//! Sibling sensors of the SMT100 share the Modbus protocol, but may
+//! differ in the addresses and the scaling of their registers or may
+//! not measure all quantities. A `SensorProfile` describes these
+//! differences with its register map, i.e. a fleet of mixed sensors is
+//! read and decoded by the same code with a profile per sensor:
+//!
+//! ```ignore
+//! let profile: &dyn SensorProfile = &Smt100;
+//! let selection = MeasurementSelection::all();
+//! if let Some((start, count)) = profile.selected_measurements_reg_range(selection) {
+//!     // Read `count` holding registers starting at `start`
+//!     let measurements = profile.decode_selected_measurements(selection, &regs)?;
+//! }
+//! ```
+//!
+//! The proxies `modbus::SlaveProxy` and `modbus::sync::SlaveProxy`
+//! read selected measurements by the profile of their sensor.
+//!
+//! Only the SMT100 is included. Profiles for siblings like the SMT50 or
+//! the AquaFlex will follow once their register maps have been verified
+//! with real devices. Until then additional sensors are supported by
+//! implementing `SensorProfile` with their register map. The default
+//! methods decode measurements by the scale and offset of the register
+//! descriptors.
+
+use super::{modbus::*, *};
+
+/// The register layout and scaling of a sensor type.
+pub trait SensorProfile {
+    /// The name of the sensor type, e.g. `SMT100`.
+    fn name(&self) -> &'static str;
+
+    /// The descriptions of all registers ordered by address.
+    fn register_map(&self) -> &'static [RegisterDescriptor];
+
+    /// The description of a register, `None` if the sensor doesn't
+    /// provide it.
+    fn descriptor(&self, register: Register) -> Option<&'static RegisterDescriptor> {
+        self.register_map()
+            .iter()
+            .find(|descriptor| descriptor.register == register)
+    }
+
+    /// All quantities that are measured by the sensor.
+    fn measurements(&self) -> MeasurementSelection {
+        MeasurementSelection::all()
+            .quantities()
+            .filter(|quantity| self.descriptor(Register::from(*quantity)).is_some())
+            .fold(MeasurementSelection::empty(), |selection, quantity| {
+                selection | quantity.into()
+            })
+    }
+
+    /// Decode the contents of the register of a single quantity.
+    fn decode_quantity(&self, quantity: Quantity, input: u16) -> DecodeResult<RegisterValue> {
+        let descriptor = self
+            .descriptor(Register::from(quantity))
+            .ok_or(DecodeError::InvalidInput)?;
+        let value = descriptor.scale_value(input);
+        let res = match quantity {
+            Quantity::Temperature => RegisterValue::Temperature(Temperature::from_degree_celsius(value)),
+            Quantity::WaterContent => {
+                RegisterValue::WaterContent(VolumetricWaterContent::from_percent(value))
+            }
+            Quantity::Permittivity => RegisterValue::Permittivity(RelativePermittivity::from_ratio(value)),
+            Quantity::RawCounts => RegisterValue::RawCounts(RawCounts::from(input)),
+        };
+        Ok(res)
+    }
+
+    /// The consecutive registers that contain all selected quantities
+    /// as `(start, count)`.
+    ///
+    /// Quantities that are not measured by the sensor are ignored.
+    /// Returns `None` if nothing remains.
+    fn selected_measurements_reg_range(&self, selection: MeasurementSelection) -> Option<(u16, u16)> {
+        let mut regs = (selection & self.measurements())
+            .quantities()
+            .filter_map(|quantity| self.descriptor(Register::from(quantity)));
+        let first = regs.next()?;
+        let (start, end) = regs.fold((first.address, first.address + first.count), |(start, end), reg| {
+            (start.min(reg.address), end.max(reg.address + reg.count))
+        });
+        Some((start, end - start))
+    }
+
+    /// Decode the selected quantities from the registers in
+    /// `selected_measurements_reg_range()`.
+    fn decode_selected_measurements(
+        &self,
+        selection: MeasurementSelection,
+        input: &[u16],
+    ) -> DecodeResult<SelectedMeasurements> {
+        let mut measurements = SelectedMeasurements::default();
+        let (start, count) = match self.selected_measurements_reg_range(selection) {
+            Some(range) => range,
+            None => return Ok(measurements),
+        };
+        if input.len() != usize::from(count) {
+            return Err(DecodeError::InvalidInput);
+        }
+        let regs = (selection & self.measurements())
+            .quantities()
+            .filter_map(|quantity| Some((quantity, self.descriptor(Register::from(quantity))?)));
+        for (quantity, descriptor) in regs {
+            let reg = input[usize::from(descriptor.address - start)];
+            match self.decode_quantity(quantity, reg)? {
+                RegisterValue::Temperature(val) => measurements.temperature = Some(val),
+                RegisterValue::WaterContent(val) => measurements.water_content = Some(val),
+                RegisterValue::Permittivity(val) => measurements.permittivity = Some(val),
+                RegisterValue::RawCounts(val) => measurements.raw_counts = Some(val),
+                _ => return Err(DecodeError::InvalidData),
+            }
+        }
+        Ok(measurements)
+    }
+}
+
+/// The TRUEBNER SMT100 Soil Moisture Sensor.
+///
+/// Decodes all quantities with the same functions as `core::modbus`
+/// instead of the scale and offset of the register descriptors.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Smt100;
+
+impl SensorProfile for Smt100 {
+    fn name(&self) -> &'static str {
+        "SMT100"
+    }
+
+    fn register_map(&self) -> &'static [RegisterDescriptor] {
+        register_map()
+    }
+
+    fn descriptor(&self, register: Register) -> Option<&'static RegisterDescriptor> {
+        Some(register.descriptor())
+    }
+
+    fn decode_quantity(&self, quantity: Quantity, input: u16) -> DecodeResult<RegisterValue> {
+        Register::from(quantity).decode(&[input])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A sensor without permittivity and raw counts that stores the
+    /// water content in tenths of a percent two registers after the
+    /// temperature.
+    struct Swapped;
+
+    const SWAPPED_MAP: [RegisterDescriptor; 2] = [
+        RegisterDescriptor {
+            register: Register::Temperature,
+            name: "temperature",
+            address: 0x0010,
+            count: 1,
+            scale: 0.01,
+            offset: -100.0,
+            unit: Some("°C"),
+            access: Access::ReadOnly,
+        },
+        RegisterDescriptor {
+            register: Register::WaterContent,
+            name: "water_content",
+            address: 0x0012,
+            count: 1,
+            scale: 0.1,
+            offset: 0.0,
+            unit: Some("%"),
+            access: Access::ReadOnly,
+        },
+    ];
+
+    impl SensorProfile for Swapped {
+        fn name(&self) -> &'static str {
+            "Swapped"
+        }
+
+        fn register_map(&self) -> &'static [RegisterDescriptor] {
+            &SWAPPED_MAP
+        }
+    }
+
+    #[test]
+    fn smt100_profile() {
+        let profile = Smt100;
+        assert_eq!(MeasurementSelection::all(), profile.measurements());
+        assert_eq!(Some((0, 4)), profile.selected_measurements_reg_range(MeasurementSelection::all()));
+        let measurements = profile
+            .decode_selected_measurements(MeasurementSelection::all(), &[0x31FD, 0x0D71, 0x05F0, 0x2B41])
+            .unwrap();
+        assert_eq!(Some(Temperature::from_degree_celsius(27.97)), measurements.temperature);
+        assert_eq!(Some(RawCounts::from(0x2B41)), measurements.raw_counts);
+    }
+
+    #[test]
+    fn custom_profile() {
+        let profile: &dyn SensorProfile = &Swapped;
+        assert_eq!(
+            MeasurementSelection::TEMPERATURE | MeasurementSelection::WATER_CONTENT,
+            profile.measurements()
+        );
+        assert_eq!(None, profile.selected_measurements_reg_range(MeasurementSelection::RAW_COUNTS));
+        assert_eq!(
+            Some((0x0010, 3)),
+            profile.selected_measurements_reg_range(MeasurementSelection::all())
+        );
+        let measurements = profile
+            .decode_selected_measurements(MeasurementSelection::all(), &[0x31FD, 0xFFFF, 0x0159])
+            .unwrap();
+        let temperature = measurements.temperature.unwrap().to_degree_celsius();
+        assert!((27.97 - temperature).abs() < 1e-9);
+        let water_content = measurements.water_content.unwrap().to_percent();
+        assert!((34.5 - water_content).abs() < 1e-9);
+        assert_eq!(None, measurements.permittivity);
+        assert_eq!(None, measurements.raw_counts);
+        assert_eq!(
+            Err(DecodeError::InvalidInput),
+            profile.decode_selected_measurements(MeasurementSelection::all(), &[0x31FD])
+        );
+    }
+}
@@ -0,0 +1,154 @@
+//! Rate of change of measurements, e.g. drying or wetting soil.
+//!
+//! Timestamps are given as the elapsed time since an arbitrary but
+//! fixed point in time, e.g. the Unix epoch.
+
+use super::*;
+
+/// The linear trend of the water content.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SoilState {
+    /// The water content is increasing, e.g. during rain or irrigation.
+    Wetting,
+
+    /// The water content is decreasing.
+    Drying,
+
+    /// The water content changes less than the configured band.
+    Stable,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TrendConfig {
+    /// Only the samples within this period before the latest sample
+    /// are considered.
+    pub window: Duration,
+
+    /// The maximum rate of change of the water content in % per hour
+    /// that is still considered as stable.
+    pub stable_band: f64,
+}
+
+impl Default for TrendConfig {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_secs(6 * 3600),
+            stable_band: 0.05,
+        }
+    }
+}
+
+impl TrendConfig {
+    pub fn soil_state(&self, water_content_per_hour: f64) -> SoilState {
+        if water_content_per_hour > self.stable_band {
+            SoilState::Wetting
+        } else if water_content_per_hour < -self.stable_band {
+            SoilState::Drying
+        } else {
+            SoilState::Stable
+        }
+    }
+}
+
+/// Estimate the rate of change per hour of the values by linear
+/// regression (least squares).
+///
+/// The samples must be ordered chronologically. Returns `None` if
+/// the window contains less than two distinct timestamps.
+pub fn slope_per_hour<T>(samples: &[(Duration, T)], window: Duration, value: impl Fn(&T) -> f64) -> Option<f64> {
+    let (latest, _) = samples.last()?;
+    let since = latest.checked_sub(window).unwrap_or_default();
+    let start = samples.iter().position(|(t, _)| *t >= since)?;
+    let samples = &samples[start..];
+    let (t0, _) = samples[0];
+    // Relative hours keep the precision of the sums
+    let hours = |t: Duration| (t - t0).as_secs_f64() / 3600.0;
+    let n = samples.len() as f64;
+    let mean_t = samples.iter().map(|(t, _)| hours(*t)).sum::<f64>() / n;
+    let mean_v = samples.iter().map(|(_, v)| value(v)).sum::<f64>() / n;
+    let (cov, var) = samples.iter().fold((0.0, 0.0), |(cov, var), (t, v)| {
+        let dt = hours(*t) - mean_t;
+        (cov + dt * (value(v) - mean_v), var + dt * dt)
+    });
+    if var > 0.0 {
+        Some(cov / var)
+    } else {
+        None
+    }
+}
+
+/// The rates of change of water content and temperature.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Trend {
+    /// Change of the water content in % per hour.
+    pub water_content_per_hour: Option<f64>,
+
+    /// Change of the temperature in °C per hour.
+    pub temperature_per_hour: Option<f64>,
+
+    pub soil_state: Option<SoilState>,
+}
+
+impl Trend {
+    pub fn new(
+        water_content: &[(Duration, VolumetricWaterContent)],
+        temperature: &[(Duration, Temperature)],
+        config: &TrendConfig,
+    ) -> Self {
+        let water_content_per_hour = slope_per_hour(water_content, config.window, |val| val.to_percent());
+        let temperature_per_hour = slope_per_hour(temperature, config.window, |val| val.to_degree_celsius());
+        Self {
+            water_content_per_hour,
+            temperature_per_hour,
+            soil_state: water_content_per_hour.map(|slope| config.soil_state(slope)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_approx_eq(expected: f64, actual: f64) {
+        assert!((expected - actual).abs() < 1e-9, "{} != {}", expected, actual);
+    }
+
+    fn hours(hours: u64) -> Duration {
+        Duration::from_secs(hours * 3600)
+    }
+
+    #[test]
+    fn slope_of_linear_values() {
+        let samples = [(hours(10), 30.0), (hours(11), 29.5), (hours(12), 29.0), (hours(13), 28.5)];
+        assert_approx_eq(-0.5, slope_per_hour(&samples, hours(24), |v| *v).unwrap());
+        // Only the last two samples
+        assert_approx_eq(-0.5, slope_per_hour(&samples, hours(1), |v| *v).unwrap());
+    }
+
+    #[test]
+    fn slope_requires_distinct_timestamps() {
+        assert!(slope_per_hour::<f64>(&[], hours(1), |v| *v).is_none());
+        assert!(slope_per_hour(&[(hours(1), 1.0)], hours(1), |v| *v).is_none());
+        assert!(slope_per_hour(&[(hours(1), 1.0), (hours(1), 2.0)], hours(1), |v| *v).is_none());
+        // The first sample is outside of the window
+        assert!(slope_per_hour(&[(hours(1), 1.0), (hours(3), 2.0)], hours(1), |v| *v).is_none());
+    }
+
+    #[test]
+    fn classify_soil_state() {
+        let config = TrendConfig::default();
+        let water_content = [
+            (hours(0), VolumetricWaterContent::from_percent(20.0)),
+            (hours(1), VolumetricWaterContent::from_percent(25.0)),
+        ];
+        let trend = Trend::new(&water_content, &[], &config);
+        assert_eq!(Some(SoilState::Wetting), trend.soil_state);
+        assert!(trend.temperature_per_hour.is_none());
+        assert_eq!(SoilState::Drying, config.soil_state(-0.1));
+        assert_eq!(SoilState::Stable, config.soil_state(0.05));
+        assert_eq!(SoilState::Stable, config.soil_state(-0.01));
+    }
+}
@@ -0,0 +1,80 @@
+//! Conversions between the value types and _uom_ quantities.
+//!
+//! The water content and the permittivity are both dimensionless
+//! and represented by `Ratio`, i.e. a water content of 25% equals
+//! a ratio of 0.25.
+
+use super::*;
+
+use ::uom::si::{
+    f64::{Ratio, ThermodynamicTemperature},
+    ratio::{percent, ratio},
+    thermodynamic_temperature::degree_celsius,
+};
+
+impl From<Temperature> for ThermodynamicTemperature {
+    fn from(from: Temperature) -> Self {
+        Self::new::<degree_celsius>(from.to_degree_celsius())
+    }
+}
+
+impl From<ThermodynamicTemperature> for Temperature {
+    fn from(from: ThermodynamicTemperature) -> Self {
+        Self::from_degree_celsius(from.get::<degree_celsius>())
+    }
+}
+
+impl From<VolumetricWaterContent> for Ratio {
+    fn from(from: VolumetricWaterContent) -> Self {
+        Self::new::<percent>(from.to_percent())
+    }
+}
+
+impl From<Ratio> for VolumetricWaterContent {
+    fn from(from: Ratio) -> Self {
+        Self::from_percent(from.get::<percent>())
+    }
+}
+
+impl From<RelativePermittivity> for Ratio {
+    fn from(from: RelativePermittivity) -> Self {
+        Self::new::<ratio>(from.to_ratio())
+    }
+}
+
+impl From<Ratio> for RelativePermittivity {
+    fn from(from: Ratio) -> Self {
+        Self::from_ratio(from.get::<ratio>())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_approx_eq(expected: f64, actual: f64) {
+        assert!((expected - actual).abs() < 1e-9, "{} != {}", expected, actual);
+    }
+
+    #[test]
+    fn temperature_roundtrip() {
+        let temperature = Temperature::from_degree_celsius(-12.5);
+        let quantity = ThermodynamicTemperature::from(temperature);
+        assert_approx_eq(260.65, quantity.get::<::uom::si::thermodynamic_temperature::kelvin>());
+        assert_approx_eq(-12.5, Temperature::from(quantity).to_degree_celsius());
+    }
+
+    #[test]
+    fn water_content_as_ratio() {
+        let quantity = Ratio::from(VolumetricWaterContent::from_percent(25.0));
+        assert_approx_eq(0.25, quantity.get::<ratio>());
+        assert_approx_eq(25.0, VolumetricWaterContent::from(quantity).to_percent());
+    }
+
+    #[test]
+    fn permittivity_as_ratio() {
+        let quantity = Ratio::from(RelativePermittivity::from_ratio(12.0));
+        assert_approx_eq(1200.0, quantity.get::<percent>());
+        assert_approx_eq(12.0, RelativePermittivity::from(quantity).to_ratio());
+    }
+}
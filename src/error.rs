@@ -0,0 +1,450 @@
+use crate::core::{DecodeError, Quantity};
+
+use std::{error, fmt, io, time::Duration};
+
+/// Errors that might occur when communicating with a sensor.
+#[derive(Debug)]
+pub enum Error {
+    /// The response could not be decoded.
+    Decode(DecodeError),
+
+    /// No response has been received in time.
+    Timeout(TimeoutError),
+
+    /// The device responded with a Modbus exception.
+    ModbusException(ModbusException),
+
+    /// No connection is available.
+    NotConnected,
+
+    /// A register contained a different value after writing it.
+    VerificationFailed(VerificationError),
+
+    /// A measured value has been rejected as implausible.
+    Rejected(RejectionError),
+
+    /// A configuration request has been refused before sending it.
+    Commissioning(CommissioningError),
+
+    /// The underlying transport failed.
+    Transport(io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// A copy of an `Error` without the boxed details of transport errors.
+///
+/// Could be stored without any heap allocations, e.g. to remember the
+/// most recent error of each request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    Decode(DecodeError),
+    Timeout(TimeoutError),
+    ModbusException(ModbusException),
+    NotConnected,
+    VerificationFailed(VerificationError),
+    Rejected(RejectionError),
+    Commissioning(CommissioningError),
+    Transport(io::ErrorKind),
+}
+
+/// The context of a request that timed out.
+///
+/// Fields are `None` if they are unknown in the layer that
+/// detected the timeout.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TimeoutError {
+    /// The quantity that has been read.
+    pub quantity: Option<Quantity>,
+
+    /// The Modbus slave address of the sensor.
+    pub slave: Option<u8>,
+
+    /// The configured timeout.
+    pub timeout: Option<Duration>,
+}
+
+impl TimeoutError {
+    pub fn after(timeout: Duration) -> Self {
+        Self {
+            timeout: Some(timeout),
+            ..Default::default()
+        }
+    }
+}
+
+impl fmt::Display for TimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Timed out")?;
+        if let Some(quantity) = self.quantity {
+            write!(f, " reading {:?}", quantity)?;
+        }
+        if let Some(slave) = self.slave {
+            write!(f, " from slave {}", slave)?;
+        }
+        if let Some(timeout) = self.timeout {
+            write!(f, " after {:?}", timeout)?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for TimeoutError {}
+
+impl From<TimeoutError> for Error {
+    fn from(from: TimeoutError) -> Self {
+        Error::Timeout(from)
+    }
+}
+
+impl Error {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Decode(err) => ErrorKind::Decode(*err),
+            Error::Timeout(err) => ErrorKind::Timeout(*err),
+            Error::ModbusException(exception) => ErrorKind::ModbusException(*exception),
+            Error::NotConnected => ErrorKind::NotConnected,
+            Error::VerificationFailed(err) => ErrorKind::VerificationFailed(*err),
+            Error::Rejected(err) => ErrorKind::Rejected(*err),
+            Error::Commissioning(err) => ErrorKind::Commissioning(*err),
+            Error::Transport(err) => ErrorKind::Transport(err.kind()),
+        }
+    }
+
+    /// Add the quantity to a timeout error that doesn't have one yet.
+    pub fn with_timeout_quantity(mut self, quantity: Quantity) -> Self {
+        if let Error::Timeout(err) = &mut self {
+            err.quantity.get_or_insert(quantity);
+        }
+        self
+    }
+
+    /// Add the slave to a timeout error that doesn't have one yet.
+    pub fn with_timeout_slave(mut self, slave: u8) -> Self {
+        if let Error::Timeout(err) = &mut self {
+            err.slave.get_or_insert(slave);
+        }
+        self
+    }
+}
+
+/// The first register that has been read back with an unexpected
+/// value after writing it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VerificationError {
+    pub register: u16,
+    pub expected: u16,
+    pub actual: u16,
+}
+
+impl fmt::Display for VerificationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Verification failed: read back 0x{:04X} from register 0x{:04X}, expected 0x{:04X}",
+            self.actual, self.register, self.expected
+        )
+    }
+}
+
+impl error::Error for VerificationError {}
+
+impl From<VerificationError> for Error {
+    fn from(from: VerificationError) -> Self {
+        Error::VerificationFailed(from)
+    }
+}
+
+/// A measured value that failed a plausibility check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RejectionError {
+    pub quantity: Quantity,
+
+    /// The number of consecutive rejections of the quantity
+    /// including this one.
+    pub consecutive: u32,
+}
+
+impl fmt::Display for RejectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Rejected implausible {:?} value ({} consecutive)",
+            self.quantity, self.consecutive
+        )
+    }
+}
+
+impl error::Error for RejectionError {}
+
+impl From<RejectionError> for Error {
+    fn from(from: RejectionError) -> Self {
+        Error::Rejected(from)
+    }
+}
+
+/// Configuration requests that are refused, e.g. outside of a
+/// `modbus::CommissioningSession`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommissioningError {
+    /// The register could only be written within a commissioning
+    /// session.
+    ProtectedRegister(u16),
+
+    /// The number of responding devices if not exactly a single
+    /// device is connected.
+    SingleDeviceExpected(usize),
+}
+
+impl fmt::Display for CommissioningError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommissioningError::ProtectedRegister(register) => write!(
+                f,
+                "Register 0x{:04X} could only be written in a commissioning session",
+                register
+            ),
+            CommissioningError::SingleDeviceExpected(found) => {
+                write!(f, "Expected a single device on the bus, found {}", found)
+            }
+        }
+    }
+}
+
+impl error::Error for CommissioningError {}
+
+impl From<CommissioningError> for Error {
+    fn from(from: CommissioningError) -> Self {
+        Error::Commissioning(from)
+    }
+}
+
+/// The exception codes of the Modbus specification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ExceptionCode {
+    IllegalFunction,
+    IllegalDataAddress,
+    IllegalDataValue,
+    ServerDeviceFailure,
+    Acknowledge,
+    ServerDeviceBusy,
+    Other(u8),
+}
+
+impl From<u8> for ExceptionCode {
+    fn from(from: u8) -> Self {
+        use ExceptionCode::*;
+        match from {
+            0x01 => IllegalFunction,
+            0x02 => IllegalDataAddress,
+            0x03 => IllegalDataValue,
+            0x04 => ServerDeviceFailure,
+            0x05 => Acknowledge,
+            0x06 => ServerDeviceBusy,
+            code => Other(code),
+        }
+    }
+}
+
+impl From<ExceptionCode> for u8 {
+    fn from(from: ExceptionCode) -> Self {
+        use ExceptionCode::*;
+        match from {
+            IllegalFunction => 0x01,
+            IllegalDataAddress => 0x02,
+            IllegalDataValue => 0x03,
+            ServerDeviceFailure => 0x04,
+            Acknowledge => 0x05,
+            ServerDeviceBusy => 0x06,
+            Other(code) => code,
+        }
+    }
+}
+
+impl fmt::Display for ExceptionCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ExceptionCode::*;
+        match self {
+            IllegalFunction => write!(f, "Illegal function"),
+            IllegalDataAddress => write!(f, "Illegal data address"),
+            IllegalDataValue => write!(f, "Illegal data value"),
+            ServerDeviceFailure => write!(f, "Server device failure"),
+            Acknowledge => write!(f, "Acknowledge"),
+            ServerDeviceBusy => write!(f, "Server device busy"),
+            Other(code) => write!(f, "Exception 0x{:02X}", code),
+        }
+    }
+}
+
+/// An exception response together with the rejected request.
+///
+/// The function code and the start register are only available if
+/// the request is known.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModbusException {
+    pub code: ExceptionCode,
+    pub function: Option<u8>,
+    pub register: Option<u16>,
+}
+
+impl ModbusException {
+    pub fn new(code: impl Into<ExceptionCode>) -> Self {
+        Self {
+            code: code.into(),
+            function: None,
+            register: None,
+        }
+    }
+
+    pub fn with_request(mut self, function: u8, register: u16) -> Self {
+        self.function = Some(function);
+        self.register = Some(register);
+        self
+    }
+}
+
+impl fmt::Display for ModbusException {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Modbus exception: {}", self.code)?;
+        if let Some(function) = self.function {
+            write!(f, " (function 0x{:02X}", function)?;
+            if let Some(register) = self.register {
+                write!(f, ", register 0x{:04X}", register)?;
+            }
+            f.write_str(")")?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for ModbusException {}
+
+impl From<ModbusException> for Error {
+    fn from(from: ModbusException) -> Self {
+        Error::ModbusException(from)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Error::*;
+        match self {
+            Decode(err) => write!(f, "Decoding failed: {}", err),
+            Timeout(err) => write!(f, "{}", err),
+            ModbusException(exception) => write!(f, "{}", exception),
+            NotConnected => write!(f, "Not connected"),
+            VerificationFailed(err) => write!(f, "{}", err),
+            Rejected(err) => write!(f, "{}", err),
+            Commissioning(err) => write!(f, "{}", err),
+            Transport(err) => write!(f, "Transport failed: {}", err),
+        }
+    }
+}
+
+/// The same messages as `Error` apart from the details of
+/// transport errors.
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ErrorKind::*;
+        match self {
+            Decode(err) => write!(f, "Decoding failed: {}", err),
+            Timeout(err) => write!(f, "{}", err),
+            ModbusException(exception) => write!(f, "{}", exception),
+            NotConnected => write!(f, "Not connected"),
+            VerificationFailed(err) => write!(f, "{}", err),
+            Rejected(err) => write!(f, "{}", err),
+            Commissioning(err) => write!(f, "{}", err),
+            Transport(kind) => write!(f, "Transport failed: {}", kind),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        use Error::*;
+        match self {
+            Decode(err) => Some(err),
+            Timeout(err) => Some(err),
+            ModbusException(err) => Some(err),
+            VerificationFailed(err) => Some(err),
+            Rejected(err) => Some(err),
+            Commissioning(err) => Some(err),
+            Transport(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<DecodeError> for Error {
+    fn from(from: DecodeError) -> Self {
+        Error::Decode(from)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(from: io::Error) -> Self {
+        match from.kind() {
+            io::ErrorKind::TimedOut => Error::Timeout(TimeoutError::default()),
+            io::ErrorKind::NotConnected => Error::NotConnected,
+            _ => {
+                if let Some(err) = from.get_ref().and_then(|err| err.downcast_ref::<DecodeError>()) {
+                    Error::Decode(*err)
+                } else {
+                    Error::Transport(from)
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl From<tokio::time::error::Elapsed> for Error {
+    fn from(_: tokio::time::error::Elapsed) -> Self {
+        Error::Timeout(TimeoutError::default())
+    }
+}
+
+#[cfg(feature = "tokio-modbus")]
+impl From<tokio_modbus::Error> for Error {
+    fn from(from: tokio_modbus::Error) -> Self {
+        match from {
+            tokio_modbus::Error::Transport(err) => err.into(),
+            err => {
+                log::debug!("Invalid response: {}", err);
+                Error::Decode(DecodeError::InvalidInput)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "tokio-modbus")]
+impl From<tokio_modbus::ExceptionCode> for Error {
+    fn from(from: tokio_modbus::ExceptionCode) -> Self {
+        Error::ModbusException(ModbusException::new(u8::from(from)))
+    }
+}
+
+impl From<DecodeError> for io::Error {
+    fn from(from: DecodeError) -> Self {
+        use DecodeError::*;
+        match from {
+            InsufficientInput | InvalidInput => Self::new(io::ErrorKind::InvalidInput, from),
+            InvalidData | InvalidChecksum => Self::new(io::ErrorKind::InvalidData, from),
+        }
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(from: Error) -> Self {
+        use Error::*;
+        match from {
+            Decode(err) => err.into(),
+            Timeout(_) => io::Error::new(io::ErrorKind::TimedOut, from),
+            ModbusException(_) => io::Error::other(from),
+            NotConnected => io::Error::new(io::ErrorKind::NotConnected, from),
+            VerificationFailed(_) | Rejected(_) => io::Error::new(io::ErrorKind::InvalidData, from),
+            Commissioning(_) => io::Error::new(io::ErrorKind::InvalidInput, from),
+            Transport(err) => err,
+        }
+    }
+}
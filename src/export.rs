@@ -0,0 +1,204 @@
+//! Export of collected samples as Arrow record batches and Parquet
+//! files for offline analysis, e.g. with _pandas_ or _polars_.
+//!
+//! Samples are taken from a `History` or an SQLite database and stored
+//! in long format with a row per quantity:
+//!
+//! | Column     | Type                     | Nullable |
+//! |------------|--------------------------|----------|
+//! | `ts`       | Timestamp (ms, UTC)      | no       |
+//! | `sensor`   | UInt8                    | yes      |
+//! | `quantity` | Utf8, e.g. `temperature` | no       |
+//! | `value`    | Float64                  | no       |
+//!
+//! Values are given in the units of the accessors, i.e. °C for the
+//! temperature and % for the water content.
+
+use super::*;
+
+use crate::{history::Sample, sink::csv::DateTime};
+
+use arrow_array::{ArrayRef, Float64Array, RecordBatch, StringArray, TimestampMillisecondArray, UInt8Array};
+use arrow_schema::{ArrowError, DataType, Field, Schema, SchemaRef, TimeUnit};
+use parquet::arrow::ArrowWriter;
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const SECS_PER_DAY: u64 = 86_400;
+
+fn unix_millis(ts: SystemTime) -> i64 {
+    ts.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as i64
+}
+
+/// The schema of all record batches.
+pub fn schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("ts", DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into())), false),
+        Field::new("sensor", DataType::UInt8, true),
+        Field::new("quantity", DataType::Utf8, false),
+        Field::new("value", DataType::Float64, false),
+    ]))
+}
+
+/// A single record batch with all samples.
+pub fn record_batch(samples: &[Sample]) -> std::result::Result<RecordBatch, ArrowError> {
+    let ts = TimestampMillisecondArray::from_iter_values(samples.iter().map(|sample| unix_millis(sample.ts)))
+        .with_timezone("UTC");
+    let sensor: UInt8Array = samples.iter().map(|sample| sample.sensor).collect();
+    let quantity: StringArray = samples
+        .iter()
+        .map(|sample| Some(sample.quantity.name()))
+        .collect();
+    let value = Float64Array::from_iter_values(samples.iter().map(|sample| sample.value));
+    let columns: Vec<ArrayRef> = vec![Arc::new(ts), Arc::new(sensor), Arc::new(quantity), Arc::new(value)];
+    RecordBatch::try_new(schema(), columns)
+}
+
+/// Write all samples as a Parquet file.
+pub fn write_parquet<W>(writer: W, samples: &[Sample]) -> io::Result<()>
+where
+    W: Write + Send,
+{
+    let batch = record_batch(samples).map_err(io::Error::other)?;
+    let mut writer = ArrowWriter::try_new(writer, schema(), None).map_err(io::Error::other)?;
+    writer.write(&batch).map_err(io::Error::other)?;
+    writer.close().map_err(io::Error::other)?;
+    Ok(())
+}
+
+/// Write a Parquet file per day in UTC into `dir`, e.g.
+/// `smt100-2019-10-16.parquet` for the prefix `smt100`.
+///
+/// Existing files are replaced. Returns the paths of all files in
+/// chronological order.
+pub fn write_parquet_per_day(
+    dir: impl AsRef<Path>,
+    prefix: &str,
+    samples: &[Sample],
+) -> io::Result<Vec<PathBuf>> {
+    let mut days: BTreeMap<u64, Vec<Sample>> = BTreeMap::new();
+    for sample in samples {
+        let secs = sample.ts.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        days.entry(secs / SECS_PER_DAY).or_default().push(*sample);
+    }
+    let mut paths = Vec::with_capacity(days.len());
+    for (days_since_epoch, samples) in days {
+        let date = UNIX_EPOCH + Duration::from_secs(days_since_epoch * SECS_PER_DAY);
+        let DateTime { year, month, day, .. } = DateTime::from(date);
+        let path = dir
+            .as_ref()
+            .join(format!("{}-{:04}-{:02}-{:02}.parquet", prefix, year, month, day));
+        write_parquet(File::create(&path)?, &samples)?;
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{history::History, poller::Measurement};
+
+    use arrow_array::Array;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    /// 2019-10-16T08:00:00.123Z
+    fn started() -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(1_571_212_800_123)
+    }
+
+    fn measurements(degree_celsius: f64) -> Measurements {
+        Measurements {
+            temperature: Temperature::from_degree_celsius(degree_celsius),
+            water_content: VolumetricWaterContent::from_percent(34.5),
+            permittivity: RelativePermittivity::from_ratio(15.25),
+            raw_counts: RawCounts::from(11_345),
+        }
+    }
+
+    fn history() -> History<Measurements> {
+        let mut history = History::new(2);
+        history.push(Measurement::at(started(), measurements(21.5)));
+        history.push(Measurement::at(started() + Duration::from_secs(SECS_PER_DAY), measurements(22.0)));
+        history
+    }
+
+    fn read_parquet(path: &Path) -> RecordBatch {
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let batches: Vec<_> = reader.collect::<std::result::Result<_, _>>().unwrap();
+        assert_eq!(1, batches.len());
+        batches.into_iter().next().unwrap()
+    }
+
+    #[test]
+    fn parquet_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("smt100.parquet");
+        let samples = history().samples(Some(1));
+        assert_eq!(8, samples.len());
+        write_parquet(File::create(&path).unwrap(), &samples).unwrap();
+        let batch = read_parquet(&path);
+        assert_eq!(schema().fields(), batch.schema().fields());
+        assert_eq!(8, batch.num_rows());
+        let column = |name| batch.column_by_name(name).unwrap();
+        let ts = column("ts")
+            .as_any()
+            .downcast_ref::<TimestampMillisecondArray>()
+            .unwrap();
+        let sensor = column("sensor").as_any().downcast_ref::<UInt8Array>().unwrap();
+        let quantity = column("quantity").as_any().downcast_ref::<StringArray>().unwrap();
+        let value = column("value").as_any().downcast_ref::<Float64Array>().unwrap();
+        for (row, sample) in samples.iter().enumerate() {
+            assert_eq!(unix_millis(sample.ts), ts.value(row));
+            assert!(sensor.is_valid(row));
+            assert_eq!(1, sensor.value(row));
+            assert_eq!(sample.quantity.name(), quantity.value(row));
+            assert_eq!(sample.value, value.value(row));
+        }
+        assert_eq!(1_571_212_800_123, ts.value(0));
+        assert_eq!("temperature", quantity.value(0));
+        assert_eq!(21.5, value.value(0));
+        assert_eq!("raw_counts", quantity.value(3));
+        assert_eq!(11_345.0, value.value(3));
+    }
+
+    #[test]
+    fn missing_sensor_is_null() {
+        let batch = record_batch(&history().samples(None)).unwrap();
+        assert_eq!(8, batch.column_by_name("sensor").unwrap().null_count());
+        let batch = record_batch(&[]).unwrap();
+        assert_eq!(0, batch.num_rows());
+    }
+
+    #[test]
+    fn write_parquet_file_per_day() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = write_parquet_per_day(dir.path(), "smt100", &history().samples(Some(1))).unwrap();
+        assert_eq!(
+            vec![
+                dir.path().join("smt100-2019-10-16.parquet"),
+                dir.path().join("smt100-2019-10-17.parquet"),
+            ],
+            paths
+        );
+        let batch = read_parquet(&paths[1]);
+        assert_eq!(4, batch.num_rows());
+        let value = batch
+            .column_by_name("value")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<Float64Array>()
+            .unwrap();
+        assert_eq!(22.0, value.value(0));
+    }
+}
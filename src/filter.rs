@@ -0,0 +1,507 @@
+//! Smoothing of noisy measurements and rejection of outliers.
+
+use super::*;
+
+use futures::{future::BoxFuture, FutureExt};
+use std::{
+    collections::VecDeque,
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::Duration,
+};
+
+/// Filter algorithm for a single quantity.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum FilterConfig {
+    /// Pass through all values unmodified.
+    #[default]
+    None,
+
+    /// Arithmetic mean of the last `window` values.
+    Mean { window: usize },
+
+    /// Median of the last `window` values.
+    Median { window: usize },
+
+    /// Exponentially weighted moving average with smoothing
+    /// factor `alpha` in the range (0, 1].
+    Ewma { alpha: f64 },
+}
+
+/// Stateful filter for a sequence of values.
+#[derive(Clone, Debug)]
+pub struct Filter {
+    config: FilterConfig,
+    window: VecDeque<f64>,
+    average: Option<f64>,
+}
+
+impl Filter {
+    pub fn new(config: FilterConfig) -> Self {
+        Self {
+            config,
+            window: VecDeque::new(),
+            average: None,
+        }
+    }
+
+    pub fn config(&self) -> FilterConfig {
+        self.config
+    }
+
+    /// Discard all previous values.
+    pub fn reset(&mut self) {
+        self.window.clear();
+        self.average = None;
+    }
+
+    /// Feed the next value and return the filtered value.
+    pub fn apply(&mut self, value: f64) -> f64 {
+        match self.config {
+            FilterConfig::None => value,
+            FilterConfig::Mean { window } => {
+                self.push(window, value);
+                self.window.iter().sum::<f64>() / self.window.len() as f64
+            }
+            FilterConfig::Median { window } => {
+                self.push(window, value);
+                let mut sorted: Vec<_> = self.window.iter().copied().collect();
+                sorted.sort_by(|lhs, rhs| lhs.partial_cmp(rhs).unwrap_or(std::cmp::Ordering::Equal));
+                let mid = sorted.len() / 2;
+                if sorted.len() % 2 == 0 {
+                    (sorted[mid - 1] + sorted[mid]) / 2.0
+                } else {
+                    sorted[mid]
+                }
+            }
+            FilterConfig::Ewma { alpha } => {
+                let average = match self.average {
+                    Some(average) => alpha * value + (1.0 - alpha) * average,
+                    None => value,
+                };
+                self.average = Some(average);
+                average
+            }
+        }
+    }
+
+    fn push(&mut self, window: usize, value: f64) {
+        self.window.push_back(value);
+        while self.window.len() > window.max(1) {
+            self.window.pop_front();
+        }
+    }
+}
+
+/// Plausibility check that rejects physically impossible values,
+/// e.g. caused by corrupted frames.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PlausibilityCheck {
+    /// Lower bound for all values.
+    pub min: Option<f64>,
+
+    /// Upper bound for all values.
+    pub max: Option<f64>,
+
+    /// Maximum difference to the last accepted value.
+    pub max_delta: Option<f64>,
+
+    /// The number of consecutive values that exceed `max_delta` after
+    /// which the next value is accepted as the new baseline. Otherwise
+    /// an actual step change would be rejected forever.
+    pub max_consecutive_jumps: u32,
+}
+
+impl Default for PlausibilityCheck {
+    fn default() -> Self {
+        Self {
+            min: None,
+            max: None,
+            max_delta: None,
+            max_consecutive_jumps: 3,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Plausibility {
+    check: PlausibilityCheck,
+    last_accepted: Option<f64>,
+    consecutive_jumps: u32,
+    consecutive_rejections: u32,
+}
+
+impl Plausibility {
+    fn new(check: PlausibilityCheck) -> Self {
+        Self {
+            check,
+            last_accepted: None,
+            consecutive_jumps: 0,
+            consecutive_rejections: 0,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.last_accepted = None;
+        self.consecutive_jumps = 0;
+        self.consecutive_rejections = 0;
+    }
+
+    /// Returns the number of consecutive rejections if `value`
+    /// is rejected.
+    fn accept(&mut self, value: f64) -> std::result::Result<(), u32> {
+        let PlausibilityCheck {
+            min,
+            max,
+            max_delta,
+            max_consecutive_jumps,
+        } = self.check;
+        if min.map(|min| value < min).unwrap_or(false) || max.map(|max| value > max).unwrap_or(false) {
+            return Err(self.reject());
+        }
+        if let (Some(max_delta), Some(last_accepted)) = (max_delta, self.last_accepted) {
+            if (value - last_accepted).abs() > max_delta && self.consecutive_jumps < max_consecutive_jumps {
+                self.consecutive_jumps += 1;
+                return Err(self.reject());
+            }
+        }
+        self.last_accepted = Some(value);
+        self.consecutive_jumps = 0;
+        self.consecutive_rejections = 0;
+        Ok(())
+    }
+
+    fn reject(&mut self) -> u32 {
+        self.consecutive_rejections += 1;
+        self.consecutive_rejections
+    }
+}
+
+/// Plausibility checks for each quantity.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PlausibilitySettings {
+    pub temperature: PlausibilityCheck,
+    pub water_content: PlausibilityCheck,
+    pub permittivity: PlausibilityCheck,
+}
+
+/// The number of values that have been rejected by plausibility checks.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Diagnostics {
+    pub rejected_temperature: u64,
+    pub rejected_water_content: u64,
+    pub rejected_permittivity: u64,
+}
+
+/// Filters for each quantity. Raw counts are never filtered.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FilterSettings {
+    pub temperature: FilterConfig,
+    pub water_content: FilterConfig,
+    pub permittivity: FilterConfig,
+}
+
+struct Filters {
+    temperature: (Plausibility, Filter),
+    water_content: (Plausibility, Filter),
+    permittivity: (Plausibility, Filter),
+    diagnostics: Diagnostics,
+}
+
+fn check_and_filter(
+    quantity: Quantity,
+    (plausibility, filter): &mut (Plausibility, Filter),
+    rejected: &mut u64,
+    value: f64,
+) -> Result<f64> {
+    match plausibility.accept(value) {
+        Ok(()) => Ok(filter.apply(value)),
+        Err(consecutive) => {
+            log::warn!("Rejecting implausible {:?} value: {}", quantity, value);
+            *rejected += 1;
+            Err(RejectionError { quantity, consecutive }.into())
+        }
+    }
+}
+
+/// Decorator that filters all measurements of the wrapped
+/// capabilities, e.g. for use with a `Poller`.
+///
+/// Implausible values are rejected before filtering and reported as
+/// `Error::Rejected`. Failed measurements don't affect the
+/// state of the filters.
+pub struct FilteringProxy<C> {
+    capabilities: C,
+    filters: Mutex<Filters>,
+}
+
+impl<C> FilteringProxy<C> {
+    pub fn new(capabilities: C, settings: FilterSettings) -> Self {
+        Self::with_plausibility_checks(capabilities, settings, Default::default())
+    }
+
+    pub fn with_plausibility_checks(
+        capabilities: C,
+        settings: FilterSettings,
+        checks: PlausibilitySettings,
+    ) -> Self {
+        let filters = Filters {
+            temperature: (
+                Plausibility::new(checks.temperature),
+                Filter::new(settings.temperature),
+            ),
+            water_content: (
+                Plausibility::new(checks.water_content),
+                Filter::new(settings.water_content),
+            ),
+            permittivity: (
+                Plausibility::new(checks.permittivity),
+                Filter::new(settings.permittivity),
+            ),
+            diagnostics: Default::default(),
+        };
+        Self {
+            capabilities,
+            filters: Mutex::new(filters),
+        }
+    }
+
+    pub fn capabilities(&self) -> &C {
+        &self.capabilities
+    }
+
+    pub fn diagnostics(&self) -> Diagnostics {
+        lock_filters(&self.filters).diagnostics
+    }
+
+    /// Discard the history of all filters and checks.
+    pub fn reset(&self) {
+        let filters = &mut *lock_filters(&self.filters);
+        for (plausibility, filter) in &mut [
+            &mut filters.temperature,
+            &mut filters.water_content,
+            &mut filters.permittivity,
+        ] {
+            plausibility.reset();
+            filter.reset();
+        }
+    }
+}
+
+fn lock_filters(filters: &Mutex<Filters>) -> MutexGuard<'_, Filters> {
+    filters.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl<C: Capabilities> Capabilities for FilteringProxy<C> {
+    fn read_temperature(&self, timeout: Option<Duration>) -> BoxFuture<'_, Result<Temperature>> {
+        let filters = &self.filters;
+        let read = self.capabilities.read_temperature(timeout);
+        async move {
+            let val = read.await?;
+            let Filters {
+                temperature,
+                diagnostics,
+                ..
+            } = &mut *lock_filters(filters);
+            let filtered = check_and_filter(
+                Quantity::Temperature,
+                temperature,
+                &mut diagnostics.rejected_temperature,
+                val.to_degree_celsius(),
+            )?;
+            Ok(Temperature::from_degree_celsius(filtered))
+        }
+        .boxed()
+    }
+
+    fn read_water_content(&self, timeout: Option<Duration>) -> BoxFuture<'_, Result<VolumetricWaterContent>> {
+        let filters = &self.filters;
+        let read = self.capabilities.read_water_content(timeout);
+        async move {
+            let val = read.await?;
+            let Filters {
+                water_content,
+                diagnostics,
+                ..
+            } = &mut *lock_filters(filters);
+            let filtered = check_and_filter(
+                Quantity::WaterContent,
+                water_content,
+                &mut diagnostics.rejected_water_content,
+                val.to_percent(),
+            )?;
+            Ok(VolumetricWaterContent::from_percent(filtered))
+        }
+        .boxed()
+    }
+
+    fn read_permittivity(&self, timeout: Option<Duration>) -> BoxFuture<'_, Result<RelativePermittivity>> {
+        let filters = &self.filters;
+        let read = self.capabilities.read_permittivity(timeout);
+        async move {
+            let val = read.await?;
+            let Filters {
+                permittivity,
+                diagnostics,
+                ..
+            } = &mut *lock_filters(filters);
+            let filtered = check_and_filter(
+                Quantity::Permittivity,
+                permittivity,
+                &mut diagnostics.rejected_permittivity,
+                val.to_ratio(),
+            )?;
+            Ok(RelativePermittivity::from_ratio(filtered))
+        }
+        .boxed()
+    }
+
+    fn read_raw_counts(&self, timeout: Option<Duration>) -> BoxFuture<'_, Result<RawCounts>> {
+        self.capabilities.read_raw_counts(timeout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply_all(config: FilterConfig, values: &[f64]) -> Vec<f64> {
+        let mut filter = Filter::new(config);
+        values.iter().map(|value| filter.apply(*value)).collect()
+    }
+
+    #[test]
+    fn pass_through_without_filter() {
+        assert_eq!(vec![1.0, 5.0, 2.0], apply_all(FilterConfig::None, &[1.0, 5.0, 2.0]));
+    }
+
+    #[test]
+    fn mean_of_window() {
+        // The first values are averaged over a shorter window
+        assert_eq!(
+            vec![2.0, 3.0, 4.0, 6.0, 8.0],
+            apply_all(FilterConfig::Mean { window: 3 }, &[2.0, 4.0, 6.0, 8.0, 10.0])
+        );
+    }
+
+    #[test]
+    fn median_of_odd_window() {
+        assert_eq!(
+            vec![5.0, 52.5, 5.0, 6.0, 6.0],
+            apply_all(FilterConfig::Median { window: 3 }, &[5.0, 100.0, 1.0, 6.0, 7.0])
+        );
+    }
+
+    #[test]
+    fn median_of_even_window() {
+        assert_eq!(
+            vec![4.0, 3.0, 4.0, 5.0, 6.5],
+            apply_all(FilterConfig::Median { window: 4 }, &[4.0, 2.0, 8.0, 6.0, 7.0])
+        );
+    }
+
+    #[test]
+    fn empty_window_keeps_last_value() {
+        assert_eq!(vec![1.0, 2.0], apply_all(FilterConfig::Mean { window: 0 }, &[1.0, 2.0]));
+        assert_eq!(vec![1.0, 2.0], apply_all(FilterConfig::Median { window: 0 }, &[1.0, 2.0]));
+    }
+
+    #[test]
+    fn ewma_is_seeded_with_first_value() {
+        assert_eq!(
+            vec![10.0, 15.0, 17.5, 8.75],
+            apply_all(FilterConfig::Ewma { alpha: 0.5 }, &[10.0, 20.0, 20.0, 0.0])
+        );
+    }
+
+    #[test]
+    fn reset_discards_previous_values() {
+        let mut filter = Filter::new(FilterConfig::Ewma { alpha: 0.5 });
+        filter.apply(10.0);
+        filter.reset();
+        assert_eq!(30.0, filter.apply(30.0));
+        let mut filter = Filter::new(FilterConfig::Mean { window: 2 });
+        filter.apply(10.0);
+        filter.reset();
+        assert_eq!(30.0, filter.apply(30.0));
+    }
+
+    fn check_all(check: PlausibilityCheck, values: &[f64]) -> Vec<std::result::Result<(), u32>> {
+        let mut plausibility = Plausibility::new(check);
+        values.iter().map(|value| plausibility.accept(*value)).collect()
+    }
+
+    #[test]
+    fn reject_values_out_of_range() {
+        let check = PlausibilityCheck {
+            min: Some(-40.0),
+            max: Some(80.0),
+            ..Default::default()
+        };
+        assert_eq!(
+            vec![Ok(()), Err(1), Err(2), Ok(()), Err(1)],
+            check_all(check, &[20.0, -40.1, 80.1, 80.0, 1000.0])
+        );
+    }
+
+    #[test]
+    fn accept_step_change_after_max_consecutive_jumps() {
+        let check = PlausibilityCheck {
+            max_delta: Some(5.0),
+            max_consecutive_jumps: 2,
+            ..Default::default()
+        };
+        assert_eq!(
+            vec![Ok(()), Err(1), Err(2), Ok(()), Ok(())],
+            check_all(check, &[20.0, 30.0, 30.0, 30.0, 31.0])
+        );
+    }
+
+    #[test]
+    fn single_jumps_are_rejected_without_moving_the_baseline() {
+        let check = PlausibilityCheck {
+            max_delta: Some(5.0),
+            max_consecutive_jumps: 2,
+            ..Default::default()
+        };
+        assert_eq!(
+            vec![Ok(()), Err(1), Ok(()), Err(1), Ok(())],
+            check_all(check, &[20.0, 99.0, 21.0, 0.0, 22.0])
+        );
+    }
+
+    #[test]
+    fn accept_all_jumps_without_max_consecutive_jumps() {
+        let check = PlausibilityCheck {
+            max_delta: Some(5.0),
+            max_consecutive_jumps: 0,
+            ..Default::default()
+        };
+        assert_eq!(vec![Ok(()), Ok(()), Ok(())], check_all(check, &[20.0, 30.0, 40.0]));
+    }
+
+    #[test]
+    fn report_rejections() {
+        let check = PlausibilityCheck {
+            max: Some(100.0),
+            ..Default::default()
+        };
+        let mut filters = (Plausibility::new(check), Filter::new(FilterConfig::None));
+        let mut rejected = 0;
+        for consecutive in 1..=2 {
+            match check_and_filter(Quantity::WaterContent, &mut filters, &mut rejected, 101.0) {
+                Err(Error::Rejected(err)) => assert_eq!(
+                    RejectionError {
+                        quantity: Quantity::WaterContent,
+                        consecutive,
+                    },
+                    err
+                ),
+                res => panic!("Unexpected result: {:?}", res),
+            }
+        }
+        assert_eq!(2, rejected);
+        let res = check_and_filter(Quantity::WaterContent, &mut filters, &mut rejected, 50.0);
+        assert_eq!(50.0, res.unwrap());
+        assert_eq!(2, rejected);
+    }
+}
@@ -0,0 +1,295 @@
+//! Recent measurements in a fixed-size ring buffer.
+
+use super::*;
+
+use crate::{
+    poller::{Measurement, TimestampedMeasurements},
+    trend::{Trend, TrendConfig},
+};
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Access to the values of individual quantities.
+pub trait QuantityValues {
+    /// The value of `quantity` in the units of the accessors,
+    /// i.e. °C for the temperature and % for the water content.
+    ///
+    /// `None` if the quantity has not been measured.
+    fn value(&self, quantity: Quantity) -> Option<f64>;
+}
+
+impl QuantityValues for Measurements {
+    fn value(&self, quantity: Quantity) -> Option<f64> {
+        let value = match quantity {
+            Quantity::Temperature => self.temperature.to_degree_celsius(),
+            Quantity::WaterContent => self.water_content.to_percent(),
+            Quantity::Permittivity => self.permittivity.to_ratio(),
+            Quantity::RawCounts => u16::from(self.raw_counts).into(),
+        };
+        Some(value)
+    }
+}
+
+impl QuantityValues for TimestampedMeasurements {
+    fn value(&self, quantity: Quantity) -> Option<f64> {
+        match quantity {
+            Quantity::Temperature => self.temperature.map(|m| m.val.to_degree_celsius()),
+            Quantity::WaterContent => self.water_content.map(|m| m.val.to_percent()),
+            Quantity::Permittivity => self.permittivity.map(|m| m.val.to_ratio()),
+            Quantity::RawCounts => self.raw_counts.map(|m| u16::from(m.val).into()),
+        }
+    }
+}
+
+/// The value of a single quantity of a sensor.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sample {
+    /// The Modbus slave address of the sensor, if any.
+    pub sensor: Option<u8>,
+    pub quantity: Quantity,
+    pub value: f64,
+    pub ts: SystemTime,
+}
+
+/// The last `capacity` timestamped entries in chronological order.
+///
+/// The oldest entry is discarded when adding a new entry to a full
+/// history.
+#[derive(Clone, Debug)]
+pub struct History<T> {
+    capacity: usize,
+    entries: VecDeque<Measurement<T>>,
+}
+
+impl<T> History<T> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Add a new entry.
+    ///
+    /// Entries are expected to be added in chronological order.
+    pub fn push(&mut self, entry: Measurement<T>) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    /// The most recent entry.
+    pub fn latest(&self) -> Option<&Measurement<T>> {
+        self.entries.back()
+    }
+
+    /// All entries from oldest to latest.
+    pub fn iter(&self) -> impl Iterator<Item = &Measurement<T>> + '_ {
+        self.entries.iter()
+    }
+
+    /// All entries that have been added at or after `since`.
+    pub fn since(&self, since: SystemTime) -> impl Iterator<Item = &Measurement<T>> + '_ {
+        self.entries.iter().filter(move |entry| entry.ts >= since)
+    }
+
+    /// All entries that are not older than `window` at `now`.
+    ///
+    /// The current time is passed explicitly, e.g. from a `runtime::Clock`.
+    pub fn window(&self, window: Duration, now: SystemTime) -> impl Iterator<Item = &Measurement<T>> + '_ {
+        let since = now.checked_sub(window).unwrap_or(SystemTime::UNIX_EPOCH);
+        self.since(since)
+    }
+}
+
+impl<T> History<T>
+where
+    T: QuantityValues,
+{
+    fn window_values(
+        &self,
+        quantity: Quantity,
+        window: Duration,
+        now: SystemTime,
+    ) -> impl Iterator<Item = f64> + '_ {
+        self.window(window, now).filter_map(move |entry| entry.val.value(quantity))
+    }
+
+    /// The minimum value of `quantity` within `window`.
+    pub fn min(&self, quantity: Quantity, window: Duration, now: SystemTime) -> Option<f64> {
+        self.window_values(quantity, window, now).fold(None, |min, value| {
+            Some(min.map_or(value, |min: f64| min.min(value)))
+        })
+    }
+
+    /// The maximum value of `quantity` within `window`.
+    pub fn max(&self, quantity: Quantity, window: Duration, now: SystemTime) -> Option<f64> {
+        self.window_values(quantity, window, now).fold(None, |max, value| {
+            Some(max.map_or(value, |max: f64| max.max(value)))
+        })
+    }
+
+    /// The arithmetic mean of all values of `quantity` within `window`.
+    pub fn average(&self, quantity: Quantity, window: Duration, now: SystemTime) -> Option<f64> {
+        let (count, sum) = self
+            .window_values(quantity, window, now)
+            .fold((0usize, 0.0), |(count, sum), value| (count + 1, sum + value));
+        if count > 0 {
+            Some(sum / count as f64)
+        } else {
+            None
+        }
+    }
+
+    /// The values of all measured quantities of all entries from
+    /// oldest to latest.
+    pub fn samples(&self, sensor: Option<u8>) -> Vec<Sample> {
+        let mut samples = Vec::new();
+        for entry in self.iter() {
+            for quantity in MeasurementSelection::all().quantities() {
+                if let Some(value) = entry.val.value(quantity) {
+                    samples.push(Sample {
+                        sensor,
+                        quantity,
+                        value,
+                        ts: entry.ts,
+                    });
+                }
+            }
+        }
+        samples
+    }
+
+    /// The rates of change of water content and temperature.
+    pub fn trend(&self, config: &TrendConfig) -> Trend {
+        let samples = |quantity| -> Vec<_> {
+            self.iter()
+                .filter_map(|entry| {
+                    let value = entry.val.value(quantity)?;
+                    Some((entry.ts.duration_since(UNIX_EPOCH).unwrap_or_default(), value))
+                })
+                .collect()
+        };
+        let water_content: Vec<_> = samples(Quantity::WaterContent)
+            .into_iter()
+            .map(|(t, value)| (t, VolumetricWaterContent::from_percent(value)))
+            .collect();
+        let temperature: Vec<_> = samples(Quantity::Temperature)
+            .into_iter()
+            .map(|(t, value)| (t, Temperature::from_degree_celsius(value)))
+            .collect();
+        Trend::new(&water_content, &temperature, config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ts(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    fn water_content(secs: u64, percent: f64) -> Measurement<TimestampedMeasurements> {
+        let val = TimestampedMeasurements {
+            water_content: Some(Measurement::at(ts(secs), VolumetricWaterContent::from_percent(percent))),
+            ..Default::default()
+        };
+        Measurement::at(ts(secs), val)
+    }
+
+    fn history(capacity: usize, count: u64) -> History<TimestampedMeasurements> {
+        let mut history = History::new(capacity);
+        for secs in 1..=count {
+            history.push(water_content(secs, secs as f64));
+        }
+        history
+    }
+
+    fn timestamps(history: &History<TimestampedMeasurements>) -> Vec<SystemTime> {
+        history.iter().map(|entry| entry.ts).collect()
+    }
+
+    #[test]
+    fn discard_oldest_entries_when_full() {
+        let history = history(3, 5);
+        assert_eq!(3, history.len());
+        assert_eq!(vec![ts(3), ts(4), ts(5)], timestamps(&history));
+        assert_eq!(Some(ts(5)), history.latest().map(|entry| entry.ts));
+    }
+
+    #[test]
+    fn disabled_without_capacity() {
+        let history = history(0, 2);
+        assert!(history.is_empty());
+        assert_eq!(None, history.latest());
+    }
+
+    #[test]
+    fn keep_only_latest_entry_with_capacity_one() {
+        let mut history = history(1, 2);
+        assert_eq!(vec![ts(2)], timestamps(&history));
+        history.clear();
+        assert!(history.is_empty());
+        assert_eq!(1, history.capacity());
+    }
+
+    #[test]
+    fn entries_within_window() {
+        let history = history(10, 5);
+        assert_eq!(vec![ts(3), ts(4), ts(5)], history.since(ts(3)).map(|entry| entry.ts).collect::<Vec<_>>());
+        let window = Duration::from_secs(2);
+        let now = ts(6);
+        assert_eq!(2, history.window(window, now).count());
+        assert_eq!(Some(4.0), history.min(Quantity::WaterContent, window, now));
+        assert_eq!(Some(5.0), history.max(Quantity::WaterContent, window, now));
+        assert_eq!(Some(4.5), history.average(Quantity::WaterContent, window, now));
+        assert_eq!(None, history.average(Quantity::Temperature, window, now));
+        assert_eq!(None, history.average(Quantity::WaterContent, window, ts(100)));
+    }
+
+    #[test]
+    fn samples_of_all_measured_quantities() {
+        let samples = history(2, 3).samples(Some(7));
+        assert_eq!(
+            vec![
+                Sample {
+                    sensor: Some(7),
+                    quantity: Quantity::WaterContent,
+                    value: 2.0,
+                    ts: ts(2),
+                },
+                Sample {
+                    sensor: Some(7),
+                    quantity: Quantity::WaterContent,
+                    value: 3.0,
+                    ts: ts(3),
+                },
+            ],
+            samples
+        );
+    }
+}
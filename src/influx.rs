@@ -0,0 +1,233 @@
+//! Serialization of measurements in the InfluxDB line protocol.
+//!
+//! The lines can be written directly to the socket listener of
+//! Telegraf or to the write endpoint of InfluxDB, e.g.
+//!
+//! ```text
+//! smt100,slave=1 temperature=21.5,water_content=34.4,permittivity=15.2,raw_counts=11345i 1571212800123000000
+//! ```
+//!
+//! Timestamps are given in nanoseconds since the Unix epoch. Lines
+//! without a timestamp are stamped by the receiver.
+
+use super::*;
+
+use std::{
+    fmt::{self, Write as _},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+#[cfg(feature = "tokio")]
+use crate::poller::{Measurement, TimestampedMeasurements};
+
+/// Key/value pairs that are attached to each line.
+///
+/// InfluxDB recommends to sort tags by key.
+pub type Tags<'a> = &'a [(&'a str, &'a str)];
+
+enum FieldValue {
+    Float(f64),
+    Integer(i64),
+}
+
+impl fmt::Display for FieldValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldValue::Float(val) => write!(f, "{}", val),
+            FieldValue::Integer(val) => write!(f, "{}i", val),
+        }
+    }
+}
+
+fn escape(line: &mut String, s: &str, special: &[char]) {
+    for c in s.chars() {
+        if special.contains(&c) {
+            line.push('\\');
+        }
+        line.push(c);
+    }
+}
+
+fn line_protocol(
+    measurement_name: &str,
+    tags: Tags<'_>,
+    fields: &[(&str, FieldValue)],
+    ts: Option<SystemTime>,
+) -> String {
+    let mut line = String::new();
+    escape(&mut line, measurement_name, &[',', ' ']);
+    for (key, val) in tags {
+        line.push(',');
+        escape(&mut line, key, &[',', '=', ' ']);
+        line.push('=');
+        escape(&mut line, val, &[',', '=', ' ']);
+    }
+    for (i, (key, val)) in fields.iter().enumerate() {
+        line.push(if i == 0 { ' ' } else { ',' });
+        escape(&mut line, key, &[',', '=', ' ']);
+        let _ = write!(line, "={}", val);
+    }
+    if let Some(ts) = ts {
+        let nanos = ts.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+        let _ = write!(line, " {}", nanos);
+    }
+    line
+}
+
+impl Temperature {
+    /// A single line with the field `temperature` in °C.
+    pub fn to_line_protocol(self, measurement_name: &str, tags: Tags<'_>, ts: Option<SystemTime>) -> String {
+        let fields = [(Quantity::Temperature.name(), FieldValue::Float(self.to_degree_celsius()))];
+        line_protocol(measurement_name, tags, &fields, ts)
+    }
+}
+
+impl VolumetricWaterContent {
+    /// A single line with the field `water_content` in %.
+    pub fn to_line_protocol(self, measurement_name: &str, tags: Tags<'_>, ts: Option<SystemTime>) -> String {
+        let fields = [(Quantity::WaterContent.name(), FieldValue::Float(self.to_percent()))];
+        line_protocol(measurement_name, tags, &fields, ts)
+    }
+}
+
+impl RelativePermittivity {
+    /// A single line with the field `permittivity`.
+    pub fn to_line_protocol(self, measurement_name: &str, tags: Tags<'_>, ts: Option<SystemTime>) -> String {
+        let fields = [(Quantity::Permittivity.name(), FieldValue::Float(self.to_ratio()))];
+        line_protocol(measurement_name, tags, &fields, ts)
+    }
+}
+
+impl RawCounts {
+    /// A single line with the integer field `raw_counts`.
+    pub fn to_line_protocol(self, measurement_name: &str, tags: Tags<'_>, ts: Option<SystemTime>) -> String {
+        let fields = [(Quantity::RawCounts.name(), FieldValue::Integer(u16::from(self).into()))];
+        line_protocol(measurement_name, tags, &fields, ts)
+    }
+}
+
+impl Measurements {
+    /// A single line with all quantities as fields.
+    pub fn to_line_protocol(&self, measurement_name: &str, tags: Tags<'_>, ts: Option<SystemTime>) -> String {
+        let Measurements {
+            temperature,
+            water_content,
+            permittivity,
+            raw_counts,
+        } = *self;
+        let fields = [
+            (Quantity::Temperature.name(), FieldValue::Float(temperature.to_degree_celsius())),
+            (Quantity::WaterContent.name(), FieldValue::Float(water_content.to_percent())),
+            (Quantity::Permittivity.name(), FieldValue::Float(permittivity.to_ratio())),
+            (Quantity::RawCounts.name(), FieldValue::Integer(u16::from(raw_counts).into())),
+        ];
+        line_protocol(measurement_name, tags, &fields, ts)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl TimestampedMeasurements {
+    /// One line for each available measurement, because the
+    /// measurements of a poll cycle are received at different times.
+    ///
+    /// Each line is terminated by a line break. The result is empty
+    /// if no measurements are available.
+    pub fn to_line_protocol(&self, measurement_name: &str, tags: Tags<'_>) -> String {
+        let TimestampedMeasurements {
+            temperature,
+            water_content,
+            permittivity,
+            raw_counts,
+        } = self;
+        let mut lines = String::new();
+        let mut push_line = |line: String| {
+            lines.push_str(&line);
+            lines.push('\n');
+        };
+        if let Some(Measurement { ts, val }) = temperature {
+            push_line(val.to_line_protocol(measurement_name, tags, Some(*ts)));
+        }
+        if let Some(Measurement { ts, val }) = water_content {
+            push_line(val.to_line_protocol(measurement_name, tags, Some(*ts)));
+        }
+        if let Some(Measurement { ts, val }) = permittivity {
+            push_line(val.to_line_protocol(measurement_name, tags, Some(*ts)));
+        }
+        if let Some(Measurement { ts, val }) = raw_counts {
+            push_line(val.to_line_protocol(measurement_name, tags, Some(*ts)));
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2019-10-16T08:00:00.123Z
+    fn ts() -> SystemTime {
+        UNIX_EPOCH + std::time::Duration::from_millis(1_571_212_800_123)
+    }
+
+    #[test]
+    fn single_quantity() {
+        let temperature = Temperature::from_degree_celsius(21.5);
+        assert_eq!("smt100 temperature=21.5", temperature.to_line_protocol("smt100", &[], None));
+        assert_eq!(
+            "smt100,slave=1 raw_counts=11345i 1571212800123000000",
+            RawCounts::from(11345).to_line_protocol("smt100", &[("slave", "1")], Some(ts()))
+        );
+    }
+
+    #[test]
+    fn escape_names_and_tags() {
+        let water_content = VolumetricWaterContent::from_percent(34.5);
+        assert_eq!(
+            r"soil\ moisture\,raw,field\=1=north\ side water_content=34.5",
+            water_content.to_line_protocol("soil moisture,raw", &[("field=1", "north side")], None)
+        );
+    }
+
+    #[test]
+    fn all_measurements() {
+        let measurements = Measurements {
+            temperature: Temperature::from_degree_celsius(21.5),
+            water_content: VolumetricWaterContent::from_percent(34.5),
+            permittivity: RelativePermittivity::from_ratio(15.25),
+            raw_counts: RawCounts::from(11345),
+        };
+        assert_eq!(
+            "smt100,slave=1 temperature=21.5,water_content=34.5,permittivity=15.25,raw_counts=11345i \
+             1571212800123000000",
+            measurements.to_line_protocol("smt100", &[("slave", "1")], Some(ts()))
+        );
+    }
+
+    #[cfg(feature = "tokio-mock")]
+    #[tokio::test]
+    async fn line_per_measurement_of_poll_cycle() {
+        use crate::{
+            mock::{self, Driver},
+            poller::{PollConfig, Poller},
+            runtime::ManualClock,
+        };
+        use std::sync::Arc;
+
+        let clock = Arc::new(ManualClock::starting_at(ts()));
+        let mut proxy = mock::Proxy::with_clock(clock.clone());
+        proxy.set_temperature(Temperature::from_degree_celsius(21.5));
+        proxy.set_water_content(VolumetricWaterContent::from_percent(34.5));
+        let config = PollConfig {
+            order: vec![Quantity::Temperature, Quantity::WaterContent],
+            ..Default::default()
+        };
+        let mut poller = Poller::new(proxy, config).with_clock(clock);
+        assert_eq!("", poller.measurements().to_line_protocol("smt100", &[]));
+        poller.poll_once().await;
+        assert_eq!(
+            "smt100,slave=1 temperature=21.5 1571212800123000000\n\
+             smt100,slave=1 water_content=34.5 1571212800123000000\n",
+            poller.measurements().to_line_protocol("smt100", &[("slave", "1")])
+        );
+    }
+}
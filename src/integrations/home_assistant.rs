@@ -0,0 +1,253 @@
+//! Zero-config setup of sensors in Home Assistant.
+//!
+//! The [discovery](https://www.home-assistant.io/integrations/mqtt/#mqtt-discovery) messages announce all quantities of a sensor as
+//! entities of a single device. The location of the sensor becomes the
+//! suggested area of the device.
+//!
+//! All entities become unavailable while the availability topic of the
+//! sensor is `offline`, e.g. if the `Watchdog` considers the bus down.
+
+use super::mqtt::{json_string, publish_all, Message, Publisher, TopicConfig};
+
+use crate::registry::SensorMetadata;
+
+use std::io;
+
+#[cfg(feature = "tokio-modbus-rtu")]
+use crate::modbus::BusHealth;
+
+#[cfg(feature = "tokio-modbus-rtu")]
+use tokio::sync::watch;
+
+pub const PAYLOAD_ONLINE: &str = "online";
+pub const PAYLOAD_OFFLINE: &str = "offline";
+
+/// The availability topic of a sensor, e.g. `smt100/1/availability`.
+pub fn availability_topic(config: &TopicConfig, sensor_id: &str) -> String {
+    format!("{}/{}/availability", config.prefix, sensor_id)
+}
+
+/// The retained availability of a sensor.
+pub fn availability_message(config: &TopicConfig, sensor_id: &str, available: bool) -> Message {
+    Message {
+        topic: availability_topic(config, sensor_id),
+        payload: if available { PAYLOAD_ONLINE } else { PAYLOAD_OFFLINE }.to_owned(),
+        retain: true,
+    }
+}
+
+/// Discovery messages for all quantities of a sensor, including its
+/// metadata and availability topic.
+///
+/// Empty if discovery has been disabled.
+pub fn discovery_messages(config: &TopicConfig, sensor_id: &str, metadata: &SensorMetadata) -> Vec<Message> {
+    let SensorMetadata {
+        location,
+        depth,
+        soil_type,
+    } = metadata;
+    let mut device_fields = Vec::new();
+    if let Some(location) = location {
+        device_fields.push(("suggested_area", json_string(location)));
+    }
+    // Home Assistant doesn't support custom device attributes
+    let details: Vec<_> = depth
+        .map(|depth| format!("{} m", depth))
+        .into_iter()
+        .chain(soil_type.clone())
+        .collect();
+    if !details.is_empty() {
+        device_fields.push(("hw_version", json_string(&details.join(", "))));
+    }
+    config.discovery_messages_with(
+        sensor_id,
+        Some(&availability_topic(config, sensor_id)),
+        &device_fields,
+    )
+}
+
+/// Publish the availability of all sensors on a bus whenever the
+/// health that is reported by the `Watchdog` changes.
+///
+/// A degraded bus is still considered available. The returned future
+/// finishes after the watchdog has been dropped or fails if publishing
+/// fails.
+#[cfg(feature = "tokio-modbus-rtu")]
+pub async fn publish_availability<P>(
+    publisher: &P,
+    config: &TopicConfig,
+    sensor_ids: &[&str],
+    mut health: watch::Receiver<BusHealth>,
+) -> io::Result<()>
+where
+    P: Publisher,
+{
+    let mut last_available = None;
+    loop {
+        let available = *health.borrow_and_update() != BusHealth::Down;
+        if last_available != Some(available) {
+            let messages = sensor_ids
+                .iter()
+                .map(|sensor_id| availability_message(config, sensor_id, available))
+                .collect();
+            publish_all(publisher, messages).await?;
+            last_available = Some(available);
+        }
+        if health.changed().await.is_err() {
+            return Ok(());
+        }
+    }
+}
+
+/// Announce a sensor and mark it as available.
+pub async fn announce<P>(
+    publisher: &P,
+    config: &TopicConfig,
+    sensor_id: &str,
+    metadata: &SensorMetadata,
+) -> io::Result<()>
+where
+    P: Publisher,
+{
+    publish_all(publisher, discovery_messages(config, sensor_id, metadata)).await?;
+    publisher.publish(availability_message(config, sensor_id, true)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        poller::{Measurement, TimestampedMeasurements},
+        Temperature,
+    };
+    use futures::future::{self, BoxFuture, FutureExt as _};
+    use std::{
+        sync::Mutex,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<Message>>);
+
+    impl Recorder {
+        fn take(&self) -> Vec<Message> {
+            std::mem::take(&mut *self.0.lock().unwrap())
+        }
+    }
+
+    impl Publisher for Recorder {
+        fn publish(&self, message: Message) -> BoxFuture<'_, io::Result<()>> {
+            self.0.lock().unwrap().push(message);
+            future::ok(()).boxed()
+        }
+    }
+
+    fn metadata() -> SensorMetadata {
+        SensorMetadata {
+            location: Some("Greenhouse \"3\"".to_owned()),
+            depth: Some(0.3),
+            soil_type: Some("sandy loam".to_owned()),
+        }
+    }
+
+    #[test]
+    fn discover_entities_with_metadata() {
+        let config = TopicConfig::default();
+        let messages = discovery_messages(&config, "1", &metadata());
+        assert_eq!(4, messages.len());
+        let temperature = &messages[0];
+        assert_eq!("homeassistant/sensor/smt100_1_temperature/config", temperature.topic);
+        assert!(temperature.retain);
+        assert_eq!(
+            concat!(
+                r#"{"name":"SMT100 1 temperature","unique_id":"smt100_1_temperature","#,
+                r#""state_topic":"smt100/1/temperature","value_template":"{{ value_json.value }}","#,
+                r#""state_class":"measurement","device_class":"temperature","unit_of_measurement":"°C","#,
+                r#""availability_topic":"smt100/1/availability","#,
+                r#""device":{"identifiers":["smt100_1"],"name":"SMT100 1","#,
+                r#""manufacturer":"TRUEBNER","model":"SMT100","#,
+                r#""suggested_area":"Greenhouse \"3\"","hw_version":"0.3 m, sandy loam"}}"#,
+            ),
+            temperature.payload
+        );
+        // The state of the entity
+        let ts = UNIX_EPOCH + Duration::from_millis(1_571_212_800_123);
+        let measurements = TimestampedMeasurements {
+            temperature: Some(Measurement::at(ts, Temperature::from_degree_celsius(21.5))),
+            ..Default::default()
+        };
+        let state = config.measurement_messages("1", &measurements);
+        assert_eq!(
+            vec![Message {
+                topic: "smt100/1/temperature".to_owned(),
+                payload: r#"{"ts":1571212800.123,"value":21.5}"#.to_owned(),
+                retain: false,
+            }],
+            state
+        );
+    }
+
+    #[test]
+    fn discover_entities_without_metadata() {
+        let config = TopicConfig::default();
+        let messages = discovery_messages(&config, "1", &SensorMetadata::default());
+        assert!(messages[0]
+            .payload
+            .ends_with(r#""manufacturer":"TRUEBNER","model":"SMT100"}}"#));
+        let config = TopicConfig {
+            discovery_prefix: None,
+            ..Default::default()
+        };
+        assert!(discovery_messages(&config, "1", &metadata()).is_empty());
+    }
+
+    #[tokio::test]
+    async fn announce_sensor() {
+        let publisher = Recorder::default();
+        let config = TopicConfig::default();
+        announce(&publisher, &config, "1", &metadata()).await.unwrap();
+        let messages = publisher.take();
+        assert_eq!(5, messages.len());
+        assert_eq!(availability_message(&config, "1", true), messages[4]);
+        assert_eq!("smt100/1/availability", messages[4].topic);
+        assert_eq!(PAYLOAD_ONLINE, messages[4].payload);
+        assert!(messages[4].retain);
+    }
+
+    #[cfg(feature = "tokio-modbus-rtu")]
+    #[tokio::test]
+    async fn publish_availability_of_bus() {
+        let publisher = Recorder::default();
+        let config = TopicConfig::default();
+        let (tx, rx) = watch::channel(BusHealth::Healthy);
+        let publish = publish_availability(&publisher, &config, &["1", "2"], rx);
+        let update = async {
+            for health in [BusHealth::Degraded, BusHealth::Down] {
+                tokio::task::yield_now().await;
+                tx.send(health).unwrap();
+            }
+            tokio::task::yield_now().await;
+            drop(tx);
+        };
+        let (res, ()) = tokio::join!(publish, update);
+        res.unwrap();
+        let payloads: Vec<_> = publisher
+            .take()
+            .into_iter()
+            .map(|message| (message.topic, message.payload))
+            .collect();
+        let availability = |sensor_id, payload: &str| {
+            (availability_topic(&config, sensor_id), payload.to_owned())
+        };
+        assert_eq!(
+            vec![
+                availability("1", PAYLOAD_ONLINE),
+                availability("2", PAYLOAD_ONLINE),
+                availability("1", PAYLOAD_OFFLINE),
+                availability("2", PAYLOAD_OFFLINE),
+            ],
+            payloads
+        );
+    }
+}
@@ -0,0 +1,212 @@
+//! Read-only HTTP API for the latest measurements of pollers,
+//! e.g. for dashboards and health checks.
+//!
+//! ```text
+//! GET /sensors                 [{"slave":1},{"slave":2}]
+//! GET /sensors/1/measurements  {"temperature":{"ts":1571212800.123,"value":21.5},...}
+//! GET /sensors/1/events        text/event-stream
+//! ```
+//!
+//! Quantities that have not been measured yet are `null`.
+//!
+//! The event stream is a live feed of server-sent events for browser
+//! dashboards. It starts with the current measurements and then sends
+//! the same JSON as `measurements` events after each poll cycle.
+
+use crate::{poller::TimestampedMeasurements, watch::UnixTimestamp};
+
+use axum::{
+    extract::{Path, State},
+    http::{header, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    routing::get,
+    Router,
+};
+use futures::{stream, Future};
+use std::{collections::BTreeMap, convert::Infallible, fmt, io, sync::Arc, time::SystemTime};
+use tokio::{net::TcpListener, sync::watch};
+
+/// Serves the measurements of sensors that are addressed by their
+/// Modbus slave address.
+#[derive(Clone, Default)]
+pub struct HttpApi {
+    sensors: BTreeMap<u8, watch::Receiver<TimestampedMeasurements>>,
+}
+
+impl HttpApi {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serve the measurements of a `Poller`.
+    ///
+    /// See also: `Poller::subscribe()`
+    pub fn add_sensor(&mut self, slave: u8, measurements: watch::Receiver<TimestampedMeasurements>) {
+        self.sensors.insert(slave, measurements);
+    }
+
+    /// The routes of the API that could be merged into the router
+    /// of an existing application.
+    pub fn router(self) -> Router {
+        Router::new()
+            .route("/sensors", get(list_sensors))
+            .route("/sensors/{slave}/measurements", get(sensor_measurements))
+            .route("/sensors/{slave}/events", get(sensor_events))
+            .with_state(Arc::new(self))
+    }
+
+    /// Serve the API until `shutdown` resolves.
+    pub async fn serve(
+        self,
+        listener: TcpListener,
+        shutdown: impl Future<Output = ()> + Send + 'static,
+    ) -> io::Result<()> {
+        axum::serve(listener, self.router())
+            .with_graceful_shutdown(shutdown)
+            .await
+    }
+}
+
+fn json_response(json: String) -> Response {
+    ([(header::CONTENT_TYPE, "application/json")], json).into_response()
+}
+
+async fn list_sensors(State(api): State<Arc<HttpApi>>) -> Response {
+    let sensors: Vec<_> = api
+        .sensors
+        .keys()
+        .map(|slave| format!("{{\"slave\":{}}}", slave))
+        .collect();
+    json_response(format!("[{}]", sensors.join(",")))
+}
+
+async fn sensor_measurements(State(api): State<Arc<HttpApi>>, Path(slave): Path<u8>) -> Response {
+    match api.sensors.get(&slave) {
+        Some(measurements) => json_response(measurements_json(&measurements.borrow())),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+async fn sensor_events(State(api): State<Arc<HttpApi>>, Path(slave): Path<u8>) -> Response {
+    let mut measurements = match api.sensors.get(&slave) {
+        Some(measurements) => measurements.clone(),
+        None => return StatusCode::NOT_FOUND.into_response(),
+    };
+    // Start with the current measurements
+    measurements.mark_changed();
+    let events = stream::unfold(measurements, |mut measurements| async move {
+        // Finishes after the poller has been dropped
+        measurements.changed().await.ok()?;
+        let json = measurements_json(&measurements.borrow_and_update());
+        let event = Event::default().event("measurements").data(json);
+        Some((Ok::<_, Infallible>(event), measurements))
+    });
+    Sse::new(events).keep_alive(KeepAlive::default()).into_response()
+}
+
+fn measurement_json(measurement: Option<(SystemTime, impl fmt::Display)>) -> String {
+    match measurement {
+        Some((ts, value)) => format!("{{\"ts\":{},\"value\":{}}}", UnixTimestamp(ts), value),
+        None => "null".to_owned(),
+    }
+}
+
+fn measurements_json(measurements: &TimestampedMeasurements) -> String {
+    let TimestampedMeasurements {
+        temperature,
+        water_content,
+        permittivity,
+        raw_counts,
+    } = measurements;
+    format!(
+        "{{\"temperature\":{},\"water_content\":{},\"permittivity\":{},\"raw_counts\":{}}}",
+        measurement_json(temperature.map(|m| (m.ts, m.val.to_degree_celsius()))),
+        measurement_json(water_content.map(|m| (m.ts, m.val.to_percent()))),
+        measurement_json(permittivity.map(|m| (m.ts, m.val.to_ratio()))),
+        measurement_json(raw_counts.map(|m| (m.ts, m.val))),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{poller::Measurement, Temperature};
+    use futures::StreamExt;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn measurements() -> TimestampedMeasurements {
+        let ts = UNIX_EPOCH + Duration::from_millis(1_571_212_800_123);
+        TimestampedMeasurements {
+            temperature: Some(Measurement::at(ts, Temperature::from_degree_celsius(21.5))),
+            ..Default::default()
+        }
+    }
+
+    async fn body(response: Response) -> String {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn serialize_measurements() {
+        assert_eq!(
+            "{\"temperature\":{\"ts\":1571212800.123,\"value\":21.5},\
+             \"water_content\":null,\"permittivity\":null,\"raw_counts\":null}",
+            measurements_json(&measurements())
+        );
+    }
+
+    #[tokio::test]
+    async fn get_measurements() {
+        let (_tx, rx) = watch::channel(measurements());
+        let mut api = HttpApi::new();
+        api.add_sensor(2, rx.clone());
+        api.add_sensor(1, rx);
+        let api = Arc::new(api);
+        assert_eq!(
+            "[{\"slave\":1},{\"slave\":2}]",
+            body(list_sensors(State(Arc::clone(&api))).await).await
+        );
+        let response = sensor_measurements(State(Arc::clone(&api)), Path(1)).await;
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(
+            Some("application/json"),
+            response.headers().get(header::CONTENT_TYPE).and_then(|value| value.to_str().ok())
+        );
+        assert_eq!(measurements_json(&measurements()), body(response).await);
+        let response = sensor_measurements(State(api), Path(3)).await;
+        assert_eq!(StatusCode::NOT_FOUND, response.status());
+    }
+
+    #[tokio::test]
+    async fn stream_measurement_events() {
+        let (tx, rx) = watch::channel(TimestampedMeasurements::default());
+        let mut api = HttpApi::new();
+        api.add_sensor(1, rx);
+        let api = Arc::new(api);
+        let response = sensor_events(State(Arc::clone(&api)), Path(3)).await;
+        assert_eq!(StatusCode::NOT_FOUND, response.status());
+        let response = sensor_events(State(api), Path(1)).await;
+        assert_eq!(
+            Some("text/event-stream"),
+            response.headers().get(header::CONTENT_TYPE).and_then(|value| value.to_str().ok())
+        );
+        let mut events = response.into_body().into_data_stream();
+        let event = |measurements: &TimestampedMeasurements| -> String {
+            format!("event: measurements\ndata: {}\n\n", measurements_json(measurements))
+        };
+        // Starts with the current measurements
+        let first = events.next().await.unwrap().unwrap();
+        assert_eq!(event(&TimestampedMeasurements::default()).as_bytes(), &first[..]);
+        tx.send(measurements()).unwrap();
+        let second = events.next().await.unwrap().unwrap();
+        assert_eq!(event(&measurements()).as_bytes(), &second[..]);
+        // Finishes after the poller has been dropped
+        drop(tx);
+        assert!(events.next().await.is_none());
+    }
+}
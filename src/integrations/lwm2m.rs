@@ -0,0 +1,220 @@
+//! Mapping of measurements to LwM2M resources of IPSO smart objects,
+//! e.g. for CoAP servers on constrained gateways.
+//!
+//! The module doesn't depend on a particular CoAP server. Resources are
+//! addressed by their URI path and the values are encoded as plain text.
+//!
+//! | Quantity      | Object              | Instance |
+//! |---------------|---------------------|----------|
+//! | Temperature   | 3303 Temperature    | `n`      |
+//! | Water content | 3304 Humidity       | `n`      |
+//! | Permittivity  | 3300 Generic Sensor | `2n`     |
+//! | Raw counts    | 3300 Generic Sensor | `2n + 1` |
+//!
+//! The sensor value is available at resource 5700, e.g. `/3303/0/5700`
+//! for the temperature of the first sensor. The instance `n` of each
+//! sensor should be unique among all sensors, e.g. the index of the
+//! sensor on the bus. The generic sensor objects of each sensor are
+//! distinguished by their application type.
+
+use crate::{
+    core::{Quantity, Temperature, VolumetricWaterContent},
+    poller::TimestampedMeasurements,
+};
+
+use std::{
+    fmt,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+pub const OBJECT_GENERIC_SENSOR: u16 = 3300;
+pub const OBJECT_TEMPERATURE: u16 = 3303;
+pub const OBJECT_HUMIDITY: u16 = 3304;
+
+pub const RESOURCE_MIN_RANGE_VALUE: u16 = 5603;
+pub const RESOURCE_MAX_RANGE_VALUE: u16 = 5604;
+pub const RESOURCE_SENSOR_VALUE: u16 = 5700;
+pub const RESOURCE_SENSOR_UNITS: u16 = 5701;
+pub const RESOURCE_APPLICATION_TYPE: u16 = 5750;
+pub const RESOURCE_TIMESTAMP: u16 = 5518;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResourceValue {
+    Float(f64),
+    Integer(i64),
+    String(&'static str),
+    Time(SystemTime),
+}
+
+/// The plain text encoding of the value.
+impl fmt::Display for ResourceValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResourceValue::Float(value) => write!(f, "{}", value),
+            ResourceValue::Integer(value) => write!(f, "{}", value),
+            ResourceValue::String(value) => f.write_str(value),
+            ResourceValue::Time(ts) => {
+                let secs = ts.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+                write!(f, "{}", secs)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Resource {
+    pub object_id: u16,
+    pub instance_id: u16,
+    pub resource_id: u16,
+    pub value: ResourceValue,
+}
+
+impl Resource {
+    /// The URI path, e.g. `/3303/0/5700`.
+    pub fn path(&self) -> String {
+        format!("/{}/{}/{}", self.object_id, self.instance_id, self.resource_id)
+    }
+}
+
+/// The object and instance of a quantity.
+pub fn object_instance(quantity: Quantity, sensor_instance: u16) -> (u16, u16) {
+    match quantity {
+        Quantity::Temperature => (OBJECT_TEMPERATURE, sensor_instance),
+        Quantity::WaterContent => (OBJECT_HUMIDITY, sensor_instance),
+        Quantity::Permittivity => (OBJECT_GENERIC_SENSOR, 2 * sensor_instance),
+        Quantity::RawCounts => (OBJECT_GENERIC_SENSOR, 2 * sensor_instance + 1),
+    }
+}
+
+fn static_resources(quantity: Quantity) -> Vec<(u16, ResourceValue)> {
+    match quantity {
+        Quantity::Temperature => vec![
+            (RESOURCE_SENSOR_UNITS, ResourceValue::String("Cel")),
+            (RESOURCE_MIN_RANGE_VALUE, ResourceValue::Float(Temperature::min_degree_celsius())),
+            (RESOURCE_MAX_RANGE_VALUE, ResourceValue::Float(Temperature::max_degree_celsius())),
+        ],
+        Quantity::WaterContent => vec![
+            (RESOURCE_SENSOR_UNITS, ResourceValue::String("%")),
+            (RESOURCE_MIN_RANGE_VALUE, ResourceValue::Float(VolumetricWaterContent::min_percent())),
+            (RESOURCE_MAX_RANGE_VALUE, ResourceValue::Float(VolumetricWaterContent::max_percent())),
+        ],
+        Quantity::Permittivity => vec![(RESOURCE_APPLICATION_TYPE, ResourceValue::String("Permittivity"))],
+        Quantity::RawCounts => vec![(RESOURCE_APPLICATION_TYPE, ResourceValue::String("Raw counts"))],
+    }
+}
+
+/// All resources of a sensor, including the current sensor values and
+/// their timestamps.
+///
+/// The sensor values of quantities that have not been measured yet
+/// are omitted.
+pub fn resources(sensor_instance: u16, measurements: &TimestampedMeasurements) -> Vec<Resource> {
+    let TimestampedMeasurements {
+        temperature,
+        water_content,
+        permittivity,
+        raw_counts,
+    } = *measurements;
+    let values = [
+        (
+            Quantity::Temperature,
+            temperature.map(|m| (m.ts, ResourceValue::Float(m.val.to_degree_celsius()))),
+        ),
+        (
+            Quantity::WaterContent,
+            water_content.map(|m| (m.ts, ResourceValue::Float(m.val.to_percent()))),
+        ),
+        (
+            Quantity::Permittivity,
+            permittivity.map(|m| (m.ts, ResourceValue::Float(m.val.to_ratio()))),
+        ),
+        (
+            Quantity::RawCounts,
+            raw_counts.map(|m| (m.ts, ResourceValue::Integer(u16::from(m.val).into()))),
+        ),
+    ];
+    let mut resources = Vec::new();
+    for (quantity, value) in values.iter().cloned() {
+        let (object_id, instance_id) = object_instance(quantity, sensor_instance);
+        let resource = |resource_id, value| Resource {
+            object_id,
+            instance_id,
+            resource_id,
+            value,
+        };
+        if let Some((ts, value)) = value {
+            resources.push(resource(RESOURCE_SENSOR_VALUE, value));
+            resources.push(resource(RESOURCE_TIMESTAMP, ResourceValue::Time(ts)));
+        }
+        for (resource_id, value) in static_resources(quantity) {
+            resources.push(resource(resource_id, value));
+        }
+    }
+    resources
+}
+
+/// Find the resource of a CoAP request by its URI path.
+pub fn find_resource<'a>(resources: &'a [Resource], path: &str) -> Option<&'a Resource> {
+    let path = path.trim_end_matches('/');
+    resources.iter().find(|resource| resource.path() == path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{core::RawCounts, poller::Measurement};
+    use std::time::Duration;
+
+    fn measurements() -> TimestampedMeasurements {
+        let ts = UNIX_EPOCH + Duration::from_millis(1_571_212_800_123);
+        TimestampedMeasurements {
+            temperature: Some(Measurement::at(ts, Temperature::from_degree_celsius(21.5))),
+            raw_counts: Some(Measurement::at(ts, RawCounts::from(11_345))),
+            ..Default::default()
+        }
+    }
+
+    fn plain_text(resources: &[Resource], path: &str) -> Option<String> {
+        find_resource(resources, path).map(|resource| resource.value.to_string())
+    }
+
+    #[test]
+    fn encode_resources_as_plain_text() {
+        let resources = resources(1, &measurements());
+        let text = |path| plain_text(&resources, path);
+        assert_eq!(Some("21.5".to_owned()), text("/3303/1/5700"));
+        assert_eq!(Some("1571212800".to_owned()), text("/3303/1/5518"));
+        assert_eq!(Some("Cel".to_owned()), text("/3303/1/5701"));
+        assert_eq!(
+            Some(Temperature::min_degree_celsius().to_string()),
+            text("/3303/1/5603")
+        );
+        assert_eq!(Some("11345".to_owned()), text("/3300/3/5700/"));
+        assert_eq!(Some("Raw counts".to_owned()), text("/3300/3/5750"));
+        // Not measured yet
+        assert_eq!(None, text("/3304/1/5700"));
+        assert_eq!(Some("%".to_owned()), text("/3304/1/5701"));
+        assert_eq!(None, text("/3300/2/5700"));
+        assert_eq!(Some("Permittivity".to_owned()), text("/3300/2/5750"));
+        // Other sensors
+        assert_eq!(None, text("/3303/0/5700"));
+    }
+
+    #[test]
+    fn object_instances_of_sensors() {
+        assert_eq!((OBJECT_TEMPERATURE, 0), object_instance(Quantity::Temperature, 0));
+        assert_eq!((OBJECT_HUMIDITY, 2), object_instance(Quantity::WaterContent, 2));
+        assert_eq!((OBJECT_GENERIC_SENSOR, 4), object_instance(Quantity::Permittivity, 2));
+        assert_eq!((OBJECT_GENERIC_SENSOR, 5), object_instance(Quantity::RawCounts, 2));
+        let resource = Resource {
+            object_id: OBJECT_TEMPERATURE,
+            instance_id: 0,
+            resource_id: RESOURCE_SENSOR_VALUE,
+            value: ResourceValue::Float(21.5),
+        };
+        assert_eq!("/3303/0/5700", resource.path());
+        // Only the static resources without measurements
+        assert_eq!(8, resources(0, &TimestampedMeasurements::default()).len());
+    }
+}
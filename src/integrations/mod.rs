@@ -0,0 +1,20 @@
+//! Integrations with downstream systems.
+
+// Only used by some integrations
+#[allow(unused_imports)]
+use super::*;
+
+#[cfg(feature = "http-api")]
+pub mod http;
+
+#[cfg(feature = "lwm2m")]
+pub mod lwm2m;
+
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+
+#[cfg(feature = "mqtt")]
+pub mod home_assistant;
+
+#[cfg(feature = "opcua")]
+pub mod opcua;
@@ -0,0 +1,437 @@
+//! Publishing of measurements to an MQTT broker.
+//!
+//! The module doesn't depend on a particular MQTT client. Implement
+//! `Publisher` for the client of your choice, e.g. _rumqttc_.
+//!
+//! Each quantity is published as JSON on a separate topic, e.g.
+//!
+//! ```text
+//! smt100/1/temperature {"ts":1571212800.123,"value":21.5}
+//! ```
+//!
+//! Optional [Home Assistant](https://www.home-assistant.io/integrations/mqtt/#mqtt-discovery)
+//! discovery messages announce all quantities of a sensor as entities.
+
+use super::*;
+
+use crate::{poller::TimestampedMeasurements, watch::UnixTimestamp};
+
+use futures::{future::BoxFuture, Stream, StreamExt};
+use std::{
+    fmt::{self, Write as _},
+    io,
+    time::SystemTime,
+};
+use tokio::sync::watch;
+
+/// Publishes messages through an MQTT client.
+pub trait Publisher {
+    fn publish(&self, message: Message) -> BoxFuture<'_, io::Result<()>>;
+}
+
+/// A single MQTT message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub topic: String,
+    pub payload: String,
+
+    /// Discovery messages are retained by the broker.
+    pub retain: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TopicConfig {
+    /// The first level of all measurement topics.
+    pub prefix: String,
+
+    /// The discovery prefix of Home Assistant or `None` to disable
+    /// discovery messages.
+    pub discovery_prefix: Option<String>,
+}
+
+impl Default for TopicConfig {
+    fn default() -> Self {
+        Self {
+            prefix: "smt100".to_owned(),
+            discovery_prefix: Some("homeassistant".to_owned()),
+        }
+    }
+}
+
+/// Quotes and escapes a string value.
+pub(super) fn json_string(value: &str) -> String {
+    let mut json = String::with_capacity(value.len() + 2);
+    json.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            c if c.is_control() => {
+                let _ = write!(json, "\\u{:04x}", u32::from(c));
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+impl TopicConfig {
+    /// The topic of a quantity, e.g. `smt100/1/temperature`.
+    ///
+    /// The `sensor_id` should be unique among all sensors that are
+    /// published with the same prefix, e.g. the Modbus slave address.
+    pub fn topic(&self, sensor_id: &str, quantity: Quantity) -> String {
+        format!("{}/{}/{}", self.prefix, sensor_id, quantity.name())
+    }
+
+    fn message(&self, sensor_id: &str, quantity: Quantity, ts: SystemTime, value: impl fmt::Display) -> Message {
+        Message {
+            topic: self.topic(sensor_id, quantity),
+            payload: format!("{{\"ts\":{},\"value\":{}}}", UnixTimestamp(ts), value),
+            retain: false,
+        }
+    }
+
+    /// Messages for all available measurements.
+    pub fn measurement_messages(&self, sensor_id: &str, measurements: &TimestampedMeasurements) -> Vec<Message> {
+        let TimestampedMeasurements {
+            temperature,
+            water_content,
+            permittivity,
+            raw_counts,
+        } = measurements;
+        let mut messages = Vec::with_capacity(Quantity::ALL.len());
+        if let Some(m) = temperature {
+            messages.push(self.message(sensor_id, Quantity::Temperature, m.ts, m.val.to_degree_celsius()));
+        }
+        if let Some(m) = water_content {
+            messages.push(self.message(sensor_id, Quantity::WaterContent, m.ts, m.val.to_percent()));
+        }
+        if let Some(m) = permittivity {
+            messages.push(self.message(sensor_id, Quantity::Permittivity, m.ts, m.val.to_ratio()));
+        }
+        if let Some(m) = raw_counts {
+            messages.push(self.message(sensor_id, Quantity::RawCounts, m.ts, m.val));
+        }
+        messages
+    }
+
+    /// Home Assistant discovery messages for all quantities.
+    ///
+    /// Empty if discovery has been disabled.
+    ///
+    /// See also: `home_assistant::discovery_messages()`
+    pub fn discovery_messages(&self, sensor_id: &str) -> Vec<Message> {
+        self.discovery_messages_with(sensor_id, None, &[])
+    }
+
+    /// Discovery messages with an optional availability topic and
+    /// additional fields of the device as pairs of names and JSON values.
+    pub(super) fn discovery_messages_with(
+        &self,
+        sensor_id: &str,
+        availability_topic: Option<&str>,
+        device_fields: &[(&str, String)],
+    ) -> Vec<Message> {
+        let discovery_prefix = match &self.discovery_prefix {
+            Some(discovery_prefix) => discovery_prefix,
+            None => return Vec::new(),
+        };
+        let device_id = format!("smt100_{}", sensor_id);
+        Quantity::ALL
+            .iter()
+            .map(|&quantity| {
+                let object_id = format!("{}_{}", device_id, quantity.name());
+                let mut payload = format!(
+                    "{{\"name\":{},\"unique_id\":{},\"state_topic\":{},\
+                     \"value_template\":\"{{{{ value_json.value }}}}\",\"state_class\":\"measurement\"",
+                    json_string(&format!("SMT100 {} {}", sensor_id, quantity.name())),
+                    json_string(&object_id),
+                    json_string(&self.topic(sensor_id, quantity)),
+                );
+                match quantity {
+                    Quantity::Temperature => {
+                        payload.push_str(",\"device_class\":\"temperature\",\"unit_of_measurement\":\"°C\"");
+                    }
+                    Quantity::WaterContent => {
+                        payload.push_str(",\"device_class\":\"moisture\",\"unit_of_measurement\":\"%\"");
+                    }
+                    Quantity::Permittivity | Quantity::RawCounts => (),
+                }
+                if let Some(availability_topic) = availability_topic {
+                    let _ = write!(payload, ",\"availability_topic\":{}", json_string(availability_topic));
+                }
+                let _ = write!(
+                    payload,
+                    ",\"device\":{{\"identifiers\":[{}],\"name\":{},\
+                     \"manufacturer\":\"TRUEBNER\",\"model\":\"SMT100\"",
+                    json_string(&device_id),
+                    json_string(&format!("SMT100 {}", sensor_id)),
+                );
+                for (name, value) in device_fields {
+                    let _ = write!(payload, ",{}:{}", json_string(name), value);
+                }
+                payload.push_str("}}");
+                Message {
+                    topic: format!("{}/sensor/{}/config", discovery_prefix, object_id),
+                    payload,
+                    retain: true,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Publish all messages one after another.
+pub async fn publish_all<P>(publisher: &P, messages: Vec<Message>) -> io::Result<()>
+where
+    P: Publisher,
+{
+    for message in messages {
+        publisher.publish(message).await?;
+    }
+    Ok(())
+}
+
+/// Publish the discovery messages and then the measurements of
+/// a `Poller` after each poll cycle.
+///
+/// The returned future finishes after the poller has been dropped
+/// or fails if publishing fails.
+pub async fn publish_poller<P>(
+    publisher: &P,
+    config: &TopicConfig,
+    sensor_id: &str,
+    mut subscription: watch::Receiver<TimestampedMeasurements>,
+) -> io::Result<()>
+where
+    P: Publisher,
+{
+    publish_all(publisher, config.discovery_messages(sensor_id)).await?;
+    while subscription.changed().await.is_ok() {
+        let measurements = *subscription.borrow_and_update();
+        publish_all(publisher, config.measurement_messages(sensor_id, &measurements)).await?;
+    }
+    Ok(())
+}
+
+/// Publish the discovery messages and then the measurements of
+/// a `poller::measurement_stream()`.
+///
+/// Failed poll cycles are logged and skipped. The returned future
+/// finishes at the end of the stream or fails if publishing fails.
+pub async fn publish_stream<P, S>(
+    publisher: &P,
+    config: &TopicConfig,
+    sensor_id: &str,
+    measurements: S,
+) -> io::Result<()>
+where
+    P: Publisher,
+    S: Stream<Item = Result<TimestampedMeasurements>>,
+{
+    publish_all(publisher, config.discovery_messages(sensor_id)).await?;
+    futures::pin_mut!(measurements);
+    while let Some(res) = measurements.next().await {
+        match res {
+            Ok(measurements) => {
+                publish_all(publisher, config.measurement_messages(sensor_id, &measurements)).await?;
+            }
+            Err(err) => {
+                log::warn!("Failed to measure: {}", err);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        poller::Measurement,
+        RawCounts, RelativePermittivity, Temperature, TimeoutError, VolumetricWaterContent,
+    };
+    use futures::{
+        future::{self, FutureExt as _},
+        stream,
+    };
+    use std::{
+        sync::Mutex,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<Message>>);
+
+    impl Recorder {
+        fn take(&self) -> Vec<Message> {
+            std::mem::take(&mut *self.0.lock().unwrap())
+        }
+    }
+
+    impl Publisher for Recorder {
+        fn publish(&self, message: Message) -> BoxFuture<'_, io::Result<()>> {
+            self.0.lock().unwrap().push(message);
+            future::ok(()).boxed()
+        }
+    }
+
+    /// The broker is unreachable.
+    struct Offline;
+
+    impl Publisher for Offline {
+        fn publish(&self, _message: Message) -> BoxFuture<'_, io::Result<()>> {
+            future::err(io::ErrorKind::NotConnected.into()).boxed()
+        }
+    }
+
+    fn measurements(degree_celsius: f64) -> TimestampedMeasurements {
+        let ts = UNIX_EPOCH + Duration::from_millis(1_571_212_800_123);
+        TimestampedMeasurements {
+            temperature: Some(Measurement::at(ts, Temperature::from_degree_celsius(degree_celsius))),
+            water_content: Some(Measurement::at(ts, VolumetricWaterContent::from_percent(34.5))),
+            permittivity: None,
+            raw_counts: Some(Measurement::at(ts + Duration::from_millis(5), RawCounts::from(11_345))),
+        }
+    }
+
+    fn topics(messages: &[Message]) -> Vec<&str> {
+        messages.iter().map(|message| message.topic.as_str()).collect()
+    }
+
+    #[test]
+    fn topics_of_quantities() {
+        let config = TopicConfig::default();
+        assert_eq!("smt100/1/temperature", config.topic("1", Quantity::Temperature));
+        assert_eq!("smt100/1/water_content", config.topic("1", Quantity::WaterContent));
+        let config = TopicConfig {
+            prefix: "garden/soil".to_owned(),
+            ..Default::default()
+        };
+        assert_eq!("garden/soil/north/raw_counts", config.topic("north", Quantity::RawCounts));
+    }
+
+    #[test]
+    fn payloads_of_available_measurements() {
+        let config = TopicConfig::default();
+        let mut measurements = measurements(21.5);
+        let permittivity = RelativePermittivity::from_ratio(15.25);
+        measurements.permittivity = Some(Measurement::at(UNIX_EPOCH, permittivity));
+        let messages = config.measurement_messages("1", &measurements);
+        let payloads: Vec<_> = messages.iter().map(|message| message.payload.as_str()).collect();
+        assert_eq!(
+            vec![
+                r#"{"ts":1571212800.123,"value":21.5}"#,
+                r#"{"ts":1571212800.123,"value":34.5}"#,
+                r#"{"ts":0.000,"value":15.25}"#,
+                r#"{"ts":1571212800.128,"value":11345}"#,
+            ],
+            payloads
+        );
+        assert!(messages.iter().all(|message| !message.retain));
+        assert!(config
+            .measurement_messages("1", &TimestampedMeasurements::default())
+            .is_empty());
+    }
+
+    #[test]
+    fn discovery_messages_of_all_quantities() {
+        let config = TopicConfig::default();
+        let messages = config.discovery_messages("1");
+        assert_eq!(
+            vec![
+                "homeassistant/sensor/smt100_1_temperature/config",
+                "homeassistant/sensor/smt100_1_water_content/config",
+                "homeassistant/sensor/smt100_1_permittivity/config",
+                "homeassistant/sensor/smt100_1_raw_counts/config",
+            ],
+            topics(&messages)
+        );
+        assert!(messages.iter().all(|message| message.retain));
+        assert_eq!(
+            concat!(
+                r#"{"name":"SMT100 1 water_content","unique_id":"smt100_1_water_content","#,
+                r#""state_topic":"smt100/1/water_content","value_template":"{{ value_json.value }}","#,
+                r#""state_class":"measurement","device_class":"moisture","unit_of_measurement":"%","#,
+                r#""device":{"identifiers":["smt100_1"],"name":"SMT100 1","#,
+                r#""manufacturer":"TRUEBNER","model":"SMT100"}}"#,
+            ),
+            messages[1].payload
+        );
+        let config = TopicConfig {
+            discovery_prefix: None,
+            ..Default::default()
+        };
+        assert!(config.discovery_messages("1").is_empty());
+    }
+
+    #[test]
+    fn escape_json_strings() {
+        assert_eq!(r#""a \"b\" \\ c\u000a""#, json_string("a \"b\" \\ c\n"));
+    }
+
+    #[tokio::test]
+    async fn publish_poller_until_dropped() {
+        let publisher = Recorder::default();
+        let config = TopicConfig::default();
+        let (tx, rx) = watch::channel(TimestampedMeasurements::default());
+        let publish = publish_poller(&publisher, &config, "1", rx);
+        let update = async {
+            for degree_celsius in [21.5, 22.0] {
+                tokio::task::yield_now().await;
+                tx.send(measurements(degree_celsius)).unwrap();
+            }
+            tokio::task::yield_now().await;
+            drop(tx);
+        };
+        let (res, ()) = futures::join!(publish, update);
+        res.unwrap();
+        let messages = publisher.take();
+        // 4 discovery messages and 3 measurements per poll cycle
+        assert_eq!(10, messages.len());
+        assert!(messages[..4].iter().all(|message| message.retain));
+        assert_eq!(
+            vec!["smt100/1/temperature", "smt100/1/water_content", "smt100/1/raw_counts"],
+            topics(&messages[4..7])
+        );
+        assert_eq!(r#"{"ts":1571212800.123,"value":21.5}"#, messages[4].payload);
+        assert_eq!(r#"{"ts":1571212800.123,"value":22}"#, messages[7].payload);
+    }
+
+    #[tokio::test]
+    async fn publish_stream_skips_failed_cycles() {
+        let publisher = Recorder::default();
+        let config = TopicConfig {
+            discovery_prefix: None,
+            ..Default::default()
+        };
+        let cycles = stream::iter(vec![
+            Ok(measurements(21.5)),
+            Err(TimeoutError::default().into()),
+            Ok(measurements(22.0)),
+        ]);
+        publish_stream(&publisher, &config, "1", cycles).await.unwrap();
+        let messages = publisher.take();
+        assert_eq!(6, messages.len());
+        assert_eq!(r#"{"ts":1571212800.123,"value":21.5}"#, messages[0].payload);
+        assert_eq!(r#"{"ts":1571212800.123,"value":22}"#, messages[3].payload);
+    }
+
+    #[tokio::test]
+    async fn abort_if_publishing_fails() {
+        let config = TopicConfig::default();
+        let (_tx, rx) = watch::channel(TimestampedMeasurements::default());
+        let err = publish_poller(&Offline, &config, "1", rx).await.unwrap_err();
+        assert_eq!(io::ErrorKind::NotConnected, err.kind());
+        let cycles = stream::iter(vec![Ok(measurements(21.5))]);
+        let config = TopicConfig {
+            discovery_prefix: None,
+            ..Default::default()
+        };
+        let err = publish_stream(&Offline, &config, "1", cycles).await.unwrap_err();
+        assert_eq!(io::ErrorKind::NotConnected, err.kind());
+    }
+}
@@ -0,0 +1,355 @@
+//! Exposure of measurements as OPC UA variables.
+//!
+//! The module doesn't depend on a particular OPC UA server. Implement
+//! `AddressSpace` for the address space of the server of your choice,
+//! e.g. _async-opcua_.
+//!
+//! Each sensor is represented by a folder with a variable per quantity
+//! that are identified by string node ids, e.g.
+//!
+//! ```text
+//! SMT100                      ns=2;s=smt100
+//! └── 1                       ns=2;s=smt100/1
+//!     ├── Temperature         ns=2;s=smt100/1/temperature
+//!     ├── WaterContent        ns=2;s=smt100/1/water_content
+//!     ├── Permittivity        ns=2;s=smt100/1/permittivity
+//!     └── RawCounts           ns=2;s=smt100/1/raw_counts
+//! ```
+
+use super::*;
+
+use crate::poller::{Measurement, TimestampedMeasurements};
+
+use std::{
+    sync::{Mutex, PoisonError},
+    time::SystemTime,
+};
+use tokio::sync::watch;
+
+/// A node id with a string identifier.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct NodeId {
+    pub namespace: u16,
+    pub identifier: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataType {
+    Double,
+    UInt16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Variant {
+    Double(f64),
+    UInt16(u16),
+}
+
+/// The value of a variable.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DataValue {
+    /// A measured value with the time when it has been received.
+    Good { value: Variant, source_timestamp: SystemTime },
+
+    /// No value has been measured yet (`BadWaitingForInitialData`).
+    WaitingForInitialData,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VariableDefinition {
+    pub node_id: NodeId,
+    pub parent_id: NodeId,
+    pub browse_name: &'static str,
+    pub data_type: DataType,
+
+    /// The engineering unit or `None` for dimensionless quantities.
+    pub unit: Option<&'static str>,
+}
+
+/// The address space of an OPC UA server.
+pub trait AddressSpace {
+    /// Add a folder below the parent or below the objects folder
+    /// if `parent_id` is `None`.
+    fn add_folder(&mut self, node_id: &NodeId, browse_name: &str, parent_id: Option<&NodeId>);
+
+    fn add_variable(&mut self, definition: &VariableDefinition);
+
+    fn set_value(&mut self, node_id: &NodeId, value: DataValue);
+}
+
+fn browse_name(quantity: Quantity) -> &'static str {
+    match quantity {
+        Quantity::Temperature => "Temperature",
+        Quantity::WaterContent => "WaterContent",
+        Quantity::Permittivity => "Permittivity",
+        Quantity::RawCounts => "RawCounts",
+    }
+}
+
+/// Maps the measurements of sensors to nodes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeMapping {
+    /// The namespace index of all nodes.
+    pub namespace: u16,
+
+    /// The identifier of the root folder and the prefix of all
+    /// other identifiers.
+    pub root: String,
+}
+
+impl Default for NodeMapping {
+    fn default() -> Self {
+        Self {
+            namespace: 2,
+            root: "smt100".to_owned(),
+        }
+    }
+}
+
+impl NodeMapping {
+    fn node_id(&self, identifier: String) -> NodeId {
+        NodeId {
+            namespace: self.namespace,
+            identifier,
+        }
+    }
+
+    pub fn root_id(&self) -> NodeId {
+        self.node_id(self.root.clone())
+    }
+
+    /// The folder of a sensor.
+    ///
+    /// The `sensor_id` should be unique among all sensors in the
+    /// same address space, e.g. the Modbus slave address.
+    pub fn sensor_id(&self, sensor_id: &str) -> NodeId {
+        self.node_id(format!("{}/{}", self.root, sensor_id))
+    }
+
+    pub fn variable_id(&self, sensor_id: &str, quantity: Quantity) -> NodeId {
+        self.node_id(format!("{}/{}/{}", self.root, sensor_id, quantity.name()))
+    }
+
+    /// Add the root folder that contains all sensors.
+    pub fn add_root<A: AddressSpace + ?Sized>(&self, address_space: &mut A) {
+        address_space.add_folder(&self.root_id(), "SMT100", None);
+    }
+
+    /// Add the folder and the variables of a sensor below the root
+    /// folder.
+    ///
+    /// All variables are waiting for initial data.
+    pub fn add_sensor<A: AddressSpace + ?Sized>(&self, address_space: &mut A, sensor_id: &str) {
+        let parent_id = self.sensor_id(sensor_id);
+        address_space.add_folder(&parent_id, sensor_id, Some(&self.root_id()));
+        for &quantity in &Quantity::ALL {
+            let (data_type, unit) = match quantity {
+                Quantity::Temperature => (DataType::Double, Some("°C")),
+                Quantity::WaterContent => (DataType::Double, Some("%")),
+                Quantity::Permittivity => (DataType::Double, None),
+                Quantity::RawCounts => (DataType::UInt16, None),
+            };
+            let node_id = self.variable_id(sensor_id, quantity);
+            address_space.add_variable(&VariableDefinition {
+                node_id: node_id.clone(),
+                parent_id: parent_id.clone(),
+                browse_name: browse_name(quantity),
+                data_type,
+                unit,
+            });
+            address_space.set_value(&node_id, DataValue::WaitingForInitialData);
+        }
+    }
+
+    /// Update the variables of a sensor with all available measurements.
+    pub fn update<A: AddressSpace + ?Sized>(
+        &self,
+        address_space: &mut A,
+        sensor_id: &str,
+        measurements: &TimestampedMeasurements,
+    ) {
+        fn good<T>(measurement: Measurement<T>, variant: impl FnOnce(T) -> Variant) -> DataValue {
+            DataValue::Good {
+                value: variant(measurement.val),
+                source_timestamp: measurement.ts,
+            }
+        }
+        let TimestampedMeasurements {
+            temperature,
+            water_content,
+            permittivity,
+            raw_counts,
+        } = *measurements;
+        let values = [
+            (
+                Quantity::Temperature,
+                temperature.map(|m| good(m, |val| Variant::Double(val.to_degree_celsius()))),
+            ),
+            (
+                Quantity::WaterContent,
+                water_content.map(|m| good(m, |val| Variant::Double(val.to_percent()))),
+            ),
+            (
+                Quantity::Permittivity,
+                permittivity.map(|m| good(m, |val| Variant::Double(val.to_ratio()))),
+            ),
+            (
+                Quantity::RawCounts,
+                raw_counts.map(|m| good(m, |val| Variant::UInt16(val.into()))),
+            ),
+        ];
+        for (quantity, value) in values.iter() {
+            if let Some(value) = value {
+                address_space.set_value(&self.variable_id(sensor_id, *quantity), *value);
+            }
+        }
+    }
+}
+
+/// Update the variables of a sensor with the measurements of
+/// a `Poller` after each poll cycle.
+///
+/// The sensor must have been added before. The returned future
+/// finishes after the poller has been dropped.
+pub async fn update_from_poller<A>(
+    address_space: &Mutex<A>,
+    mapping: &NodeMapping,
+    sensor_id: &str,
+    mut subscription: watch::Receiver<TimestampedMeasurements>,
+) where
+    A: AddressSpace,
+{
+    while subscription.changed().await.is_ok() {
+        let measurements = *subscription.borrow_and_update();
+        let mut address_space = address_space.lock().unwrap_or_else(PoisonError::into_inner);
+        mapping.update(&mut *address_space, sensor_id, &measurements);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{RawCounts, Temperature};
+    use std::{
+        collections::HashMap,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    #[derive(Default)]
+    struct Nodes {
+        folders: Vec<(NodeId, String, Option<NodeId>)>,
+        variables: Vec<VariableDefinition>,
+        values: HashMap<NodeId, DataValue>,
+    }
+
+    impl AddressSpace for Nodes {
+        fn add_folder(&mut self, node_id: &NodeId, browse_name: &str, parent_id: Option<&NodeId>) {
+            self.folders
+                .push((node_id.clone(), browse_name.to_owned(), parent_id.cloned()));
+        }
+
+        fn add_variable(&mut self, definition: &VariableDefinition) {
+            self.variables.push(definition.clone());
+        }
+
+        fn set_value(&mut self, node_id: &NodeId, value: DataValue) {
+            self.values.insert(node_id.clone(), value);
+        }
+    }
+
+    fn node_id(identifier: &str) -> NodeId {
+        NodeId {
+            namespace: 2,
+            identifier: identifier.to_owned(),
+        }
+    }
+
+    fn measurements() -> TimestampedMeasurements {
+        let ts = UNIX_EPOCH + Duration::from_secs(1_571_212_800);
+        TimestampedMeasurements {
+            temperature: Some(Measurement::at(ts, Temperature::from_degree_celsius(21.5))),
+            raw_counts: Some(Measurement::at(ts, RawCounts::from(11_345))),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn add_sensor_nodes() {
+        let mapping = NodeMapping::default();
+        let mut nodes = Nodes::default();
+        mapping.add_root(&mut nodes);
+        mapping.add_sensor(&mut nodes, "1");
+        assert_eq!(
+            vec![
+                (node_id("smt100"), "SMT100".to_owned(), None),
+                (node_id("smt100/1"), "1".to_owned(), Some(node_id("smt100"))),
+            ],
+            nodes.folders
+        );
+        assert_eq!(4, nodes.variables.len());
+        assert_eq!(
+            VariableDefinition {
+                node_id: node_id("smt100/1/temperature"),
+                parent_id: node_id("smt100/1"),
+                browse_name: "Temperature",
+                data_type: DataType::Double,
+                unit: Some("°C"),
+            },
+            nodes.variables[0]
+        );
+        assert_eq!(node_id("smt100/1/raw_counts"), nodes.variables[3].node_id);
+        assert_eq!(DataType::UInt16, nodes.variables[3].data_type);
+        assert_eq!(None, nodes.variables[3].unit);
+        assert!(nodes
+            .values
+            .values()
+            .all(|value| *value == DataValue::WaitingForInitialData));
+    }
+
+    #[test]
+    fn update_measured_variables() {
+        let mapping = NodeMapping::default();
+        let mut nodes = Nodes::default();
+        mapping.add_sensor(&mut nodes, "1");
+        mapping.update(&mut nodes, "1", &measurements());
+        let source_timestamp = UNIX_EPOCH + Duration::from_secs(1_571_212_800);
+        assert_eq!(
+            Some(&DataValue::Good {
+                value: Variant::Double(21.5),
+                source_timestamp,
+            }),
+            nodes.values.get(&node_id("smt100/1/temperature"))
+        );
+        assert_eq!(
+            Some(&DataValue::Good {
+                value: Variant::UInt16(11_345),
+                source_timestamp,
+            }),
+            nodes.values.get(&node_id("smt100/1/raw_counts"))
+        );
+        // Not measured yet
+        assert_eq!(
+            Some(&DataValue::WaitingForInitialData),
+            nodes.values.get(&node_id("smt100/1/water_content"))
+        );
+    }
+
+    #[tokio::test]
+    async fn update_variables_from_poller() {
+        let mapping = NodeMapping {
+            namespace: 3,
+            root: "soil".to_owned(),
+        };
+        let nodes = Mutex::new(Nodes::default());
+        mapping.add_sensor(&mut *nodes.lock().unwrap(), "bed-A");
+        let (tx, rx) = watch::channel(TimestampedMeasurements::default());
+        tx.send(measurements()).unwrap();
+        drop(tx);
+        update_from_poller(&nodes, &mapping, "bed-A", rx).await;
+        let nodes = nodes.into_inner().unwrap();
+        let temperature = mapping.variable_id("bed-A", Quantity::Temperature);
+        assert_eq!("soil/bed-A/temperature", temperature.identifier);
+        assert!(matches!(nodes.values.get(&temperature), Some(DataValue::Good { .. })));
+    }
+}
@@ -5,40 +5,183 @@ pub mod core;
 
 pub use self::core::*;
 
+#[cfg(feature = "std")]
+mod error;
+
+#[cfg(feature = "std")]
+pub use self::error::*;
+
 #[cfg(feature = "tokio-modbus-rtu")]
 pub mod modbus;
 
+#[cfg(feature = "tokio-ascii")]
+pub mod ascii;
+
 #[cfg(feature = "tokio-mock")]
 pub mod mock;
 
 #[cfg(feature = "std")]
-use futures::Future;
+pub mod filter;
+
+#[cfg(feature = "std")]
+pub mod runtime;
+
+#[cfg(feature = "futures-io")]
+pub mod transport;
 
 #[cfg(feature = "std")]
-use std::{io::Error, time::Duration};
+pub mod registry;
+
+#[cfg(all(feature = "std", feature = "tokio"))]
+pub mod poller;
+
+#[cfg(all(feature = "std", feature = "tokio"))]
+pub mod retry;
+
+#[cfg(all(feature = "std", feature = "tokio"))]
+pub mod replay;
+
+#[cfg(all(feature = "std", feature = "tokio"))]
+pub mod watch;
+
+#[cfg(all(feature = "std", feature = "tokio"))]
+pub mod sink;
+
+#[cfg(all(feature = "std", feature = "tokio"))]
+pub mod alarms;
+
+#[cfg(all(feature = "std", feature = "tokio"))]
+pub mod history;
+
+#[cfg(all(feature = "std", feature = "tokio"))]
+pub mod stats;
+
+#[cfg(feature = "arrow")]
+pub mod export;
+
+#[cfg(feature = "config")]
+pub mod config;
+
+#[cfg(feature = "influx")]
+pub mod influx;
+
+#[cfg(any(feature = "mqtt", feature = "http-api", feature = "lwm2m", feature = "opcua"))]
+pub mod integrations;
+
+#[cfg(feature = "std")]
+use futures::future::BoxFuture;
+
+#[cfg(feature = "std")]
+use std::time::Duration;
 
 /// Asynchronous interface that exposes the generic capabilities of the
 /// TRUEBNER SMT100 Soil Moisture Sensor.
+///
+/// The trait is object safe, i.e. different backends can be stored
+/// as `Box<dyn Capabilities>` or `Arc<dyn Capabilities>`.
 #[cfg(feature = "std")]
 pub trait Capabilities {
     /// Measure the current temperature in the range from -40°C to +80°C
     /// (analog version from -40°C to +60°C).
-    fn read_temperature(&self, timeout: Option<Duration>)
-        -> Box<dyn Future<Item = Temperature, Error = Error>>;
+    fn read_temperature(&self, timeout: Option<Duration>) -> BoxFuture<'_, Result<Temperature>>;
 
     /// Measure the current water content of the medium (soil) around the sensor
     /// in the range from 0% to 60% (up to 100% with limited accuracy).
+    fn read_water_content(&self, timeout: Option<Duration>) -> BoxFuture<'_, Result<VolumetricWaterContent>>;
+
+    /// Measure the current (relative) permittivity of the medium around the sensor.
+    fn read_permittivity(&self, timeout: Option<Duration>) -> BoxFuture<'_, Result<RelativePermittivity>>;
+
+    /// Retrieve the current raw and uncalibrated signal of the sensor.
+    fn read_raw_counts(&self, timeout: Option<Duration>) -> BoxFuture<'_, Result<RawCounts>>;
+}
+
+/// Forward all capabilities to the referenced sensor.
+#[cfg(feature = "std")]
+macro_rules! impl_capabilities_for_pointer {
+    ($($pointer:ty),+) => {
+        $(
+            impl<C> Capabilities for $pointer
+            where
+                C: Capabilities + ?Sized,
+            {
+                fn read_temperature(&self, timeout: Option<Duration>) -> BoxFuture<'_, Result<Temperature>> {
+                    (**self).read_temperature(timeout)
+                }
+
+                fn read_water_content(&self, timeout: Option<Duration>) -> BoxFuture<'_, Result<VolumetricWaterContent>> {
+                    (**self).read_water_content(timeout)
+                }
+
+                fn read_permittivity(&self, timeout: Option<Duration>) -> BoxFuture<'_, Result<RelativePermittivity>> {
+                    (**self).read_permittivity(timeout)
+                }
+
+                fn read_raw_counts(&self, timeout: Option<Duration>) -> BoxFuture<'_, Result<RawCounts>> {
+                    (**self).read_raw_counts(timeout)
+                }
+            }
+        )+
+    };
+}
+
+#[cfg(feature = "std")]
+impl_capabilities_for_pointer!(&C, Box<C>, std::rc::Rc<C>, std::sync::Arc<C>);
+
+/// Exposes the asynchronous `Capabilities` through the `[no_std]`
+/// `AsyncCapabilities` trait, e.g. for sharing application code with
+/// firmware.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AsyncCapabilitiesAdapter<C>(pub C);
+
+#[cfg(feature = "std")]
+impl<C> AsyncCapabilities for AsyncCapabilitiesAdapter<C>
+where
+    C: Capabilities,
+{
+    type ReadError = Error;
+
+    fn read_temperature(
+        &self,
+        timeout: Option<Duration>,
+    ) -> impl std::future::Future<Output = Result<Temperature>> {
+        self.0.read_temperature(timeout)
+    }
+
     fn read_water_content(
         &self,
         timeout: Option<Duration>,
-    ) -> Box<dyn Future<Item = VolumetricWaterContent, Error = Error>>;
+    ) -> impl std::future::Future<Output = Result<VolumetricWaterContent>> {
+        self.0.read_water_content(timeout)
+    }
 
-    /// Measure the current (relative) permittivity of the medium around the sensor.
     fn read_permittivity(
         &self,
         timeout: Option<Duration>,
-    ) -> Box<dyn Future<Item = RelativePermittivity, Error = Error>>;
+    ) -> impl std::future::Future<Output = Result<RelativePermittivity>> {
+        self.0.read_permittivity(timeout)
+    }
 
-    /// Retrieve the current raw and uncalibrated signal of the sensor.
-    fn read_raw_counts(&self, timeout: Option<Duration>) -> Box<dyn Future<Item = RawCounts, Error = Error>>;
+    fn read_raw_counts(&self, timeout: Option<Duration>) -> impl std::future::Future<Output = Result<RawCounts>> {
+        self.0.read_raw_counts(timeout)
+    }
+}
+
+/// Fail with `Error::Timeout` if the request doesn't finish in time.
+#[cfg(feature = "tokio-modbus-rtu")]
+async fn with_timeout<T>(
+    timeout: Option<Duration>,
+    request: impl std::future::Future<Output = Result<T>>,
+) -> Result<T> {
+    runtime::timeout(&runtime::Tokio, timeout, request).await
+}
+
+/// Fail with `Error::Timeout` if the request doesn't finish before `deadline`.
+#[cfg(feature = "tokio-modbus-rtu")]
+async fn with_deadline<T>(
+    deadline: tokio::time::Instant,
+    request: impl std::future::Future<Output = Result<T>>,
+) -> Result<T> {
+    runtime::timeout_at(&runtime::Tokio, deadline.into_std(), request).await
 }
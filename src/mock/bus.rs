@@ -0,0 +1,219 @@
+//! A simulated Modbus RTU bus with multiple virtual slaves.
+//!
+//! Each slave is an emulated `Sensor` with its own measurements,
+//! latency distribution and faults. The bus creates a new `Transport` for
+//! each connection, i.e. it could be shared by a `BusManager` that
+//! reconnects after failures:
+//!
+//! ```ignore
+//! let bus = Bus::new();
+//! let first = bus.add_slave(Slave(1));
+//! let second = bus.add_slave(Slave(2));
+//! second.set_faults(Some(Faults { timeout: 1.0, ..Default::default() }));
+//! let manager = BusManager::new(bus.shared_context());
+//! ```
+
+use super::server::{Faults, Sensor, Transport};
+
+use crate::modbus::{rtu, NewContext, SharedContext};
+
+use futures::{future::BoxFuture, FutureExt};
+use std::{
+    io,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+use tokio_modbus::{client, prelude::Slave};
+
+#[derive(Debug, Default)]
+struct State {
+    sensors: Vec<Sensor>,
+    faults: Faults,
+    seed: u64,
+}
+
+/// Virtual slaves that share the same bus.
+///
+/// Cloned instances share the same slaves.
+#[derive(Debug, Clone, Default)]
+pub struct Bus {
+    state: Arc<Mutex<State>>,
+}
+
+impl Bus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Inject faults into the responses of all slaves that don't
+    /// override them.
+    ///
+    /// Each connection uses a new random number generator that is
+    /// seeded with `seed`.
+    pub fn with_faults(self, faults: Faults, seed: u64) -> Self {
+        {
+            let mut state = self.state();
+            state.faults = faults;
+            state.seed = seed;
+        }
+        self
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Connect a new slave with default measurements.
+    ///
+    /// Multiple slaves may have the same address, e.g. to test
+    /// address conflicts. The returned sensor controls the slave.
+    pub fn add_slave(&self, slave: Slave) -> Sensor {
+        let sensor = Sensor::new(slave);
+        self.state().sensors.push(sensor.clone());
+        sensor
+    }
+
+    /// Disconnect all slaves with the given address.
+    ///
+    /// Returns `false` if no slave has been found.
+    pub fn remove_slave(&self, slave: Slave) -> bool {
+        let mut state = self.state();
+        let len = state.sensors.len();
+        state.sensors.retain(|sensor| sensor.slave() != slave);
+        state.sensors.len() < len
+    }
+
+    /// The first slave with the given address.
+    ///
+    /// The address might have been changed by the client.
+    pub fn slave(&self, slave: Slave) -> Option<Sensor> {
+        self.state().sensors.iter().find(|sensor| sensor.slave() == slave).cloned()
+    }
+
+    /// The current addresses of all slaves in the order they have
+    /// been added.
+    pub fn slaves(&self) -> Vec<Slave> {
+        self.state().sensors.iter().map(Sensor::slave).collect()
+    }
+
+    /// A transport that is connected to all current slaves.
+    ///
+    /// Slaves that are added or removed afterwards only affect
+    /// subsequent transports.
+    pub fn transport(&self) -> Transport {
+        let state = self.state();
+        Transport::new(state.sensors.iter().cloned()).with_faults(state.faults, state.seed)
+    }
+
+    /// Connect a Modbus RTU client to all current slaves.
+    pub fn connect(&self) -> client::Context {
+        rtu::connect(self.transport())
+    }
+
+    /// A context that is shared by all slaves on the bus and that
+    /// connects a new transport upon each reconnect.
+    pub fn shared_context(&self) -> Arc<SharedContext> {
+        Arc::new(SharedContext::new(Some(self.connect()), Box::new(self.clone())))
+    }
+}
+
+impl NewContext for Bus {
+    fn new_context(&self) -> BoxFuture<'_, io::Result<client::Context>> {
+        futures::future::ok(self.connect()).boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        modbus, Error, Measurements, RawCounts, RelativePermittivity, Temperature, VolumetricWaterContent,
+    };
+    use std::time::Duration;
+    use tokio_modbus::prelude::SlaveContext as _;
+
+    const TIMEOUT: Duration = Duration::from_millis(100);
+
+    fn bus(temperatures: &[f64]) -> Bus {
+        let bus = Bus::new();
+        for (slave, degree_celsius) in (1..).zip(temperatures) {
+            bus.add_slave(Slave(slave)).set_measurements(&Measurements {
+                temperature: Temperature::from_degree_celsius(*degree_celsius),
+                water_content: VolumetricWaterContent::from_percent(25.0),
+                permittivity: RelativePermittivity::from_ratio(12.0),
+                raw_counts: RawCounts::from(4_000),
+            });
+        }
+        bus
+    }
+
+    async fn read_temperature(context: &mut client::Context, slave: Slave) -> Result<f64, Error> {
+        context.set_slave(slave);
+        let temperature = modbus::read_temperature_with_timeout(context, TIMEOUT).await?;
+        Ok(temperature.to_degree_celsius())
+    }
+
+    #[tokio::test]
+    async fn address_slaves_individually() {
+        let bus = bus(&[10.0, 20.0]);
+        let mut context = bus.connect();
+        assert_eq!(20.0, read_temperature(&mut context, Slave(2)).await.unwrap());
+        assert_eq!(10.0, read_temperature(&mut context, Slave(1)).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn track_changed_slave_addresses() {
+        let bus = bus(&[10.0, 20.0]);
+        let mut context = bus.connect();
+        modbus::set_slave_address(&mut context, Slave(2), Slave(3)).await.unwrap();
+        assert_eq!(vec![Slave(1), Slave(3)], bus.slaves());
+        assert!(bus.slave(Slave(2)).is_none());
+        assert_eq!(Slave(3), bus.slave(Slave(3)).unwrap().slave());
+        assert_eq!(20.0, read_temperature(&mut context, Slave(3)).await.unwrap());
+        assert!(bus.remove_slave(Slave(3)));
+        assert!(!bus.remove_slave(Slave(3)));
+        assert_eq!(vec![Slave(1)], bus.slaves());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn connect_current_slaves() {
+        let bus = bus(&[10.0]);
+        let mut context = bus.connect();
+        bus.add_slave(Slave(2));
+        assert_eq!(2, bus.transport().sensors().len());
+        // Only subsequent connections are affected
+        let res = read_temperature(&mut context, Slave(2)).await;
+        assert!(matches!(res, Err(Error::Timeout(_))));
+        let mut context = bus.connect();
+        assert!(read_temperature(&mut context, Slave(2)).await.is_ok());
+        assert!(bus.remove_slave(Slave(1)));
+        let mut context = bus.connect();
+        let res = read_temperature(&mut context, Slave(1)).await;
+        assert!(matches!(res, Err(Error::Timeout(_))));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn override_faults_of_the_bus() {
+        let faults = Faults {
+            timeout: 1.0,
+            ..Default::default()
+        };
+        let bus = bus(&[10.0, 20.0]).with_faults(faults, 7);
+        bus.slave(Slave(2)).unwrap().set_faults(Some(Faults::default()));
+        let mut context = bus.connect();
+        let res = read_temperature(&mut context, Slave(1)).await;
+        assert!(matches!(res, Err(Error::Timeout(_))));
+        let mut context = bus.connect();
+        assert_eq!(20.0, read_temperature(&mut context, Slave(2)).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn reconnect_shared_context() {
+        let bus = bus(&[10.0]);
+        let shared_context = bus.shared_context();
+        bus.add_slave(Slave(2));
+        shared_context.reconnect().await.unwrap();
+        let proxy = modbus::SlaveProxy::new(Slave(2), shared_context);
+        assert!(proxy.read_temperature(Some(TIMEOUT)).await.is_ok());
+    }
+}
@@ -0,0 +1,297 @@
+use super::*;
+
+#[cfg(feature = "tokio-modbus-rtu")]
+pub mod server;
+
+#[cfg(feature = "tokio-modbus-rtu")]
+pub mod bus;
+#[cfg(feature = "tokio-modbus-rtu")]
+pub use self::bus::Bus;
+
+pub mod simulation;
+pub use self::simulation::Simulation;
+
+use crate::runtime::{self, Clock, Runtime as _};
+
+use futures::{future::BoxFuture, Future, FutureExt};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
+};
+
+/// A single step of a scripted scenario that is consumed
+/// by the next read request.
+pub struct Step {
+    /// Overrides the default delay of the proxy
+    pub delay: Option<Duration>,
+
+    /// The readings replace all current values of the proxy
+    pub outcome: Result<Measurements>,
+}
+
+impl Step {
+    pub fn reading(measurements: Measurements) -> Self {
+        Self {
+            delay: None,
+            outcome: Ok(measurements),
+        }
+    }
+
+    pub fn error(error: Error) -> Self {
+        Self {
+            delay: None,
+            outcome: Err(error),
+        }
+    }
+
+    pub fn with_delay(self, delay: Duration) -> Self {
+        Self {
+            delay: Some(delay),
+            ..self
+        }
+    }
+}
+
+struct State {
+    temperature: Temperature,
+    water_content: VolumetricWaterContent,
+    permittivity: RelativePermittivity,
+    raw_counts: RawCounts,
+    next_error: Option<Error>,
+    steps: VecDeque<Step>,
+    simulation: Option<(Simulation, Instant)>,
+}
+
+impl State {
+    fn set_measurements(&mut self, measurements: Measurements) {
+        self.temperature = measurements.temperature;
+        self.water_content = measurements.water_content;
+        self.permittivity = measurements.permittivity;
+        self.raw_counts = measurements.raw_counts;
+    }
+
+    fn simulate(&mut self, now: Instant) {
+        if let Some((simulation, started)) = &self.simulation {
+            let measurements = simulation.measurements_at(now.saturating_duration_since(*started));
+            self.set_measurements(measurements);
+        }
+    }
+}
+
+pub struct Proxy {
+    state: Mutex<State>,
+    delay: Duration,
+    clock: Arc<dyn Clock>,
+}
+
+pub trait Driver {
+    fn set_delay(&mut self, delay: Duration);
+
+    fn set_next_error(&mut self, next_error: Option<Error>);
+
+    fn set_temperature(&mut self, temperature: Temperature);
+
+    fn set_water_content(&mut self, water_content: VolumetricWaterContent);
+
+    fn set_permittivity(&mut self, permittivity: RelativePermittivity);
+
+    fn set_raw_counts(&mut self, raw_counts: RawCounts);
+
+    /// Enqueue new readings that become available after all
+    /// preceding steps have been consumed.
+    fn push_reading(&mut self, measurements: Measurements);
+
+    /// Enqueue an error that is returned after all preceding
+    /// steps have been consumed.
+    fn push_error(&mut self, error: Error);
+
+    /// Replace all pending steps of the scenario.
+    fn set_sequence(&mut self, steps: Vec<Step>);
+
+    /// Start a simulation that replaces the current values
+    /// upon each read request.
+    ///
+    /// The simulated time is measured by the clock of the proxy,
+    /// i.e. it could be advanced manually in tests.
+    fn set_simulation(&mut self, simulation: Option<Simulation>);
+}
+
+async fn delay_result<T>(
+    clock: Arc<dyn Clock>,
+    result: Result<T>,
+    delay: Duration,
+    timeout: Option<Duration>,
+) -> Result<T> {
+    let delayed = clock.sleep(delay).map(|()| result);
+    runtime::timeout(&*clock, timeout, delayed).await
+}
+
+impl Proxy {
+    /// Delays and simulations are driven by `clock` instead of _tokio_,
+    /// e.g. by a `runtime::ManualClock`.
+    pub fn with_clock(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            clock,
+            ..Default::default()
+        }
+    }
+
+    pub fn default_temperature() -> Temperature {
+        Temperature::from_degree_celsius(20.0)
+    }
+
+    pub fn default_water_content() -> VolumetricWaterContent {
+        VolumetricWaterContent::from_percent(30.0)
+    }
+
+    pub fn default_permittivity() -> RelativePermittivity {
+        RelativePermittivity::min()
+    }
+
+    pub /*const*/ fn default_raw_counts() -> RawCounts {
+        Default::default()
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn read_value<T>(
+        &self,
+        timeout: Option<Duration>,
+        value: fn(&State) -> T,
+    ) -> impl Future<Output = Result<T>> {
+        let mut state = self.state();
+        let (result, delay) = if let Some(Step { delay, outcome }) = state.steps.pop_front() {
+            let result = outcome.map(|measurements| {
+                state.set_measurements(measurements);
+                value(&state)
+            });
+            (result, Some(delay.unwrap_or(self.delay)))
+        } else if timeout.is_some() {
+            state.simulate(self.clock.now());
+            let result = if let Some(error) = state.next_error.take() {
+                Err(error)
+            } else {
+                Ok(value(&state))
+            };
+            (result, Some(self.delay))
+        } else {
+            state.simulate(self.clock.now());
+            (Ok(value(&state)), None)
+        };
+        let clock = Arc::clone(&self.clock);
+        async move {
+            if let Some(delay) = delay {
+                delay_result(clock, result, delay, timeout).await
+            } else {
+                result
+            }
+        }
+    }
+
+    /// Implementation of Capabilities::read_temperature()
+    pub fn read_temperature(&self, timeout: Option<Duration>) -> impl Future<Output = Result<Temperature>> {
+        self.read_value(timeout, |state| state.temperature)
+    }
+
+    /// Implementation of Capabilities::read_water_content()
+    pub fn read_water_content(
+        &self,
+        timeout: Option<Duration>,
+    ) -> impl Future<Output = Result<VolumetricWaterContent>> {
+        self.read_value(timeout, |state| state.water_content)
+    }
+
+    /// Implementation of Capabilities::read_permittivity()
+    pub fn read_permittivity(
+        &self,
+        timeout: Option<Duration>,
+    ) -> impl Future<Output = Result<RelativePermittivity>> {
+        self.read_value(timeout, |state| state.permittivity)
+    }
+
+    /// Implementation of Capabilities::read_raw_counts()
+    pub fn read_raw_counts(&self, timeout: Option<Duration>) -> impl Future<Output = Result<RawCounts>> {
+        self.read_value(timeout, |state| state.raw_counts)
+    }
+}
+
+impl Default for Proxy {
+    fn default() -> Self {
+        Self {
+            state: Mutex::new(State {
+                temperature: Self::default_temperature(),
+                water_content: Self::default_water_content(),
+                permittivity: Self::default_permittivity(),
+                raw_counts: Self::default_raw_counts(),
+                next_error: None,
+                steps: VecDeque::new(),
+                simulation: None,
+            }),
+            delay: Duration::default(),
+            clock: Arc::new(runtime::Tokio),
+        }
+    }
+}
+
+impl Driver for Proxy {
+    fn set_delay(&mut self, delay: Duration) {
+        self.delay = delay;
+    }
+
+    fn set_next_error(&mut self, next_error: Option<Error>) {
+        self.state().next_error = next_error;
+    }
+
+    fn set_temperature(&mut self, temperature: Temperature) {
+        self.state().temperature = temperature;
+    }
+
+    fn set_water_content(&mut self, water_content: VolumetricWaterContent) {
+        self.state().water_content = water_content;
+    }
+
+    fn set_permittivity(&mut self, permittivity: RelativePermittivity) {
+        self.state().permittivity = permittivity;
+    }
+
+    fn set_raw_counts(&mut self, raw_counts: RawCounts) {
+        self.state().raw_counts = raw_counts;
+    }
+
+    fn push_reading(&mut self, measurements: Measurements) {
+        self.state().steps.push_back(Step::reading(measurements));
+    }
+
+    fn push_error(&mut self, error: Error) {
+        self.state().steps.push_back(Step::error(error));
+    }
+
+    fn set_sequence(&mut self, steps: Vec<Step>) {
+        self.state().steps = steps.into();
+    }
+
+    fn set_simulation(&mut self, simulation: Option<Simulation>) {
+        self.state().simulation = simulation.map(|simulation| (simulation, self.clock.now()));
+    }
+}
+
+impl Capabilities for Proxy {
+    fn read_temperature(&self, timeout: Option<Duration>) -> BoxFuture<'_, Result<Temperature>> {
+        self.read_temperature(timeout).boxed()
+    }
+
+    fn read_water_content(&self, timeout: Option<Duration>) -> BoxFuture<'_, Result<VolumetricWaterContent>> {
+        self.read_water_content(timeout).boxed()
+    }
+
+    fn read_permittivity(&self, timeout: Option<Duration>) -> BoxFuture<'_, Result<RelativePermittivity>> {
+        self.read_permittivity(timeout).boxed()
+    }
+
+    fn read_raw_counts(&self, timeout: Option<Duration>) -> BoxFuture<'_, Result<RawCounts>> {
+        self.read_raw_counts(timeout).boxed()
+    }
+}
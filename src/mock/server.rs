@@ -0,0 +1,765 @@
+//! Emulation of the Modbus RTU register map of the sensor.
+//!
+//! The `Transport` could be connected to a Modbus RTU client instead
+//! of a serial port for testing the actual `modbus` client code without
+//! any hardware:
+//!
+//! ```ignore
+//! let sensor = Sensor::new(Slave(1));
+//! let transport = Transport::new(vec![sensor.clone()]);
+//! let context = modbus::rtu::connect(transport);
+//! ```
+//!
+//! The quirks of the sensor's broadcast address 0xFD are emulated:
+//! Only the slave address could be written via the broadcast address
+//! and all other requests are answered with the slave address 0. All
+//! connected sensors respond to the broadcast address, i.e. their
+//! responses collide if multiple sensors are connected.
+//!
+//! Communication errors are injected randomly into responses by
+//! `Transport::with_faults()`. The random number generator is seeded
+//! to reproduce the same sequence of faults in each test run.
+
+use super::*;
+
+use crate::core::modbus::{rtu::*, *};
+
+use std::{
+    collections::VecDeque,
+    future::Future,
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    task::{Context, Poll, Waker},
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    time::{sleep_until, Instant, Sleep},
+};
+use tokio_modbus::prelude::{Slave, SlaveId};
+
+const REG_COUNT: usize = 5;
+
+const FN_READ_INPUT_REGISTERS: u8 = 0x04;
+
+const EXCEPTION_ILLEGAL_FUNCTION: u8 = 0x01;
+const EXCEPTION_ILLEGAL_DATA_ADDRESS: u8 = 0x02;
+const EXCEPTION_ILLEGAL_DATA_VALUE: u8 = 0x03;
+const EXCEPTION_SERVER_DEVICE_FAILURE: u8 = 0x04;
+
+/// Failed requests are answered with an exception code.
+type ExceptionResult<T> = std::result::Result<T, u8>;
+
+/// Modbus broadcast requests are never answered.
+const MODBUS_BROADCAST_SLAVE_ADDR: SlaveId = 0;
+
+#[derive(Debug)]
+struct Registers {
+    slave_id: SlaveId,
+    values: [u16; REG_COUNT],
+
+    /// Overrides the faults of the transport
+    faults: Option<Faults>,
+    latency: LatencyDistribution,
+
+    /// Writes are acknowledged but not applied
+    lose_writes: bool,
+}
+
+/// An emulated sensor with its register map.
+///
+/// Cloned instances share the same registers, i.e. the measurements
+/// could be modified after the transport has been handed over to
+/// the client.
+#[derive(Debug, Clone)]
+pub struct Sensor {
+    registers: Arc<Mutex<Registers>>,
+}
+
+impl Sensor {
+    pub fn new(slave: Slave) -> Self {
+        let sensor = Self {
+            registers: Arc::new(Mutex::new(Registers {
+                slave_id: slave.into(),
+                values: [0; REG_COUNT],
+                faults: None,
+                lose_writes: false,
+                latency: LatencyDistribution::default(),
+            })),
+        };
+        sensor.set_measurements(&Measurements {
+            temperature: Proxy::default_temperature(),
+            water_content: Proxy::default_water_content(),
+            permittivity: Proxy::default_permittivity(),
+            raw_counts: Proxy::default_raw_counts(),
+        });
+        sensor
+    }
+
+    fn registers(&self) -> MutexGuard<'_, Registers> {
+        self.registers.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// The current slave address that might have been changed by the client.
+    pub fn slave(&self) -> Slave {
+        Slave(self.registers().slave_id)
+    }
+
+    pub fn set_measurements(&self, measurements: &Measurements) {
+        let start = usize::from(MEASUREMENTS_REG_START);
+        let end = start + usize::from(MEASUREMENTS_REG_COUNT);
+        self.registers().values[start..end]
+            .copy_from_slice(&encode_measurements_to_u16s(measurements));
+    }
+
+    /// Acknowledge all subsequent writes without applying them, e.g.
+    /// to emulate writes that are silently lost on a noisy bus.
+    pub fn set_lose_writes(&self, lose_writes: bool) {
+        self.registers().lose_writes = lose_writes;
+    }
+
+    /// Inject faults into the responses of this sensor instead of
+    /// the faults of the transport.
+    pub fn set_faults(&self, faults: Option<Faults>) {
+        self.registers().faults = faults;
+    }
+
+    /// Delay all responses of this sensor, e.g. to exceed the timeout
+    /// of the client.
+    pub fn set_response_delay(&self, response_delay: Duration) {
+        self.set_latency(Latency::Fixed(response_delay).into());
+    }
+
+    /// Delay each response of this sensor randomly.
+    pub fn set_latency(&self, latency: LatencyDistribution) {
+        self.registers().latency = latency;
+    }
+
+    fn is_addressed(&self, slave_id: SlaveId) -> bool {
+        slave_id == MODBUS_BROADCAST_SLAVE_ADDR
+            || slave_id == BROADCAST_SLAVE_ADDR
+            || slave_id == self.registers().slave_id
+    }
+
+    fn read_registers(&self, addr: u16, cnt: u16) -> ExceptionResult<Vec<u8>> {
+        let start = usize::from(addr);
+        let end = start + usize::from(cnt);
+        if cnt == 0 || end > REG_COUNT {
+            return Err(EXCEPTION_ILLEGAL_DATA_ADDRESS);
+        }
+        let mut registers = self.registers();
+        registers.values[usize::from(Register::SlaveAddress.address())] = u16::from(registers.slave_id);
+        let mut data = Vec::with_capacity(1 + 2 * usize::from(cnt));
+        data.push((2 * cnt) as u8);
+        for value in &registers.values[start..end] {
+            data.extend_from_slice(&value.to_be_bytes());
+        }
+        Ok(data)
+    }
+
+    fn write_register(&self, addr: u16, value: u16) -> ExceptionResult<()> {
+        if self.registers().lose_writes {
+            return Ok(());
+        }
+        if addr == Register::SlaveAddress.address() {
+            return match value {
+                1..=247 => {
+                    self.registers().slave_id = value as SlaveId;
+                    Ok(())
+                }
+                _ => Err(EXCEPTION_ILLEGAL_DATA_VALUE),
+            };
+        }
+        // All other registers are read-only
+        Err(EXCEPTION_ILLEGAL_DATA_ADDRESS)
+    }
+
+    fn write_registers(&self, addr: u16, data: &[u8]) -> ExceptionResult<()> {
+        let values: Vec<_> = data
+            .chunks_exact(2)
+            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+            .collect();
+        if values.is_empty() || usize::from(addr) + values.len() > REG_COUNT {
+            return Err(EXCEPTION_ILLEGAL_DATA_ADDRESS);
+        }
+        for (addr, value) in (addr..).zip(values) {
+            self.write_register(addr, value)?;
+        }
+        Ok(())
+    }
+
+    /// Process a request PDU and return the response PDU.
+    fn process(&self, slave_id: SlaveId, pdu: &[u8]) -> Vec<u8> {
+        let function = pdu[0];
+        let arg = |i: usize| u16::from_be_bytes([pdu[1 + 2 * i], pdu[2 + 2 * i]]);
+        let is_write = function == FN_WRITE_SINGLE_REGISTER || function == FN_WRITE_MULTIPLE_REGISTERS;
+        let res = match function {
+            // Only the slave address could be written via the broadcast address
+            _ if slave_id == BROADCAST_SLAVE_ADDR && is_write && !is_broadcast_slave_write(pdu) => {
+                Err(EXCEPTION_ILLEGAL_DATA_ADDRESS)
+            }
+            FN_READ_HOLDING_REGISTERS | FN_READ_INPUT_REGISTERS if pdu.len() == 5 => {
+                self.read_registers(arg(0), arg(1))
+            }
+            FN_WRITE_SINGLE_REGISTER if pdu.len() == 5 => {
+                self.write_register(arg(0), arg(1)).map(|()| pdu[1..].to_vec())
+            }
+            FN_WRITE_MULTIPLE_REGISTERS
+                if pdu.len() > 6
+                    && pdu.len() == 6 + usize::from(pdu[5])
+                    && usize::from(pdu[5]) == 2 * usize::from(arg(1)) =>
+            {
+                self.write_registers(arg(0), &pdu[6..]).map(|()| pdu[1..5].to_vec())
+            }
+            _ => Err(EXCEPTION_ILLEGAL_FUNCTION),
+        };
+        match res {
+            Ok(data) => {
+                let mut rsp = Vec::with_capacity(1 + data.len());
+                rsp.push(function);
+                rsp.extend(data);
+                rsp
+            }
+            Err(exception) => vec![function | EXCEPTION_FLAG, exception],
+        }
+    }
+}
+
+/// The only write request that is accepted via the broadcast address
+/// and that is answered with the broadcast address.
+fn is_broadcast_slave_write(pdu: &[u8]) -> bool {
+    pdu.len() == 5
+        && pdu[0] == FN_WRITE_SINGLE_REGISTER
+        && u16::from_be_bytes([pdu[1], pdu[2]]) == BROADCAST_REG_ADDR
+}
+
+fn encode_adu(slave_id: SlaveId, pdu: Vec<u8>) -> Vec<u8> {
+    let mut adu = Vec::with_capacity(1 + pdu.len() + 2);
+    adu.push(slave_id);
+    adu.extend(pdu);
+    let crc = crc16(&adu);
+    adu.extend_from_slice(&crc.to_le_bytes());
+    adu
+}
+
+/// Superimpose two frames that are sent at the same time.
+///
+/// The dominant low bits win. The checksum of the resulting frame
+/// is invalid even if both frames are equal, because the sensors
+/// are never exactly in sync.
+fn collide(mut adu: Vec<u8>, other: Vec<u8>) -> Vec<u8> {
+    if other.len() > adu.len() {
+        return collide(other, adu);
+    }
+    for (byte, other) in adu.iter_mut().zip(&other) {
+        *byte &= other;
+    }
+    if let Some(last) = adu.last_mut() {
+        *last = !*last;
+    }
+    adu
+}
+
+/// The length of the next request frame or `None` if not yet known.
+fn request_frame_len(input: &[u8]) -> Option<usize> {
+    match *input.get(1)? {
+        FN_WRITE_MULTIPLE_REGISTERS => input.get(6).map(|byte_count| 9 + usize::from(*byte_count)),
+        _ => Some(8),
+    }
+}
+
+/// Probabilities of communication errors for each response.
+///
+/// At most a single fault is injected per response. The sum of all
+/// probabilities should not exceed 1.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Faults {
+    /// No response is sent at all.
+    pub timeout: f64,
+
+    /// The response is replaced by a Modbus exception.
+    pub exception: f64,
+
+    /// A single bit of the response data is flipped. The checksum
+    /// is calculated afterwards, i.e. it remains valid.
+    pub bit_flip: f64,
+
+    /// The response is sent after `delay`, e.g. after the client
+    /// has given up waiting.
+    pub delayed: f64,
+
+    pub delay: Duration,
+}
+
+/// The response time of a sensor.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Latency {
+    Fixed(Duration),
+
+    /// Uniformly distributed within `min..=max`.
+    Uniform { min: Duration, max: Duration },
+
+    /// Normally distributed around `mean` with the standard deviation
+    /// `jitter`. Negative samples are clamped to zero.
+    Normal { mean: Duration, jitter: Duration },
+}
+
+impl Default for Latency {
+    fn default() -> Self {
+        Latency::Fixed(Duration::ZERO)
+    }
+}
+
+/// The response time of a sensor with an occasional long tail,
+/// e.g. while the sensor is busy with a measurement.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LatencyDistribution {
+    pub latency: Latency,
+
+    /// The probability that `tail` is added to the latency.
+    pub tail_probability: f64,
+
+    pub tail: Duration,
+}
+
+impl From<Latency> for LatencyDistribution {
+    fn from(latency: Latency) -> Self {
+        Self {
+            latency,
+            ..Default::default()
+        }
+    }
+}
+
+impl LatencyDistribution {
+    fn sample(&self, rng: &mut Rng) -> Duration {
+        let latency = match self.latency {
+            Latency::Fixed(latency) => latency,
+            Latency::Uniform { min, max } => {
+                let range = max.saturating_sub(min).as_secs_f64();
+                min + Duration::from_secs_f64(range * rng.next_f64())
+            }
+            Latency::Normal { mean, jitter } => {
+                // Box-Muller transform
+                let (u1, u2) = (1.0 - rng.next_f64(), rng.next_f64());
+                let z = (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos();
+                Duration::from_secs_f64((mean.as_secs_f64() + z * jitter.as_secs_f64()).max(0.0))
+            }
+        };
+        if self.tail_probability > 0.0 && rng.next_f64() < self.tail_probability {
+            latency + self.tail
+        } else {
+            latency
+        }
+    }
+}
+
+enum Fault {
+    Timeout,
+    Exception,
+    BitFlip,
+    Delayed,
+}
+
+/// Xorshift64* pseudo random number generator.
+#[derive(Debug)]
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // The state must not be zero
+        Self(seed | 1)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// A uniformly distributed number in the range [0, 1).
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+impl Faults {
+    fn choose(&self, rng: &mut Rng) -> Option<Fault> {
+        let mut sample = rng.next_f64();
+        for (probability, fault) in [
+            (self.timeout, Fault::Timeout),
+            (self.exception, Fault::Exception),
+            (self.bit_flip, Fault::BitFlip),
+            (self.delayed, Fault::Delayed),
+        ] {
+            if sample < probability {
+                return Some(fault);
+            }
+            sample -= probability;
+        }
+        None
+    }
+}
+
+/// In-memory transport that connects a Modbus RTU client with
+/// emulated sensors on the same bus.
+///
+/// Requests with an invalid checksum are silently ignored like on a
+/// real bus. If multiple sensors are addressed at once their responses
+/// collide and the client receives a corrupted frame.
+#[derive(Debug)]
+pub struct Transport {
+    sensors: Vec<Sensor>,
+    request: Vec<u8>,
+    response: Vec<u8>,
+    reader: Option<Waker>,
+    faults: Faults,
+    rng: Rng,
+    delayed: VecDeque<(Instant, Vec<u8>)>,
+    delay_timer: Option<Pin<Box<Sleep>>>,
+}
+
+impl Transport {
+    pub fn new(sensors: impl IntoIterator<Item = Sensor>) -> Self {
+        Self {
+            sensors: sensors.into_iter().collect(),
+            request: Vec::new(),
+            response: Vec::new(),
+            reader: None,
+            faults: Faults::default(),
+            rng: Rng::new(0),
+            delayed: VecDeque::new(),
+            delay_timer: None,
+        }
+    }
+
+    /// Inject faults into responses using a seeded random number
+    /// generator.
+    pub fn with_faults(self, faults: Faults, seed: u64) -> Self {
+        Self {
+            faults,
+            rng: Rng::new(seed),
+            ..self
+        }
+    }
+
+    pub fn sensors(&self) -> &[Sensor] {
+        &self.sensors
+    }
+
+    fn process_frame(&mut self, frame: &[u8]) {
+        let (adu, crc) = frame.split_at(frame.len() - 2);
+        if crc16(adu).to_le_bytes() != crc {
+            log::debug!("Ignoring request with invalid checksum: {:?}", frame);
+            return;
+        }
+        let (slave_id, pdu) = (adu[0], &adu[1..]);
+        let mut responses: Vec<_> = self
+            .sensors
+            .iter()
+            .filter(|sensor| sensor.is_addressed(slave_id))
+            .map(|sensor| (sensor.process(slave_id, pdu), sensor))
+            .collect();
+        if slave_id == MODBUS_BROADCAST_SLAVE_ADDR {
+            return;
+        }
+        let response_slave_id = if slave_id == BROADCAST_SLAVE_ADDR && !is_broadcast_slave_write(pdu) {
+            MODBUS_BROADCAST_SLAVE_ADDR
+        } else {
+            slave_id
+        };
+        if responses.len() > 1 {
+            log::debug!("Responses of {} sensors collide", responses.len());
+            let adu = responses
+                .iter()
+                .map(|(pdu, _)| encode_adu(response_slave_id, pdu.clone()))
+                .reduce(collide)
+                .unwrap_or_default();
+            self.respond(adu);
+            return;
+        }
+        let (mut pdu, sensor) = match responses.pop() {
+            Some(response) => response,
+            None => return,
+        };
+        let (faults, latency) = {
+            let registers = sensor.registers();
+            (registers.faults.unwrap_or(self.faults), registers.latency)
+        };
+        let mut delay = latency.sample(&mut self.rng);
+        let fault = faults.choose(&mut self.rng);
+        match fault {
+            Some(Fault::Timeout) => {
+                log::debug!("Injecting timeout");
+                return;
+            }
+            Some(Fault::Exception) => {
+                log::debug!("Injecting exception");
+                pdu = vec![pdu[0] | EXCEPTION_FLAG, EXCEPTION_SERVER_DEVICE_FAILURE];
+            }
+            Some(Fault::BitFlip) if pdu.len() > 2 => {
+                let bit = self.rng.next_u64() as usize % (8 * (pdu.len() - 2));
+                log::debug!("Injecting bit flip");
+                pdu[2 + bit / 8] ^= 1 << (bit % 8);
+            }
+            _ => (),
+        }
+        let adu = encode_adu(response_slave_id, pdu);
+        if let Some(Fault::Delayed) = fault {
+            log::debug!("Injecting delay");
+            delay += faults.delay;
+        }
+        if delay > Duration::ZERO {
+            self.delayed.push_back((Instant::now() + delay, adu));
+            if let Some(reader) = self.reader.take() {
+                reader.wake();
+            }
+        } else {
+            self.respond(adu);
+        }
+    }
+
+    fn respond(&mut self, adu: Vec<u8>) {
+        self.response.extend(adu);
+        if let Some(reader) = self.reader.take() {
+            reader.wake();
+        }
+    }
+
+    /// Move all delayed responses that are due into the response.
+    ///
+    /// Returns `Poll::Pending` and schedules a wakeup if a delayed
+    /// response is still outstanding.
+    fn poll_delayed(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        while let Some((deadline, _)) = self.delayed.front() {
+            let deadline = *deadline;
+            if deadline > Instant::now() {
+                let timer = self.delay_timer.get_or_insert_with(|| Box::pin(sleep_until(deadline)));
+                timer.as_mut().reset(deadline);
+                if timer.as_mut().poll(cx).is_pending() {
+                    return Poll::Pending;
+                }
+            }
+            if let Some((_, adu)) = self.delayed.pop_front() {
+                self.response.extend(adu);
+            }
+        }
+        Poll::Ready(())
+    }
+}
+
+impl AsyncRead for Transport {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let _ = self.poll_delayed(cx);
+        if self.response.is_empty() {
+            self.reader = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+        let len = buf.remaining().min(self.response.len());
+        buf.put_slice(&self.response[..len]);
+        self.response.drain(..len);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for Transport {
+    fn poll_write(mut self: Pin<&mut Self>, _cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        self.request.extend_from_slice(buf);
+        while let Some(len) = request_frame_len(&self.request) {
+            if self.request.len() < len {
+                break;
+            }
+            let frame: Vec<_> = self.request.drain(..len).collect();
+            self.process_frame(&frame);
+        }
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::modbus::{self, rtu};
+    use tokio_modbus::prelude::SlaveContext as _;
+
+    const RAW_COUNTS: u16 = Register::RawCounts.address();
+
+    fn read_request(addr: u16, cnt: u16) -> Vec<u8> {
+        let mut pdu = vec![FN_READ_HOLDING_REGISTERS];
+        pdu.extend_from_slice(&addr.to_be_bytes());
+        pdu.extend_from_slice(&cnt.to_be_bytes());
+        pdu
+    }
+
+    fn write_request(addr: u16, value: u16) -> Vec<u8> {
+        let mut pdu = vec![FN_WRITE_SINGLE_REGISTER];
+        pdu.extend_from_slice(&addr.to_be_bytes());
+        pdu.extend_from_slice(&value.to_be_bytes());
+        pdu
+    }
+
+    /// Send a request frame and return the response frame that has
+    /// been sent without any delay.
+    fn transact(transport: &mut Transport, slave_id: SlaveId, pdu: &[u8]) -> Vec<u8> {
+        transport.process_frame(&encode_adu(slave_id, pdu.to_vec()));
+        std::mem::take(&mut transport.response)
+    }
+
+    fn has_valid_crc(adu: &[u8]) -> bool {
+        let (adu, crc) = adu.split_at(adu.len() - 2);
+        crc16(adu).to_le_bytes() == crc
+    }
+
+    #[test]
+    fn write_slave_address_via_broadcast_address() {
+        let sensor = Sensor::new(Slave(1));
+        let mut transport = Transport::new(vec![sensor.clone()]);
+        let request = write_request(BROADCAST_REG_ADDR, 5);
+        // Answered with the broadcast address
+        assert_eq!(
+            encode_adu(BROADCAST_SLAVE_ADDR, request.clone()),
+            transact(&mut transport, BROADCAST_SLAVE_ADDR, &request)
+        );
+        assert_eq!(Slave(5), sensor.slave());
+        let rsp = transact(&mut transport, BROADCAST_SLAVE_ADDR, &write_request(BROADCAST_REG_ADDR, 0));
+        let exception = vec![FN_WRITE_SINGLE_REGISTER | EXCEPTION_FLAG, EXCEPTION_ILLEGAL_DATA_VALUE];
+        assert_eq!(encode_adu(BROADCAST_SLAVE_ADDR, exception), rsp);
+        assert_eq!(Slave(5), sensor.slave());
+    }
+
+    #[test]
+    fn answer_other_broadcast_requests_with_slave_address_zero() {
+        let sensor = Sensor::new(Slave(1));
+        let mut transport = Transport::new(vec![sensor]);
+        let rsp = transact(&mut transport, BROADCAST_SLAVE_ADDR, &read_request(BROADCAST_REG_ADDR, 1));
+        assert_eq!(encode_adu(MODBUS_BROADCAST_SLAVE_ADDR, vec![FN_READ_HOLDING_REGISTERS, 2, 0, 1]), rsp);
+        // All other registers are read-only via the broadcast address
+        let rsp = transact(&mut transport, BROADCAST_SLAVE_ADDR, &write_request(RAW_COUNTS, 4));
+        let exception = vec![FN_WRITE_SINGLE_REGISTER | EXCEPTION_FLAG, EXCEPTION_ILLEGAL_DATA_ADDRESS];
+        assert_eq!(encode_adu(MODBUS_BROADCAST_SLAVE_ADDR, exception), rsp);
+    }
+
+    #[test]
+    fn never_answer_modbus_broadcast_requests() {
+        let sensors = vec![Sensor::new(Slave(1)), Sensor::new(Slave(2))];
+        let mut transport = Transport::new(sensors.clone());
+        let request = write_request(Register::SlaveAddress.address(), 4);
+        let rsp = transact(&mut transport, MODBUS_BROADCAST_SLAVE_ADDR, &request);
+        assert!(rsp.is_empty());
+        // Applied by all sensors
+        assert!(sensors.iter().all(|sensor| sensor.slave() == Slave(4)));
+    }
+
+    #[test]
+    fn collide_responses_of_multiple_sensors() {
+        let sensors = vec![Sensor::new(Slave(1)), Sensor::new(Slave(2))];
+        let mut transport = Transport::new(sensors.clone());
+        let rsp = transact(&mut transport, BROADCAST_SLAVE_ADDR, &read_request(BROADCAST_REG_ADDR, 1));
+        assert_eq!(7, rsp.len());
+        assert!(!has_valid_crc(&rsp));
+        // Equal responses collide as well
+        let rsp = transact(&mut transport, BROADCAST_SLAVE_ADDR, &write_request(BROADCAST_REG_ADDR, 3));
+        assert!(!has_valid_crc(&rsp));
+        assert!(sensors.iter().all(|sensor| sensor.slave() == Slave(3)));
+        // Both sensors now share the same address
+        let rsp = transact(&mut transport, 3, &read_request(BROADCAST_REG_ADDR, 1));
+        assert!(!has_valid_crc(&rsp));
+    }
+
+    #[test]
+    fn ignore_requests_with_invalid_checksum() {
+        let mut transport = Transport::new(vec![Sensor::new(Slave(1))]);
+        let mut adu = encode_adu(1, read_request(RAW_COUNTS, 1));
+        *adu.last_mut().unwrap() ^= 0xFF;
+        transport.process_frame(&adu);
+        assert!(transport.response.is_empty());
+    }
+
+    #[tokio::test]
+    async fn broadcast_slave_address_by_client() {
+        let sensor = Sensor::new(Slave(1));
+        let mut context = rtu::connect(Transport::new(vec![sensor.clone()]));
+        modbus::broadcast_slave(&mut context, Slave(7)).await.unwrap();
+        assert_eq!(Slave(7), sensor.slave());
+        context.set_slave(Slave(7));
+        assert_eq!(Slave(7), modbus::read_slave_address(&mut context).await.unwrap());
+    }
+
+    fn samples(latency: LatencyDistribution, count: usize) -> Vec<Duration> {
+        let mut rng = Rng::new(42);
+        (0..count).map(|_| latency.sample(&mut rng)).collect()
+    }
+
+    #[test]
+    fn sample_latency_distributions() {
+        let ms = Duration::from_millis;
+        let fixed = LatencyDistribution::from(Latency::Fixed(ms(20)));
+        assert!(samples(fixed, 100).iter().all(|latency| *latency == ms(20)));
+        let uniform = LatencyDistribution::from(Latency::Uniform {
+            min: ms(10),
+            max: ms(30),
+        });
+        let latencies = samples(uniform, 1_000);
+        assert!(latencies.iter().all(|latency| (ms(10)..=ms(30)).contains(latency)));
+        assert!(latencies.iter().any(|latency| *latency < ms(15)));
+        assert!(latencies.iter().any(|latency| *latency > ms(25)));
+        // Reproducible with the same seed
+        assert_eq!(latencies, samples(uniform, 1_000));
+        let normal = LatencyDistribution::from(Latency::Normal {
+            mean: ms(5),
+            jitter: ms(10),
+        });
+        let latencies = samples(normal, 1_000);
+        // Negative samples are clamped
+        assert!(latencies.contains(&Duration::ZERO));
+        let mean = latencies.iter().sum::<Duration>() / 1_000;
+        assert!(mean > ms(5) && mean < ms(10), "{:?}", mean);
+    }
+
+    #[test]
+    fn sample_latency_tail() {
+        let ms = Duration::from_millis;
+        let mut latency = LatencyDistribution {
+            latency: Latency::Fixed(ms(10)),
+            tail_probability: 0.25,
+            tail: ms(500),
+        };
+        let tails = samples(latency, 1_000)
+            .into_iter()
+            .filter(|latency| *latency == ms(510))
+            .count();
+        assert!((200..300).contains(&tails), "{}", tails);
+        latency.tail_probability = 1.0;
+        assert!(samples(latency, 100).iter().all(|latency| *latency == ms(510)));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn delay_responses_by_latency() {
+        let sensor = Sensor::new(Slave(1));
+        sensor.set_latency(LatencyDistribution::from(Latency::Uniform {
+            min: Duration::from_millis(50),
+            max: Duration::from_millis(80),
+        }));
+        let mut context = rtu::connect(Transport::new(vec![sensor.clone()]));
+        context.set_slave(Slave(1));
+        for _ in 0..10 {
+            let started = Instant::now();
+            modbus::read_measurements(&mut context).await.unwrap();
+            let elapsed = started.elapsed();
+            assert!(elapsed >= Duration::from_millis(50) && elapsed <= Duration::from_millis(80));
+        }
+        sensor.set_response_delay(Duration::from_millis(200));
+        let res = modbus::read_measurements_with_timeout(&mut context, Duration::from_millis(100)).await;
+        assert!(matches!(res, Err(Error::Timeout(_))));
+    }
+}
@@ -0,0 +1,272 @@
+//! A simple physical model of drying soil.
+//!
+//! The water content decays exponentially towards a residual water
+//! content and recovers instantly by irrigation. The temperature follows
+//! a sinusoidal diurnal cycle. The permittivity is derived from the water
+//! content by inverting the equation of Topp et al.
+
+use super::*;
+
+use crate::calib::{CalibrationCurve, TOPP};
+
+use std::f64::consts::PI;
+
+/// Water that is added to the soil at a point in time.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Irrigation {
+    /// The elapsed simulation time.
+    pub at: Duration,
+
+    /// The increase of the water content in percentage points.
+    pub amount: f64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SoilModel {
+    pub initial_water_content: VolumetricWaterContent,
+
+    /// The water content that is approached asymptotically by drying.
+    pub residual_water_content: VolumetricWaterContent,
+
+    /// The water content that can't be exceeded by irrigation.
+    pub saturated_water_content: VolumetricWaterContent,
+
+    /// The decay rate of the water content per hour.
+    pub evaporation_constant: f64,
+
+    /// The mean temperature of the diurnal cycle in °C.
+    pub mean_temperature: f64,
+
+    /// The half peak-to-peak amplitude of the diurnal cycle in °C.
+    pub temperature_amplitude: f64,
+
+    /// The hour of the day with the maximum temperature.
+    pub peak_hour: f64,
+
+    /// The hour of the day when the simulation starts.
+    pub start_hour: f64,
+
+    /// Irrigation events that are sorted chronologically by
+    /// `Simulation::new()`.
+    pub irrigation: Vec<Irrigation>,
+}
+
+impl Default for SoilModel {
+    fn default() -> Self {
+        Self {
+            initial_water_content: VolumetricWaterContent::from_percent(35.0),
+            residual_water_content: VolumetricWaterContent::from_percent(5.0),
+            saturated_water_content: VolumetricWaterContent::from_percent(45.0),
+            evaporation_constant: 0.01,
+            mean_temperature: 15.0,
+            temperature_amplitude: 5.0,
+            peak_hour: 15.0,
+            start_hour: 0.0,
+            irrigation: Vec::new(),
+        }
+    }
+}
+
+impl SoilModel {
+    pub fn water_content_at(&self, elapsed: Duration) -> VolumetricWaterContent {
+        let residual = self.residual_water_content.to_percent();
+        let saturated = self.saturated_water_content.to_percent();
+        let dry = |water_content: f64, hours: f64| {
+            residual + (water_content - residual) * (-self.evaporation_constant * hours).exp()
+        };
+        let mut water_content = self.initial_water_content.to_percent();
+        let mut since = Duration::default();
+        for irrigation in self.irrigation.iter().take_while(|irrigation| irrigation.at <= elapsed) {
+            let hours = irrigation.at.saturating_sub(since).as_secs_f64() / 3600.0;
+            water_content = (dry(water_content, hours) + irrigation.amount).min(saturated);
+            since = irrigation.at;
+        }
+        let hours = (elapsed - since).as_secs_f64() / 3600.0;
+        VolumetricWaterContent::from_percent(dry(water_content, hours))
+    }
+
+    pub fn temperature_at(&self, elapsed: Duration) -> Temperature {
+        let hour = self.start_hour + elapsed.as_secs_f64() / 3600.0;
+        let phase = 2.0 * PI * (hour - self.peak_hour) / 24.0;
+        Temperature::from_degree_celsius(self.mean_temperature + self.temperature_amplitude * phase.cos())
+    }
+
+    pub fn measurements_at(&self, elapsed: Duration) -> Measurements {
+        let water_content = self.water_content_at(elapsed);
+        Measurements {
+            temperature: self.temperature_at(elapsed),
+            water_content,
+            permittivity: permittivity_of(water_content),
+            raw_counts: Proxy::default_raw_counts(),
+        }
+    }
+}
+
+/// Invert the monotonic calibration curve by bisection.
+fn permittivity_of(water_content: VolumetricWaterContent) -> RelativePermittivity {
+    let (mut lower, mut upper) = (RelativePermittivity::min_ratio(), 80.0);
+    for _ in 0..50 {
+        let mid = (lower + upper) / 2.0;
+        if TOPP.water_content(RelativePermittivity::from_ratio(mid)) < water_content {
+            lower = mid;
+        } else {
+            upper = mid;
+        }
+    }
+    RelativePermittivity::from_ratio((lower + upper) / 2.0)
+}
+
+/// Replaces the constant values of the mock with a time series.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Simulation {
+    model: SoilModel,
+    time_scale: f64,
+}
+
+impl Simulation {
+    /// Simulate the model in real time.
+    pub fn new(mut model: SoilModel) -> Self {
+        model.irrigation.sort_by_key(|irrigation| irrigation.at);
+        Self {
+            model,
+            time_scale: 1.0,
+        }
+    }
+
+    /// Simulated time per real time, e.g. 3600.0 for simulating
+    /// an hour per second.
+    ///
+    /// Returns `None` if the time scale is negative or not finite.
+    pub fn with_time_scale(self, time_scale: f64) -> Option<Self> {
+        if time_scale.is_finite() && time_scale >= 0.0 {
+            Some(Self { time_scale, ..self })
+        } else {
+            None
+        }
+    }
+
+    pub fn model(&self) -> &SoilModel {
+        &self.model
+    }
+
+    pub fn time_scale(&self) -> f64 {
+        self.time_scale
+    }
+
+    /// The measurements after `elapsed` real time.
+    ///
+    /// The simulated time saturates instead of overflowing.
+    pub fn measurements_at(&self, elapsed: Duration) -> Measurements {
+        let simulated = Duration::try_from_secs_f64(elapsed.as_secs_f64() * self.time_scale)
+            .unwrap_or(Duration::MAX);
+        self.model.measurements_at(simulated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: Duration = Duration::from_secs(3_600);
+
+    fn percent(water_content: VolumetricWaterContent) -> f64 {
+        water_content.to_percent()
+    }
+
+    #[test]
+    fn dry_towards_the_residual_water_content() {
+        let model = SoilModel::default();
+        assert!((35.0 - percent(model.water_content_at(Duration::ZERO))).abs() < 1e-9);
+        let mut previous = model.water_content_at(Duration::ZERO);
+        for hours in 1..=48 {
+            let water_content = model.water_content_at(HOUR * hours);
+            assert!(water_content < previous);
+            previous = water_content;
+        }
+        // 30 % above the residual water content decay by 1 % per hour
+        let expected = 5.0 + 30.0 * (-0.01 * 100.0f64).exp();
+        assert!((expected - percent(model.water_content_at(HOUR * 100))).abs() < 1e-9);
+        assert!((5.0 - percent(model.water_content_at(HOUR * 10_000))).abs() < 1e-3);
+    }
+
+    #[test]
+    fn irrigate_up_to_the_saturated_water_content() {
+        let model = SoilModel {
+            evaporation_constant: 0.0,
+            irrigation: vec![
+                Irrigation {
+                    at: HOUR,
+                    amount: 5.0,
+                },
+                Irrigation {
+                    at: HOUR * 2,
+                    amount: 20.0,
+                },
+            ],
+            ..Default::default()
+        };
+        assert!((35.0 - percent(model.water_content_at(HOUR / 2))).abs() < 1e-9);
+        assert!((40.0 - percent(model.water_content_at(HOUR))).abs() < 1e-9);
+        assert!((45.0 - percent(model.water_content_at(HOUR * 3))).abs() < 1e-9);
+    }
+
+    #[test]
+    fn sort_the_irrigation_schedule() {
+        let irrigation = |hours| Irrigation {
+            at: HOUR * hours,
+            amount: 5.0,
+        };
+        let model = SoilModel {
+            irrigation: vec![irrigation(5), irrigation(1), irrigation(3)],
+            ..Default::default()
+        };
+        let simulation = Simulation::new(model.clone());
+        let sorted = SoilModel {
+            irrigation: vec![irrigation(1), irrigation(3), irrigation(5)],
+            ..model
+        };
+        assert_eq!(&sorted, simulation.model());
+        for hours in 0..8 {
+            assert_eq!(sorted.measurements_at(HOUR * hours), simulation.measurements_at(HOUR * hours));
+        }
+    }
+
+    #[test]
+    fn follow_the_diurnal_temperature_cycle() {
+        let model = SoilModel::default();
+        let peak = model.temperature_at(HOUR * 15);
+        assert!((20.0 - peak.to_degree_celsius()).abs() < 1e-9);
+        assert!((10.0 - model.temperature_at(HOUR * 3).to_degree_celsius()).abs() < 1e-9);
+        assert_eq!(peak, model.temperature_at(HOUR * 39));
+    }
+
+    #[test]
+    fn derive_the_permittivity_from_the_water_content() {
+        let measurements = SoilModel::default().measurements_at(HOUR);
+        let water_content = TOPP.water_content(measurements.permittivity);
+        assert!((percent(measurements.water_content) - percent(water_content)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn scale_the_simulated_time() {
+        let simulation = Simulation::new(SoilModel::default());
+        assert_eq!(1.0, simulation.time_scale());
+        let simulation = simulation.with_time_scale(3_600.0).unwrap();
+        assert_eq!(
+            simulation.model().measurements_at(HOUR * 10),
+            simulation.measurements_at(Duration::from_secs(10))
+        );
+        // Saturates instead of overflowing
+        simulation.measurements_at(Duration::MAX);
+        let frozen = simulation.clone().with_time_scale(0.0).unwrap();
+        assert_eq!(frozen.measurements_at(Duration::ZERO), frozen.measurements_at(HOUR));
+    }
+
+    #[test]
+    fn reject_invalid_time_scales() {
+        let simulation = Simulation::new(SoilModel::default());
+        assert!(simulation.clone().with_time_scale(-1.0).is_none());
+        assert!(simulation.clone().with_time_scale(f64::NAN).is_none());
+        assert!(simulation.with_time_scale(f64::INFINITY).is_none());
+    }
+}
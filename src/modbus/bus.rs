@@ -0,0 +1,667 @@
+use super::*;
+
+use crate::{
+    history::History,
+    poller::Measurement,
+    runtime::{self, Clock, Runtime},
+};
+
+use futures::future::BoxFuture;
+use std::{
+    collections::BTreeMap,
+    io,
+    sync::{Mutex as StdMutex, MutexGuard},
+    time::{Instant, SystemTime},
+};
+use tokio::sync::Mutex;
+
+/// Creates a new Modbus client context, e.g. by opening a serial port.
+pub trait NewContext: Send + Sync {
+    fn new_context(&self) -> BoxFuture<'_, io::Result<client::Context>>;
+}
+
+struct State {
+    context: Option<client::Context>,
+    last_finished: Option<Instant>,
+}
+
+/// A reconnectable Modbus context that is shared by multiple slaves
+/// on the same bus.
+///
+/// Requests are serialized, i.e. each request waits until its
+/// predecessor has finished before it is started. An optional minimum
+/// delay between the end of a request and the start of the next request
+/// keeps the bus silent in between.
+///
+/// The shared context doesn't depend on a particular async runtime.
+/// Timers are provided by the `Runtime`, i.e. _tokio_ by default.
+/// The current time is provided by the `Clock`, if any.
+pub struct SharedContext {
+    state: Mutex<State>,
+    new_context: Box<dyn NewContext>,
+    runtime: Box<dyn Runtime>,
+    clock: Option<Arc<dyn Clock>>,
+    inter_request_delay: StdMutex<Duration>,
+    diagnostics: StdMutex<BTreeMap<Slave, Diagnostics>>,
+}
+
+impl SharedContext {
+    /// Share an initial context or start disconnected, i.e. until the
+    /// first `reconnect()`.
+    pub fn new(context: Option<client::Context>, new_context: Box<dyn NewContext>) -> Self {
+        Self::with_clock(context, new_context, Arc::new(runtime::Tokio))
+    }
+
+    /// Use the timers of a different runtime than _tokio_.
+    ///
+    /// The time is read from the system clock.
+    pub fn with_runtime(
+        context: Option<client::Context>,
+        new_context: Box<dyn NewContext>,
+        runtime: Box<dyn Runtime>,
+    ) -> Self {
+        Self::with_runtime_and_clock(context, new_context, runtime, None)
+    }
+
+    /// Use both the timers and the time of `clock`, e.g. of a
+    /// `runtime::ManualClock` in tests.
+    pub fn with_clock(
+        context: Option<client::Context>,
+        new_context: Box<dyn NewContext>,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        let runtime = Box::new(Arc::clone(&clock));
+        Self::with_runtime_and_clock(context, new_context, runtime, Some(clock))
+    }
+
+    fn with_runtime_and_clock(
+        context: Option<client::Context>,
+        new_context: Box<dyn NewContext>,
+        runtime: Box<dyn Runtime>,
+        clock: Option<Arc<dyn Clock>>,
+    ) -> Self {
+        Self {
+            state: Mutex::new(State {
+                context,
+                last_finished: None,
+            }),
+            new_context,
+            runtime,
+            clock,
+            inter_request_delay: StdMutex::new(Duration::default()),
+            diagnostics: StdMutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn runtime(&self) -> &dyn Runtime {
+        &*self.runtime
+    }
+
+    fn now(&self) -> Instant {
+        self.clock.as_ref().map_or_else(Instant::now, |clock| clock.now())
+    }
+
+    fn system_time(&self) -> SystemTime {
+        self.clock.as_ref().map_or_else(SystemTime::now, |clock| clock.system_time())
+    }
+
+    /// The minimum delay between two consecutive requests on the bus.
+    pub fn inter_request_delay(&self) -> Duration {
+        *self.inter_request_delay.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Enforce a minimum delay between two consecutive requests on the bus.
+    ///
+    /// Modbus RTU requires a silent interval of at least 3.5 character
+    /// times between frames, i.e. ~4 ms at 9600 baud. Slow transceivers
+    /// might need considerably longer pauses. The delay is disabled by
+    /// default.
+    pub fn set_inter_request_delay(&self, delay: Duration) {
+        *self.inter_request_delay.lock().unwrap_or_else(PoisonError::into_inner) = delay;
+    }
+
+    /// The time since the last request has finished.
+    ///
+    /// Returns `None` while a request is pending. The bus is idle
+    /// forever if no request has been sent yet.
+    pub fn idle_time(&self) -> Option<Duration> {
+        let state = self.state.try_lock().ok()?;
+        Some(state.last_finished.map_or(Duration::MAX, |last_finished| {
+            self.now().saturating_duration_since(last_finished)
+        }))
+    }
+
+    fn lock_diagnostics(&self) -> MutexGuard<'_, BTreeMap<Slave, Diagnostics>> {
+        self.diagnostics.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// The health statistics of all requests for `slave`.
+    pub fn diagnostics(&self, slave: Slave) -> Diagnostics {
+        self.lock_diagnostics().get(&slave).cloned().unwrap_or_default()
+    }
+
+    pub(super) fn record_reconnect(&self, slave: Slave) {
+        self.lock_diagnostics().entry(slave).or_default().record_reconnect();
+    }
+
+    /// Replace the current context with a new context after all
+    /// pending requests have finished.
+    ///
+    /// The current context is disconnected first. Errors while
+    /// disconnecting are ignored, because the connection is usually
+    /// broken anyway. If connecting fails the shared context remains
+    /// disconnected.
+    pub async fn reconnect(&self) -> Result<()> {
+        let mut state = self.state.lock().await;
+        if let Some(mut context) = state.context.take() {
+            if let Err(err) = context.disconnect().await {
+                log::debug!("Failed to disconnect: {}", err);
+            }
+        }
+        state.context = Some(self.new_context.new_context().await?);
+        Ok(())
+    }
+
+    /// Execute a request for `slave` exclusively on the bus after all
+    /// pending requests have finished.
+    pub async fn request<T, F>(&self, slave: Slave, request: F) -> Result<T>
+    where
+        F: for<'c> FnOnce(&'c mut client::Context) -> BoxFuture<'c, Result<T>> + Send,
+    {
+        self.request_with_timeout(slave, None, request).await
+    }
+
+    /// Execute a request for `slave` exclusively on the bus after all
+    /// pending requests have finished.
+    ///
+    /// The timeout only starts when the request is actually sent, i.e.
+    /// it doesn't include the waiting time for pending requests.
+    pub async fn request_with_timeout<T, F>(
+        &self,
+        slave: Slave,
+        timeout: Option<Duration>,
+        request: F,
+    ) -> Result<T>
+    where
+        F: for<'c> FnOnce(&'c mut client::Context) -> BoxFuture<'c, Result<T>> + Send,
+    {
+        self.execute(slave, || Ok(timeout), request).await
+    }
+
+    /// Execute a request for `slave` exclusively on the bus after all
+    /// pending requests have finished.
+    ///
+    /// In contrast to `request_with_timeout()` the waiting time for
+    /// pending requests is included, e.g. for enforcing a common time
+    /// budget for all slaves on the bus. The request is not sent at all
+    /// if `deadline` has already passed.
+    pub async fn request_until<T, F>(
+        &self,
+        slave: Slave,
+        deadline: tokio::time::Instant,
+        request: F,
+    ) -> Result<T>
+    where
+        F: for<'c> FnOnce(&'c mut client::Context) -> BoxFuture<'c, Result<T>> + Send,
+    {
+        let remaining = || {
+            let remaining = deadline.into_std().saturating_duration_since(self.now());
+            if remaining > Duration::default() {
+                Ok(Some(remaining))
+            } else {
+                Err(TimeoutError::default().into())
+            }
+        };
+        self.execute(slave, remaining, request).await
+    }
+
+    /// The timeout is determined right before the request is sent.
+    async fn execute<T, F>(
+        &self,
+        slave: Slave,
+        timeout: impl FnOnce() -> Result<Option<Duration>>,
+        request: F,
+    ) -> Result<T>
+    where
+        F: for<'c> FnOnce(&'c mut client::Context) -> BoxFuture<'c, Result<T>> + Send,
+    {
+        let mut state = self.state.lock().await;
+        if let Some(last_finished) = state.last_finished {
+            let earliest_start = last_finished + self.inter_request_delay();
+            let delay = earliest_start.saturating_duration_since(self.now());
+            if delay > Duration::default() {
+                self.runtime.sleep(delay).await;
+            }
+        }
+        let res = match (state.context.as_mut(), timeout()) {
+            (Some(context), Ok(timeout)) => {
+                context.set_slave(slave);
+                let res = runtime::timeout(&*self.runtime, timeout, request(context)).await;
+                state.last_finished = Some(self.now());
+                res
+            }
+            (None, _) => Err(Error::NotConnected),
+            (_, Err(err)) => Err(err),
+        };
+        let res = res.map_err(|err| err.with_timeout_slave(slave.into()));
+        let now = self.system_time();
+        self.lock_diagnostics().entry(slave).or_default().record_result(&res, now);
+        res
+    }
+}
+
+/// Manages multiple slaves that are connected to the same bus.
+///
+/// All proxies that are handed out by the manager share the same
+/// Modbus context and their requests are serialized to prevent
+/// interleaving frames on the bus.
+pub struct BusManager {
+    shared_context: Arc<SharedContext>,
+    slaves: Vec<Slave>,
+    history_capacity: usize,
+    histories: StdMutex<BTreeMap<Slave, History<Measurements>>>,
+}
+
+impl BusManager {
+    pub fn new(shared_context: Arc<SharedContext>) -> Self {
+        Self {
+            shared_context,
+            slaves: Vec::new(),
+            history_capacity: 0,
+            histories: StdMutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn shared_context(&self) -> &Arc<SharedContext> {
+        &self.shared_context
+    }
+
+    /// The registered slaves in polling order.
+    pub fn slaves(&self) -> &[Slave] {
+        &self.slaves
+    }
+
+    /// Register a slave for polling and obtain a proxy for it.
+    pub fn register(&mut self, slave: Slave) -> SlaveProxy {
+        if !self.slaves.contains(&slave) {
+            self.slaves.push(slave);
+        }
+        self.slave_proxy(slave)
+    }
+
+    /// Remove a slave from polling and discard its history.
+    ///
+    /// Returns `false` if the slave has not been registered.
+    pub fn unregister(&mut self, slave: Slave) -> bool {
+        let len = self.slaves.len();
+        self.slaves.retain(|s| *s != slave);
+        self.lock_histories().remove(&slave);
+        self.slaves.len() < len
+    }
+
+    fn lock_histories(&self) -> MutexGuard<'_, BTreeMap<Slave, History<Measurements>>> {
+        self.histories.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// The number of successful reads that are kept per slave.
+    pub fn history_capacity(&self) -> usize {
+        self.history_capacity
+    }
+
+    /// Keep the measurements of the last `capacity` successful reads
+    /// of `poll_all()` per slave.
+    ///
+    /// The history is disabled by default. Changing the capacity
+    /// discards all histories.
+    pub fn set_history_capacity(&mut self, capacity: usize) {
+        self.history_capacity = capacity;
+        self.lock_histories().clear();
+    }
+
+    /// The recent measurements of a registered slave.
+    pub fn history(&self, slave: Slave) -> Option<History<Measurements>> {
+        self.lock_histories().get(&slave).cloned()
+    }
+
+    /// Obtain a proxy for any slave on the bus, registered or not.
+    pub fn slave_proxy(&self, slave: Slave) -> SlaveProxy {
+        SlaveProxy::new(slave, Arc::clone(&self.shared_context))
+    }
+
+    /// The minimum delay between two consecutive requests on the bus.
+    pub fn inter_request_delay(&self) -> Duration {
+        self.shared_context.inter_request_delay()
+    }
+
+    /// Enforce a minimum delay between two consecutive requests on the bus.
+    ///
+    /// See also: `SharedContext::set_inter_request_delay()`
+    pub fn set_inter_request_delay(&self, delay: Duration) {
+        self.shared_context.set_inter_request_delay(delay);
+    }
+
+    /// Reconnect the shared Modbus context after all pending requests
+    /// have finished.
+    pub async fn reconnect(&self) -> Result<()> {
+        self.shared_context.reconnect().await
+    }
+
+    /// Read all measurements from each registered slave one after
+    /// another in registration order.
+    ///
+    /// Failed reads are reported per slave. The shared context is
+    /// reconnected after errors that render the connection unusable.
+    /// The returned future only fails if reconnecting fails.
+    pub async fn poll_all(&self, timeout: Option<Duration>) -> Result<Vec<(Slave, Result<Measurements>)>> {
+        let mut results = Vec::with_capacity(self.slaves.len());
+        for slave in &self.slaves {
+            let (res, reconnect) = self.read_slave(*slave, timeout).await;
+            results.push((*slave, res));
+            if reconnect {
+                self.slave_proxy(*slave).reconnect().await?;
+            }
+        }
+        Ok(results)
+    }
+
+    /// Read all measurements from each registered slave one after
+    /// another in registration order.
+    ///
+    /// The `timeout_per_slave` applies to each slave separately and
+    /// the inter-request delay of the bus is respected between all
+    /// requests. In contrast to `poll_all()` a failed reconnect doesn't
+    /// abort the whole cycle. It is only logged and the remaining slaves
+    /// will fail with `Error::NotConnected`.
+    pub async fn read_all_slaves(
+        &self,
+        timeout_per_slave: Option<Duration>,
+    ) -> Vec<(Slave, Result<Measurements>)> {
+        let mut results = Vec::with_capacity(self.slaves.len());
+        for slave in &self.slaves {
+            let (res, reconnect) = self.read_slave(*slave, timeout_per_slave).await;
+            results.push((*slave, res));
+            if reconnect {
+                if let Err(err) = self.slave_proxy(*slave).reconnect().await {
+                    log::warn!("Failed to reconnect: {}", err);
+                }
+            }
+        }
+        results
+    }
+
+    /// Read all measurements from `slave` and record them in its history.
+    ///
+    /// Also returns if the shared context needs to be reconnected.
+    async fn read_slave(&self, slave: Slave, timeout: Option<Duration>) -> (Result<Measurements>, bool) {
+        let res = self.slave_proxy(slave).read_all(timeout).await;
+        match res {
+            Ok(measurements) => {
+                self.lock_histories()
+                    .entry(slave)
+                    .or_insert_with(|| History::new(self.history_capacity))
+                    .push(Measurement::at(self.shared_context.system_time(), measurements));
+                (res, false)
+            }
+            Err(ref err) if requires_reconnect(err) => {
+                log::warn!("Failed to read slave {:?}: {}", slave, err);
+                (res, true)
+            }
+            Err(_) => (res, false),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "tokio-mock"))]
+mod tests {
+    use super::*;
+
+    use crate::{
+        mock::{server::Faults, Bus},
+        runtime::ManualClock,
+    };
+    use futures::poll;
+
+    const TIMEOUT: Duration = Duration::from_millis(100);
+
+    #[tokio::test(start_paused = true)]
+    async fn record_diagnostics_per_slave() {
+        let bus = Bus::new();
+        bus.add_slave(Slave(1));
+        let dead = bus.add_slave(Slave(2));
+        dead.set_faults(Some(Faults {
+            timeout: 1.0,
+            ..Default::default()
+        }));
+        let shared_context = bus.shared_context();
+        let alive = SlaveProxy::new(Slave(1), Arc::clone(&shared_context));
+        let dead = SlaveProxy::new(Slave(2), Arc::clone(&shared_context));
+        assert!(alive.read_all(Some(TIMEOUT)).await.is_ok());
+        assert!(alive.read_all(Some(TIMEOUT)).await.is_ok());
+        assert!(dead.read_all(Some(TIMEOUT)).await.is_err());
+        let alive = shared_context.diagnostics(Slave(1));
+        assert_eq!(2, alive.successes);
+        assert_eq!(0, alive.timeouts);
+        let dead = shared_context.diagnostics(Slave(2));
+        assert_eq!(0, dead.successes);
+        assert_eq!(1, dead.timeouts);
+        assert_eq!(Diagnostics::default(), shared_context.diagnostics(Slave(3)));
+    }
+
+    #[tokio::test]
+    async fn delay_requests_by_clock() {
+        let bus = Bus::new();
+        bus.add_slave(Slave(1));
+        let clock = ManualClock::new();
+        let shared_context = Arc::new(SharedContext::with_clock(
+            Some(bus.connect()),
+            Box::new(bus.clone()),
+            Arc::new(clock.clone()),
+        ));
+        let proxy = SlaveProxy::new(Slave(1), Arc::clone(&shared_context));
+        assert_eq!(Some(Duration::MAX), shared_context.idle_time());
+        proxy.read_temperature(None).await.unwrap();
+        assert_eq!(Some(Duration::default()), shared_context.idle_time());
+        clock.advance(Duration::from_millis(5));
+        assert_eq!(Some(Duration::from_millis(5)), shared_context.idle_time());
+        shared_context.set_inter_request_delay(Duration::from_millis(20));
+        let read = proxy.read_temperature(None);
+        futures::pin_mut!(read);
+        assert!(poll!(read.as_mut()).is_pending());
+        assert_eq!(None, shared_context.idle_time());
+        clock.advance(Duration::from_millis(14));
+        assert!(poll!(read.as_mut()).is_pending());
+        clock.advance(Duration::from_millis(1));
+        assert!(poll!(read.as_mut()).is_ready());
+    }
+
+    #[tokio::test]
+    async fn record_last_success_by_clock() {
+        let bus = Bus::new();
+        bus.add_slave(Slave(1));
+        let clock = ManualClock::starting_at(SystemTime::UNIX_EPOCH);
+        let shared_context = Arc::new(SharedContext::with_clock(
+            Some(bus.connect()),
+            Box::new(bus.clone()),
+            Arc::new(clock.clone()),
+        ));
+        let proxy = SlaveProxy::new(Slave(1), Arc::clone(&shared_context));
+        clock.advance(Duration::from_secs(60));
+        proxy.read_temperature(None).await.unwrap();
+        assert_eq!(
+            Some(SystemTime::UNIX_EPOCH + Duration::from_secs(60)),
+            shared_context.diagnostics(Slave(1)).last_success
+        );
+    }
+
+    fn measurements(degree_celsius: f64) -> Measurements {
+        Measurements {
+            temperature: Temperature::from_degree_celsius(degree_celsius),
+            water_content: VolumetricWaterContent::from_percent(30.0),
+            permittivity: RelativePermittivity::from_ratio(12.0),
+            raw_counts: RawCounts::from(4_000),
+        }
+    }
+
+    /// A bus with one slave per temperature at addresses 1, 2, ...
+    fn bus(temperatures: &[f64]) -> Bus {
+        let bus = Bus::new();
+        for (slave, degree_celsius) in (1..).zip(temperatures) {
+            bus.add_slave(Slave(slave)).set_measurements(&measurements(*degree_celsius));
+        }
+        bus
+    }
+
+    fn temperatures(results: &[(Slave, Result<Measurements>)]) -> Vec<(Slave, Option<f64>)> {
+        results
+            .iter()
+            .map(|(slave, res)| {
+                let temperature = res.as_ref().ok().map(|m| m.temperature.to_degree_celsius());
+                (*slave, temperature)
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn poll_registered_slaves_in_order() {
+        let bus = bus(&[10.0, 20.0, 30.0]);
+        let mut manager = BusManager::new(bus.shared_context());
+        manager.set_history_capacity(2);
+        manager.register(Slave(2));
+        manager.register(Slave(1));
+        manager.register(Slave(2));
+        assert_eq!(&[Slave(2), Slave(1)], manager.slaves());
+        let results = manager.poll_all(Some(TIMEOUT)).await.unwrap();
+        assert_eq!(vec![(Slave(2), Some(20.0)), (Slave(1), Some(10.0))], temperatures(&results));
+        manager.poll_all(Some(TIMEOUT)).await.unwrap();
+        manager.poll_all(Some(TIMEOUT)).await.unwrap();
+        assert_eq!(2, manager.history(Slave(1)).unwrap().len());
+        assert!(manager.history(Slave(3)).is_none());
+        assert!(manager.unregister(Slave(2)));
+        assert!(!manager.unregister(Slave(2)));
+        assert!(manager.history(Slave(2)).is_none());
+        assert_eq!(&[Slave(1)], manager.slaves());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn reconnect_after_timeouts_while_polling() {
+        let bus = bus(&[10.0, 20.0]);
+        bus.slave(Slave(1)).unwrap().set_faults(Some(Faults {
+            timeout: 1.0,
+            ..Default::default()
+        }));
+        let mut manager = BusManager::new(bus.shared_context());
+        manager.set_history_capacity(10);
+        manager.register(Slave(1));
+        manager.register(Slave(2));
+        let results = manager.poll_all(Some(TIMEOUT)).await.unwrap();
+        assert_eq!(vec![(Slave(1), None), (Slave(2), Some(20.0))], temperatures(&results));
+        assert!(matches!(results[0].1, Err(Error::Timeout(_))));
+        let shared_context = manager.shared_context();
+        assert_eq!(1, shared_context.diagnostics(Slave(1)).reconnects);
+        assert_eq!(0, shared_context.diagnostics(Slave(2)).reconnects);
+        assert!(manager.history(Slave(1)).is_none());
+        assert_eq!(1, manager.history(Slave(2)).unwrap().len());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn delay_consecutive_requests_of_all_slaves() {
+        let bus = bus(&[10.0, 20.0]);
+        let mut manager = BusManager::new(bus.shared_context());
+        let first = manager.register(Slave(1));
+        let second = manager.register(Slave(2));
+        assert_eq!(Duration::default(), manager.inter_request_delay());
+        let delay = Duration::from_millis(50);
+        manager.set_inter_request_delay(delay);
+        assert_eq!(delay, first.inter_request_delay());
+        assert_eq!(delay, second.inter_request_delay());
+        let started = tokio::time::Instant::now();
+        first.read_temperature(None).await.unwrap();
+        // No delay before the first request
+        assert_eq!(Duration::default(), started.elapsed());
+        second.read_temperature(None).await.unwrap();
+        assert_eq!(delay, started.elapsed());
+        manager.poll_all(None).await.unwrap();
+        assert_eq!(3 * delay, started.elapsed());
+        // Pauses that exceed the delay are not extended
+        tokio::time::sleep(delay).await;
+        first.read_temperature(None).await.unwrap();
+        assert_eq!(4 * delay, started.elapsed());
+    }
+
+    /// Fails to open a new context, e.g. after unplugging the adapter.
+    struct Unplugged;
+
+    impl NewContext for Unplugged {
+        fn new_context(&self) -> BoxFuture<'_, io::Result<client::Context>> {
+            Box::pin(futures::future::err(io::Error::new(io::ErrorKind::NotFound, "unplugged")))
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn read_all_slaves_despite_failed_reconnect() {
+        let bus = bus(&[10.0, 20.0, 30.0]);
+        bus.slave(Slave(2)).unwrap().set_faults(Some(Faults {
+            timeout: 1.0,
+            ..Default::default()
+        }));
+        let shared_context = SharedContext::new(Some(bus.connect()), Box::new(Unplugged));
+        let mut manager = BusManager::new(Arc::new(shared_context));
+        for slave in 1..=3 {
+            manager.register(Slave(slave));
+        }
+        let results = manager.read_all_slaves(Some(TIMEOUT)).await;
+        assert_eq!(
+            vec![(Slave(1), Some(10.0)), (Slave(2), None), (Slave(3), None)],
+            temperatures(&results)
+        );
+        assert!(matches!(results[1].1, Err(Error::Timeout(_))));
+        assert!(matches!(results[2].1, Err(Error::NotConnected)));
+        // In contrast, polling is aborted
+        assert!(manager.poll_all(Some(TIMEOUT)).await.is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn serialize_concurrent_requests_of_slaves() {
+        let bus = bus(&[10.0, 20.0]);
+        let response_delay = Duration::from_millis(10);
+        for slave in bus.slaves() {
+            bus.slave(slave).unwrap().set_response_delay(response_delay);
+        }
+        let shared_context = bus.shared_context();
+        let first = SlaveProxy::new(Slave(1), Arc::clone(&shared_context));
+        let second = SlaveProxy::new(Slave(2), Arc::clone(&shared_context));
+        let started = tokio::time::Instant::now();
+        let (first_res, second_res) = futures::join!(first.read_all(None), second.read_all(None));
+        assert_eq!(measurements(10.0), first_res.unwrap());
+        assert_eq!(measurements(20.0), second_res.unwrap());
+        assert_eq!(2 * response_delay, started.elapsed());
+        let delay = Duration::from_millis(5);
+        shared_context.set_inter_request_delay(delay);
+        let started = tokio::time::Instant::now();
+        let (first_res, second_res) = futures::join!(first.read_all(None), second.read_all(None));
+        assert!(first_res.is_ok());
+        assert!(second_res.is_ok());
+        // The first request is delayed after the preceding requests
+        assert_eq!(2 * (delay + response_delay), started.elapsed());
+        assert_eq!(2, first.diagnostics().successes);
+        assert_eq!(2, second.diagnostics().successes);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn timeouts_start_when_the_request_is_sent() {
+        let bus = bus(&[10.0, 20.0]);
+        bus.slave(Slave(1)).unwrap().set_response_delay(3 * TIMEOUT);
+        let shared_context = bus.shared_context();
+        let first = SlaveProxy::new(Slave(1), Arc::clone(&shared_context));
+        let second = SlaveProxy::new(Slave(2), Arc::clone(&shared_context));
+        let (first_res, second_res) = futures::join!(
+            first.read_all(Some(TIMEOUT)),
+            second.read_all(Some(TIMEOUT))
+        );
+        assert!(matches!(first_res, Err(Error::Timeout(_))));
+        // Waiting for the first request doesn't count
+        assert_eq!(measurements(20.0), second_res.unwrap());
+        assert_eq!(1, shared_context.diagnostics(Slave(1)).timeouts);
+        assert_eq!(1, shared_context.diagnostics(Slave(2)).successes);
+    }
+}
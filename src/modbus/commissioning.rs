@@ -0,0 +1,236 @@
+use super::*;
+
+use futures::Future;
+
+/// Guards all configuration writes on a live bus.
+///
+/// Changing the slave address or the serial port settings affects
+/// all subsequent requests and broadcast writes even affect all devices
+/// on the bus. These writes are only available after explicitly entering
+/// a commissioning session and never by proxies that are used for
+/// polling measurements.
+///
+/// Each write is verified by reading back the written registers
+/// unless disabled by `set_verify_writes()`.
+pub struct CommissioningSession {
+    shared_context: Arc<SharedContext>,
+    verify_writes: bool,
+}
+
+/// Read back the registers after writing them to the given slave
+/// if enabled.
+async fn verify(
+    context: &mut client::Context,
+    enabled: bool,
+    slave: Slave,
+    start: u16,
+    expected: &[u16],
+) -> Result<()> {
+    if !enabled {
+        return Ok(());
+    }
+    context.set_slave(slave);
+    verify_registers(context, start, expected).await
+}
+
+impl CommissioningSession {
+    /// Enter without verifying the devices on the bus.
+    pub fn enter(shared_context: Arc<SharedContext>) -> Self {
+        log::info!("Entering commissioning session");
+        Self {
+            shared_context,
+            verify_writes: true,
+        }
+    }
+
+    /// Enter only if exactly one of the given slaves responds.
+    ///
+    /// See also: `scan_bus()`
+    pub async fn enter_single_device(
+        shared_context: Arc<SharedContext>,
+        slaves: impl IntoIterator<Item = Slave>,
+        timeout: Duration,
+    ) -> Result<(Self, Slave)> {
+        let found = scan_bus(&shared_context, slaves, timeout).await?;
+        if let [slave] = found[..] {
+            Ok((Self::enter(shared_context), slave))
+        } else {
+            Err(CommissioningError::SingleDeviceExpected(found.len()).into())
+        }
+    }
+
+    pub fn shared_context(&self) -> &Arc<SharedContext> {
+        &self.shared_context
+    }
+
+    pub fn verify_writes(&self) -> bool {
+        self.verify_writes
+    }
+
+    /// Enable or disable the verification of all subsequent writes.
+    ///
+    /// Enabled by default. Only disable it for devices with registers
+    /// that cannot be read back.
+    pub fn set_verify_writes(&mut self, verify_writes: bool) {
+        self.verify_writes = verify_writes;
+    }
+
+    /// Switch the Modbus slave address of all connected devices.
+    ///
+    /// The new address is verified by reading it back from the new
+    /// address, i.e. only a single device must be connected.
+    ///
+    /// See also: `broadcast_slave()`
+    pub async fn broadcast_slave(&self, slave: Slave) -> Result<()> {
+        let verify_writes = self.verify_writes;
+        self.shared_context
+            .request(slave, move |context| {
+                async move {
+                    self::broadcast_slave(context, slave).await?;
+                    let expected = [u16::from(slave.0)];
+                    let addr = Register::SlaveAddress.address();
+                    verify(context, verify_writes, slave, addr, &expected).await
+                }
+                .boxed()
+            })
+            .await
+    }
+
+    /// Switch the Modbus slave address of a single device.
+    ///
+    /// The new address is verified by reading it back from the new
+    /// address.
+    ///
+    /// See also: `set_slave_address()`
+    pub async fn set_slave_address(&self, current_slave: Slave, new_slave: Slave) -> Result<()> {
+        let verify_writes = self.verify_writes;
+        self.shared_context
+            .request(current_slave, move |context| {
+                async move {
+                    self::set_slave_address(context, current_slave, new_slave).await?;
+                    let expected = [u16::from(new_slave.0)];
+                    let addr = Register::SlaveAddress.address();
+                    verify(context, verify_writes, new_slave, addr, &expected).await
+                }
+                .boxed()
+            })
+            .await
+    }
+
+    /// Assign sequential slave addresses to sensors that are
+    /// connected one after another, starting at `first`.
+    ///
+    /// Before each assignment `confirm` is awaited with the next
+    /// address, e.g. for prompting the user to connect the next sensor.
+    /// The address is broadcast and then verified by reading it back
+    /// from the new address. The procedure ends when `confirm` returns
+    /// `false` or all valid addresses have been assigned.
+    ///
+    /// Only a single unassigned sensor must be connected to the bus
+    /// when confirming, because all connected devices receive the
+    /// broadcast address.
+    pub async fn assign_addresses<F, C>(
+        &self,
+        first: Slave,
+        timeout: Duration,
+        mut confirm: F,
+    ) -> Result<Vec<Slave>>
+    where
+        F: FnMut(Slave) -> C,
+        C: Future<Output = bool>,
+    {
+        let mut assigned = Vec::new();
+        for slave_id in first.0..=Slave::max_device().0 {
+            let slave = Slave(slave_id);
+            if !confirm(slave).await {
+                break;
+            }
+            self.shared_context
+                .request_with_timeout(BROADCAST_SLAVE, Some(timeout), move |context| {
+                    self::broadcast_slave(context, slave).boxed()
+                })
+                .await?;
+            let configured = self
+                .shared_context
+                .request_with_timeout(slave, Some(timeout), |context| read_slave_address(context).boxed())
+                .await?;
+            if configured != slave {
+                return Err(VerificationError {
+                    register: Register::SlaveAddress.address(),
+                    expected: slave.0.into(),
+                    actual: configured.0.into(),
+                }
+                .into());
+            }
+            log::info!("Assigned slave address {}", slave.0);
+            assigned.push(slave);
+        }
+        Ok(assigned)
+    }
+}
+
+impl Drop for CommissioningSession {
+    fn drop(&mut self) {
+        log::info!("Leaving commissioning session");
+    }
+}
+
+#[cfg(all(test, feature = "tokio-mock"))]
+mod tests {
+    use super::*;
+
+    use crate::mock::{server::Sensor, Bus};
+
+    fn enter(slave: Slave) -> (Sensor, CommissioningSession) {
+        let bus = Bus::new();
+        let sensor = bus.add_slave(slave);
+        (sensor, CommissioningSession::enter(bus.shared_context()))
+    }
+
+    #[tokio::test]
+    async fn verify_writes_by_default() {
+        let (sensor, session) = enter(Slave(1));
+        assert!(session.verify_writes());
+        session.set_slave_address(Slave(1), Slave(2)).await.unwrap();
+        assert_eq!(Slave(2), sensor.slave());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn detect_lost_writes() {
+        let (sensor, session) = enter(Slave(1));
+        sensor.set_lose_writes(true);
+        // The read-back from the new address is never answered
+        let res = tokio::time::timeout(
+            Duration::from_secs(1),
+            session.set_slave_address(Slave(1), Slave(2)),
+        )
+        .await;
+        assert!(res.is_err());
+        assert_eq!(Slave(1), sensor.slave());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn enter_only_with_a_single_device() {
+        let bus = Bus::new();
+        bus.add_slave(Slave(1));
+        let timeout = Duration::from_millis(100);
+        let slaves = || (1..=3).map(Slave);
+        let enter = || CommissioningSession::enter_single_device(bus.shared_context(), slaves(), timeout);
+        let (_session, slave) = enter().await.unwrap();
+        assert_eq!(Slave(1), slave);
+        bus.add_slave(Slave(3));
+        match enter().await {
+            Err(Error::Commissioning(err)) => assert_eq!(CommissioningError::SingleDeviceExpected(2), err),
+            res => panic!("Unexpected result: {:?}", res.map(|(_, slave)| slave)),
+        }
+    }
+
+    #[tokio::test]
+    async fn ignore_lost_writes_without_verification() {
+        let (sensor, mut session) = enter(Slave(1));
+        sensor.set_lose_writes(true);
+        session.set_verify_writes(false);
+        session.set_slave_address(Slave(1), Slave(2)).await.unwrap();
+        assert_eq!(Slave(1), sensor.slave());
+    }
+}
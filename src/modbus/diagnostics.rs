@@ -0,0 +1,62 @@
+use super::*;
+
+use crate::ErrorKind;
+
+use std::{io, time::SystemTime};
+
+/// Health statistics of a single slave.
+///
+/// The numbers help to distinguish wiring problems, e.g. frequent
+/// decode failures because of corrupted frames, from dead sensors
+/// that only time out.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Diagnostics {
+    /// The total number of requests, successful or not.
+    pub requests: u64,
+
+    /// The number of requests that succeeded.
+    pub successes: u64,
+
+    /// The number of requests without a response in time.
+    pub timeouts: u64,
+
+    /// The number of responses that have been rejected because of an
+    /// invalid checksum or contents.
+    pub decode_failures: u64,
+
+    /// The number of attempts to reconnect the shared context after
+    /// requests for this slave failed.
+    pub reconnects: u64,
+
+    /// The most recent error.
+    pub last_error: Option<ErrorKind>,
+
+    /// The time of the most recent successful request.
+    pub last_success: Option<SystemTime>,
+}
+
+impl Diagnostics {
+    pub(super) fn record_result<T>(&mut self, res: &Result<T>, now: SystemTime) {
+        self.requests += 1;
+        let err = match res {
+            Ok(_) => {
+                self.successes += 1;
+                self.last_success = Some(now);
+                return;
+            }
+            Err(err) => err,
+        };
+        match err {
+            Error::Timeout(_) => self.timeouts += 1,
+            Error::Decode(_) => self.decode_failures += 1,
+            // Frames with an invalid checksum are rejected by tokio-modbus
+            Error::Transport(err) if err.kind() == io::ErrorKind::InvalidData => self.decode_failures += 1,
+            _ => (),
+        }
+        self.last_error = Some(err.kind());
+    }
+
+    pub(super) fn record_reconnect(&mut self) {
+        self.reconnects += 1;
+    }
+}
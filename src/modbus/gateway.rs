@@ -0,0 +1,368 @@
+//! Serve the cached measurements of RTU slaves as a Modbus TCP server.
+//!
+//! The slow RS-485 bus is only polled periodically by the gateway
+//! while SCADA systems read the latest values at any rate. The
+//! measurement registers keep the layout of the sensor and the
+//! unit identifier of each request selects the slave address.
+//!
+//! Only the measurement registers could be read. Slaves without
+//! valid measurements are answered with the exception code
+//! _gateway target device failed to respond_.
+
+use super::*;
+
+use crate::{
+    core::modbus::rtu::{EXCEPTION_FLAG, MAX_FRAME_LEN},
+    runtime::{self, Clock},
+};
+
+use futures::{pin_mut, Future};
+use std::{
+    collections::HashMap,
+    io,
+    sync::{Mutex, MutexGuard},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+const FN_READ_INPUT_REGISTERS: u8 = 0x04;
+
+const EXCEPTION_ILLEGAL_FUNCTION: u8 = 0x01;
+const EXCEPTION_ILLEGAL_DATA_ADDRESS: u8 = 0x02;
+const EXCEPTION_ILLEGAL_DATA_VALUE: u8 = 0x03;
+const EXCEPTION_GATEWAY_PATH_UNAVAILABLE: u8 = 0x0A;
+const EXCEPTION_GATEWAY_TARGET_FAILED: u8 = 0x0B;
+
+/// Transaction id, protocol id, length, and unit id.
+const MBAP_HEADER_LEN: usize = 7;
+
+const MODBUS_TCP_PROTOCOL_ID: u16 = 0;
+
+/// Failed requests are answered with an exception code.
+type ExceptionResult<T> = std::result::Result<T, u8>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GatewayConfig {
+    /// The period for polling all slaves on the bus.
+    pub cycle_time: Duration,
+
+    /// The timeout for reading the measurements of each slave.
+    pub timeout: Option<Duration>,
+}
+
+impl Default for GatewayConfig {
+    fn default() -> Self {
+        Self {
+            cycle_time: Duration::from_secs(10),
+            timeout: Some(Duration::from_secs(1)),
+        }
+    }
+}
+
+/// The latest measurements of all registered slaves.
+///
+/// Entries are `None` if the last read failed.
+type Cache = Arc<Mutex<HashMap<SlaveId, Option<Measurements>>>>;
+
+fn lock_cache(cache: &Cache) -> MutexGuard<'_, HashMap<SlaveId, Option<Measurements>>> {
+    cache.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Polls all slaves of a `BusManager` and serves their latest
+/// measurements to Modbus TCP clients.
+pub struct Gateway {
+    bus: BusManager,
+    config: GatewayConfig,
+    cache: Cache,
+    clock: Arc<dyn Clock>,
+}
+
+impl Gateway {
+    /// Only slaves that are registered at the `bus` are served.
+    pub fn new(bus: BusManager, config: GatewayConfig) -> Self {
+        let cache = bus.slaves().iter().map(|slave| (slave.0, None)).collect();
+        Self {
+            bus,
+            config,
+            cache: Arc::new(Mutex::new(cache)),
+            clock: Arc::new(runtime::Tokio),
+        }
+    }
+
+    /// Schedule the poll cycles by a different clock than _tokio_,
+    /// e.g. a `runtime::ManualClock` in tests.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn bus(&self) -> &BusManager {
+        &self.bus
+    }
+
+    pub fn config(&self) -> &GatewayConfig {
+        &self.config
+    }
+
+    /// The cached measurements of a slave.
+    pub fn latest(&self, slave: Slave) -> Option<Measurements> {
+        lock_cache(&self.cache).get(&slave.0).copied().flatten()
+    }
+
+    /// Read all registered slaves once and update the cache.
+    pub async fn poll_once(&self) {
+        let results = self.bus.read_all_slaves(self.config.timeout).await;
+        let mut cache = lock_cache(&self.cache);
+        for (slave, res) in results {
+            let measurements = match res {
+                Ok(measurements) => Some(measurements),
+                Err(err) => {
+                    log::warn!("Failed to read measurements of slave {:?}: {}", slave, err);
+                    None
+                }
+            };
+            cache.insert(slave.0, measurements);
+        }
+    }
+
+    /// Poll the bus and accept clients until `shutdown` resolves.
+    ///
+    /// Each client connection is handled by a separate task.
+    pub async fn run(self, listener: TcpListener, shutdown: impl Future<Output = ()>) -> io::Result<Self> {
+        pin_mut!(shutdown);
+        let mut next_cycle = self.clock.now();
+        loop {
+            let poll_cycle = self.clock.sleep_until(next_cycle);
+            tokio::select! {
+                () = &mut shutdown => break,
+                () = poll_cycle => {
+                    self.poll_once().await;
+                    next_cycle += self.config.cycle_time;
+                    // Skip missed cycles
+                    next_cycle = next_cycle.max(self.clock.now());
+                }
+                accepted = listener.accept() => {
+                    let (stream, peer) = accepted?;
+                    log::debug!("Accepted Modbus TCP client {}", peer);
+                    let cache = Arc::clone(&self.cache);
+                    tokio::spawn(async move {
+                        if let Err(err) = serve_client(stream, cache).await {
+                            log::debug!("Disconnected Modbus TCP client {}: {}", peer, err);
+                        }
+                    });
+                }
+            }
+        }
+        Ok(self)
+    }
+}
+
+async fn serve_client(mut stream: TcpStream, cache: Cache) -> io::Result<()> {
+    let mut header = [0; MBAP_HEADER_LEN];
+    loop {
+        match stream.read_exact(&mut header).await {
+            Ok(_) => (),
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(err) => return Err(err),
+        }
+        let len = usize::from(u16::from_be_bytes([header[4], header[5]]));
+        if !(2..=MAX_FRAME_LEN).contains(&len) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid MBAP length"));
+        }
+        let mut pdu = vec![0; len - 1];
+        stream.read_exact(&mut pdu).await?;
+        let protocol_id = u16::from_be_bytes([header[2], header[3]]);
+        if protocol_id != MODBUS_TCP_PROTOCOL_ID {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid protocol id"));
+        }
+        let rsp = process(&cache, header[6], &pdu);
+        let mut frame = Vec::with_capacity(MBAP_HEADER_LEN + rsp.len());
+        frame.extend_from_slice(&header[..4]);
+        frame.extend_from_slice(&(rsp.len() as u16 + 1).to_be_bytes());
+        frame.push(header[6]);
+        frame.extend(rsp);
+        stream.write_all(&frame).await?;
+    }
+}
+
+fn read_registers(measurements: &Measurements, addr: u16, cnt: u16) -> ExceptionResult<Vec<u8>> {
+    if cnt == 0 {
+        return Err(EXCEPTION_ILLEGAL_DATA_VALUE);
+    }
+    let start = addr
+        .checked_sub(MEASUREMENTS_REG_START)
+        .map(usize::from)
+        .ok_or(EXCEPTION_ILLEGAL_DATA_ADDRESS)?;
+    let end = start + usize::from(cnt);
+    let values = encode_measurements_to_u16s(measurements);
+    let values = values.get(start..end).ok_or(EXCEPTION_ILLEGAL_DATA_ADDRESS)?;
+    let mut data = Vec::with_capacity(1 + 2 * values.len());
+    data.push((2 * cnt) as u8);
+    for value in values {
+        data.extend_from_slice(&value.to_be_bytes());
+    }
+    Ok(data)
+}
+
+/// Process a request PDU for a unit and return the response PDU.
+fn process(cache: &Cache, unit_id: SlaveId, pdu: &[u8]) -> Vec<u8> {
+    let function = pdu[0];
+    let res = match function {
+        FN_READ_HOLDING_REGISTERS | FN_READ_INPUT_REGISTERS if pdu.len() == 5 => {
+            match lock_cache(cache).get(&unit_id) {
+                None => Err(EXCEPTION_GATEWAY_PATH_UNAVAILABLE),
+                Some(None) => Err(EXCEPTION_GATEWAY_TARGET_FAILED),
+                Some(Some(measurements)) => read_registers(
+                    measurements,
+                    u16::from_be_bytes([pdu[1], pdu[2]]),
+                    u16::from_be_bytes([pdu[3], pdu[4]]),
+                ),
+            }
+        }
+        _ => Err(EXCEPTION_ILLEGAL_FUNCTION),
+    };
+    match res {
+        Ok(data) => {
+            let mut rsp = Vec::with_capacity(1 + data.len());
+            rsp.push(function);
+            rsp.extend(data);
+            rsp
+        }
+        Err(exception) => vec![function | EXCEPTION_FLAG, exception],
+    }
+}
+
+#[cfg(all(test, feature = "tokio-mock"))]
+mod tests {
+    use super::*;
+
+    use crate::{
+        mock::{server::Faults, Bus},
+        runtime::ManualClock,
+    };
+
+    fn measurements() -> Measurements {
+        Measurements {
+            temperature: Temperature::from_degree_celsius(21.5),
+            water_content: VolumetricWaterContent::from_percent(34.5),
+            permittivity: RelativePermittivity::from_ratio(15.25),
+            raw_counts: RawCounts::from(11_345),
+        }
+    }
+
+    /// Slave 1 responds and slave 2 times out.
+    async fn polled_gateway() -> Gateway {
+        let bus = Bus::new();
+        bus.add_slave(Slave(1)).set_measurements(&measurements());
+        bus.add_slave(Slave(2)).set_faults(Some(Faults {
+            timeout: 1.0,
+            ..Default::default()
+        }));
+        let mut manager = BusManager::new(bus.shared_context());
+        manager.register(Slave(1));
+        manager.register(Slave(2));
+        let config = GatewayConfig {
+            timeout: Some(Duration::from_millis(100)),
+            ..Default::default()
+        };
+        let gateway = Gateway::new(manager, config);
+        gateway.poll_once().await;
+        gateway
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn serve_cached_measurements() {
+        let gateway = polled_gateway().await;
+        assert_eq!(Some(measurements()), gateway.latest(Slave(1)));
+        assert_eq!(None, gateway.latest(Slave(2)));
+        let cache = &gateway.cache;
+        let rsp = process(cache, 1, &[FN_READ_INPUT_REGISTERS, 0x00, 0x00, 0x00, 0x04]);
+        assert_eq!(
+            vec![FN_READ_INPUT_REGISTERS, 8, 0x2F, 0x76, 0x0D, 0x7A, 0x05, 0xF5, 0x2C, 0x51],
+            rsp
+        );
+        let rsp = process(cache, 1, &[FN_READ_HOLDING_REGISTERS, 0x00, 0x01, 0x00, 0x01]);
+        assert_eq!(vec![FN_READ_HOLDING_REGISTERS, 2, 0x0D, 0x7A], rsp);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn reject_invalid_requests() {
+        let gateway = polled_gateway().await;
+        let cache = &gateway.cache;
+        let read = |unit_id, addr: u16, cnt: u16| {
+            let [addr_hi, addr_lo] = addr.to_be_bytes();
+            let [cnt_hi, cnt_lo] = cnt.to_be_bytes();
+            process(cache, unit_id, &[FN_READ_HOLDING_REGISTERS, addr_hi, addr_lo, cnt_hi, cnt_lo])
+        };
+        let exception = |code| vec![FN_READ_HOLDING_REGISTERS | EXCEPTION_FLAG, code];
+        assert_eq!(exception(EXCEPTION_GATEWAY_TARGET_FAILED), read(2, 0, 4));
+        assert_eq!(exception(EXCEPTION_GATEWAY_PATH_UNAVAILABLE), read(3, 0, 4));
+        assert_eq!(exception(EXCEPTION_ILLEGAL_DATA_ADDRESS), read(1, 3, 2));
+        assert_eq!(exception(EXCEPTION_ILLEGAL_DATA_VALUE), read(1, 0, 0));
+        assert_eq!(
+            vec![0x06 | EXCEPTION_FLAG, EXCEPTION_ILLEGAL_FUNCTION],
+            process(cache, 1, &[0x06, 0x00, 0x00, 0x00, 0x01])
+        );
+    }
+
+    #[tokio::test]
+    async fn serve_tcp_client() {
+        let bus = Bus::new();
+        bus.add_slave(Slave(1)).set_measurements(&measurements());
+        let mut manager = BusManager::new(bus.shared_context());
+        manager.register(Slave(1));
+        let gateway = Gateway::new(manager, GatewayConfig::default());
+        gateway.poll_once().await;
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(gateway.run(listener, async {
+            shutdown_rx.await.ok();
+        }));
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let request = [0x12, 0x34, 0x00, 0x00, 0x00, 0x06, 0x01, 0x04, 0x00, 0x00, 0x00, 0x01];
+        stream.write_all(&request).await.unwrap();
+        let mut response = [0; 11];
+        stream.read_exact(&mut response).await.unwrap();
+        assert_eq!([0x12, 0x34, 0x00, 0x00, 0x00, 0x05, 0x01, 0x04, 0x02, 0x2F, 0x76], response);
+        shutdown_tx.send(()).unwrap();
+        let gateway = server.await.unwrap().unwrap();
+        assert_eq!(Some(measurements()), gateway.latest(Slave(1)));
+    }
+
+    #[tokio::test]
+    async fn poll_cycles_by_clock() {
+        let bus = Bus::new();
+        bus.add_slave(Slave(1));
+        let shared_context = bus.shared_context();
+        let mut manager = BusManager::new(Arc::clone(&shared_context));
+        manager.register(Slave(1));
+        let clock = ManualClock::new();
+        let gateway = Gateway::new(manager, GatewayConfig::default()).with_clock(Arc::new(clock.clone()));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(gateway.run(listener, async {
+            shutdown_rx.await.ok();
+        }));
+        let successes = || shared_context.diagnostics(Slave(1)).successes;
+        let settle = || async {
+            for _ in 0..100 {
+                tokio::task::yield_now().await;
+            }
+        };
+        // The first cycle starts immediately
+        settle().await;
+        let polled = successes();
+        assert!(polled > 0);
+        clock.advance(Duration::from_secs(9));
+        settle().await;
+        assert_eq!(polled, successes());
+        clock.advance(Duration::from_secs(1));
+        settle().await;
+        assert_eq!(2 * polled, successes());
+        shutdown_tx.send(()).unwrap();
+        server.await.unwrap().unwrap();
+    }
+}
@@ -3,31 +3,49 @@ use super::*;
 #[cfg(feature = "rtu")]
 pub mod rtu;
 
-use crate::core::modbus::*;
+#[cfg(feature = "tokio-modbus-rtu-over-tcp")]
+pub mod rtu_over_tcp;
 
-use futures::Future;
-use std::{
-    cell::RefCell,
-    io::{Error, ErrorKind, Result},
-    rc::Rc,
-    time::Duration,
+#[cfg(feature = "modbus-rtu-sync")]
+pub mod sync;
+
+mod bus;
+pub use self::bus::{BusManager, NewContext, SharedContext};
+
+mod diagnostics;
+pub use self::diagnostics::Diagnostics;
+
+mod commissioning;
+pub use self::commissioning::CommissioningSession;
+
+#[cfg(feature = "gateway")]
+pub mod gateway;
+
+mod watchdog;
+pub use self::watchdog::{BusHealth, Watchdog, WatchdogConfig};
+
+use crate::{
+    core::{
+        modbus::{
+            rtu::{FN_READ_HOLDING_REGISTERS, FN_WRITE_MULTIPLE_REGISTERS, FN_WRITE_SINGLE_REGISTER},
+            *,
+        },
+        profiles::{SensorProfile, Smt100},
+    },
+    poller::{measurement_stream, PollConfig, TimestampedMeasurements},
+    retry::RetryPolicy,
 };
-use tokio::prelude::*;
 
-use tokio_modbus::{
-    client::util::{reconnect_shared_context, SharedContext},
-    prelude::*,
+use futures::{future::BoxFuture, FutureExt, Stream};
+use tokio::time::Instant;
+use std::{
+    convert::TryFrom,
+    io::ErrorKind,
+    sync::{Arc, PoisonError},
+    time::Duration,
 };
 
-impl From<DecodeError> for Error {
-    fn from(from: DecodeError) -> Self {
-        use DecodeError::*;
-        match from {
-            InsufficientInput | InvalidInput => Self::new(ErrorKind::InvalidInput, from),
-            InvalidData => Self::new(ErrorKind::InvalidData, from),
-        }
-    }
-}
+use tokio_modbus::prelude::{client, Client, Reader, Slave, SlaveContext, SlaveId, Writer};
 
 /// The fixed broadcast address of all sensors that cannot be altered.
 ///
@@ -37,281 +55,1179 @@ impl From<DecodeError> for Error {
 /// slave address 0 (= broadcast) and might be rejected by _tokio-modbus_!
 pub const BROADCAST_SLAVE: Slave = Slave(BROADCAST_SLAVE_ADDR);
 
+impl From<Smt100Address> for Slave {
+    fn from(from: Smt100Address) -> Self {
+        Slave(from.get())
+    }
+}
+
+impl TryFrom<Slave> for Smt100Address {
+    type Error = InvalidAddressError;
+
+    fn try_from(from: Slave) -> std::result::Result<Self, Self::Error> {
+        Self::try_from(from.0)
+    }
+}
+
+
 /// Switch the Modbus slave address of all connected devices.
-pub fn broadcast_slave(
-    context: &mut client::Context,
-    slave: Slave,
-) -> impl Future<Item = (), Error = Error> {
+pub async fn broadcast_slave(context: &mut client::Context, slave: Slave) -> Result<()> {
     context.set_slave(BROADCAST_SLAVE);
     let slave_id: SlaveId = slave.into();
-    context.write_single_register(BROADCAST_REG_ADDR, u16::from(slave_id))
+    context
+        .write_single_register(BROADCAST_REG_ADDR, u16::from(slave_id))
+        .await?
+        .map_err(exception(FN_WRITE_SINGLE_REGISTER, BROADCAST_REG_ADDR))
 }
 
-pub fn read_temperature(
+/// Switch the Modbus slave address of a single device that is
+/// currently addressed by `current_slave`.
+///
+/// In contrast to `broadcast_slave()` this function could be used
+/// if multiple devices are connected.
+pub async fn set_slave_address(
     context: &mut client::Context,
-) -> impl Future<Item = Temperature, Error = Error> {
+    current_slave: Slave,
+    new_slave: Slave,
+) -> Result<()> {
+    context.set_slave(current_slave);
+    let slave_id: SlaveId = new_slave.into();
     context
-        .read_holding_registers(TEMPERATURE_REG_START, TEMPERATURE_REG_COUNT)
-        .and_then(|rsp| {
-            if let [raw] = rsp[..] {
-                decode_temperature_from_u16(raw).map_err(Into::into)
-            } else {
-                Err(Error::new(
-                    ErrorKind::InvalidData,
-                    format!("unexpected temperature data: {:?}", rsp),
-                ))
-            }
-        })
+        .write_single_register(Register::SlaveAddress.address(), u16::from(slave_id))
+        .await?
+        .map_err(exception(FN_WRITE_SINGLE_REGISTER, Register::SlaveAddress.address()))
+}
+
+/// Read the Modbus slave address that is configured in the device.
+pub async fn read_slave_address(context: &mut client::Context) -> Result<Slave> {
+    let reg = read_single_register(context, Register::SlaveAddress.address()).await?;
+    Ok(Slave(decode_slave_address_from_u16(reg)?))
+}
+
+/// Attach the request to exception responses.
+pub(crate) fn exception(function: u8, register: u16) -> impl FnOnce(tokio_modbus::ExceptionCode) -> Error {
+    move |code| ModbusException::new(u8::from(code)).with_request(function, register).into()
+}
+
+async fn read_single_register(context: &mut client::Context, reg_addr: u16) -> Result<u16> {
+    let rsp = context
+        .read_holding_registers(reg_addr, 1)
+        .await?
+        .map_err(exception(FN_READ_HOLDING_REGISTERS, reg_addr))?;
+    if let [reg] = rsp[..] {
+        Ok(reg)
+    } else {
+        Err(DecodeError::InvalidInput.into())
+    }
+}
+
+/// Read and decode a block of consecutive holding registers within
+/// a single Modbus transaction.
+pub async fn read_block<T, const START: u16, const COUNT: u16>(context: &mut client::Context) -> Result<T>
+where
+    T: FromRegisterBlock<START, COUNT>,
+{
+    let rsp = context
+        .read_holding_registers(START, COUNT)
+        .await?
+        .map_err(exception(FN_READ_HOLDING_REGISTERS, START))?;
+    if rsp.len() == usize::from(COUNT) {
+        Ok(T::from_registers(&rsp)?)
+    } else {
+        Err(DecodeError::InvalidInput.into())
+    }
+}
+
+pub async fn read_temperature(context: &mut client::Context) -> Result<Temperature> {
+    read_block(context).await
 }
 
-pub fn read_temperature_with_timeout(
+pub async fn read_temperature_with_timeout(
     context: &mut client::Context,
     timeout: Duration,
-) -> impl Future<Item = Temperature, Error = Error> {
-    read_temperature(context).timeout(timeout)
-        .map_err(move |err| {
-            err.into_inner().unwrap_or_else(|| {
-                Error::new(
-                    ErrorKind::TimedOut,
-                    String::from("reading temperature timed out"),
-                )
-            })
-        })
+) -> Result<Temperature> {
+    with_timeout(Some(timeout), read_temperature(context)).await
 }
 
-pub fn read_water_content(
+pub async fn read_temperature_until(
     context: &mut client::Context,
-) -> impl Future<Item = VolumetricWaterContent, Error = Error> {
-    context
-        .read_holding_registers(WATER_CONTENT_REG_START, WATER_CONTENT_REG_COUNT)
-        .and_then(|rsp| {
-            if let [reg] = rsp[..] {
-                decode_water_content_from_u16(reg).map_err(Into::into)
-            } else {
-                Err(Error::new(
-                    ErrorKind::InvalidData,
-                    format!("unexpected water content data: {:?}", rsp),
-                ))
-            }
-        })
+    deadline: Instant,
+) -> Result<Temperature> {
+    with_deadline(deadline, read_temperature(context)).await
+}
+
+pub async fn read_water_content(context: &mut client::Context) -> Result<VolumetricWaterContent> {
+    read_block(context).await
 }
 
-pub fn read_water_content_with_timeout(
+pub async fn read_water_content_with_timeout(
     context: &mut client::Context,
     timeout: Duration,
-) -> impl Future<Item = VolumetricWaterContent, Error = Error> {
-    read_water_content(context)
-        .timeout(timeout)
-        .map_err(move |err| {
-            err.into_inner().unwrap_or_else(|| {
-                Error::new(
-                    ErrorKind::TimedOut,
-                    String::from("reading water content timed out"),
-                )
-            })
-        })
+) -> Result<VolumetricWaterContent> {
+    with_timeout(Some(timeout), read_water_content(context)).await
 }
 
-pub fn read_permittivity(
+pub async fn read_water_content_until(
     context: &mut client::Context,
-) -> impl Future<Item = RelativePermittivity, Error = Error> {
-    context
-        .read_holding_registers(PERMITTIVITY_REG_START, PERMITTIVITY_REG_COUNT)
-        .and_then(|rsp| {
-            if let [reg] = rsp[..] {
-                decode_permittivity_from_u16(reg).map_err(Into::into)
-            } else {
-                Err(Error::new(
-                    ErrorKind::InvalidData,
-                    format!("unexpected permittivity data: {:?}", rsp),
-                ))
-            }
-        })
+    deadline: Instant,
+) -> Result<VolumetricWaterContent> {
+    with_deadline(deadline, read_water_content(context)).await
 }
 
-pub fn read_permittivity_with_timeout(
+pub async fn read_permittivity(context: &mut client::Context) -> Result<RelativePermittivity> {
+    read_block(context).await
+}
+
+pub async fn read_permittivity_with_timeout(
     context: &mut client::Context,
     timeout: Duration,
-) -> impl Future<Item = RelativePermittivity, Error = Error> {
-    read_permittivity(context)
-        .timeout(timeout)
-        .map_err(move |err| {
-            err.into_inner().unwrap_or_else(|| {
-                Error::new(
-                    ErrorKind::TimedOut,
-                    String::from("reading permittivity timed out"),
-                )
-            })
-        })
+) -> Result<RelativePermittivity> {
+    with_timeout(Some(timeout), read_permittivity(context)).await
+}
+
+pub async fn read_permittivity_until(
+    context: &mut client::Context,
+    deadline: Instant,
+) -> Result<RelativePermittivity> {
+    with_deadline(deadline, read_permittivity(context)).await
+}
+
+pub async fn read_raw_counts(context: &mut client::Context) -> Result<RawCounts> {
+    read_block(context).await
 }
 
-pub fn read_raw_counts(
+pub async fn read_raw_counts_with_timeout(
     context: &mut client::Context,
-) -> impl Future<Item = RawCounts, Error = Error> {
+    timeout: Duration,
+) -> Result<RawCounts> {
+    with_timeout(Some(timeout), read_raw_counts(context)).await
+}
+
+pub async fn read_raw_counts_until(
+    context: &mut client::Context,
+    deadline: Instant,
+) -> Result<RawCounts> {
+    with_deadline(deadline, read_raw_counts(context)).await
+}
+
+/// Read consecutive holding registers that are not modeled by this crate.
+pub async fn read_registers(context: &mut client::Context, start: u16, count: u16) -> Result<Vec<u16>> {
+    let rsp = context
+        .read_holding_registers(start, count)
+        .await?
+        .map_err(exception(FN_READ_HOLDING_REGISTERS, start))?;
+    if rsp.len() == usize::from(count) {
+        Ok(rsp)
+    } else {
+        Err(DecodeError::InvalidInput.into())
+    }
+}
+
+/// Write a single holding register that is not modeled by this crate.
+pub async fn write_register(context: &mut client::Context, addr: u16, value: u16) -> Result<()> {
     context
-        .read_holding_registers(RAW_COUNTS_REG_START, RAW_COUNTS_REG_COUNT)
-        .and_then(|rsp| {
-            if let [reg] = rsp[..] {
-                decode_raw_counts_from_u16(reg).map_err(Into::into)
-            } else {
-                Err(Error::new(
-                    ErrorKind::InvalidData,
-                    format!("unexpected raw counts data: {:?}", rsp),
-                ))
-            }
-        })
+        .write_single_register(addr, value)
+        .await?
+        .map_err(exception(FN_WRITE_SINGLE_REGISTER, addr))
 }
 
-pub fn read_raw_counts_with_timeout(
+/// Write consecutive holding registers that are not modeled by this
+/// crate within a single transaction.
+pub async fn write_registers(context: &mut client::Context, start: u16, values: &[u16]) -> Result<()> {
+    context
+        .write_multiple_registers(start, values)
+        .await?
+        .map_err(exception(FN_WRITE_MULTIPLE_REGISTERS, start))
+}
+
+/// Refuse raw writes to the writable registers of the register map,
+/// which are reserved for a `CommissioningSession`.
+fn check_unprotected(start: u16, count: usize) -> Result<()> {
+    let end = usize::from(start) + count;
+    let protected = Register::ALL
+        .iter()
+        .filter(|reg| reg.descriptor().access == Access::ReadWrite)
+        .find(|reg| usize::from(reg.address()) < end && start < reg.range().end);
+    match protected {
+        Some(reg) => Err(CommissioningError::ProtectedRegister(reg.address()).into()),
+        None => Ok(()),
+    }
+}
+
+/// Read back consecutive holding registers after writing them.
+///
+/// Fails with `Error::VerificationFailed` for the first register that
+/// doesn't contain the expected value, e.g. if a write has silently
+/// been lost on a noisy bus.
+pub async fn verify_registers(context: &mut client::Context, start: u16, expected: &[u16]) -> Result<()> {
+    let count = u16::try_from(expected.len()).map_err(|_| DecodeError::InvalidInput)?;
+    let actual = read_registers(context, start, count).await?;
+    let mismatch = expected
+        .iter()
+        .zip(actual)
+        .zip(start..)
+        .find(|((expected, actual), _)| **expected != *actual);
+    if let Some(((&expected, actual), register)) = mismatch {
+        return Err(VerificationError {
+            register,
+            expected,
+            actual,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// Write consecutive holding registers within a single transaction
+/// and verify them by reading them back.
+///
+/// See also: `verify_registers()`
+pub async fn write_registers_verified(
+    context: &mut client::Context,
+    start: u16,
+    values: &[u16],
+) -> Result<()> {
+    write_registers(context, start, values).await?;
+    verify_registers(context, start, values).await
+}
+
+/// Read all measurements at once within a single Modbus transaction.
+pub async fn read_measurements(context: &mut client::Context) -> Result<Measurements> {
+    read_block(context).await
+}
+
+/// Read the selected measurements at once within a single Modbus
+/// transaction.
+///
+/// Only the consecutive registers that contain the selected quantities
+/// are requested. Nothing is requested if the selection is empty.
+pub async fn read_selected_measurements(
+    context: &mut client::Context,
+    selection: MeasurementSelection,
+) -> Result<SelectedMeasurements> {
+    read_selected_measurements_with_profile(context, &Smt100, selection).await
+}
+
+/// Read the selected measurements of a sensor with the register layout
+/// of the given profile at once within a single Modbus transaction.
+///
+/// Quantities that are not measured by the sensor remain `None`.
+pub async fn read_selected_measurements_with_profile(
+    context: &mut client::Context,
+    profile: &(dyn SensorProfile + Sync),
+    selection: MeasurementSelection,
+) -> Result<SelectedMeasurements> {
+    let (start, count) = match profile.selected_measurements_reg_range(selection) {
+        Some(range) => range,
+        None => return Ok(SelectedMeasurements::default()),
+    };
+    let rsp = context
+        .read_holding_registers(start, count)
+        .await?
+        .map_err(exception(FN_READ_HOLDING_REGISTERS, start))?;
+    Ok(profile.decode_selected_measurements(selection, &rsp)?)
+}
+
+pub async fn read_measurements_with_timeout(
     context: &mut client::Context,
     timeout: Duration,
-) -> impl Future<Item = RawCounts, Error = Error> {
-    read_raw_counts(context)
-        .timeout(timeout)
-        .map_err(move |err| {
-            err.into_inner().unwrap_or_else(|| {
-                Error::new(
-                    ErrorKind::TimedOut,
-                    String::from("reading raw counts timed out"),
-                )
-            })
-        })
+) -> Result<Measurements> {
+    with_timeout(Some(timeout), read_measurements(context)).await
+}
+
+pub async fn read_measurements_until(
+    context: &mut client::Context,
+    deadline: Instant,
+) -> Result<Measurements> {
+    with_deadline(deadline, read_measurements(context)).await
+}
+
+/// Probe the given slave addresses one after another and collect
+/// all slaves that responded in time.
+///
+/// Slaves that respond with a Modbus exception are present, too.
+/// The shared context is reconnected after probes that failed with
+/// a timeout or transport error, because the RTU connection might
+/// have become unusable.
+pub async fn scan_bus(
+    shared_context: &SharedContext,
+    slaves: impl IntoIterator<Item = Slave>,
+    timeout: Duration,
+) -> Result<Vec<Slave>> {
+    let slaves: Vec<_> = slaves.into_iter().collect();
+    let mut found = Vec::new();
+    for slave in slaves {
+        let probe = shared_context.request_with_timeout(slave, Some(timeout), |context| {
+            read_raw_counts(context).boxed()
+        });
+        match probe.await {
+            Ok(_) | Err(Error::ModbusException(_)) => {
+                log::debug!("Found slave {:?}", slave);
+                found.push(slave);
+            }
+            Err(err) => {
+                log::debug!("No response from slave {:?}: {}", slave, err);
+                if requires_reconnect(&err) {
+                    shared_context.reconnect().await?;
+                }
+            }
+        }
+    }
+    Ok(found)
+}
+
+/// The outcome of a liveness probe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProbeResult {
+    /// The slave sent a valid response.
+    Responding,
+
+    /// No response has been received in time, e.g. from a dead
+    /// or disconnected sensor.
+    Timeout,
+
+    /// The slave responded with a Modbus exception code.
+    Exception(ExceptionCode),
+
+    /// The response has been corrupted, e.g. due to wiring problems.
+    DecodeError(DecodeError),
 }
 
 pub struct SlaveProxy {
     slave: Slave,
-    shared_context: Rc<RefCell<SharedContext>>,
+    shared_context: Arc<SharedContext>,
+    default_timeout: Option<Duration>,
+    retry_policy: Option<RetryPolicy>,
+    profile: Arc<dyn SensorProfile + Send + Sync>,
 }
 
 impl SlaveProxy {
-    pub fn new(slave: Slave, shared_context: Rc<RefCell<SharedContext>>) -> Self {
-        Self {
-            slave,
-            shared_context,
-        }
+    /// Create a proxy for a single slave.
+    ///
+    /// Requests of all proxies that share the same context are
+    /// serialized. Use a `BusManager` for polling multiple slaves
+    /// on the same bus.
+    pub fn new(slave: Slave, shared_context: Arc<SharedContext>) -> Self {
+        Self::builder(shared_context).slave(slave).build()
+    }
+
+    /// Create a proxy with a default timeout and retries.
+    pub fn builder(shared_context: Arc<SharedContext>) -> SlaveProxyBuilder {
+        SlaveProxyBuilder::new(shared_context)
     }
 
     pub fn slave(&self) -> Slave {
         self.slave
     }
 
+    /// The minimum delay between two consecutive requests.
+    pub fn inter_request_delay(&self) -> Duration {
+        self.shared_context.inter_request_delay()
+    }
+
+    /// Enforce a minimum delay between two consecutive requests.
+    ///
+    /// The delay is shared by all proxies that share the same context.
+    /// See also `SharedContext::set_inter_request_delay()`.
+    pub fn set_inter_request_delay(&self, delay: Duration) {
+        self.shared_context.set_inter_request_delay(delay);
+    }
+
+    /// The health statistics of all requests for this slave.
+    ///
+    /// The statistics are shared by all proxies for the same slave
+    /// that share the same context.
+    pub fn diagnostics(&self) -> Diagnostics {
+        self.shared_context.diagnostics(self.slave)
+    }
+
     /// Reconnect a new, shared Modbus context to recover from communication errors.
-    pub fn reconnect(&self) -> impl Future<Item = (), Error = Error> {
-        reconnect_shared_context(&self.shared_context)
+    pub async fn reconnect(&self) -> Result<()> {
+        self.shared_context.record_reconnect(self.slave);
+        self.shared_context.reconnect().await
     }
 
-    fn shared_context(&self) -> Result<Rc<RefCell<client::Context>>> {
-        if let Some(context) = self.shared_context.borrow().share_context() {
-            Ok(context)
-        } else {
-            Err(Error::new(ErrorKind::NotConnected, "No shared context"))
-        }
+    /// The timeout of requests without an explicit timeout.
+    pub fn default_timeout(&self) -> Option<Duration> {
+        self.default_timeout
     }
 
-    /// Switch the Modbus slave address of all connected devices.
-    pub fn broadcast_slave(&self) -> impl Future<Item = (), Error = Error> {
-        match self.shared_context() {
-            Ok(shared_context) => future::Either::A(self::broadcast_slave(
-                &mut shared_context.borrow_mut(),
-                self.slave,
-            )),
-            Err(err) => future::Either::B(future::err(err)),
-        }
+    /// The policy for retrying failed requests.
+    pub fn retry_policy(&self) -> Option<&RetryPolicy> {
+        self.retry_policy.as_ref()
     }
 
-    pub fn read_temperature(
-        &self,
-        timeout: Option<Duration>,
-    ) -> impl Future<Item = Temperature, Error = Error> {
-        match self.shared_context() {
-            Ok(shared_context) => {
-                let mut context = shared_context.borrow_mut();
-                context.set_slave(self.slave);
-                future::Either::A(if let Some(timeout) = timeout {
-                    future::Either::A(read_temperature_with_timeout(&mut context, timeout))
-                } else {
-                    future::Either::B(read_temperature(&mut context))
-                })
+    /// The register layout of the sensor for reading selected measurements.
+    pub fn profile(&self) -> &(dyn SensorProfile + Send + Sync) {
+        &*self.profile
+    }
+
+    /// Execute a request exclusively on the bus after all pending
+    /// requests have finished.
+    ///
+    /// Failed requests are retried according to the retry policy. The
+    /// shared context is reconnected before retrying after errors that
+    /// render the connection unusable.
+    async fn request<T, F>(&self, timeout: Option<Duration>, request: F) -> Result<T>
+    where
+        F: for<'c> Fn(&'c mut client::Context) -> BoxFuture<'c, Result<T>> + Send + Sync,
+    {
+        let timeout = timeout.or(self.default_timeout);
+        let mut retry = 0;
+        loop {
+            let res = self
+                .shared_context
+                .request_with_timeout(self.slave, timeout, &request)
+                .await;
+            match (res, self.retry_policy.as_ref()) {
+                (Err(err), Some(policy)) if retry < policy.max_retries && (policy.retry_on)(&err) => {
+                    let delay = policy.delay(retry);
+                    log::debug!(
+                        "Retrying request for slave {:?} after {:?} ({} of {}): {}",
+                        self.slave,
+                        delay,
+                        retry + 1,
+                        policy.max_retries,
+                        err
+                    );
+                    if requires_reconnect(&err) {
+                        self.reconnect().await?;
+                    }
+                    self.shared_context.runtime().sleep(delay).await;
+                    retry += 1;
+                }
+                (res, _) => return res,
             }
-            Err(err) => future::Either::B(future::err(err)),
         }
     }
 
-    pub fn read_water_content(
+    /// Execute a request exclusively on the bus after all pending
+    /// requests have finished if `deadline` has not passed yet.
+    async fn request_until<T, F>(&self, deadline: Instant, request: F) -> Result<T>
+    where
+        F: for<'c> FnOnce(&'c mut client::Context) -> BoxFuture<'c, Result<T>> + Send,
+    {
+        self.shared_context
+            .request_until(self.slave, deadline, request)
+            .await
+    }
+
+    pub async fn read_temperature(&self, timeout: Option<Duration>) -> Result<Temperature> {
+        self.request(timeout, |context| read_temperature(context).boxed())
+            .await
+            .map_err(|err| err.with_timeout_quantity(Quantity::Temperature))
+    }
+
+    pub async fn read_water_content(&self, timeout: Option<Duration>) -> Result<VolumetricWaterContent> {
+        self.request(timeout, |context| read_water_content(context).boxed())
+            .await
+            .map_err(|err| err.with_timeout_quantity(Quantity::WaterContent))
+    }
+
+    pub async fn read_permittivity(&self, timeout: Option<Duration>) -> Result<RelativePermittivity> {
+        self.request(timeout, |context| read_permittivity(context).boxed())
+            .await
+            .map_err(|err| err.with_timeout_quantity(Quantity::Permittivity))
+    }
+
+    pub async fn read_raw_counts(&self, timeout: Option<Duration>) -> Result<RawCounts> {
+        self.request(timeout, |context| read_raw_counts(context).boxed())
+            .await
+            .map_err(|err| err.with_timeout_quantity(Quantity::RawCounts))
+    }
+
+    pub async fn read_temperature_until(&self, deadline: Instant) -> Result<Temperature> {
+        self.request_until(deadline, |context| read_temperature(context).boxed())
+            .await
+            .map_err(|err| err.with_timeout_quantity(Quantity::Temperature))
+    }
+
+    pub async fn read_water_content_until(&self, deadline: Instant) -> Result<VolumetricWaterContent> {
+        self.request_until(deadline, |context| read_water_content(context).boxed())
+            .await
+            .map_err(|err| err.with_timeout_quantity(Quantity::WaterContent))
+    }
+
+    pub async fn read_permittivity_until(&self, deadline: Instant) -> Result<RelativePermittivity> {
+        self.request_until(deadline, |context| read_permittivity(context).boxed())
+            .await
+            .map_err(|err| err.with_timeout_quantity(Quantity::Permittivity))
+    }
+
+    pub async fn read_raw_counts_until(&self, deadline: Instant) -> Result<RawCounts> {
+        self.request_until(deadline, |context| read_raw_counts(context).boxed())
+            .await
+            .map_err(|err| err.with_timeout_quantity(Quantity::RawCounts))
+    }
+
+    /// Read raw holding registers, e.g. vendor registers of newer
+    /// firmware versions that are not modeled by this crate.
+    ///
+    /// See also: `read_registers()`
+    pub async fn read_registers(
         &self,
+        start: u16,
+        count: u16,
         timeout: Option<Duration>,
-    ) -> impl Future<Item = VolumetricWaterContent, Error = Error> {
-        match self.shared_context() {
-            Ok(shared_context) => {
-                let mut context = shared_context.borrow_mut();
-                context.set_slave(self.slave);
-                future::Either::A(if let Some(timeout) = timeout {
-                    future::Either::A(read_water_content_with_timeout(&mut context, timeout))
-                } else {
-                    future::Either::B(read_water_content(&mut context))
-                })
+    ) -> Result<Vec<u16>> {
+        self.request(timeout, move |context| self::read_registers(context, start, count).boxed())
+            .await
+    }
+
+    /// Write a raw holding register.
+    ///
+    /// Writable registers of the register map like the slave address
+    /// are refused with `CommissioningError::ProtectedRegister`. Use a
+    /// `CommissioningSession` for changing them.
+    ///
+    /// See also: `write_register()`
+    pub async fn write_register(&self, addr: u16, value: u16, timeout: Option<Duration>) -> Result<()> {
+        check_unprotected(addr, 1)?;
+        self.request(timeout, move |context| self::write_register(context, addr, value).boxed())
+            .await
+    }
+
+    /// Write raw consecutive holding registers within a single
+    /// transaction.
+    ///
+    /// Refused if any of the registers is protected, see
+    /// `write_register()`.
+    ///
+    /// See also: `write_registers()`
+    pub async fn write_registers(&self, start: u16, values: &[u16], timeout: Option<Duration>) -> Result<()> {
+        check_unprotected(start, values.len())?;
+        let values = values.to_vec();
+        self.request(timeout, move |context| {
+            let values = values.clone();
+            async move { self::write_registers(context, start, &values).await }.boxed()
+        })
+        .await
+    }
+
+    /// Check if the slave is alive with a minimal request.
+    ///
+    /// Only failures that are unrelated to the slave itself, e.g. if the
+    /// shared context is not connected, are returned as errors.
+    pub async fn probe(&self, timeout: Option<Duration>) -> Result<ProbeResult> {
+        match self.read_raw_counts(timeout).await {
+            Ok(_) => Ok(ProbeResult::Responding),
+            Err(Error::Timeout(_)) => Ok(ProbeResult::Timeout),
+            Err(Error::ModbusException(exception)) => Ok(ProbeResult::Exception(exception.code)),
+            Err(Error::Decode(err)) => Ok(ProbeResult::DecodeError(err)),
+            // Frames with an invalid checksum are rejected by tokio-modbus
+            Err(Error::Transport(ref err)) if err.kind() == ErrorKind::InvalidData => {
+                Ok(ProbeResult::DecodeError(DecodeError::InvalidInput))
             }
-            Err(err) => future::Either::B(future::err(err)),
+            Err(err) => Err(err),
         }
     }
 
-    pub fn read_permittivity(
+    /// Periodically measure the configured quantities.
+    ///
+    /// See also: `poller::measurement_stream()`
+    pub fn into_measurement_stream(
+        self,
+        config: PollConfig,
+    ) -> impl Stream<Item = Result<TimestampedMeasurements>> {
+        measurement_stream(self, config)
+    }
+
+    /// Read all measurements at once within a single Modbus transaction.
+    pub async fn read_all(&self, timeout: Option<Duration>) -> Result<Measurements> {
+        self.request(timeout, |context| read_measurements(context).boxed())
+            .await
+    }
+
+    /// Read all measurements at once within a single Modbus transaction
+    /// that must finish before `deadline`.
+    pub async fn read_all_until(&self, deadline: Instant) -> Result<Measurements> {
+        self.request_until(deadline, |context| read_measurements(context).boxed())
+            .await
+    }
+
+    /// Read the selected measurements at once within a single Modbus
+    /// transaction.
+    ///
+    /// The registers are requested and decoded according to the profile
+    /// of the sensor. Quantities that are not measured by the sensor
+    /// remain `None`.
+    pub async fn read_selected(
         &self,
+        selection: MeasurementSelection,
         timeout: Option<Duration>,
-    ) -> impl Future<Item = RelativePermittivity, Error = Error> {
-        match self.shared_context() {
-            Ok(shared_context) => {
-                let mut context = shared_context.borrow_mut();
-                context.set_slave(self.slave);
-                future::Either::A(if let Some(timeout) = timeout {
-                    future::Either::A(read_permittivity_with_timeout(&mut context, timeout))
-                } else {
-                    future::Either::B(read_permittivity(&mut context))
-                })
-            }
-            Err(err) => future::Either::B(future::err(err)),
+    ) -> Result<SelectedMeasurements> {
+        self.request(timeout, move |context| {
+            let profile = Arc::clone(&self.profile);
+            async move { read_selected_measurements_with_profile(context, &*profile, selection).await }
+                .boxed()
+        })
+        .await
+    }
+}
+
+/// Creates a `SlaveProxy`.
+pub struct SlaveProxyBuilder {
+    slave: Slave,
+    shared_context: Arc<SharedContext>,
+    default_timeout: Option<Duration>,
+    retry_policy: Option<RetryPolicy>,
+    profile: Arc<dyn SensorProfile + Send + Sync>,
+}
+
+impl SlaveProxyBuilder {
+    /// The slave defaults to `Slave::min_device()` of an SMT100 without
+    /// a default timeout and retries.
+    pub fn new(shared_context: Arc<SharedContext>) -> Self {
+        Self {
+            slave: Slave::min_device(),
+            shared_context,
+            default_timeout: None,
+            retry_policy: None,
+            profile: Arc::new(Smt100),
         }
     }
 
-    pub fn read_raw_counts(&self, timeout: Option<Duration>) -> impl Future<Item = RawCounts, Error = Error> {
-        match self.shared_context() {
-            Ok(shared_context) => {
-                let mut context = shared_context.borrow_mut();
-                context.set_slave(self.slave);
-                future::Either::A(if let Some(timeout) = timeout {
-                    future::Either::A(read_raw_counts_with_timeout(&mut context, timeout))
-                } else {
-                    future::Either::B(read_raw_counts(&mut context))
-                })
-            }
-            Err(err) => future::Either::B(future::err(err)),
+    pub fn slave(mut self, slave: Slave) -> Self {
+        self.slave = slave;
+        self
+    }
+
+    /// Applies to all requests that are sent without an explicit timeout.
+    pub fn default_timeout(mut self, timeout: Duration) -> Self {
+        self.default_timeout = Some(timeout);
+        self
+    }
+
+    /// Retry failed requests with the default `RetryPolicy`.
+    pub fn retries(mut self, max_retries: u32) -> Self {
+        let policy = self.retry_policy.take().unwrap_or_default();
+        self.retry_policy = Some(RetryPolicy { max_retries, ..policy });
+        self
+    }
+
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = Some(policy);
+        self
+    }
+
+    /// Read selected measurements of a sibling sensor with a different
+    /// register layout.
+    pub fn profile(mut self, profile: impl SensorProfile + Send + Sync + 'static) -> Self {
+        self.profile = Arc::new(profile);
+        self
+    }
+
+    pub fn build(self) -> SlaveProxy {
+        let Self {
+            slave,
+            shared_context,
+            default_timeout,
+            retry_policy,
+            profile,
+        } = self;
+        SlaveProxy {
+            slave,
+            shared_context,
+            default_timeout,
+            retry_policy,
+            profile,
         }
     }
 }
 
 impl Capabilities for SlaveProxy {
-    fn read_temperature(
-        &self,
-        timeout: Option<Duration>,
-    ) -> Box<dyn Future<Item = Temperature, Error = Error>> {
-        Box::new(self.read_temperature(timeout))
+    fn read_temperature(&self, timeout: Option<Duration>) -> BoxFuture<'_, Result<Temperature>> {
+        self.read_temperature(timeout).boxed()
     }
 
-    fn read_water_content(
-        &self,
-        timeout: Option<Duration>,
-    ) -> Box<dyn Future<Item = VolumetricWaterContent, Error = Error>> {
-        Box::new(self.read_water_content(timeout))
+    fn read_water_content(&self, timeout: Option<Duration>) -> BoxFuture<'_, Result<VolumetricWaterContent>> {
+        self.read_water_content(timeout).boxed()
     }
 
-    fn read_permittivity(
-        &self,
-        timeout: Option<Duration>,
-    ) -> Box<dyn Future<Item = RelativePermittivity, Error = Error>> {
-        Box::new(self.read_permittivity(timeout))
+    fn read_permittivity(&self, timeout: Option<Duration>) -> BoxFuture<'_, Result<RelativePermittivity>> {
+        self.read_permittivity(timeout).boxed()
+    }
+
+    fn read_raw_counts(&self, timeout: Option<Duration>) -> BoxFuture<'_, Result<RawCounts>> {
+        self.read_raw_counts(timeout).boxed()
+    }
+}
+
+/// Errors that require to reconnect the Modbus context for recovery.
+///
+/// This includes timeouts, because the RTU connection might become
+/// unusable after a request timed out.
+pub fn requires_reconnect(err: &Error) -> bool {
+    match err {
+        Error::NotConnected | Error::Timeout(_) => true,
+        Error::Transport(err) => matches!(
+            err.kind(),
+            ErrorKind::BrokenPipe
+                | ErrorKind::ConnectionAborted
+                | ErrorKind::ConnectionReset
+                | ErrorKind::NotConnected
+                | ErrorKind::UnexpectedEof
+        ),
+        _ => false,
+    }
+}
+
+/// Decorator that transparently reconnects the shared Modbus context
+/// and repeats a failed request once.
+pub struct ReconnectingProxy {
+    proxy: SlaveProxy,
+}
+
+impl ReconnectingProxy {
+    pub fn new(proxy: SlaveProxy) -> Self {
+        Self { proxy }
+    }
+
+    pub fn proxy(&self) -> &SlaveProxy {
+        &self.proxy
+    }
+
+    async fn request_with_reconnect<'a, T, F>(&'a self, request: F) -> Result<T>
+    where
+        F: Fn(&'a SlaveProxy) -> BoxFuture<'a, Result<T>>,
+    {
+        match request(&self.proxy).await {
+            Err(err) if requires_reconnect(&err) => {
+                log::warn!("Reconnecting after error: {}", err);
+                self.proxy.reconnect().await?;
+                request(&self.proxy).await
+            }
+            res => res,
+        }
     }
+}
 
-    fn read_raw_counts(&self, timeout: Option<Duration>) -> Box<dyn Future<Item = RawCounts, Error = Error>> {
-        Box::new(self.read_raw_counts(timeout))
+impl Capabilities for ReconnectingProxy {
+    fn read_temperature(&self, timeout: Option<Duration>) -> BoxFuture<'_, Result<Temperature>> {
+        self.request_with_reconnect(move |proxy| proxy.read_temperature(timeout).boxed())
+            .boxed()
+    }
+
+    fn read_water_content(&self, timeout: Option<Duration>) -> BoxFuture<'_, Result<VolumetricWaterContent>> {
+        self.request_with_reconnect(move |proxy| proxy.read_water_content(timeout).boxed())
+            .boxed()
+    }
+
+    fn read_permittivity(&self, timeout: Option<Duration>) -> BoxFuture<'_, Result<RelativePermittivity>> {
+        self.request_with_reconnect(move |proxy| proxy.read_permittivity(timeout).boxed())
+            .boxed()
+    }
+
+    fn read_raw_counts(&self, timeout: Option<Duration>) -> BoxFuture<'_, Result<RawCounts>> {
+        self.request_with_reconnect(move |proxy| proxy.read_raw_counts(timeout).boxed())
+            .boxed()
+    }
+}
+
+#[cfg(all(test, feature = "tokio-mock"))]
+mod tests {
+    use super::*;
+
+    use crate::{
+        mock::{
+            server::{Faults, Sensor},
+            Bus,
+        },
+        poller::Poller,
+    };
+    use std::{
+        io,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    const TIMEOUT: Duration = Duration::from_millis(100);
+
+    fn measurements() -> Measurements {
+        Measurements {
+            temperature: Temperature::from_degree_celsius(21.5),
+            water_content: VolumetricWaterContent::from_percent(34.5),
+            permittivity: RelativePermittivity::from_ratio(15.25),
+            raw_counts: RawCounts::from(11_345),
+        }
+    }
+
+    fn timeouts() -> Option<Faults> {
+        Some(Faults {
+            timeout: 1.0,
+            ..Default::default()
+        })
+    }
+
+    fn shared_context(slave: Slave) -> (Sensor, Arc<SharedContext>) {
+        let bus = Bus::new();
+        let sensor = bus.add_slave(slave);
+        sensor.set_measurements(&measurements());
+        (sensor, bus.shared_context())
+    }
+
+    fn connect(slave: Slave) -> (Sensor, client::Context) {
+        let bus = Bus::new();
+        let sensor = bus.add_slave(slave);
+        let mut context = bus.connect();
+        context.set_slave(slave);
+        (sensor, context)
+    }
+
+    #[tokio::test]
+    async fn write_registers_with_mismatched_read_back() {
+        let (sensor, mut context) = connect(Slave(1));
+        sensor.set_lose_writes(true);
+        let addr = Register::SlaveAddress.address();
+        match write_registers_verified(&mut context, addr, &[2]).await {
+            Err(Error::VerificationFailed(err)) => {
+                assert_eq!(
+                    VerificationError {
+                        register: addr,
+                        expected: 2,
+                        actual: 1,
+                    },
+                    err
+                );
+            }
+            res => panic!("Unexpected result: {:?}", res),
+        }
+    }
+
+    #[tokio::test]
+    async fn verify_too_many_registers() {
+        let (_sensor, mut context) = connect(Slave(1));
+        let expected = vec![0; usize::from(u16::MAX) + 1];
+        match verify_registers(&mut context, 0, &expected).await {
+            Err(Error::Decode(DecodeError::InvalidInput)) => (),
+            res => panic!("Unexpected result: {:?}", res),
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn poll_slave_proxy() {
+        let (sensor, shared_context) = shared_context(Slave(1));
+        let proxy = SlaveProxy::new(Slave(1), shared_context);
+        let mut poller = Poller::new(proxy, PollConfig::default());
+        let mut subscriber = poller.subscribe();
+        poller.poll_once().await;
+        let polled = *subscriber.borrow_and_update();
+        let expected = measurements();
+        assert_eq!(Some(expected.temperature), polled.temperature.map(|m| m.val));
+        assert_eq!(Some(expected.water_content), polled.water_content.map(|m| m.val));
+        assert_eq!(Some(expected.permittivity), polled.permittivity.map(|m| m.val));
+        // Not measured by default
+        assert_eq!(None, polled.raw_counts);
+        // The last known good values are kept
+        sensor.set_faults(timeouts());
+        poller.poll_once().await;
+        assert!(subscriber.has_changed().unwrap());
+        assert_eq!(polled, *subscriber.borrow_and_update());
+    }
+
+    #[tokio::test]
+    async fn builder_defaults() {
+        let (_sensor, shared_context) = shared_context(Slave(1));
+        let proxy = SlaveProxy::builder(Arc::clone(&shared_context)).build();
+        assert_eq!(Slave::min_device(), proxy.slave());
+        assert_eq!(None, proxy.default_timeout());
+        assert!(proxy.retry_policy().is_none());
+        assert_eq!(Smt100.name(), proxy.profile().name());
+        let proxy = SlaveProxy::builder(shared_context)
+            .slave(Slave(1))
+            .default_timeout(TIMEOUT)
+            .retries(5)
+            .build();
+        assert_eq!(Slave(1), proxy.slave());
+        assert_eq!(Some(TIMEOUT), proxy.default_timeout());
+        let policy = proxy.retry_policy().unwrap();
+        assert_eq!(5, policy.max_retries);
+        assert_eq!(RetryPolicy::default().backoff, policy.backoff);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn apply_default_timeout() {
+        let (sensor, shared_context) = shared_context(Slave(1));
+        sensor.set_faults(timeouts());
+        let proxy = SlaveProxy::builder(shared_context)
+            .slave(Slave(1))
+            .default_timeout(TIMEOUT)
+            .build();
+        match proxy.read_water_content(None).await {
+            Err(Error::Timeout(err)) => assert_eq!(
+                TimeoutError {
+                    quantity: Some(Quantity::WaterContent),
+                    slave: Some(1),
+                    timeout: Some(TIMEOUT),
+                },
+                err
+            ),
+            res => panic!("Unexpected result: {:?}", res),
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retry_until_exhausted() {
+        let (sensor, shared_context) = shared_context(Slave(1));
+        sensor.set_faults(timeouts());
+        let policy = RetryPolicy {
+            max_retries: 2,
+            backoff: Duration::from_millis(10),
+            ..Default::default()
+        };
+        let proxy = SlaveProxy::builder(shared_context)
+            .slave(Slave(1))
+            .retry_policy(policy)
+            .build();
+        let started = Instant::now();
+        let res = proxy.read_temperature(Some(TIMEOUT)).await;
+        assert!(matches!(res, Err(Error::Timeout(_))));
+        // 3 timeouts + 10 ms + 20 ms
+        assert_eq!(Duration::from_millis(330), started.elapsed());
+        let diagnostics = proxy.diagnostics();
+        assert_eq!(3, diagnostics.requests);
+        assert_eq!(3, diagnostics.timeouts);
+        // Reconnected before each retry
+        assert_eq!(2, diagnostics.reconnects);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retry_until_success() {
+        let (sensor, shared_context) = shared_context(Slave(1));
+        sensor.set_faults(timeouts());
+        let proxy = SlaveProxy::builder(shared_context).slave(Slave(1)).retries(3).build();
+        let read = proxy.read_temperature(Some(TIMEOUT));
+        let recover = async {
+            tokio::time::sleep(TIMEOUT).await;
+            sensor.set_faults(None);
+        };
+        let (res, ()) = futures::join!(read, recover);
+        assert_eq!(measurements().temperature, res.unwrap());
+        let diagnostics = proxy.diagnostics();
+        assert_eq!(2, diagnostics.requests);
+        assert_eq!(1, diagnostics.successes);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn do_not_retry_permanent_errors() {
+        let (sensor, shared_context) = shared_context(Slave(1));
+        sensor.set_faults(Some(Faults {
+            exception: 1.0,
+            ..Default::default()
+        }));
+        let proxy = SlaveProxy::builder(shared_context).slave(Slave(1)).retries(3).build();
+        let res = proxy.read_temperature(Some(TIMEOUT)).await;
+        assert!(matches!(res, Err(Error::ModbusException(_))));
+        assert_eq!(1, proxy.diagnostics().requests);
+    }
+
+    /// Counts the connections that have been opened.
+    struct CountingBus(Bus, Arc<AtomicUsize>);
+
+    impl NewContext for CountingBus {
+        fn new_context(&self) -> BoxFuture<'_, io::Result<client::Context>> {
+            self.1.fetch_add(1, Ordering::SeqCst);
+            self.0.new_context()
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn scan_present_slaves() {
+        let bus = Bus::new();
+        bus.add_slave(Slave(1));
+        bus.add_slave(Slave(3)).set_faults(Some(Faults {
+            exception: 1.0,
+            ..Default::default()
+        }));
+        let connects = Arc::new(AtomicUsize::new(0));
+        let new_context = CountingBus(bus.clone(), Arc::clone(&connects));
+        let shared_context = SharedContext::new(Some(bus.connect()), Box::new(new_context));
+        let found = scan_bus(&shared_context, (1..=3).map(Slave), TIMEOUT).await.unwrap();
+        assert_eq!(vec![Slave(1), Slave(3)], found);
+        // Only reconnected after slave 2 timed out
+        assert_eq!(1, connects.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn errors_that_require_reconnect() {
+        let transport = |kind| Error::Transport(std::io::Error::new(kind, "test"));
+        assert!(requires_reconnect(&Error::NotConnected));
+        assert!(requires_reconnect(&TimeoutError::default().into()));
+        assert!(requires_reconnect(&transport(ErrorKind::BrokenPipe)));
+        assert!(requires_reconnect(&transport(ErrorKind::ConnectionReset)));
+        assert!(requires_reconnect(&transport(ErrorKind::UnexpectedEof)));
+        assert!(!requires_reconnect(&transport(ErrorKind::InvalidData)));
+        assert!(!requires_reconnect(&DecodeError::InvalidInput.into()));
+        assert!(!requires_reconnect(&ModbusException::new(0x02).into()));
+    }
+
+    #[tokio::test]
+    async fn reconnect_disconnected_context() {
+        let bus = Bus::new();
+        bus.add_slave(Slave(1)).set_measurements(&measurements());
+        let shared_context = Arc::new(SharedContext::new(None, Box::new(bus)));
+        let proxy = ReconnectingProxy::new(SlaveProxy::new(Slave(1), shared_context));
+        let temperature = Capabilities::read_temperature(&proxy, Some(TIMEOUT)).await.unwrap();
+        assert_eq!(measurements().temperature, temperature);
+        let diagnostics = proxy.proxy().diagnostics();
+        assert_eq!(2, diagnostics.requests);
+        assert_eq!(1, diagnostics.reconnects);
+        assert_eq!(Some(crate::ErrorKind::NotConnected), diagnostics.last_error);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn reconnect_and_repeat_only_once() {
+        let (sensor, shared_context) = shared_context(Slave(1));
+        sensor.set_faults(timeouts());
+        let proxy = ReconnectingProxy::new(SlaveProxy::new(Slave(1), shared_context));
+        let res = Capabilities::read_raw_counts(&proxy, Some(TIMEOUT)).await;
+        assert!(matches!(res, Err(Error::Timeout(_))));
+        let diagnostics = proxy.proxy().diagnostics();
+        assert_eq!(2, diagnostics.timeouts);
+        assert_eq!(1, diagnostics.reconnects);
+    }
+
+    #[tokio::test]
+    async fn read_selected_quantities() {
+        let (_sensor, shared_context) = shared_context(Slave(1));
+        let proxy = SlaveProxy::new(Slave(1), shared_context);
+        let selection = MeasurementSelection::TEMPERATURE | MeasurementSelection::RAW_COUNTS;
+        let selected = proxy.read_selected(selection, Some(TIMEOUT)).await.unwrap();
+        assert_eq!(
+            SelectedMeasurements {
+                temperature: Some(measurements().temperature),
+                raw_counts: Some(measurements().raw_counts),
+                ..Default::default()
+            },
+            selected
+        );
+        assert_eq!(selection, selected.selection());
+    }
+
+    /// An SMT100 sibling that only measures temperature and water content.
+    struct WithoutPermittivity;
+
+    impl SensorProfile for WithoutPermittivity {
+        fn name(&self) -> &'static str {
+            "WithoutPermittivity"
+        }
+
+        fn register_map(&self) -> &'static [RegisterDescriptor] {
+            &register_map()[..2]
+        }
+
+        fn decode_quantity(&self, quantity: Quantity, input: u16) -> DecodeResult<RegisterValue> {
+            Smt100.decode_quantity(quantity, input)
+        }
+    }
+
+    #[tokio::test]
+    async fn read_selected_by_profile() {
+        let (_sensor, shared_context) = shared_context(Slave(1));
+        let proxy = SlaveProxy::builder(shared_context)
+            .slave(Slave(1))
+            .profile(WithoutPermittivity)
+            .build();
+        assert_eq!("WithoutPermittivity", proxy.profile().name());
+        let selected = proxy
+            .read_selected(MeasurementSelection::all(), Some(TIMEOUT))
+            .await
+            .unwrap();
+        assert_eq!(
+            SelectedMeasurements {
+                temperature: Some(measurements().temperature),
+                water_content: Some(measurements().water_content),
+                ..Default::default()
+            },
+            selected
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn request_nothing_without_selection() {
+        let (sensor, shared_context) = shared_context(Slave(1));
+        sensor.set_faults(timeouts());
+        let proxy = SlaveProxy::new(Slave(1), shared_context);
+        let selected = proxy.read_selected(MeasurementSelection::empty(), Some(TIMEOUT)).await;
+        assert_eq!(SelectedMeasurements::default(), selected.unwrap());
+    }
+
+    #[tokio::test]
+    async fn poll_selected_quantities() {
+        let (_sensor, shared_context) = shared_context(Slave(1));
+        let proxy = SlaveProxy::new(Slave(1), shared_context);
+        let config = PollConfig::default().with_selection(MeasurementSelection::WATER_CONTENT);
+        let mut poller = Poller::new(proxy, config);
+        poller.poll_once().await;
+        let polled = poller.measurements();
+        assert_eq!(Some(measurements().water_content), polled.water_content.map(|m| m.val));
+        assert_eq!(None, polled.temperature);
+        assert_eq!(None, polled.permittivity);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn probe_slaves() {
+        let (sensor, shared_context) = shared_context(Slave(1));
+        let proxy = SlaveProxy::new(Slave(1), Arc::clone(&shared_context));
+        assert_eq!(ProbeResult::Responding, proxy.probe(Some(TIMEOUT)).await.unwrap());
+        sensor.set_faults(timeouts());
+        assert_eq!(ProbeResult::Timeout, proxy.probe(Some(TIMEOUT)).await.unwrap());
+        sensor.set_faults(Some(Faults {
+            exception: 1.0,
+            ..Default::default()
+        }));
+        assert_eq!(
+            ProbeResult::Exception(ExceptionCode::ServerDeviceFailure),
+            proxy.probe(Some(TIMEOUT)).await.unwrap()
+        );
+        // Unrelated to the slave
+        let shared_context = SharedContext::new(None, Box::new(Bus::new()));
+        let proxy = SlaveProxy::new(Slave(1), Arc::new(shared_context));
+        assert!(matches!(proxy.probe(Some(TIMEOUT)).await, Err(Error::NotConnected)));
+    }
+
+    #[tokio::test]
+    async fn raw_register_access() {
+        let (_sensor, shared_context) = shared_context(Slave(1));
+        let proxy = SlaveProxy::new(Slave(1), shared_context);
+        let regs = proxy.read_registers(Register::Temperature.address(), 4, Some(TIMEOUT)).await;
+        assert_eq!(vec![12_150, 3_450, 1_525, 11_345], regs.unwrap());
+        let regs = proxy.read_registers(Register::SlaveAddress.address(), 1, Some(TIMEOUT)).await;
+        assert_eq!(vec![1], regs.unwrap());
+    }
+
+    #[tokio::test]
+    async fn refuse_raw_writes_of_protected_registers() {
+        let (sensor, shared_context) = shared_context(Slave(1));
+        let proxy = SlaveProxy::new(Slave(1), shared_context);
+        let addr = Register::SlaveAddress.address();
+        let protected = |res| {
+            matches!(
+                res,
+                Err(Error::Commissioning(CommissioningError::ProtectedRegister(4)))
+            )
+        };
+        assert!(protected(proxy.write_register(addr, 2, Some(TIMEOUT)).await));
+        assert!(protected(proxy.write_registers(addr - 1, &[0, 2], Some(TIMEOUT)).await));
+        assert_eq!(Slave(1), sensor.slave());
+        assert_eq!(0, proxy.diagnostics().requests);
+        // Adjacent registers are not protected
+        let res = proxy.write_registers(addr - 2, &[0, 0], Some(TIMEOUT)).await;
+        assert!(matches!(res, Err(Error::ModbusException(_))));
+    }
+
+    #[tokio::test]
+    async fn raw_register_exceptions() {
+        let (_sensor, shared_context) = shared_context(Slave(1));
+        let proxy = SlaveProxy::new(Slave(1), shared_context);
+        let addr = Register::RawCounts.address();
+        match proxy.write_register(addr, 1, Some(TIMEOUT)).await {
+            Err(Error::ModbusException(exception)) => {
+                assert_eq!(Some(FN_WRITE_SINGLE_REGISTER), exception.function);
+                assert_eq!(Some(addr), exception.register);
+            }
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        let res = proxy.read_registers(0xFFF0, 4, Some(TIMEOUT)).await;
+        assert!(matches!(res, Err(Error::ModbusException(_))));
     }
 }
@@ -2,38 +2,750 @@ use super::*;
 
 use crate::core::modbus::rtu::*;
 
-use futures::{future, Future};
-use std::{io::Error, path::Path, time::Duration};
-use tokio_core::reactor::Handle;
-use tokio_io::{AsyncRead, AsyncWrite};
-use tokio_modbus::client::{rtu::connect_slave, Context as ClientContext};
-use tokio_serial::{Serial, SerialPortSettings};
-
-pub const SERIAL_PORT_SETTINGS: SerialPortSettings = SerialPortSettings {
-    baud_rate: BAUD_RATE,
-    data_bits: DATA_BITS,
-    stop_bits: STOP_BITS,
-    parity: PARITY,
-    flow_control: FLOW_CONTROL,
-    // A timeout is currently not supported and ignored by tokio-serial
-    // See also: https://github.com/berkowski/tokio-serial/issues/15
-    timeout: Duration::from_secs(0),
+use std::{
+    fmt::{self, Debug},
+    fs::{File, OpenOptions},
+    io::{self, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    pin::Pin,
+    task::{ready, Context, Poll},
 };
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_modbus::client::{rtu::attach_slave, Context as ClientContext};
+use tokio_serial::{Parity, SerialPortBuilder, SerialStream, StopBits};
 
-pub fn connect<T: AsyncRead + AsyncWrite + 'static>(
-    handle: &Handle,
+/// Serial port settings of a sensor.
+///
+/// Defaults to the factory settings 9600 8E1. The data bits are
+/// fixed and there is no flow control.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SerialConfig {
+    baud_rate: u32,
+    parity: Parity,
+    stop_bits: StopBits,
+}
+
+impl Default for SerialConfig {
+    fn default() -> Self {
+        Self {
+            baud_rate: BAUD_RATE,
+            parity: PARITY,
+            stop_bits: STOP_BITS,
+        }
+    }
+}
+
+impl SerialConfig {
+    pub fn with_baud_rate(mut self, baud_rate: u32) -> Self {
+        self.baud_rate = baud_rate;
+        self
+    }
+
+    pub fn with_parity(mut self, parity: Parity) -> Self {
+        self.parity = parity;
+        self
+    }
+
+    pub fn with_stop_bits(mut self, stop_bits: StopBits) -> Self {
+        self.stop_bits = stop_bits;
+        self
+    }
+
+    pub fn baud_rate(&self) -> u32 {
+        self.baud_rate
+    }
+
+    pub fn parity(&self) -> Parity {
+        self.parity
+    }
+
+    pub fn stop_bits(&self) -> StopBits {
+        self.stop_bits
+    }
+}
+
+impl SerialConfig {
+    /// Configure the serial port at `path` with these settings.
+    pub fn serial_port_builder(&self, path: impl AsRef<Path>) -> SerialPortBuilder {
+        tokio_serial::new(path.as_ref().to_string_lossy(), self.baud_rate)
+            .data_bits(DATA_BITS)
+            .parity(self.parity)
+            .stop_bits(self.stop_bits)
+            .flow_control(FLOW_CONTROL)
+    }
+}
+
+/// Switches the direction of an RS-485 transceiver around each
+/// request that is written into the transport.
+///
+/// The driver is enabled before the first byte of a request is written
+/// and the receiver is enabled after the request has been flushed. The
+/// transport must not finish flushing before the last byte has been
+/// transmitted.
+#[derive(Debug)]
+pub struct DirectionControlled<T, D> {
     transport: T,
-) -> impl Future<Item = ClientContext, Error = Error> {
-    connect_slave(handle, transport, BROADCAST_SLAVE)
+    direction: D,
+    sending: bool,
 }
 
-pub fn connect_path(
-    handle: &Handle,
+impl<T, D> DirectionControlled<T, D> {
+    pub fn new(transport: T, direction: D) -> Self {
+        Self {
+            transport,
+            direction,
+            sending: false,
+        }
+    }
+
+    /// Release the transport and the direction control.
+    pub fn into_inner(self) -> (T, D) {
+        (self.transport, self.direction)
+    }
+}
+
+impl<T, D> AsyncRead for DirectionControlled<T, D>
+where
+    T: AsyncRead + Unpin,
+    D: Unpin,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.transport).poll_read(cx, buf)
+    }
+}
+
+impl<T, D> AsyncWrite for DirectionControlled<T, D>
+where
+    T: AsyncWrite + Unpin,
+    D: DirectionControl + Unpin,
+    D::Error: Into<io::Error>,
+{
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        if !self.sending {
+            self.direction.before_send().map_err(Into::into)?;
+            self.sending = true;
+        }
+        Pin::new(&mut self.transport).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(Pin::new(&mut self.transport).poll_flush(cx))?;
+        if self.sending {
+            self.sending = false;
+            self.direction.after_send().map_err(Into::into)?;
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.transport).poll_shutdown(cx)
+    }
+}
+
+/// Receives the raw frames on the bus, e.g. for dumping hex traces
+/// or feeding a protocol analyzer.
+pub trait FrameObserver {
+    /// A complete request frame has been sent.
+    fn on_request(&mut self, frame: &[u8]);
+
+    /// A response frame has been received.
+    ///
+    /// Incomplete or corrupted frames are reported as received.
+    fn on_response(&mut self, frame: &[u8]);
+}
+
+/// Writes frames as hex dumps into the log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LogFrames {
+    pub level: log::Level,
+}
+
+impl Default for LogFrames {
+    fn default() -> Self {
+        Self {
+            level: log::Level::Debug,
+        }
+    }
+}
+
+struct Hex<'a>(&'a [u8]);
+
+impl fmt::Display for Hex<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, byte) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{:02X}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl FrameObserver for LogFrames {
+    fn on_request(&mut self, frame: &[u8]) {
+        log::log!(self.level, "Sent: {}", Hex(frame));
+    }
+
+    fn on_response(&mut self, frame: &[u8]) {
+        log::log!(self.level, "Received: {}", Hex(frame));
+    }
+}
+
+/// Passes all frames that are transferred over the transport
+/// to a `FrameObserver`.
+///
+/// Requests are reported when they are flushed. Responses are split
+/// into frames according to their function code. Responses with an
+/// unknown length are reported right before the next request is sent.
+#[derive(Debug)]
+pub struct ObservedTransport<T, O> {
+    transport: T,
+    observer: O,
+    request: Vec<u8>,
+    response: Vec<u8>,
+}
+
+impl<T, O> ObservedTransport<T, O>
+where
+    O: FrameObserver,
+{
+    pub fn new(transport: T, observer: O) -> Self {
+        Self {
+            transport,
+            observer,
+            request: Vec::new(),
+            response: Vec::new(),
+        }
+    }
+
+    /// Release the transport and the observer.
+    pub fn into_inner(self) -> (T, O) {
+        (self.transport, self.observer)
+    }
+
+    fn observe_responses(&mut self) {
+        loop {
+            match response_frame_len(&self.response) {
+                Ok(len) if len <= self.response.len() => {
+                    self.observer.on_response(&self.response[..len]);
+                    self.response.drain(..len);
+                }
+                _ => break,
+            }
+        }
+    }
+
+    fn observe_pending_response(&mut self) {
+        if !self.response.is_empty() {
+            self.observer.on_response(&self.response);
+            self.response.clear();
+        }
+    }
+}
+
+impl<T, O> AsyncRead for ObservedTransport<T, O>
+where
+    T: AsyncRead + Unpin,
+    O: FrameObserver + Unpin,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let filled = buf.filled().len();
+        ready!(Pin::new(&mut self.transport).poll_read(cx, buf))?;
+        self.response.extend_from_slice(&buf.filled()[filled..]);
+        self.observe_responses();
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<T, O> AsyncWrite for ObservedTransport<T, O>
+where
+    T: AsyncWrite + Unpin,
+    O: FrameObserver + Unpin,
+{
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        if self.request.is_empty() {
+            self.observe_pending_response();
+        }
+        let len = ready!(Pin::new(&mut self.transport).poll_write(cx, buf))?;
+        self.request.extend_from_slice(&buf[..len]);
+        Poll::Ready(Ok(len))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(Pin::new(&mut self.transport).poll_flush(cx))?;
+        if !self.request.is_empty() {
+            let request = std::mem::take(&mut self.request);
+            self.observer.on_request(&request);
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.transport).poll_shutdown(cx)
+    }
+}
+
+/// Direction control by a GPIO pin of the Linux sysfs interface
+/// that is connected to both DE and RE.
+///
+/// The pin must already be exported and configured as an output, e.g.
+/// by `echo out > /sys/class/gpio/gpio17/direction`. Implement
+/// `DirectionControl` for a line request of _libgpiod_ or _gpio-cdev_
+/// when using the character device interface instead.
+#[derive(Debug)]
+pub struct SysfsGpio {
+    path: PathBuf,
+    value: File,
+}
+
+impl SysfsGpio {
+    /// Open the value file of an exported pin.
+    pub fn open(pin: u32) -> io::Result<Self> {
+        Self::open_path(format!("/sys/class/gpio/gpio{}/value", pin))
+    }
+
+    pub fn open_path(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let value = OpenOptions::new().write(true).open(&path)?;
+        Ok(Self { path, value })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn set_value(&mut self, value: &[u8]) -> io::Result<()> {
+        self.value.seek(SeekFrom::Start(0))?;
+        self.value.write_all(value)
+    }
+}
+
+impl DirectionControl for SysfsGpio {
+    type Error = io::Error;
+
+    fn before_send(&mut self) -> io::Result<()> {
+        self.set_value(b"1")
+    }
+
+    fn after_send(&mut self) -> io::Result<()> {
+        self.set_value(b"0")
+    }
+}
+
+pub fn connect<T>(transport: T) -> ClientContext
+where
+    T: AsyncRead + AsyncWrite + Debug + Unpin + Send + 'static,
+{
+    attach_slave(transport, BROADCAST_SLAVE)
+}
+
+/// Connect with the default `SerialConfig`.
+///
+/// Must be called within the context of a _tokio_ runtime.
+pub fn connect_path(path: impl AsRef<Path>) -> io::Result<ClientContext> {
+    connect_path_with_config(path, SerialConfig::default())
+}
+
+/// Must be called within the context of a _tokio_ runtime.
+pub fn connect_path_with_config(path: impl AsRef<Path>, config: SerialConfig) -> io::Result<ClientContext> {
+    log::info!(
+        "Connecting to serial port {} ({} baud)",
+        path.as_ref().display(),
+        config.baud_rate()
+    );
+    let serial = SerialStream::open(&config.serial_port_builder(path))?;
+    Ok(connect(serial))
+}
+
+/// Connect with a transceiver that requires explicit direction control.
+///
+/// Must be called within the context of a _tokio_ runtime.
+pub fn connect_path_with_direction_control<D>(
     path: impl AsRef<Path>,
-) -> Box<dyn Future<Item = ClientContext, Error = Error>> {
-    log::info!("Connecting to serial port {}", path.as_ref().display());
-    match Serial::from_path_with_handle(path, &SERIAL_PORT_SETTINGS, &handle.new_tokio_handle()) {
-        Ok(serial) => Box::new(connect(handle, serial)),
-        Err(err) => Box::new(future::err(err)),
+    config: SerialConfig,
+    direction: D,
+) -> io::Result<ClientContext>
+where
+    D: DirectionControl + Debug + Unpin + Send + 'static,
+    D::Error: Into<io::Error>,
+{
+    log::info!(
+        "Connecting to serial port {} ({} baud) with direction control",
+        path.as_ref().display(),
+        config.baud_rate()
+    );
+    let serial = SerialStream::open(&config.serial_port_builder(path))?;
+    Ok(connect(DirectionControlled::new(serial, direction)))
+}
+
+/// Why a serial port has been selected as a candidate, ordered by
+/// relevance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PortMatch {
+    /// The serial number of a USB adapter.
+    SerialNumber,
+
+    /// The USB vendor and product ID of a known adapter.
+    UsbId,
+
+    /// The port name, the manufacturer, or the product matches a pattern.
+    NamePattern,
+}
+
+/// A serial port that might be connected to sensors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortCandidate {
+    /// The path of the serial port, e.g. `/dev/ttyUSB0`.
+    pub port_name: String,
+
+    /// Only available for USB adapters.
+    pub usb: Option<serialport::UsbPortInfo>,
+
+    pub matched: PortMatch,
+}
+
+/// Selects serial ports that might be connected to sensors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortFilter {
+    /// USB vendor and product IDs as `(vid, pid)`.
+    pub usb_ids: Vec<(u16, u16)>,
+
+    /// Serial numbers of USB adapters, e.g. for selecting a particular
+    /// adapter if multiple adapters of the same type are plugged in.
+    pub serial_numbers: Vec<String>,
+
+    /// Case-insensitive substrings of the port name, the manufacturer,
+    /// or the product.
+    pub name_patterns: Vec<String>,
+}
+
+impl Default for PortFilter {
+    /// Common USB to RS-485 adapters.
+    fn default() -> Self {
+        Self {
+            usb_ids: vec![
+                // FTDI FT232R and FT-X
+                (0x0403, 0x6001),
+                (0x0403, 0x6015),
+                // WCH CH340
+                (0x1A86, 0x7523),
+                // Silicon Labs CP210x
+                (0x10C4, 0xEA60),
+                // Prolific PL2303
+                (0x067B, 0x2303),
+            ],
+            serial_numbers: Vec::new(),
+            name_patterns: ["ttyUSB", "ttyACM", "usbserial", "RS485", "RS-485"]
+                .iter()
+                .map(|pattern| (*pattern).to_owned())
+                .collect(),
+        }
+    }
+}
+
+impl PortFilter {
+    fn matches(&self, info: &serialport::SerialPortInfo) -> Option<PortMatch> {
+        let usb = match &info.port_type {
+            serialport::SerialPortType::UsbPort(usb) => Some(usb),
+            _ => None,
+        };
+        if let Some(usb) = usb {
+            if usb
+                .serial_number
+                .as_ref()
+                .is_some_and(|serial_number| self.serial_numbers.contains(serial_number))
+            {
+                return Some(PortMatch::SerialNumber);
+            }
+            if self.usb_ids.contains(&(usb.vid, usb.pid)) {
+                return Some(PortMatch::UsbId);
+            }
+        }
+        let names = std::iter::once(Some(&info.port_name))
+            .chain(usb.map(|usb| usb.manufacturer.as_ref()))
+            .chain(usb.map(|usb| usb.product.as_ref()))
+            .flatten()
+            .map(|name| name.to_lowercase())
+            .collect::<Vec<_>>();
+        self.name_patterns
+            .iter()
+            .map(|pattern| pattern.to_lowercase())
+            .any(|pattern| names.iter().any(|name| name.contains(&pattern)))
+            .then_some(PortMatch::NamePattern)
+    }
+}
+
+/// Find USB serial ports of common RS-485 adapters.
+///
+/// The device names of USB adapters, e.g. `/dev/ttyUSB0`, might change
+/// after a reboot or when replugging the adapter.
+pub fn list_candidate_ports() -> io::Result<Vec<PortCandidate>> {
+    list_candidate_ports_with_filter(&PortFilter::default())
+}
+
+/// Find serial ports that match the filter, ordered by relevance and
+/// port name.
+pub fn list_candidate_ports_with_filter(filter: &PortFilter) -> io::Result<Vec<PortCandidate>> {
+    Ok(rank_candidate_ports(filter, serialport::available_ports()?))
+}
+
+fn rank_candidate_ports(filter: &PortFilter, ports: Vec<serialport::SerialPortInfo>) -> Vec<PortCandidate> {
+    let mut candidates: Vec<_> = ports
+        .into_iter()
+        .filter_map(|info| {
+            let matched = filter.matches(&info)?;
+            let usb = match info.port_type {
+                serialport::SerialPortType::UsbPort(usb) => Some(usb),
+                _ => None,
+            };
+            Some(PortCandidate {
+                port_name: info.port_name,
+                usb,
+                matched,
+            })
+        })
+        .collect();
+    candidates.sort_by(|lhs, rhs| (lhs.matched, &lhs.port_name).cmp(&(rhs.matched, &rhs.port_name)));
+    candidates
+}
+
+/// Connect to the first candidate port on which `slave` responds.
+///
+/// Any response, even a Modbus exception, is accepted. Fails with
+/// `io::ErrorKind::NotFound` if the slave doesn't respond on any port.
+///
+/// Must be called within the context of a _tokio_ runtime.
+pub async fn connect_auto(
+    config: SerialConfig,
+    slave: Slave,
+    timeout: Duration,
+) -> Result<(PortCandidate, ClientContext)> {
+    for candidate in list_candidate_ports()? {
+        let mut context = match connect_path_with_config(&candidate.port_name, config) {
+            Ok(context) => context,
+            Err(err) => {
+                log::debug!("Failed to open serial port {}: {}", candidate.port_name, err);
+                continue;
+            }
+        };
+        context.set_slave(slave);
+        match read_raw_counts_with_timeout(&mut context, timeout).await {
+            Ok(_) | Err(Error::ModbusException(_)) => return Ok((candidate, context)),
+            Err(err) => log::debug!("No response on serial port {}: {}", candidate.port_name, err),
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::NotFound, format!("{:?} not found on any serial port", slave)).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serialport::SerialPortInfo;
+    use std::collections::VecDeque;
+    use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+
+    const REQUEST: [u8; 8] = [0x01, 0x03, 0x00, 0x00, 0x00, 0x01, 0x84, 0x0A];
+
+    const RESPONSE: [u8; 7] = [0x01, 0x03, 0x02, 0x31, 0xFD, 0x6C, 0x55];
+
+    const EXCEPTION: [u8; 5] = [0x01, 0x83, 0x02, 0xC0, 0xF1];
+
+    /// Receives the bytes in the given chunks.
+    #[derive(Default)]
+    struct Serial {
+        rx: VecDeque<Vec<u8>>,
+    }
+
+    impl AsyncRead for Serial {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            if let Some(chunk) = self.rx.pop_front() {
+                buf.put_slice(&chunk);
+            }
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    impl AsyncWrite for Serial {
+        fn poll_write(self: Pin<&mut Self>, _cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[derive(Default)]
+    struct Frames {
+        requests: Vec<Vec<u8>>,
+        responses: Vec<Vec<u8>>,
+    }
+
+    impl FrameObserver for Frames {
+        fn on_request(&mut self, frame: &[u8]) {
+            self.requests.push(frame.to_vec());
+        }
+
+        fn on_response(&mut self, frame: &[u8]) {
+            self.responses.push(frame.to_vec());
+        }
+    }
+
+    fn observed(rx: &[&[u8]]) -> ObservedTransport<Serial, Frames> {
+        let serial = Serial {
+            rx: rx.iter().map(|chunk| chunk.to_vec()).collect(),
+        };
+        ObservedTransport::new(serial, Frames::default())
+    }
+
+    async fn read_chunks(transport: &mut ObservedTransport<Serial, Frames>, count: usize) {
+        let mut buf = [0; MAX_FRAME_LEN];
+        for _ in 0..count {
+            let len = transport.read(&mut buf).await.unwrap();
+            assert!(len > 0);
+        }
+    }
+
+    #[tokio::test]
+    async fn observe_requests_when_flushed() {
+        let mut transport = observed(&[]);
+        transport.write_all(&REQUEST[..3]).await.unwrap();
+        transport.write_all(&REQUEST[3..]).await.unwrap();
+        assert!(transport.observer.requests.is_empty());
+        transport.flush().await.unwrap();
+        transport.flush().await.unwrap();
+        let (_, frames) = transport.into_inner();
+        assert_eq!(vec![REQUEST.to_vec()], frames.requests);
+    }
+
+    #[tokio::test]
+    async fn join_split_responses() {
+        let mut transport = observed(&[&RESPONSE[..1], &RESPONSE[1..4], &RESPONSE[4..]]);
+        read_chunks(&mut transport, 2).await;
+        assert!(transport.observer.responses.is_empty());
+        read_chunks(&mut transport, 1).await;
+        assert_eq!(vec![RESPONSE.to_vec()], transport.observer.responses);
+    }
+
+    #[tokio::test]
+    async fn split_concatenated_responses() {
+        let concatenated = [&RESPONSE[..], &EXCEPTION[..], &RESPONSE[..2]].concat();
+        let mut transport = observed(&[&concatenated, &RESPONSE[2..]]);
+        read_chunks(&mut transport, 1).await;
+        assert_eq!(vec![RESPONSE.to_vec(), EXCEPTION.to_vec()], transport.observer.responses);
+        read_chunks(&mut transport, 1).await;
+        assert_eq!(3, transport.observer.responses.len());
+        assert_eq!(RESPONSE.to_vec(), transport.observer.responses[2]);
+    }
+
+    #[tokio::test]
+    async fn observe_unknown_responses_before_next_request() {
+        // Neither the length nor the function code is known
+        let mut transport = observed(&[&[0x01, 0x2B, 0x0E, 0x01]]);
+        read_chunks(&mut transport, 1).await;
+        assert!(transport.observer.responses.is_empty());
+        transport.write_all(&REQUEST).await.unwrap();
+        assert_eq!(vec![vec![0x01, 0x2B, 0x0E, 0x01]], transport.observer.responses);
+        transport.flush().await.unwrap();
+        assert_eq!(vec![REQUEST.to_vec()], transport.observer.requests);
+    }
+
+    fn usb_port(port_name: &str, vid: u16, pid: u16, serial_number: &str, product: &str) -> SerialPortInfo {
+        SerialPortInfo {
+            port_name: port_name.to_owned(),
+            port_type: serialport::SerialPortType::UsbPort(serialport::UsbPortInfo {
+                vid,
+                pid,
+                serial_number: Some(serial_number.to_owned()),
+                manufacturer: None,
+                product: Some(product.to_owned()),
+            }),
+        }
+    }
+
+    fn pci_port(port_name: &str) -> SerialPortInfo {
+        SerialPortInfo {
+            port_name: port_name.to_owned(),
+            port_type: serialport::SerialPortType::PciPort,
+        }
+    }
+
+    #[test]
+    fn match_known_adapters() {
+        let filter = PortFilter::default();
+        let ftdi = usb_port("/dev/cu.usbmodem1", 0x0403, 0x6001, "A10K", "FT232R USB UART");
+        assert_eq!(Some(PortMatch::UsbId), filter.matches(&ftdi));
+        // Other product of the same vendor
+        let other = usb_port("/dev/cu.usbmodem2", 0x0403, 0x6010, "A10L", "Dual RS485 Converter");
+        assert_eq!(Some(PortMatch::NamePattern), filter.matches(&other));
+        let unknown = usb_port("/dev/ttyUSB3", 0x1234, 0x5678, "XYZ", "Gadget");
+        assert_eq!(Some(PortMatch::NamePattern), filter.matches(&unknown));
+        let unknown = usb_port("/dev/cu.usbmodem3", 0x1234, 0x5678, "XYZ", "Gadget");
+        assert_eq!(None, filter.matches(&unknown));
+        assert_eq!(None, filter.matches(&pci_port("/dev/ttyS0")));
+    }
+
+    #[test]
+    fn match_custom_filters() {
+        let filter = PortFilter {
+            usb_ids: vec![(0x1234, 0x5678)],
+            serial_numbers: vec!["A10L".to_owned()],
+            name_patterns: vec!["gadget".to_owned()],
+        };
+        let gadget = usb_port("/dev/ttyUSB0", 0x1234, 0x5678, "XYZ", "Other");
+        assert_eq!(Some(PortMatch::UsbId), filter.matches(&gadget));
+        let gadget = usb_port("/dev/ttyUSB1", 0x1234, 0x5679, "XYZ", "Gadget");
+        assert_eq!(Some(PortMatch::NamePattern), filter.matches(&gadget));
+        let ftdi = usb_port("/dev/ttyUSB2", 0x0403, 0x6001, "A10L", "FT232R USB UART");
+        assert_eq!(Some(PortMatch::SerialNumber), filter.matches(&ftdi));
+        let ftdi = usb_port("/dev/ttyUSB3", 0x0403, 0x6001, "A10K", "FT232R USB UART");
+        assert_eq!(None, filter.matches(&ftdi));
+        assert_eq!(None, filter.matches(&pci_port("/dev/ttyS0")));
+    }
+
+    #[test]
+    fn rank_candidates_by_relevance_and_name() {
+        let filter = PortFilter {
+            serial_numbers: vec!["A10L".to_owned()],
+            ..Default::default()
+        };
+        let ports = vec![
+            usb_port("/dev/ttyUSB2", 0x1234, 0x5678, "XYZ", "Gadget"),
+            pci_port("/dev/ttyS0"),
+            usb_port("/dev/ttyUSB1", 0x1A86, 0x7523, "A10K", "USB Serial"),
+            usb_port("/dev/ttyUSB3", 0x0403, 0x6001, "A10L", "FT232R USB UART"),
+            pci_port("/dev/ttyACM0"),
+        ];
+        let candidates: Vec<_> = rank_candidate_ports(&filter, ports)
+            .into_iter()
+            .map(|candidate| (candidate.port_name, candidate.matched))
+            .collect();
+        assert_eq!(
+            vec![
+                ("/dev/ttyUSB3".to_owned(), PortMatch::SerialNumber),
+                ("/dev/ttyUSB1".to_owned(), PortMatch::UsbId),
+                ("/dev/ttyACM0".to_owned(), PortMatch::NamePattern),
+                ("/dev/ttyUSB2".to_owned(), PortMatch::NamePattern),
+            ],
+            candidates
+        );
     }
 }
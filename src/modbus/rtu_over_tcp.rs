@@ -0,0 +1,21 @@
+//! Modbus RTU over TCP.
+//!
+//! Transparent serial device servers forward the raw RTU frames
+//! including the checksum through a TCP connection instead of
+//! translating them into Modbus TCP. The resulting client context
+//! can be used just like a context that is connected to a local
+//! serial port, e.g. for a `SlaveProxy`.
+
+use super::*;
+
+use std::{io, net::SocketAddr};
+use tokio::net::TcpStream;
+use tokio_modbus::client::Context as ClientContext;
+
+pub async fn connect(addr: SocketAddr) -> io::Result<ClientContext> {
+    log::info!("Connecting to RTU over TCP gateway {}", addr);
+    let stream = TcpStream::connect(addr).await?;
+    // Requests are short and must not be delayed
+    stream.set_nodelay(true)?;
+    Ok(rtu::connect(stream))
+}
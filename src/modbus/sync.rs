@@ -0,0 +1,566 @@
+use super::*;
+
+use crate::core::Capabilities as BlockingCapabilities;
+
+use std::{cell::RefCell, sync::Arc};
+use tokio_modbus::client::sync::{rtu::connect_slave, Context, Reader, Writer};
+
+/// A synchronous Modbus client, e.g. the `Context` of _tokio-modbus_.
+pub trait Client: Reader + Writer {
+    /// Set the timeout of all subsequent requests or disable it with `None`.
+    fn set_timeout(&mut self, timeout: Option<Duration>);
+}
+
+impl Client for Context {
+    fn set_timeout(&mut self, timeout: Option<Duration>) {
+        Context::set_timeout(self, timeout);
+    }
+}
+
+/// Connect with the default `SerialConfig`.
+pub fn connect_path(path: &str) -> Result<Context> {
+    connect_path_with_config(path, Default::default())
+}
+
+pub fn connect_path_with_config(path: &str, config: rtu::SerialConfig) -> Result<Context> {
+    log::info!("Connecting to serial port {} ({} baud)", path, config.baud_rate());
+    Ok(connect_slave(&config.serial_port_builder(path), BROADCAST_SLAVE)?)
+}
+
+/// Switch the Modbus slave address of all connected devices.
+fn broadcast_slave(context: &mut impl Writer, slave: Slave) -> Result<()> {
+    context.set_slave(BROADCAST_SLAVE);
+    let slave_id: SlaveId = slave.into();
+    context
+        .write_single_register(BROADCAST_REG_ADDR, u16::from(slave_id))?
+        .map_err(exception(FN_WRITE_SINGLE_REGISTER, BROADCAST_REG_ADDR))
+}
+
+/// Switch the Modbus slave address of a single device that is
+/// currently addressed by `current_slave`.
+fn set_slave_address(context: &mut impl Writer, current_slave: Slave, new_slave: Slave) -> Result<()> {
+    context.set_slave(current_slave);
+    let slave_id: SlaveId = new_slave.into();
+    context
+        .write_single_register(Register::SlaveAddress.address(), u16::from(slave_id))?
+        .map_err(exception(
+            FN_WRITE_SINGLE_REGISTER,
+            Register::SlaveAddress.address(),
+        ))
+}
+
+/// Read back the slave address from the new address.
+fn verify_slave_address(context: &mut impl Reader, slave: Slave) -> Result<()> {
+    context.set_slave(slave);
+    let addr = Register::SlaveAddress.address();
+    let rsp = context
+        .read_holding_registers(addr, 1)?
+        .map_err(exception(FN_READ_HOLDING_REGISTERS, addr))?;
+    let actual = match rsp[..] {
+        [actual] => actual,
+        _ => return Err(DecodeError::InvalidInput.into()),
+    };
+    let expected = u16::from(slave.0);
+    if actual != expected {
+        return Err(VerificationError {
+            register: addr,
+            expected,
+            actual,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// Guards all configuration writes with an exclusive, synchronous
+/// Modbus context.
+///
+/// The blocking counterpart of `modbus::CommissioningSession`. The
+/// context is handed back by `leave()` for polling measurements
+/// afterwards.
+pub struct CommissioningSession<C = Context> {
+    context: C,
+    verify_writes: bool,
+}
+
+impl<C> CommissioningSession<C>
+where
+    C: Client,
+{
+    pub fn enter(context: C) -> Self {
+        log::info!("Entering commissioning session");
+        Self {
+            context,
+            verify_writes: true,
+        }
+    }
+
+    pub fn leave(self) -> C {
+        log::info!("Leaving commissioning session");
+        self.context
+    }
+
+    pub fn verify_writes(&self) -> bool {
+        self.verify_writes
+    }
+
+    /// Enable or disable the verification of all subsequent writes.
+    pub fn set_verify_writes(&mut self, verify_writes: bool) {
+        self.verify_writes = verify_writes;
+    }
+
+    /// Switch the Modbus slave address of all connected devices.
+    ///
+    /// The new address is verified by reading it back from the new
+    /// address, i.e. only a single device must be connected.
+    pub fn broadcast_slave(&mut self, slave: Slave) -> Result<()> {
+        broadcast_slave(&mut self.context, slave)?;
+        if self.verify_writes {
+            verify_slave_address(&mut self.context, slave)?;
+        }
+        Ok(())
+    }
+
+    /// Switch the Modbus slave address of a single device.
+    pub fn set_slave_address(&mut self, current_slave: Slave, new_slave: Slave) -> Result<()> {
+        set_slave_address(&mut self.context, current_slave, new_slave)?;
+        if self.verify_writes {
+            verify_slave_address(&mut self.context, new_slave)?;
+        }
+        Ok(())
+    }
+}
+
+/// Read and decode a block of consecutive holding registers within
+/// a single Modbus transaction.
+pub fn read_block<T, const START: u16, const COUNT: u16>(context: &mut impl Reader) -> Result<T>
+where
+    T: FromRegisterBlock<START, COUNT>,
+{
+    let rsp = context
+        .read_holding_registers(START, COUNT)?
+        .map_err(exception(FN_READ_HOLDING_REGISTERS, START))?;
+    if rsp.len() == usize::from(COUNT) {
+        Ok(T::from_registers(&rsp)?)
+    } else {
+        Err(DecodeError::InvalidInput.into())
+    }
+}
+
+pub fn read_temperature(context: &mut impl Reader) -> Result<Temperature> {
+    read_block(context)
+}
+
+pub fn read_water_content(context: &mut impl Reader) -> Result<VolumetricWaterContent> {
+    read_block(context)
+}
+
+pub fn read_permittivity(context: &mut impl Reader) -> Result<RelativePermittivity> {
+    read_block(context)
+}
+
+pub fn read_raw_counts(context: &mut impl Reader) -> Result<RawCounts> {
+    read_block(context)
+}
+
+/// Read all measurements at once within a single Modbus transaction.
+pub fn read_measurements(context: &mut impl Reader) -> Result<Measurements> {
+    read_block(context)
+}
+
+/// Read the selected measurements at once within a single Modbus
+/// transaction.
+///
+/// Only the consecutive registers that contain the selected quantities
+/// are requested. Nothing is requested if the selection is empty.
+pub fn read_selected_measurements(
+    context: &mut impl Reader,
+    selection: MeasurementSelection,
+) -> Result<SelectedMeasurements> {
+    read_selected_measurements_with_profile(context, &Smt100, selection)
+}
+
+/// Read the selected measurements of a sensor with the register layout
+/// of the given profile at once within a single Modbus transaction.
+///
+/// Quantities that are not measured by the sensor remain `None`.
+pub fn read_selected_measurements_with_profile(
+    context: &mut impl Reader,
+    profile: &dyn SensorProfile,
+    selection: MeasurementSelection,
+) -> Result<SelectedMeasurements> {
+    let (start, count) = match profile.selected_measurements_reg_range(selection) {
+        Some(range) => range,
+        None => return Ok(SelectedMeasurements::default()),
+    };
+    let rsp = context
+        .read_holding_registers(start, count)?
+        .map_err(exception(FN_READ_HOLDING_REGISTERS, start))?;
+    Ok(profile.decode_selected_measurements(selection, &rsp)?)
+}
+
+/// Blocking proxy for a single slave device with an exclusive,
+/// synchronous Modbus context.
+pub struct SlaveProxy<C = Context> {
+    slave: Slave,
+    context: RefCell<C>,
+    profile: Arc<dyn SensorProfile + Send + Sync>,
+}
+
+impl<C> SlaveProxy<C>
+where
+    C: Client,
+{
+    /// Create a proxy for an SMT100.
+    pub fn new(slave: Slave, context: C) -> Self {
+        Self {
+            slave,
+            context: RefCell::new(context),
+            profile: Arc::new(Smt100),
+        }
+    }
+
+    /// Read selected measurements of a sibling sensor with a different
+    /// register layout.
+    pub fn with_profile(mut self, profile: impl SensorProfile + Send + Sync + 'static) -> Self {
+        self.profile = Arc::new(profile);
+        self
+    }
+
+    pub fn slave(&self) -> Slave {
+        self.slave
+    }
+
+    /// The register layout of the sensor for reading selected measurements.
+    pub fn profile(&self) -> &(dyn SensorProfile + Send + Sync) {
+        &*self.profile
+    }
+
+    /// Recover from communication errors by replacing the Modbus context.
+    pub fn reconnect(&self, context: C) {
+        *self.context.borrow_mut() = context;
+    }
+
+    fn with_context<T>(&self, timeout: Option<Duration>, f: impl FnOnce(&mut C) -> Result<T>) -> Result<T> {
+        let mut context = self.context.borrow_mut();
+        context.set_slave(self.slave);
+        context.set_timeout(timeout);
+        f(&mut context)
+            .map_err(|err| match (err, timeout) {
+                (Error::Timeout(_), Some(timeout)) => Error::from(TimeoutError::after(timeout)),
+                (err, _) => err,
+            })
+            .map_err(|err| err.with_timeout_slave(self.slave.0))
+    }
+
+    /// Read all measurements at once within a single Modbus transaction.
+    pub fn read_all(&self, timeout: Option<Duration>) -> Result<Measurements> {
+        self.with_context(timeout, read_measurements)
+    }
+
+    /// Read the selected measurements at once within a single Modbus
+    /// transaction.
+    ///
+    /// The registers are requested and decoded according to the profile
+    /// of the sensor. Quantities that are not measured by the sensor
+    /// remain `None`.
+    pub fn read_selected(
+        &self,
+        selection: MeasurementSelection,
+        timeout: Option<Duration>,
+    ) -> Result<SelectedMeasurements> {
+        self.with_context(timeout, |context| {
+            read_selected_measurements_with_profile(context, self.profile(), selection)
+        })
+    }
+}
+
+impl<C> BlockingCapabilities for SlaveProxy<C>
+where
+    C: Client,
+{
+    type ReadError = Error;
+
+    fn read_temperature(&self, timeout: Option<Duration>) -> Result<Temperature> {
+        self.with_context(timeout, read_temperature)
+            .map_err(|err| err.with_timeout_quantity(Quantity::Temperature))
+    }
+
+    fn read_water_content(&self, timeout: Option<Duration>) -> Result<VolumetricWaterContent> {
+        self.with_context(timeout, read_water_content)
+            .map_err(|err| err.with_timeout_quantity(Quantity::WaterContent))
+    }
+
+    fn read_permittivity(&self, timeout: Option<Duration>) -> Result<RelativePermittivity> {
+        self.with_context(timeout, read_permittivity)
+            .map_err(|err| err.with_timeout_quantity(Quantity::Permittivity))
+    }
+
+    fn read_raw_counts(&self, timeout: Option<Duration>) -> Result<RawCounts> {
+        self.with_context(timeout, read_raw_counts)
+            .map_err(|err| err.with_timeout_quantity(Quantity::RawCounts))
+    }
+}
+
+#[cfg(all(test, feature = "tokio-mock"))]
+mod tests {
+    use super::*;
+
+    use crate::mock::{server::Faults, Bus};
+    use std::{future::Future, io};
+    use tokio::runtime::Runtime;
+    use tokio_modbus::{
+        client::{sync, Reader as _, Writer as _},
+        prelude::{Request, Response, SlaveContext},
+        Address, Quantity as Count,
+    };
+
+    /// Blocks on the async client of the mock bus on a runtime with a
+    /// paused clock, like the `Context` of _tokio-modbus_ does.
+    struct MockContext {
+        runtime: Runtime,
+        context: client::Context,
+        timeout: Option<Duration>,
+    }
+
+    impl MockContext {
+        fn connect(bus: &Bus) -> Self {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_time()
+                .start_paused(true)
+                .build()
+                .unwrap();
+            let context = {
+                let _guard = runtime.enter();
+                bus.connect()
+            };
+            Self {
+                runtime,
+                context,
+                timeout: None,
+            }
+        }
+    }
+
+    fn block_on<T>(
+        runtime: &Runtime,
+        timeout: Option<Duration>,
+        request: impl Future<Output = tokio_modbus::Result<T>>,
+    ) -> tokio_modbus::Result<T> {
+        runtime.block_on(async {
+            match timeout {
+                Some(timeout) => tokio::time::timeout(timeout, request)
+                    .await
+                    .unwrap_or_else(|elapsed| Err(io::Error::new(io::ErrorKind::TimedOut, elapsed).into())),
+                None => request.await,
+            }
+        })
+    }
+
+    impl SlaveContext for MockContext {
+        fn set_slave(&mut self, slave: Slave) {
+            self.context.set_slave(slave);
+        }
+    }
+
+    impl sync::Client for MockContext {
+        fn call(&mut self, req: Request<'_>) -> tokio_modbus::Result<Response> {
+            use tokio_modbus::client::Client as _;
+            block_on(&self.runtime, self.timeout, self.context.call(req))
+        }
+    }
+
+    impl Reader for MockContext {
+        fn read_coils(&mut self, addr: Address, cnt: Count) -> tokio_modbus::Result<Vec<bool>> {
+            block_on(&self.runtime, self.timeout, self.context.read_coils(addr, cnt))
+        }
+
+        fn read_discrete_inputs(&mut self, addr: Address, cnt: Count) -> tokio_modbus::Result<Vec<bool>> {
+            block_on(
+                &self.runtime,
+                self.timeout,
+                self.context.read_discrete_inputs(addr, cnt),
+            )
+        }
+
+        fn read_input_registers(&mut self, addr: Address, cnt: Count) -> tokio_modbus::Result<Vec<u16>> {
+            block_on(
+                &self.runtime,
+                self.timeout,
+                self.context.read_input_registers(addr, cnt),
+            )
+        }
+
+        fn read_holding_registers(&mut self, addr: Address, cnt: Count) -> tokio_modbus::Result<Vec<u16>> {
+            block_on(
+                &self.runtime,
+                self.timeout,
+                self.context.read_holding_registers(addr, cnt),
+            )
+        }
+
+        fn read_write_multiple_registers(
+            &mut self,
+            read_addr: Address,
+            read_count: Count,
+            write_addr: Address,
+            write_data: &[u16],
+        ) -> tokio_modbus::Result<Vec<u16>> {
+            let request = self
+                .context
+                .read_write_multiple_registers(read_addr, read_count, write_addr, write_data);
+            block_on(&self.runtime, self.timeout, request)
+        }
+    }
+
+    impl Writer for MockContext {
+        fn write_single_coil(&mut self, addr: Address, coil: bool) -> tokio_modbus::Result<()> {
+            block_on(
+                &self.runtime,
+                self.timeout,
+                self.context.write_single_coil(addr, coil),
+            )
+        }
+
+        fn write_multiple_coils(&mut self, addr: Address, coils: &[bool]) -> tokio_modbus::Result<()> {
+            block_on(
+                &self.runtime,
+                self.timeout,
+                self.context.write_multiple_coils(addr, coils),
+            )
+        }
+
+        fn write_single_register(&mut self, addr: Address, word: u16) -> tokio_modbus::Result<()> {
+            block_on(
+                &self.runtime,
+                self.timeout,
+                self.context.write_single_register(addr, word),
+            )
+        }
+
+        fn write_multiple_registers(&mut self, addr: Address, words: &[u16]) -> tokio_modbus::Result<()> {
+            block_on(
+                &self.runtime,
+                self.timeout,
+                self.context.write_multiple_registers(addr, words),
+            )
+        }
+
+        fn masked_write_register(
+            &mut self,
+            addr: Address,
+            and_mask: u16,
+            or_mask: u16,
+        ) -> tokio_modbus::Result<()> {
+            let request = self.context.masked_write_register(addr, and_mask, or_mask);
+            block_on(&self.runtime, self.timeout, request)
+        }
+    }
+
+    impl Client for MockContext {
+        fn set_timeout(&mut self, timeout: Option<Duration>) {
+            self.timeout = timeout;
+        }
+    }
+
+    fn measurements() -> Measurements {
+        Measurements {
+            temperature: Temperature::from_degree_celsius(21.5),
+            water_content: VolumetricWaterContent::from_percent(23.4),
+            permittivity: RelativePermittivity::from_ratio(12.3),
+            raw_counts: RawCounts::from(2500),
+        }
+    }
+
+    #[test]
+    fn read_all() {
+        let bus = Bus::new();
+        bus.add_slave(Slave(1));
+        bus.add_slave(Slave(2)).set_measurements(&measurements());
+        let proxy = SlaveProxy::new(Slave(2), MockContext::connect(&bus));
+        let read = proxy.read_all(Some(Duration::from_millis(500))).unwrap();
+        assert_eq!(measurements(), read);
+        assert_eq!(measurements().temperature, proxy.read_temperature(None).unwrap());
+    }
+
+    #[test]
+    fn read_timeout() {
+        let bus = Bus::new();
+        bus.add_slave(Slave(1)).set_faults(Some(Faults {
+            timeout: 1.0,
+            ..Default::default()
+        }));
+        let proxy = SlaveProxy::new(Slave(1), MockContext::connect(&bus));
+        let timeout = Duration::from_millis(500);
+        match proxy.read_water_content(Some(timeout)) {
+            Err(Error::Timeout(err)) => assert_eq!(
+                TimeoutError {
+                    quantity: Some(Quantity::WaterContent),
+                    slave: Some(1),
+                    timeout: Some(timeout),
+                },
+                err
+            ),
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        assert!(matches!(proxy.read_all(Some(timeout)), Err(Error::Timeout(_))));
+    }
+
+    /// An SMT100 sibling that only measures temperature and water content.
+    struct WithoutPermittivity;
+
+    impl SensorProfile for WithoutPermittivity {
+        fn name(&self) -> &'static str {
+            "WithoutPermittivity"
+        }
+
+        fn register_map(&self) -> &'static [RegisterDescriptor] {
+            &register_map()[..2]
+        }
+
+        fn decode_quantity(&self, quantity: Quantity, input: u16) -> DecodeResult<RegisterValue> {
+            Smt100.decode_quantity(quantity, input)
+        }
+    }
+
+    #[test]
+    fn read_selected_by_profile() {
+        let bus = Bus::new();
+        let sensor = bus.add_slave(Slave(1));
+        sensor.set_measurements(&measurements());
+        let proxy = SlaveProxy::new(Slave(1), MockContext::connect(&bus));
+        let selection = MeasurementSelection::PERMITTIVITY | MeasurementSelection::RAW_COUNTS;
+        let selected = proxy.read_selected(selection, None).unwrap();
+        assert_eq!(Some(measurements().permittivity), selected.permittivity);
+        assert_eq!(Some(measurements().raw_counts), selected.raw_counts);
+        let proxy = proxy.with_profile(WithoutPermittivity);
+        assert_eq!(
+            SelectedMeasurements {
+                temperature: Some(measurements().temperature),
+                water_content: Some(measurements().water_content),
+                ..Default::default()
+            },
+            proxy.read_selected(MeasurementSelection::all(), None).unwrap()
+        );
+        // Nothing is requested from the sensor
+        sensor.set_faults(Some(Faults {
+            timeout: 1.0,
+            ..Default::default()
+        }));
+        let timeout = Some(Duration::from_millis(500));
+        assert_eq!(
+            SelectedMeasurements::default(),
+            proxy.read_selected(MeasurementSelection::RAW_COUNTS, timeout).unwrap()
+        );
+    }
+
+    #[test]
+    fn commission_slave_address() {
+        let bus = Bus::new();
+        bus.add_slave(Slave(1));
+        let mut session = CommissioningSession::enter(MockContext::connect(&bus));
+        session.set_slave_address(Slave(1), Slave(5)).unwrap();
+        let proxy = SlaveProxy::new(Slave(5), session.leave());
+        assert!(proxy.read_all(None).is_ok());
+    }
+}
@@ -0,0 +1,281 @@
+use super::*;
+
+use futures::future::{self, Either, Future};
+use tokio::sync::watch;
+
+/// The health of the bus as observed by the `Watchdog`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BusHealth {
+    /// The last probe succeeded.
+    Healthy,
+
+    /// Some consecutive probes failed, but not enough for reconnecting.
+    Degraded,
+
+    /// Too many consecutive probes failed and the bus is reconnected.
+    Down,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatchdogConfig {
+    /// The slave that is probed.
+    pub slave: Slave,
+
+    /// The bus is only probed after it has been idle for this period.
+    pub interval: Duration,
+
+    /// The timeout of each probe.
+    pub timeout: Duration,
+
+    /// The number of consecutive failed probes until the bus is
+    /// considered down and reconnected.
+    pub max_failures: u32,
+}
+
+impl Default for WatchdogConfig {
+    fn default() -> Self {
+        Self {
+            slave: Slave::min_device(),
+            interval: Duration::from_secs(60),
+            timeout: Duration::from_millis(500),
+            max_failures: 3,
+        }
+    }
+}
+
+/// Keeps an idle bus alive and detects dead serial adapters early.
+///
+/// The watchdog periodically probes a single slave with a cheap read
+/// if no other requests have been sent recently. Any response, even
+/// a Modbus exception, proves that the bus is working. The bus is
+/// reconnected after `max_failures` consecutive failed probes.
+pub struct Watchdog {
+    shared_context: Arc<SharedContext>,
+    config: WatchdogConfig,
+    failures: u32,
+    sender: watch::Sender<BusHealth>,
+}
+
+impl Watchdog {
+    pub fn new(shared_context: Arc<SharedContext>, config: WatchdogConfig) -> Self {
+        let (sender, _) = watch::channel(BusHealth::Healthy);
+        Self {
+            shared_context,
+            config,
+            failures: 0,
+            sender,
+        }
+    }
+
+    pub fn config(&self) -> &WatchdogConfig {
+        &self.config
+    }
+
+    pub fn health(&self) -> BusHealth {
+        *self.sender.borrow()
+    }
+
+    /// Receive the updated health after each probe.
+    pub fn subscribe(&self) -> watch::Receiver<BusHealth> {
+        self.sender.subscribe()
+    }
+
+    /// Probe the bus if it has been idle for at least the configured
+    /// interval.
+    ///
+    /// The bus is reconnected if it is considered down.
+    pub async fn check(&mut self) -> BusHealth {
+        match self.shared_context.idle_time() {
+            Some(idle) if idle >= self.config.interval => (),
+            // Other requests are pending or have been sent recently
+            _ => return self.health(),
+        }
+        let proxy = SlaveProxy::new(self.config.slave, Arc::clone(&self.shared_context));
+        let responding = match proxy.probe(Some(self.config.timeout)).await {
+            Ok(ProbeResult::Timeout) => false,
+            Ok(_) => true,
+            Err(err) => {
+                log::debug!("Failed to probe the bus: {}", err);
+                false
+            }
+        };
+        let health = if responding {
+            self.failures = 0;
+            BusHealth::Healthy
+        } else {
+            self.failures = self.failures.saturating_add(1);
+            if self.failures < self.config.max_failures {
+                BusHealth::Degraded
+            } else {
+                log::warn!("Reconnecting after {} failed probes", self.failures);
+                if let Err(err) = self.shared_context.reconnect().await {
+                    log::warn!("Failed to reconnect: {}", err);
+                }
+                BusHealth::Down
+            }
+        };
+        self.sender.send_replace(health);
+        health
+    }
+
+    /// Check the bus periodically until `shutdown` resolves.
+    pub async fn run(mut self, shutdown: impl Future<Output = ()>) -> Self {
+        futures::pin_mut!(shutdown);
+        loop {
+            let sleep = self.shared_context.runtime().sleep(self.config.interval);
+            match future::select(&mut shutdown, sleep).await {
+                Either::Left(((), _)) => break,
+                Either::Right(((), _)) => {
+                    self.check().await;
+                }
+            }
+        }
+        self
+    }
+}
+
+#[cfg(all(test, feature = "tokio-mock"))]
+mod tests {
+    use super::*;
+
+    use crate::{
+        mock::{server::Faults, Bus},
+        runtime::ManualClock,
+    };
+    use futures::{future::BoxFuture, poll};
+    use std::{
+        io,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    const INTERVAL: Duration = Duration::from_secs(60);
+
+    const TIMEOUT: Duration = Duration::from_millis(500);
+
+    /// Counts the connections that have been opened.
+    struct CountingBus(Bus, Arc<AtomicUsize>);
+
+    impl NewContext for CountingBus {
+        fn new_context(&self) -> BoxFuture<'_, io::Result<client::Context>> {
+            self.1.fetch_add(1, Ordering::SeqCst);
+            self.0.new_context()
+        }
+    }
+
+    fn watchdog(bus: &Bus, clock: &ManualClock) -> (Watchdog, Arc<AtomicUsize>) {
+        let connects = Arc::new(AtomicUsize::new(0));
+        let new_context = CountingBus(bus.clone(), Arc::clone(&connects));
+        let shared_context = Arc::new(SharedContext::with_clock(
+            Some(bus.connect()),
+            Box::new(new_context),
+            Arc::new(clock.clone()),
+        ));
+        let config = WatchdogConfig {
+            slave: Slave(1),
+            interval: INTERVAL,
+            timeout: TIMEOUT,
+            max_failures: 2,
+        };
+        (Watchdog::new(shared_context, config), connects)
+    }
+
+    /// Wait for the clock to advance by the timeout of a probe.
+    async fn check_until_timeout(watchdog: &mut Watchdog, clock: &ManualClock) -> BusHealth {
+        let check = watchdog.check();
+        futures::pin_mut!(check);
+        assert!(poll!(check.as_mut()).is_pending());
+        clock.advance(TIMEOUT);
+        check.await
+    }
+
+    #[tokio::test]
+    async fn only_probe_idle_bus() {
+        let bus = Bus::new();
+        bus.add_slave(Slave(1));
+        let clock = ManualClock::new();
+        let (mut watchdog, _) = watchdog(&bus, &clock);
+        let requests = |watchdog: &Watchdog| watchdog.shared_context.diagnostics(Slave(1)).requests;
+        // Never used before
+        assert_eq!(BusHealth::Healthy, watchdog.check().await);
+        assert_eq!(1, requests(&watchdog));
+        clock.advance(INTERVAL - Duration::from_secs(1));
+        assert_eq!(BusHealth::Healthy, watchdog.check().await);
+        assert_eq!(1, requests(&watchdog));
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(BusHealth::Healthy, watchdog.check().await);
+        assert_eq!(2, requests(&watchdog));
+    }
+
+    #[tokio::test]
+    async fn exceptions_prove_a_working_bus() {
+        let bus = Bus::new();
+        bus.add_slave(Slave(1)).set_faults(Some(Faults {
+            exception: 1.0,
+            ..Default::default()
+        }));
+        let clock = ManualClock::new();
+        let (mut watchdog, connects) = watchdog(&bus, &clock);
+        assert_eq!(BusHealth::Healthy, watchdog.check().await);
+        assert_eq!(0, connects.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn reconnect_stalled_bus() {
+        let bus = Bus::new();
+        let sensor = bus.add_slave(Slave(1));
+        sensor.set_faults(Some(Faults {
+            timeout: 1.0,
+            ..Default::default()
+        }));
+        let clock = ManualClock::new();
+        let (mut watchdog, connects) = watchdog(&bus, &clock);
+        let mut subscriber = watchdog.subscribe();
+        assert_eq!(BusHealth::Degraded, check_until_timeout(&mut watchdog, &clock).await);
+        assert_eq!(0, connects.load(Ordering::SeqCst));
+        assert_eq!(BusHealth::Degraded, *subscriber.borrow_and_update());
+        clock.advance(INTERVAL);
+        assert_eq!(BusHealth::Down, check_until_timeout(&mut watchdog, &clock).await);
+        assert_eq!(1, connects.load(Ordering::SeqCst));
+        assert_eq!(BusHealth::Down, *subscriber.borrow_and_update());
+        // Reconnected again after each subsequent failure
+        clock.advance(INTERVAL);
+        assert_eq!(BusHealth::Down, check_until_timeout(&mut watchdog, &clock).await);
+        assert_eq!(2, connects.load(Ordering::SeqCst));
+        // Recovered
+        sensor.set_faults(None);
+        clock.advance(INTERVAL);
+        assert_eq!(BusHealth::Healthy, watchdog.check().await);
+        assert_eq!(BusHealth::Healthy, watchdog.health());
+        assert_eq!(2, connects.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn check_periodically_until_shutdown() {
+        let bus = Bus::new();
+        bus.add_slave(Slave(1));
+        let clock = ManualClock::new();
+        let (watchdog, _) = watchdog(&bus, &clock);
+        let shared_context = Arc::clone(&watchdog.shared_context);
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+        let run = tokio::spawn(watchdog.run(async {
+            shutdown_rx.await.ok();
+        }));
+        let settle = || async {
+            for _ in 0..100 {
+                tokio::task::yield_now().await;
+            }
+        };
+        let requests = || shared_context.diagnostics(Slave(1)).requests;
+        settle().await;
+        assert_eq!(0, requests());
+        clock.advance(INTERVAL);
+        settle().await;
+        assert_eq!(1, requests());
+        clock.advance(INTERVAL);
+        settle().await;
+        assert_eq!(2, requests());
+        shutdown_tx.send(()).unwrap();
+        let watchdog = run.await.unwrap();
+        assert_eq!(BusHealth::Healthy, watchdog.health());
+    }
+}
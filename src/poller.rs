@@ -0,0 +1,456 @@
+use super::*;
+
+use crate::{
+    history::History,
+    runtime::{self, Clock},
+    sink::MeasurementSink,
+    stats::WindowStats,
+};
+
+use futures::{stream, Future, Stream};
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use tokio::sync::watch;
+
+/// A measured value together with the time when it has been received.
+///
+/// The timestamp is (de-)serialized as an RFC 3339 string if either
+/// the feature `chrono` or `time` is enabled.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(
+    all(feature = "serde", any(feature = "chrono", feature = "time")),
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct Measurement<T> {
+    #[cfg_attr(
+        all(feature = "serde", any(feature = "chrono", feature = "time")),
+        serde(with = "rfc3339")
+    )]
+    pub ts: SystemTime,
+    pub val: T,
+}
+
+impl<T> Measurement<T> {
+    /// Timestamp `val` with the current system time.
+    pub fn new(val: T) -> Self {
+        Self::at(SystemTime::now(), val)
+    }
+
+    /// Timestamp `val` explicitly, e.g. by an injected `Clock`.
+    pub fn at(ts: SystemTime, val: T) -> Self {
+        Self { ts, val }
+    }
+
+    /// The timestamp as a _chrono_ date-time in UTC.
+    #[cfg(feature = "chrono")]
+    pub fn ts_chrono(&self) -> chrono::DateTime<chrono::Utc> {
+        self.ts.into()
+    }
+
+    /// The timestamp as a _time_ date-time in UTC.
+    #[cfg(feature = "time")]
+    pub fn ts_time(&self) -> ::time::OffsetDateTime {
+        self.ts.into()
+    }
+}
+
+/// RFC 3339 timestamps, preferably formatted by _chrono_.
+#[cfg(all(feature = "serde", any(feature = "chrono", feature = "time")))]
+mod rfc3339 {
+    use serde::{de, ser, Deserialize, Deserializer, Serializer};
+    use std::time::SystemTime;
+
+    #[cfg(feature = "chrono")]
+    fn format(ts: SystemTime) -> Result<String, String> {
+        Ok(chrono::DateTime::<chrono::Utc>::from(ts).to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true))
+    }
+
+    #[cfg(feature = "chrono")]
+    fn parse(input: &str) -> Result<SystemTime, String> {
+        chrono::DateTime::parse_from_rfc3339(input)
+            .map(Into::into)
+            .map_err(|err| err.to_string())
+    }
+
+    #[cfg(not(feature = "chrono"))]
+    fn format(ts: SystemTime) -> Result<String, String> {
+        ::time::OffsetDateTime::from(ts)
+            .format(&::time::format_description::well_known::Rfc3339)
+            .map_err(|err| err.to_string())
+    }
+
+    #[cfg(not(feature = "chrono"))]
+    fn parse(input: &str) -> Result<SystemTime, String> {
+        ::time::OffsetDateTime::parse(input, &::time::format_description::well_known::Rfc3339)
+            .map(Into::into)
+            .map_err(|err| err.to_string())
+    }
+
+    pub fn serialize<S: Serializer>(ts: &SystemTime, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format(*ts).map_err(ser::Error::custom)?)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<SystemTime, D::Error> {
+        let input = String::deserialize(deserializer)?;
+        parse(&input).map_err(de::Error::custom)
+    }
+}
+
+/// The last known good measurements.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(
+    all(feature = "serde", any(feature = "chrono", feature = "time")),
+    derive(serde::Serialize, serde::Deserialize)
+)]
+pub struct TimestampedMeasurements {
+    pub temperature: Option<Measurement<Temperature>>,
+    pub water_content: Option<Measurement<VolumetricWaterContent>>,
+    pub permittivity: Option<Measurement<RelativePermittivity>>,
+    pub raw_counts: Option<Measurement<RawCounts>>,
+}
+
+impl TimestampedMeasurements {
+    /// The time of the most recent measurement, if any.
+    pub fn latest_ts(&self) -> Option<SystemTime> {
+        let Self {
+            temperature,
+            water_content,
+            permittivity,
+            raw_counts,
+        } = self;
+        [
+            temperature.map(|m| m.ts),
+            water_content.map(|m| m.ts),
+            permittivity.map(|m| m.ts),
+            raw_counts.map(|m| m.ts),
+        ]
+        .iter()
+        .flatten()
+        .max()
+        .copied()
+    }
+
+    fn measured_since(&self, since: SystemTime) -> Self {
+        fn filter<T>(measurement: Option<Measurement<T>>, since: SystemTime) -> Option<Measurement<T>> {
+            measurement.filter(|m| m.ts >= since)
+        }
+        Self {
+            temperature: filter(self.temperature, since),
+            water_content: filter(self.water_content, since),
+            permittivity: filter(self.permittivity, since),
+            raw_counts: filter(self.raw_counts, since),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PollConfig {
+    /// The interval between two consecutive poll cycles.
+    pub cycle: Duration,
+
+    /// The timeout for each single measurement.
+    pub timeout: Duration,
+
+    /// The quantities that are measured one after another during
+    /// each poll cycle.
+    ///
+    /// See also: `with_selection()`
+    pub order: Vec<Quantity>,
+
+    /// The Modbus slave address of the sensor that is reported
+    /// to sinks.
+    pub slave: Option<u8>,
+
+    /// The number of poll cycles that are kept in the history.
+    ///
+    /// The history is disabled by default.
+    pub history_capacity: usize,
+}
+
+impl Default for PollConfig {
+    fn default() -> Self {
+        Self {
+            cycle: Duration::from_millis(1000),
+            timeout: Duration::from_millis(500),
+            order: vec![
+                Quantity::Temperature,
+                Quantity::WaterContent,
+                Quantity::Permittivity,
+            ],
+            slave: None,
+            history_capacity: 0,
+        }
+    }
+}
+
+impl PollConfig {
+    /// Only measure the selected quantities in register order.
+    ///
+    /// Quantities that are not selected remain `None` in
+    /// `TimestampedMeasurements`.
+    pub fn with_selection(mut self, selection: MeasurementSelection) -> Self {
+        self.order = selection.quantities().collect();
+        self
+    }
+
+    /// The quantities that are measured during each poll cycle.
+    pub fn selection(&self) -> MeasurementSelection {
+        self.order
+            .iter()
+            .fold(MeasurementSelection::empty(), |selection, quantity| {
+                selection | MeasurementSelection::from(*quantity)
+            })
+    }
+}
+
+/// Periodically measures all configured quantities of a single sensor.
+///
+/// Failed measurements are logged and skipped, i.e. the last known
+/// good value is kept. Subscribers and sinks are notified after each
+/// poll cycle.
+pub struct Poller<C> {
+    capabilities: C,
+    config: PollConfig,
+    measurements: TimestampedMeasurements,
+    sender: watch::Sender<TimestampedMeasurements>,
+    sinks: Vec<Box<dyn MeasurementSink>>,
+    history: History<TimestampedMeasurements>,
+    clock: Arc<dyn Clock>,
+}
+
+impl<C> Poller<C>
+where
+    C: Capabilities,
+{
+    pub fn new(capabilities: C, config: PollConfig) -> Self {
+        let measurements = TimestampedMeasurements::default();
+        let (sender, _) = watch::channel(measurements);
+        let history = History::new(config.history_capacity);
+        Self {
+            capabilities,
+            config,
+            measurements,
+            sender,
+            sinks: Vec::new(),
+            history,
+            clock: Arc::new(runtime::Tokio),
+        }
+    }
+
+    /// Schedule the poll cycles by a different clock than _tokio_,
+    /// e.g. a `runtime::ManualClock` in tests.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn config(&self) -> &PollConfig {
+        &self.config
+    }
+
+    /// The last known good measurements.
+    pub fn measurements(&self) -> &TimestampedMeasurements {
+        &self.measurements
+    }
+
+    /// The values that have been measured during recent poll cycles.
+    ///
+    /// Each entry only contains the measurements of the corresponding
+    /// poll cycle, i.e. failed measurements are missing.
+    pub fn history(&self) -> &History<TimestampedMeasurements> {
+        &self.history
+    }
+
+    /// Statistics of the poll cycles within `window`.
+    ///
+    /// The window is limited by the capacity of the history, see
+    /// also `PollConfig::history_capacity`.
+    pub fn window_stats(&self, window: Duration) -> WindowStats {
+        WindowStats::new(&self.history, window, self.clock.system_time())
+    }
+
+    /// Receive the updated measurements after each poll cycle.
+    pub fn subscribe(&self) -> watch::Receiver<TimestampedMeasurements> {
+        self.sender.subscribe()
+    }
+
+    /// Record the updated measurements after each poll cycle.
+    pub fn add_sink(&mut self, sink: Box<dyn MeasurementSink>) {
+        self.sinks.push(sink);
+    }
+
+    async fn measure(&mut self, quantity: Quantity) {
+        let timeout = Some(self.config.timeout);
+        let measurements = &mut self.measurements;
+        match quantity {
+            Quantity::Temperature => {
+                let res = self.capabilities.read_temperature(timeout).await;
+                if let Some(val) = ok_or_log(quantity, res) {
+                    measurements.temperature = Some(Measurement::at(self.clock.system_time(), val));
+                }
+            }
+            Quantity::WaterContent => {
+                let res = self.capabilities.read_water_content(timeout).await;
+                if let Some(val) = ok_or_log(quantity, res) {
+                    measurements.water_content = Some(Measurement::at(self.clock.system_time(), val));
+                }
+            }
+            Quantity::Permittivity => {
+                let res = self.capabilities.read_permittivity(timeout).await;
+                if let Some(val) = ok_or_log(quantity, res) {
+                    measurements.permittivity = Some(Measurement::at(self.clock.system_time(), val));
+                }
+            }
+            Quantity::RawCounts => {
+                let res = self.capabilities.read_raw_counts(timeout).await;
+                if let Some(val) = ok_or_log(quantity, res) {
+                    measurements.raw_counts = Some(Measurement::at(self.clock.system_time(), val));
+                }
+            }
+        }
+    }
+
+    /// Measure all configured quantities once and notify subscribers
+    /// and sinks.
+    ///
+    /// Failed sinks are logged and skipped.
+    pub async fn poll_once(&mut self) {
+        let started = self.clock.system_time();
+        for quantity in self.config.order.clone() {
+            self.measure(quantity).await;
+        }
+        self.history.push(Measurement {
+            ts: started,
+            val: self.measurements.measured_since(started),
+        });
+        // Subscribers that join later receive the current value
+        self.sender.send_replace(self.measurements);
+        if let Err(err) = self.sinks.record(self.config.slave, self.measurements).await {
+            log::warn!("Failed to record measurements: {}", err);
+        }
+    }
+
+    /// Run the poll cycle until `shutdown` resolves.
+    ///
+    /// A poll cycle that is in progress will be finished before
+    /// the poller is returned.
+    pub async fn run(mut self, shutdown: impl Future<Output = ()>) -> Self {
+        let cycle = self.config.cycle;
+        let mut next_cycle = self.clock.now() + cycle;
+        futures::pin_mut!(shutdown);
+        loop {
+            tokio::select! {
+                () = &mut shutdown => break,
+                () = self.clock.sleep_until(next_cycle) => {
+                    // Delayed poll cycles are caught up
+                    next_cycle += cycle;
+                    self.poll_once().await;
+                }
+            }
+        }
+        self
+    }
+}
+
+fn ok_or_log<T>(quantity: Quantity, res: Result<T>) -> Option<T> {
+    match res.map_err(|err| err.with_timeout_quantity(quantity)) {
+        Ok(val) => Some(val),
+        Err(err) => {
+            log::warn!("Failed to measure {:?}: {}", quantity, err);
+            None
+        }
+    }
+}
+
+async fn read_quantities<C>(
+    capabilities: &C,
+    clock: &dyn Clock,
+    order: &[Quantity],
+    timeout: Option<Duration>,
+) -> Result<TimestampedMeasurements>
+where
+    C: Capabilities,
+{
+    let mut measurements = TimestampedMeasurements::default();
+    for quantity in order {
+        match quantity {
+            Quantity::Temperature => {
+                let val = capabilities
+                    .read_temperature(timeout)
+                    .await
+                    .map_err(|err| err.with_timeout_quantity(*quantity))?;
+                measurements.temperature = Some(Measurement::at(clock.system_time(), val));
+            }
+            Quantity::WaterContent => {
+                let val = capabilities
+                    .read_water_content(timeout)
+                    .await
+                    .map_err(|err| err.with_timeout_quantity(*quantity))?;
+                measurements.water_content = Some(Measurement::at(clock.system_time(), val));
+            }
+            Quantity::Permittivity => {
+                let val = capabilities
+                    .read_permittivity(timeout)
+                    .await
+                    .map_err(|err| err.with_timeout_quantity(*quantity))?;
+                measurements.permittivity = Some(Measurement::at(clock.system_time(), val));
+            }
+            Quantity::RawCounts => {
+                let val = capabilities
+                    .read_raw_counts(timeout)
+                    .await
+                    .map_err(|err| err.with_timeout_quantity(*quantity))?;
+                measurements.raw_counts = Some(Measurement::at(clock.system_time(), val));
+            }
+        }
+    }
+    Ok(measurements)
+}
+
+/// Periodically measure all configured quantities.
+///
+/// Each poll cycle yields either all measurements or the first error
+/// that occurred. Errors don't terminate the stream, only the next
+/// poll cycle is awaited. The stream never ends.
+pub fn measurement_stream<C>(
+    capabilities: C,
+    config: PollConfig,
+) -> impl Stream<Item = Result<TimestampedMeasurements>>
+where
+    C: Capabilities,
+{
+    measurement_stream_with_clock(capabilities, config, Arc::new(runtime::Tokio))
+}
+
+/// Periodically measure all configured quantities, scheduled by
+/// a different clock than _tokio_.
+///
+/// See also: `measurement_stream()`
+pub fn measurement_stream_with_clock<C>(
+    capabilities: C,
+    config: PollConfig,
+    clock: Arc<dyn Clock>,
+) -> impl Stream<Item = Result<TimestampedMeasurements>>
+where
+    C: Capabilities,
+{
+    let PollConfig {
+        cycle,
+        timeout,
+        order,
+        slave: _,
+        history_capacity: _,
+    } = config;
+    let next_cycle = clock.now() + cycle;
+    stream::unfold(
+        (capabilities, clock, order, next_cycle),
+        move |(capabilities, clock, order, next_cycle)| async move {
+            // Delayed poll cycles are caught up
+            clock.sleep_until(next_cycle).await;
+            let res = read_quantities(&capabilities, &*clock, &order, Some(timeout)).await;
+            Some((res, (capabilities, clock, order, next_cycle + cycle)))
+        },
+    )
+}
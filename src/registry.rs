@@ -0,0 +1,182 @@
+//! Sensors that are referenced by user-defined names.
+//!
+//! Application config files can reference sensors by names like
+//! `"bed-A"` or `"greenhouse-3"` instead of Modbus slave addresses.
+
+use super::*;
+
+/// Describes where and how a sensor is installed.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SensorMetadata {
+    /// Free text, e.g. "Greenhouse 3, north-east corner".
+    pub location: Option<String>,
+
+    /// The installation depth below the surface in meters.
+    pub depth: Option<f64>,
+
+    /// Free text, e.g. "sandy loam".
+    pub soil_type: Option<String>,
+}
+
+/// A sensor in the registry.
+pub struct RegisteredSensor {
+    capabilities: Box<dyn Capabilities + Send + Sync>,
+    metadata: SensorMetadata,
+}
+
+impl RegisteredSensor {
+    pub fn capabilities(&self) -> &(dyn Capabilities + Send + Sync) {
+        &*self.capabilities
+    }
+
+    pub fn metadata(&self) -> &SensorMetadata {
+        &self.metadata
+    }
+
+    pub fn metadata_mut(&mut self) -> &mut SensorMetadata {
+        &mut self.metadata
+    }
+
+    /// Release the sensor.
+    pub fn into_capabilities(self) -> Box<dyn Capabilities + Send + Sync> {
+        self.capabilities
+    }
+}
+
+/// Maps unique names to sensors with different backends, e.g.
+/// Modbus proxies and mocks.
+///
+/// Sensors are iterated in insertion order.
+#[derive(Default)]
+pub struct SensorRegistry {
+    sensors: Vec<(String, RegisteredSensor)>,
+}
+
+impl SensorRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.sensors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sensors.is_empty()
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.sensors.iter().position(|(n, _)| n == name)
+    }
+
+    /// Add a sensor or replace the sensor with the same name.
+    ///
+    /// Returns the replaced sensor that keeps its position.
+    pub fn insert(
+        &mut self,
+        name: impl Into<String>,
+        capabilities: Box<dyn Capabilities + Send + Sync>,
+        metadata: SensorMetadata,
+    ) -> Option<RegisteredSensor> {
+        let name = name.into();
+        let sensor = RegisteredSensor {
+            capabilities,
+            metadata,
+        };
+        if let Some(pos) = self.position(&name) {
+            Some(std::mem::replace(&mut self.sensors[pos].1, sensor))
+        } else {
+            self.sensors.push((name, sensor));
+            None
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<RegisteredSensor> {
+        let pos = self.position(name)?;
+        Some(self.sensors.remove(pos).1)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.position(name).is_some()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&RegisteredSensor> {
+        self.position(name).map(|pos| &self.sensors[pos].1)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut RegisteredSensor> {
+        let pos = self.position(name)?;
+        Some(&mut self.sensors[pos].1)
+    }
+
+    /// The capabilities of the named sensor.
+    pub fn capabilities(&self, name: &str) -> Option<&(dyn Capabilities + Send + Sync)> {
+        self.get(name).map(RegisteredSensor::capabilities)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.sensors.iter().map(|(name, _)| name.as_str())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &RegisteredSensor)> {
+        self.sensors.iter().map(|(name, sensor)| (name.as_str(), sensor))
+    }
+}
+
+#[cfg(all(test, feature = "tokio-mock"))]
+mod tests {
+    use super::*;
+
+    use crate::mock::{self, Driver};
+
+    fn sensor(degree_celsius: f64) -> Box<dyn Capabilities + Send + Sync> {
+        let mut proxy = mock::Proxy::default();
+        proxy.set_temperature(Temperature::from_degree_celsius(degree_celsius));
+        Box::new(proxy)
+    }
+
+    fn located(location: &str) -> SensorMetadata {
+        SensorMetadata {
+            location: Some(location.to_owned()),
+            ..Default::default()
+        }
+    }
+
+    async fn temperature(registry: &SensorRegistry, name: &str) -> Option<f64> {
+        let capabilities = registry.capabilities(name)?;
+        let temperature = capabilities.read_temperature(None).await.unwrap();
+        Some(temperature.to_degree_celsius())
+    }
+
+    #[tokio::test]
+    async fn replace_sensors_in_place() {
+        let mut registry = SensorRegistry::new();
+        assert!(registry.is_empty());
+        assert!(registry.insert("bed-B", sensor(10.0), located("south")).is_none());
+        assert!(registry.insert("bed-A", sensor(20.0), Default::default()).is_none());
+        let replaced = registry.insert("bed-B", sensor(30.0), located("north")).unwrap();
+        assert_eq!(&located("south"), replaced.metadata());
+        assert_eq!(2, registry.len());
+        assert_eq!(vec!["bed-B", "bed-A"], registry.names().collect::<Vec<_>>());
+        assert_eq!(Some(30.0), temperature(&registry, "bed-B").await);
+        assert_eq!(Some(20.0), temperature(&registry, "bed-A").await);
+        assert_eq!(None, temperature(&registry, "bed-C").await);
+        let metadata: Vec<_> = registry.iter().map(|(_, sensor)| sensor.metadata().clone()).collect();
+        assert_eq!(vec![located("north"), Default::default()], metadata);
+    }
+
+    #[tokio::test]
+    async fn update_and_remove_sensors() {
+        let mut registry = SensorRegistry::new();
+        registry.insert("bed-A", sensor(20.0), Default::default());
+        registry.get_mut("bed-A").unwrap().metadata_mut().depth = Some(0.3);
+        assert_eq!(Some(0.3), registry.get("bed-A").unwrap().metadata().depth);
+        assert!(registry.get_mut("bed-B").is_none());
+        let removed = registry.remove("bed-A").unwrap();
+        assert!(!registry.contains("bed-A"));
+        assert!(registry.remove("bed-A").is_none());
+        let capabilities = removed.into_capabilities();
+        let temperature = capabilities.read_temperature(None).await.unwrap();
+        assert_eq!(Temperature::from_degree_celsius(20.0), temperature);
+    }
+}
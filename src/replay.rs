@@ -0,0 +1,352 @@
+//! Record and replay the raw traffic of a transport.
+//!
+//! Traces are stored as plain text with one frame per line. Requests
+//! that have been sent to the sensor start with `>` and responses that
+//! have been received from the sensor start with `<`, followed by the
+//! hex encoded bytes, e.g.
+//!
+//! ```text
+//! # Read temperature
+//! > 01 03 00 00 00 01 84 0A
+//! < 01 03 02 27 10 A2 78
+//! ```
+//!
+//! Empty lines and lines starting with `#` are ignored.
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    io::{self, BufRead, Write},
+    pin::Pin,
+    task::{ready, Context, Poll, Waker},
+};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// The direction of a frame as seen from the client.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Request,
+    Response,
+}
+
+impl Direction {
+    fn prefix(self) -> char {
+        match self {
+            Direction::Request => '>',
+            Direction::Response => '<',
+        }
+    }
+}
+
+/// Raw bytes that have been transmitted in one direction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub direction: Direction,
+    pub bytes: Vec<u8>,
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.direction.prefix())?;
+        for byte in &self.bytes {
+            write!(f, " {:02X}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+fn invalid_trace(line_number: usize, line: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid trace in line {}: {}", line_number, line),
+    )
+}
+
+/// Parse all frames from a trace.
+pub fn read_trace(reader: impl BufRead) -> io::Result<Vec<Frame>> {
+    let mut frames = Vec::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let direction = match line.chars().next() {
+            Some('>') => Direction::Request,
+            Some('<') => Direction::Response,
+            _ => return Err(invalid_trace(index + 1, line)),
+        };
+        let bytes = line[1..]
+            .split_whitespace()
+            .map(|hex| u8::from_str_radix(hex, 16))
+            .collect::<std::result::Result<_, _>>()
+            .map_err(|_| invalid_trace(index + 1, line))?;
+        frames.push(Frame { direction, bytes });
+    }
+    Ok(frames)
+}
+
+/// Transparently records all traffic of a transport into a trace.
+///
+/// Failures while writing the trace are logged and don't affect the
+/// communication.
+#[derive(Debug)]
+pub struct Recorder<T, W> {
+    transport: T,
+    trace: W,
+}
+
+impl<T, W: Write> Recorder<T, W> {
+    pub fn new(transport: T, trace: W) -> Self {
+        Self { transport, trace }
+    }
+
+    pub fn into_inner(self) -> (T, W) {
+        (self.transport, self.trace)
+    }
+
+    fn record(&mut self, direction: Direction, bytes: &[u8]) {
+        if bytes.is_empty() {
+            return;
+        }
+        let frame = Frame {
+            direction,
+            bytes: bytes.to_vec(),
+        };
+        if let Err(err) = writeln!(self.trace, "{}", frame) {
+            log::warn!("Failed to record frame {}: {}", frame, err);
+        }
+    }
+}
+
+impl<T: AsyncRead + Unpin, W: Write + Unpin> AsyncRead for Recorder<T, W> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let filled = buf.filled().len();
+        ready!(Pin::new(&mut self.transport).poll_read(cx, buf))?;
+        self.record(Direction::Response, &buf.filled()[filled..]);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<T: AsyncWrite + Unpin, W: Write + Unpin> AsyncWrite for Recorder<T, W> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let len = ready!(Pin::new(&mut self.transport).poll_write(cx, buf))?;
+        self.record(Direction::Request, &buf[..len]);
+        Poll::Ready(Ok(len))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if let Err(err) = self.trace.flush() {
+            log::warn!("Failed to flush trace: {}", err);
+        }
+        Pin::new(&mut self.transport).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.transport).poll_shutdown(cx)
+    }
+}
+
+/// Fake transport that replays a recorded trace.
+///
+/// All requests must match the recorded requests exactly, otherwise
+/// writing fails. Reading after the end of the trace has been reached
+/// signals the end of the stream.
+#[derive(Debug)]
+pub struct Replayer {
+    frames: VecDeque<Frame>,
+    reader: Option<Waker>,
+}
+
+impl Replayer {
+    pub fn new(frames: impl IntoIterator<Item = Frame>) -> Self {
+        // Consecutive frames in the same direction are merged, because
+        // the chunks depend on the timing of the recorded session.
+        let mut merged: VecDeque<Frame> = VecDeque::new();
+        for frame in frames {
+            match merged.back_mut() {
+                Some(last) if last.direction == frame.direction => last.bytes.extend(frame.bytes),
+                _ => merged.push_back(frame),
+            }
+        }
+        Self {
+            frames: merged,
+            reader: None,
+        }
+    }
+
+    pub fn from_trace(reader: impl BufRead) -> io::Result<Self> {
+        read_trace(reader).map(Self::new)
+    }
+
+    /// Check if the whole trace has been replayed.
+    pub fn is_finished(&self) -> bool {
+        self.frames.is_empty()
+    }
+}
+
+impl AsyncRead for Replayer {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        match self.frames.front_mut() {
+            Some(Frame {
+                direction: Direction::Response,
+                bytes,
+            }) => {
+                let len = buf.remaining().min(bytes.len());
+                buf.put_slice(&bytes[..len]);
+                bytes.drain(..len);
+                if bytes.is_empty() {
+                    self.frames.pop_front();
+                }
+                Poll::Ready(Ok(()))
+            }
+            Some(Frame {
+                direction: Direction::Request,
+                ..
+            }) => {
+                // Wait for the next request
+                self.reader = Some(cx.waker().clone());
+                Poll::Pending
+            }
+            None => Poll::Ready(Ok(())),
+        }
+    }
+}
+
+impl AsyncWrite for Replayer {
+    fn poll_write(mut self: Pin<&mut Self>, _cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let bytes = match self.frames.front_mut() {
+            Some(Frame {
+                direction: Direction::Request,
+                bytes,
+            }) if bytes.starts_with(&buf[..buf.len().min(bytes.len())]) => bytes,
+            Some(frame) => {
+                return Poll::Ready(Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unexpected request, expected {}", frame),
+                )));
+            }
+            None => {
+                return Poll::Ready(Err(io::Error::new(io::ErrorKind::UnexpectedEof, "end of trace")));
+            }
+        };
+        let len = buf.len().min(bytes.len());
+        bytes.drain(..len);
+        if bytes.is_empty() {
+            self.frames.pop_front();
+            if let Some(reader) = self.reader.take() {
+                reader.wake();
+            }
+        }
+        Poll::Ready(Ok(len))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+
+    const TRACE: &str = "# Read temperature
+> 01 03 00 00 00 01 84 0A
+
+< 01 03 02
+< 27 10 A2 78
+";
+
+    #[test]
+    fn parse_trace() {
+        let frames = read_trace(TRACE.as_bytes()).unwrap();
+        assert_eq!(3, frames.len());
+        assert_eq!(Direction::Request, frames[0].direction);
+        assert_eq!(vec![0x01, 0x03, 0x02], frames[1].bytes);
+        assert_eq!(Direction::Response, frames[2].direction);
+        assert_eq!("< 27 10 A2 78", frames[2].to_string());
+    }
+
+    #[test]
+    fn reject_malformed_trace() {
+        for (trace, line) in [
+            ("> 01 03\n01 03 02", "line 2"),
+            ("> 01 0X", "line 1"),
+            ("# Comment\n\n< 01 103", "line 3"),
+        ] {
+            let err = read_trace(trace.as_bytes()).unwrap_err();
+            assert_eq!(io::ErrorKind::InvalidData, err.kind());
+            assert!(err.to_string().contains(line), "{}", err);
+        }
+    }
+
+    #[tokio::test]
+    async fn replay_trace() {
+        let mut replayer = Replayer::from_trace(TRACE.as_bytes()).unwrap();
+        replayer.write_all(&[0x01, 0x03, 0x00, 0x00]).await.unwrap();
+        replayer.write_all(&[0x00, 0x01, 0x84, 0x0A]).await.unwrap();
+        let mut rsp = [0; 7];
+        replayer.read_exact(&mut rsp).await.unwrap();
+        assert_eq!([0x01, 0x03, 0x02, 0x27, 0x10, 0xA2, 0x78], rsp);
+        assert!(replayer.is_finished());
+        let err = replayer.write_all(&[0x01]).await.unwrap_err();
+        assert_eq!(io::ErrorKind::UnexpectedEof, err.kind());
+    }
+
+    #[tokio::test]
+    async fn reject_unexpected_request() {
+        let mut replayer = Replayer::from_trace(TRACE.as_bytes()).unwrap();
+        let err = replayer.write_all(&[0x02, 0x03]).await.unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+        assert!(!replayer.is_finished());
+    }
+
+    #[cfg(all(feature = "tokio-mock", feature = "tokio-modbus-rtu"))]
+    #[tokio::test]
+    async fn record_and_replay_mock_session() {
+        use crate::{
+            mock::Bus,
+            modbus::{self, rtu},
+        };
+        use std::sync::{Arc, Mutex};
+        use tokio_modbus::{prelude::SlaveContext as _, Slave};
+
+        /// A trace that is shared with the recorder.
+        #[derive(Debug, Clone, Default)]
+        struct SharedTrace(Arc<Mutex<Vec<u8>>>);
+
+        impl Write for SharedTrace {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let bus = Bus::new();
+        bus.add_slave(Slave(1));
+        let trace = SharedTrace::default();
+        let mut context = rtu::connect(Recorder::new(bus.transport(), trace.clone()));
+        context.set_slave(Slave(1));
+        let recorded = modbus::read_measurements(&mut context).await.unwrap();
+        let trace = trace.0.lock().unwrap().clone();
+        let frames = read_trace(trace.as_slice()).unwrap();
+        assert!(frames.iter().any(|frame| frame.direction == Direction::Request));
+        assert!(frames.iter().any(|frame| frame.direction == Direction::Response));
+        // The sensor is not needed for replaying the session
+        let mut context = rtu::connect(Replayer::new(frames));
+        context.set_slave(Slave(1));
+        assert_eq!(recorded, modbus::read_measurements(&mut context).await.unwrap());
+        // The trace has been replayed completely
+        assert!(modbus::read_temperature(&mut context).await.is_err());
+    }
+}
@@ -0,0 +1,153 @@
+use super::*;
+
+use crate::runtime::{self, Runtime};
+
+use futures::{future::BoxFuture, Future, FutureExt};
+use std::{io, sync::Arc, time::Duration};
+
+/// Decides if a failed request should be retried.
+pub type RetryOn = fn(&Error) -> bool;
+
+/// Timeouts, corrupt responses, and implausible values are considered
+/// as transient errors that might disappear when repeating the request.
+pub fn is_transient(err: &Error) -> bool {
+    match err {
+        Error::Timeout(_) | Error::Decode(_) | Error::Rejected(_) => true,
+        Error::Transport(err) => err.kind() == io::ErrorKind::InvalidData,
+        _ => false,
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    /// The maximum number of retries after the initial request failed.
+    pub max_retries: u32,
+
+    /// The delay before the first retry that is doubled for each
+    /// subsequent retry.
+    pub backoff: Duration,
+
+    /// Only matching errors are retried.
+    pub retry_on: RetryOn,
+}
+
+impl RetryPolicy {
+    /// The delay before the given retry, starting at 0.
+    pub fn delay(&self, retry: u32) -> Duration {
+        self.backoff * 2u32.saturating_pow(retry)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            backoff: Duration::from_millis(100),
+            retry_on: is_transient,
+        }
+    }
+}
+
+/// Repeat a request according to the policy until it succeeds.
+pub async fn retry<T, F, R>(policy: RetryPolicy, request: F) -> Result<T>
+where
+    F: FnMut() -> R,
+    R: Future<Output = Result<T>>,
+{
+    retry_with_runtime(&runtime::Tokio, policy, request).await
+}
+
+/// Repeat a request according to the policy until it succeeds,
+/// waiting for the timers of `runtime` between retries.
+pub async fn retry_with_runtime<T, F, R>(
+    runtime: &(impl Runtime + ?Sized),
+    policy: RetryPolicy,
+    mut request: F,
+) -> Result<T>
+where
+    F: FnMut() -> R,
+    R: Future<Output = Result<T>>,
+{
+    let mut retry = 0;
+    loop {
+        match request().await {
+            Err(err) if retry < policy.max_retries && (policy.retry_on)(&err) => {
+                let delay = policy.delay(retry);
+                log::debug!(
+                    "Retrying request after {:?} ({} of {}): {}",
+                    delay,
+                    retry + 1,
+                    policy.max_retries,
+                    err
+                );
+                runtime.sleep(delay).await;
+                retry += 1;
+            }
+            res => return res,
+        }
+    }
+}
+
+/// Decorator that retries failed requests according to a policy.
+pub struct RetryingProxy<C> {
+    capabilities: C,
+    policy: RetryPolicy,
+    runtime: Arc<dyn Runtime>,
+}
+
+impl<C> RetryingProxy<C>
+where
+    C: Capabilities,
+{
+    pub fn new(capabilities: C, policy: RetryPolicy) -> Self {
+        Self {
+            capabilities,
+            policy,
+            runtime: Arc::new(runtime::Tokio),
+        }
+    }
+
+    /// Wait between retries for the timers of a different runtime
+    /// than _tokio_, e.g. a `runtime::ManualClock` in tests.
+    pub fn with_runtime(mut self, runtime: Arc<dyn Runtime>) -> Self {
+        self.runtime = runtime;
+        self
+    }
+
+    pub fn policy(&self) -> &RetryPolicy {
+        &self.policy
+    }
+
+    pub fn capabilities(&self) -> &C {
+        &self.capabilities
+    }
+}
+
+impl<C> Capabilities for RetryingProxy<C>
+where
+    C: Capabilities + Sync,
+{
+    fn read_temperature(&self, timeout: Option<Duration>) -> BoxFuture<'_, Result<Temperature>> {
+        let capabilities = &self.capabilities;
+        let request = move || capabilities.read_temperature(timeout);
+        retry_with_runtime(&*self.runtime, self.policy, request).boxed()
+    }
+
+    fn read_water_content(&self, timeout: Option<Duration>) -> BoxFuture<'_, Result<VolumetricWaterContent>> {
+        let capabilities = &self.capabilities;
+        let request = move || capabilities.read_water_content(timeout);
+        retry_with_runtime(&*self.runtime, self.policy, request).boxed()
+    }
+
+    fn read_permittivity(&self, timeout: Option<Duration>) -> BoxFuture<'_, Result<RelativePermittivity>> {
+        let capabilities = &self.capabilities;
+        let request = move || capabilities.read_permittivity(timeout);
+        retry_with_runtime(&*self.runtime, self.policy, request).boxed()
+    }
+
+    fn read_raw_counts(&self, timeout: Option<Duration>) -> BoxFuture<'_, Result<RawCounts>> {
+        let capabilities = &self.capabilities;
+        let request = move || capabilities.read_raw_counts(timeout);
+        retry_with_runtime(&*self.runtime, self.policy, request).boxed()
+    }
+}
@@ -0,0 +1,220 @@
+//! Timers of the async runtime.
+//!
+//! The Modbus clients only depend on the runtime for timeouts and the
+//! delay between consecutive requests. Other runtimes than _tokio_ are
+//! supported by implementing `Runtime`, e.g. for _smol_ together with
+//! `transport::Proxy`:
+//!
+//! ```ignore
+//! struct Smol;
+//!
+//! impl Runtime for Smol {
+//!     fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+//!         async_io::Timer::after(duration).map(|_| ()).boxed()
+//!     }
+//! }
+//! ```
+//!
+//! Each `Clock` is also a `Runtime`. A `ManualClock` replaces the
+//! clock of the runtime in deterministic tests.
+
+use super::*;
+
+use futures::future::{self, BoxFuture, Either, Future};
+use std::{
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant, SystemTime},
+};
+
+pub trait Runtime: Send + Sync {
+    /// Complete after `duration` has elapsed.
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()>;
+}
+
+/// The current time and timers for scheduling periodic tasks.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+
+    /// The current wall-clock time, e.g. for timestamping measurements.
+    fn system_time(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    /// Complete when `deadline` has been reached.
+    fn sleep_until(&self, deadline: Instant) -> BoxFuture<'static, ()>;
+}
+
+impl<C: Clock + ?Sized> Clock for Arc<C> {
+    fn now(&self) -> Instant {
+        (**self).now()
+    }
+
+    fn system_time(&self) -> SystemTime {
+        (**self).system_time()
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> BoxFuture<'static, ()> {
+        (**self).sleep_until(deadline)
+    }
+}
+
+impl<C: Clock + ?Sized> Runtime for C {
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        self.sleep_until(self.now() + duration)
+    }
+}
+
+/// The _tokio_ runtime.
+///
+/// The clock could be paused and advanced in tests with the
+/// `test-util` feature of _tokio_.
+#[cfg(feature = "tokio")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Tokio;
+
+#[cfg(feature = "tokio")]
+impl Clock for Tokio {
+    fn now(&self) -> Instant {
+        tokio::time::Instant::now().into_std()
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> BoxFuture<'static, ()> {
+        Box::pin(tokio::time::sleep_until(deadline.into()))
+    }
+}
+
+#[derive(Debug)]
+struct ManualState {
+    now: Instant,
+    system_time: SystemTime,
+    sleepers: Vec<Waker>,
+}
+
+/// A clock that only advances when told so.
+///
+/// Cloned instances share the same time.
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+    state: Arc<Mutex<ManualState>>,
+}
+
+impl ManualClock {
+    /// Start at the current time.
+    pub fn new() -> Self {
+        Self::starting_at(SystemTime::now())
+    }
+
+    /// Start at a fixed wall-clock time.
+    pub fn starting_at(system_time: SystemTime) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(ManualState {
+                now: Instant::now(),
+                system_time,
+                sleepers: Vec::new(),
+            })),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, ManualState> {
+        lock_manual_state(&self.state)
+    }
+
+    /// Advance the time and wake up all sleepers that have
+    /// reached their deadline.
+    pub fn advance(&self, duration: Duration) {
+        let sleepers = {
+            let mut state = self.state();
+            state.now += duration;
+            state.system_time += duration;
+            std::mem::take(&mut state.sleepers)
+        };
+        // Sleepers that are not yet due register themselves again
+        for sleeper in sleepers {
+            sleeper.wake();
+        }
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn lock_manual_state(state: &Mutex<ManualState>) -> MutexGuard<'_, ManualState> {
+    state.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.state().now
+    }
+
+    fn system_time(&self) -> SystemTime {
+        self.state().system_time
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> BoxFuture<'static, ()> {
+        Box::pin(ManualSleep {
+            state: Arc::clone(&self.state),
+            deadline,
+        })
+    }
+}
+
+struct ManualSleep {
+    state: Arc<Mutex<ManualState>>,
+    deadline: Instant,
+}
+
+impl Future for ManualSleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = lock_manual_state(&self.state);
+        if state.now >= self.deadline {
+            Poll::Ready(())
+        } else {
+            state.sleepers.push(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+/// Fail with `Error::Timeout` if the request doesn't finish in time.
+pub async fn timeout<T, R>(
+    runtime: &R,
+    timeout: Option<Duration>,
+    request: impl Future<Output = Result<T>>,
+) -> Result<T>
+where
+    R: Runtime + ?Sized,
+{
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return request.await,
+    };
+    futures::pin_mut!(request);
+    match future::select(request, runtime.sleep(timeout)).await {
+        Either::Left((res, _)) => res,
+        Either::Right(((), _)) => Err(TimeoutError::after(timeout).into()),
+    }
+}
+
+/// Fail with `Error::Timeout` if the request doesn't finish before `deadline`.
+pub async fn timeout_at<T, C>(
+    clock: &C,
+    deadline: Instant,
+    request: impl Future<Output = Result<T>>,
+) -> Result<T>
+where
+    C: Clock + ?Sized,
+{
+    futures::pin_mut!(request);
+    match future::select(request, clock.sleep_until(deadline)).await {
+        Either::Left((res, _)) => res,
+        Either::Right(((), _)) => Err(TimeoutError::default().into()),
+    }
+}
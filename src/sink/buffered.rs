@@ -0,0 +1,333 @@
+//! Store-and-forward buffering of measurements when the downstream
+//! sink fails, e.g. while the uplink of a field gateway is down.
+//!
+//! Measurements that could not be recorded are spooled into an
+//! append-only file with a single line per record and forwarded in
+//! their original order as soon as the sink has recovered. The spool
+//! file survives restarts.
+
+use super::*;
+
+use crate::poller::Measurement;
+
+use std::{
+    collections::VecDeque,
+    fmt::Write as _,
+    fs::{self, OpenOptions},
+    io::Write as _,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{Duration, UNIX_EPOCH},
+};
+
+type Record = (Option<u8>, TimestampedMeasurements);
+
+/// Spools measurements while the inner sink fails.
+///
+/// At most `capacity` records are spooled. The oldest records are
+/// discarded when the capacity is exceeded.
+pub struct BufferedSink<S> {
+    inner: S,
+    path: PathBuf,
+    capacity: usize,
+    spooled: VecDeque<String>,
+}
+
+/// Buffer the measurements for `inner` in the spool file at `path`.
+///
+/// Records that have been spooled before are loaded from the file.
+pub fn buffered<S>(inner: S, path: impl Into<PathBuf>, capacity: usize) -> io::Result<BufferedSink<S>>
+where
+    S: MeasurementSink,
+{
+    let path = path.into();
+    let mut spooled: VecDeque<_> = match fs::read_to_string(&path) {
+        Ok(contents) => contents.lines().map(ToOwned::to_owned).collect(),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => VecDeque::new(),
+        Err(err) => return Err(err),
+    };
+    let mut sink = BufferedSink {
+        inner,
+        path,
+        capacity,
+        spooled: VecDeque::new(),
+    };
+    if spooled.len() > capacity {
+        log::warn!("Discarding {} spooled records", spooled.len() - capacity);
+        spooled.drain(..spooled.len() - capacity);
+        sink.spooled = spooled;
+        sink.rewrite()?;
+    } else {
+        sink.spooled = spooled;
+    }
+    Ok(sink)
+}
+
+impl<S> BufferedSink<S> {
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The number of records that are waiting to be forwarded.
+    pub fn spooled_len(&self) -> usize {
+        self.spooled.len()
+    }
+
+    /// Replace the spool file with the remaining records.
+    fn rewrite(&self) -> io::Result<()> {
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        let mut contents = String::new();
+        for line in &self.spooled {
+            contents.push_str(line);
+            contents.push('\n');
+        }
+        fs::write(&tmp_path, contents)?;
+        fs::rename(&tmp_path, &self.path)
+    }
+
+    fn spool(&mut self, record: &Record) -> io::Result<()> {
+        let line = encode_record(record);
+        self.spooled.push_back(line.clone());
+        if self.spooled.len() > self.capacity {
+            log::warn!("Discarding the oldest spooled record");
+            self.spooled.pop_front();
+            return self.rewrite();
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        writeln!(file, "{}", line)
+    }
+}
+
+impl<S> BufferedSink<S>
+where
+    S: MeasurementSink,
+{
+    /// Forward spooled records until the inner sink fails.
+    ///
+    /// Returns `true` if all records have been forwarded.
+    pub async fn drain(&mut self) -> io::Result<bool> {
+        let mut forwarded = 0;
+        while let Some(line) = self.spooled.front() {
+            let (slave, measurements) = match decode_record(line) {
+                Some(record) => record,
+                None => {
+                    log::warn!("Discarding invalid spooled record: {}", line);
+                    self.spooled.pop_front();
+                    forwarded += 1;
+                    continue;
+                }
+            };
+            if let Err(err) = self.inner.record(slave, measurements).await {
+                log::debug!("Failed to forward spooled record: {}", err);
+                break;
+            }
+            self.spooled.pop_front();
+            forwarded += 1;
+        }
+        if forwarded > 0 {
+            log::info!("Forwarded {} spooled records", forwarded);
+            self.rewrite()?;
+        }
+        Ok(self.spooled.is_empty())
+    }
+}
+
+/// Only fails if spooling fails.
+impl<S> MeasurementSink for BufferedSink<S>
+where
+    S: MeasurementSink,
+{
+    fn record(&mut self, slave: Option<u8>, measurements: TimestampedMeasurements) -> BoxFuture<'_, io::Result<()>> {
+        async move {
+            // Preserve the order of all records
+            if self.drain().await? {
+                match self.inner.record(slave, measurements).await {
+                    Ok(()) => return Ok(()),
+                    Err(err) => log::warn!("Spooling measurements after failure: {}", err),
+                }
+            }
+            self.spool(&(slave, measurements))
+        }
+        .boxed()
+    }
+}
+
+fn encode_measurement<T>(
+    line: &mut String,
+    measurement: Option<Measurement<T>>,
+    value: impl FnOnce(T) -> String,
+) {
+    line.push(';');
+    if let Some(Measurement { ts, val }) = measurement {
+        let millis = ts.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+        let _ = write!(line, "{}@{}", millis, value(val));
+    }
+}
+
+/// A single line `slave;temperature;water_content;permittivity;raw_counts`
+/// with measurements as `unix_millis@value`, e.g. `1;1571212800123@21.5;;;`.
+fn encode_record((slave, measurements): &Record) -> String {
+    let mut line = slave.map(|slave| slave.to_string()).unwrap_or_default();
+    let TimestampedMeasurements {
+        temperature,
+        water_content,
+        permittivity,
+        raw_counts,
+    } = *measurements;
+    encode_measurement(&mut line, temperature, |val| val.to_degree_celsius().to_string());
+    encode_measurement(&mut line, water_content, |val| val.to_percent().to_string());
+    encode_measurement(&mut line, permittivity, |val| val.to_ratio().to_string());
+    encode_measurement(&mut line, raw_counts, |val| u16::from(val).to_string());
+    line
+}
+
+fn decode_measurement<T, V>(field: &str, value: impl FnOnce(V) -> T) -> Option<Option<Measurement<T>>>
+where
+    V: FromStr,
+{
+    if field.is_empty() {
+        return Some(None);
+    }
+    let mut parts = field.splitn(2, '@');
+    let millis = parts.next()?.parse().ok()?;
+    let val = parts.next()?.parse().ok()?;
+    Some(Some(Measurement {
+        ts: UNIX_EPOCH + Duration::from_millis(millis),
+        val: value(val),
+    }))
+}
+
+fn decode_record(line: &str) -> Option<Record> {
+    let fields: Vec<_> = line.split(';').collect();
+    if let [slave, temperature, water_content, permittivity, raw_counts] = fields[..] {
+        let slave = if slave.is_empty() {
+            None
+        } else {
+            Some(slave.parse().ok()?)
+        };
+        let measurements = TimestampedMeasurements {
+            temperature: decode_measurement(temperature, Temperature::from_degree_celsius)?,
+            water_content: decode_measurement(water_content, VolumetricWaterContent::from_percent)?,
+            permittivity: decode_measurement(permittivity, RelativePermittivity::from_ratio)?,
+            raw_counts: decode_measurement(raw_counts, |val: u16| RawCounts::from(val))?,
+        };
+        Some((slave, measurements))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Records measurements only while online.
+    #[derive(Default)]
+    struct Uplink {
+        online: bool,
+        records: Vec<Record>,
+    }
+
+    impl MeasurementSink for Uplink {
+        fn record(
+            &mut self,
+            slave: Option<u8>,
+            measurements: TimestampedMeasurements,
+        ) -> BoxFuture<'_, io::Result<()>> {
+            let res = if self.online {
+                self.records.push((slave, measurements));
+                Ok(())
+            } else {
+                Err(io::Error::new(io::ErrorKind::NotConnected, "offline"))
+            };
+            future::ready(res).boxed()
+        }
+    }
+
+    fn measurements(degree_celsius: f64) -> TimestampedMeasurements {
+        let ts = UNIX_EPOCH + Duration::from_millis(1_571_212_800_123);
+        TimestampedMeasurements {
+            temperature: Some(Measurement::at(ts, Temperature::from_degree_celsius(degree_celsius))),
+            raw_counts: Some(Measurement::at(ts, RawCounts::from(11_345))),
+            ..Default::default()
+        }
+    }
+
+    fn temperatures(records: &[Record]) -> Vec<f64> {
+        records
+            .iter()
+            .filter_map(|(_, measurements)| measurements.temperature)
+            .map(|m| m.val.to_degree_celsius())
+            .collect()
+    }
+
+    #[test]
+    fn encode_and_decode_records() {
+        let record = (Some(1), measurements(21.5));
+        let line = encode_record(&record);
+        assert_eq!("1;1571212800123@21.5;;;1571212800123@11345", line);
+        assert_eq!(Some(record), decode_record(&line));
+        let record = (None, TimestampedMeasurements::default());
+        assert_eq!(";;;;", encode_record(&record));
+        assert_eq!(Some(record), decode_record(";;;;"));
+        assert_eq!(None, decode_record("1;;;"));
+        assert_eq!(None, decode_record("1;21.5;;;"));
+        assert_eq!(None, decode_record("x;;;;"));
+    }
+
+    #[tokio::test]
+    async fn forward_spooled_records_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("spool.txt");
+        let mut sink = buffered(Uplink::default(), &path, 10).unwrap();
+        sink.record(Some(1), measurements(10.0)).await.unwrap();
+        sink.record(Some(1), measurements(20.0)).await.unwrap();
+        assert_eq!(2, sink.spooled_len());
+        assert_eq!(2, fs::read_to_string(&path).unwrap().lines().count());
+        assert!(!sink.drain().await.unwrap());
+        sink.inner.online = true;
+        sink.record(Some(1), measurements(30.0)).await.unwrap();
+        assert_eq!(0, sink.spooled_len());
+        assert_eq!("", fs::read_to_string(&path).unwrap());
+        assert_eq!(vec![10.0, 20.0, 30.0], temperatures(&sink.inner().records));
+    }
+
+    #[tokio::test]
+    async fn reload_spooled_records_after_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("spool.txt");
+        let mut sink = buffered(Uplink::default(), &path, 2).unwrap();
+        for degree_celsius in &[10.0, 20.0, 30.0] {
+            sink.record(None, measurements(*degree_celsius)).await.unwrap();
+        }
+        // The oldest record has been discarded
+        assert_eq!(2, sink.spooled_len());
+        drop(sink);
+        let mut contents = fs::read_to_string(&path).unwrap();
+        contents.push_str("invalid\n");
+        fs::write(&path, contents).unwrap();
+        let online = Uplink {
+            online: true,
+            ..Default::default()
+        };
+        let mut sink = buffered(online, &path, 3).unwrap();
+        assert_eq!(3, sink.spooled_len());
+        assert!(sink.drain().await.unwrap());
+        assert_eq!(vec![20.0, 30.0], temperatures(&sink.inner().records));
+        // Discard records that exceed a reduced capacity
+        drop(sink);
+        fs::write(&path, ";;;;\n;;;;\n;;;;\n").unwrap();
+        let sink = buffered(Uplink::default(), &path, 1).unwrap();
+        assert_eq!(1, sink.spooled_len());
+        assert_eq!(";;;;\n", fs::read_to_string(&path).unwrap());
+    }
+}
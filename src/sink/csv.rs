@@ -0,0 +1,424 @@
+//! Logging of measurements into CSV files.
+//!
+//! Each poll cycle is appended as a single row, e.g.
+//!
+//! ```text
+//! timestamp,slave,temperature_c,vwc_percent,permittivity,raw,status
+//! 2019-10-16T08:00:00.123Z,1,21.5,34.4,15.2,11345,ok
+//! 2019-10-16T08:00:10.125Z,1,,,,,"Timed out"
+//! ```
+//!
+//! Quantities that have not been measured are left empty. The status
+//! contains the error message of failed poll cycles. Rows are
+//! timestamped by the most recent measurement or by the poll cycle
+//! if nothing has been measured. Timestamps are given in UTC.
+//!
+//! Files are rotated after they exceeded a maximum size or age. The
+//! current file is then renamed by appending the rotation time to the
+//! file stem, e.g. `smt100.csv` becomes `smt100-20191016T080000Z.csv`,
+//! and a new file is started.
+
+use super::*;
+
+use crate::poller::{Measurement, TimestampedMeasurements};
+
+use futures::{Stream, StreamExt};
+use std::{
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const HEADER: &str = "timestamp,slave,temperature_c,vwc_percent,permittivity,raw,status";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RotationPolicy {
+    /// Rotate before a row would exceed the file size in bytes.
+    pub max_size: Option<u64>,
+
+    /// Rotate files that have been started longer ago.
+    pub max_age: Option<Duration>,
+}
+
+/// Date and time in UTC.
+pub(crate) struct DateTime {
+    pub(crate) year: i64,
+    pub(crate) month: u32,
+    pub(crate) day: u32,
+    pub(crate) hour: u32,
+    pub(crate) minute: u32,
+    pub(crate) second: u32,
+    pub(crate) millis: u32,
+}
+
+impl From<SystemTime> for DateTime {
+    fn from(from: SystemTime) -> Self {
+        let since_epoch = from.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let secs = since_epoch.as_secs();
+        let secs_of_day = (secs % 86_400) as u32;
+        // Civil from days, see http://howardhinnant.github.io/date_algorithms.html
+        let z = (secs / 86_400) as i64 + 719_468;
+        let era = z / 146_097;
+        let doe = z - era * 146_097;
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        let year = yoe + era * 400 + i64::from(month <= 2);
+        Self {
+            year,
+            month,
+            day,
+            hour: secs_of_day / 3600,
+            minute: secs_of_day / 60 % 60,
+            second: secs_of_day % 60,
+            millis: since_epoch.subsec_millis(),
+        }
+    }
+}
+
+/// RFC 3339 with millisecond precision.
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+            self.year, self.month, self.day, self.hour, self.minute, self.second, self.millis
+        )
+    }
+}
+
+fn write_value<T>(row: &mut String, measurement: Option<Measurement<T>>, to_string: impl FnOnce(T) -> String) {
+    row.push(',');
+    if let Some(Measurement { val, .. }) = measurement {
+        row.push_str(&to_string(val));
+    }
+}
+
+/// Quotes and escapes a field of arbitrary text.
+fn quoted(field: &str) -> String {
+    format!("\"{}\"", field.replace('"', "\"\""))
+}
+
+fn format_row(ts: SystemTime, slave: Option<u8>, res: &Result<TimestampedMeasurements>) -> String {
+    let mut row = DateTime::from(ts).to_string();
+    row.push(',');
+    if let Some(slave) = slave {
+        row.push_str(&slave.to_string());
+    }
+    let measurements = res.as_ref().ok().copied().unwrap_or_default();
+    let TimestampedMeasurements {
+        temperature,
+        water_content,
+        permittivity,
+        raw_counts,
+    } = measurements;
+    write_value(&mut row, temperature, |val| val.to_degree_celsius().to_string());
+    write_value(&mut row, water_content, |val| val.to_percent().to_string());
+    write_value(&mut row, permittivity, |val| val.to_ratio().to_string());
+    write_value(&mut row, raw_counts, |val| val.to_string());
+    row.push(',');
+    match res {
+        Ok(_) => row.push_str("ok"),
+        Err(err) => row.push_str(&quoted(&err.to_string())),
+    }
+    row.push('\n');
+    row
+}
+
+fn rotated_path(path: &Path, ts: SystemTime) -> PathBuf {
+    let DateTime {
+        year,
+        month,
+        day,
+        hour,
+        minute,
+        second,
+        ..
+    } = DateTime::from(ts);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let suffix = format!("{:04}{:02}{:02}T{:02}{:02}{:02}Z", year, month, day, hour, minute, second);
+    let extension = path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+    let mut rotated = path.with_file_name(format!("{}-{}{}", stem, suffix, extension));
+    // Never overwrite the file of a preceding rotation within the same second
+    let mut n = 1;
+    while rotated.exists() {
+        rotated = path.with_file_name(format!("{}-{}-{}{}", stem, suffix, n, extension));
+        n += 1;
+    }
+    rotated
+}
+
+/// Appends measurements as rows to a CSV file.
+#[derive(Debug)]
+pub struct CsvSink {
+    path: PathBuf,
+    rotation: RotationPolicy,
+    file: File,
+    size: u64,
+    started: Option<SystemTime>,
+}
+
+impl CsvSink {
+    /// Append to an existing file or create a new file.
+    ///
+    /// The age of a file is measured from the first row that has
+    /// been appended by this sink.
+    pub fn open(path: impl Into<PathBuf>, rotation: RotationPolicy) -> io::Result<Self> {
+        let path = path.into();
+        let (file, size) = Self::open_file(&path)?;
+        Ok(Self {
+            path,
+            rotation,
+            file,
+            size,
+            started: None,
+        })
+    }
+
+    fn open_file(path: &Path) -> io::Result<(File, u64)> {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        let mut size = file.metadata()?.len();
+        if size == 0 {
+            writeln!(file, "{}", HEADER)?;
+            size = HEADER.len() as u64 + 1;
+        }
+        Ok((file, size))
+    }
+
+    /// The path of the current file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Rename the current file and start a new file.
+    pub fn rotate(&mut self) -> io::Result<()> {
+        self.rotate_at(SystemTime::now())
+    }
+
+    fn rotate_at(&mut self, ts: SystemTime) -> io::Result<()> {
+        self.file.flush()?;
+        fs::rename(&self.path, rotated_path(&self.path, ts))?;
+        let (file, size) = Self::open_file(&self.path)?;
+        self.file = file;
+        self.size = size;
+        self.started = None;
+        Ok(())
+    }
+
+    fn needs_rotation(&self, row_len: u64, now: SystemTime) -> bool {
+        let RotationPolicy { max_size, max_age } = self.rotation;
+        // Each file contains at least a single row
+        let has_rows = self.size > HEADER.len() as u64 + 1;
+        if max_size.map(|max_size| has_rows && self.size + row_len > max_size).unwrap_or(false) {
+            return true;
+        }
+        match (max_age, self.started) {
+            (Some(max_age), Some(started)) => now.duration_since(started).unwrap_or_default() >= max_age,
+            _ => false,
+        }
+    }
+
+    /// Append the outcome of a single poll cycle that has
+    /// just finished.
+    pub fn write_row(&mut self, slave: Option<u8>, res: &Result<TimestampedMeasurements>) -> io::Result<()> {
+        self.write_row_at(SystemTime::now(), slave, res)
+    }
+
+    /// Append the outcome of a single poll cycle at `cycle_ts`.
+    ///
+    /// The row is timestamped by the most recent measurement. The time
+    /// of the poll cycle is only used if nothing has been measured.
+    pub fn write_row_at(
+        &mut self,
+        cycle_ts: SystemTime,
+        slave: Option<u8>,
+        res: &Result<TimestampedMeasurements>,
+    ) -> io::Result<()> {
+        let ts = res
+            .as_ref()
+            .ok()
+            .and_then(TimestampedMeasurements::latest_ts)
+            .unwrap_or(cycle_ts);
+        let row = format_row(ts, slave, res);
+        if self.needs_rotation(row.len() as u64, ts) {
+            self.rotate_at(ts)?;
+        }
+        self.file.write_all(row.as_bytes())?;
+        self.file.flush()?;
+        self.size += row.len() as u64;
+        self.started.get_or_insert(ts);
+        Ok(())
+    }
+}
+
+/// Append all poll cycles of a `poller::measurement_stream()`.
+///
+/// The returned future finishes at the end of the stream or fails
+/// if writing fails.
+pub async fn log_stream<S>(sink: &mut CsvSink, slave: Option<u8>, measurements: S) -> io::Result<()>
+where
+    S: Stream<Item = Result<TimestampedMeasurements>>,
+{
+    futures::pin_mut!(measurements);
+    while let Some(res) = measurements.next().await {
+        sink.write_row(slave, &res)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    fn ts(millis: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(millis)
+    }
+
+    /// 2019-10-16T08:00:00.000Z
+    fn started() -> SystemTime {
+        ts(1_571_212_800_000)
+    }
+
+    fn measurements(ts: SystemTime) -> TimestampedMeasurements {
+        TimestampedMeasurements {
+            temperature: Some(Measurement::at(ts, Temperature::from_degree_celsius(21.5))),
+            ..Default::default()
+        }
+    }
+
+    fn rows(path: &Path) -> Vec<String> {
+        fs::read_to_string(path).unwrap().lines().map(ToOwned::to_owned).collect()
+    }
+
+    fn files(dir: &Path) -> Vec<String> {
+        let mut files: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn format_date_time() {
+        assert_eq!("1970-01-01T00:00:00.000Z", DateTime::from(UNIX_EPOCH).to_string());
+        let millis = started() + Duration::from_millis(123);
+        assert_eq!("2019-10-16T08:00:00.123Z", DateTime::from(millis).to_string());
+        assert_eq!("2000-02-29T23:59:59.999Z", DateTime::from(ts(951_868_799_999)).to_string());
+        assert_eq!("2000-03-01T00:00:00.000Z", DateTime::from(ts(951_868_800_000)).to_string());
+        assert_eq!("2100-03-01T00:00:00.000Z", DateTime::from(ts(4_107_542_400_000)).to_string());
+        // Times before the epoch are clamped
+        assert_eq!("1970-01-01T00:00:00.000Z", DateTime::from(UNIX_EPOCH - SECOND).to_string());
+    }
+
+    #[test]
+    fn format_rows() {
+        assert_eq!(
+            "2019-10-16T08:00:00.000Z,1,21.5,,,,ok\n",
+            format_row(started(), Some(1), &Ok(measurements(started())))
+        );
+        assert_eq!(
+            "2019-10-16T08:00:00.000Z,,,,,,\"Not connected\"\n",
+            format_row(started(), None, &Err(Error::NotConnected))
+        );
+    }
+
+    #[test]
+    fn number_rotated_paths_within_the_same_second() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("smt100.csv");
+        let rotated = rotated_path(&path, started() + Duration::from_millis(500));
+        assert_eq!(dir.path().join("smt100-20191016T080000Z.csv"), rotated);
+        File::create(&rotated).unwrap();
+        let rotated = rotated_path(&path, started());
+        assert_eq!(dir.path().join("smt100-20191016T080000Z-1.csv"), rotated);
+        File::create(&rotated).unwrap();
+        let rotated = rotated_path(&path, started());
+        assert_eq!(dir.path().join("smt100-20191016T080000Z-2.csv"), rotated);
+        let rotated = rotated_path(&dir.path().join("smt100"), started());
+        assert_eq!(dir.path().join("smt100-20191016T080000Z"), rotated);
+    }
+
+    #[test]
+    fn timestamp_rows_by_measurement_or_poll_cycle() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("smt100.csv");
+        let mut sink = CsvSink::open(&path, Default::default()).unwrap();
+        let cycle_ts = started() + 10 * SECOND;
+        sink.write_row_at(cycle_ts, Some(1), &Ok(measurements(started()))).unwrap();
+        sink.write_row_at(cycle_ts, Some(1), &Ok(Default::default())).unwrap();
+        sink.write_row_at(cycle_ts, Some(1), &Err(Error::NotConnected)).unwrap();
+        assert_eq!(
+            vec![
+                HEADER,
+                "2019-10-16T08:00:00.000Z,1,21.5,,,,ok",
+                "2019-10-16T08:00:10.000Z,1,,,,,ok",
+                "2019-10-16T08:00:10.000Z,1,,,,,\"Not connected\"",
+            ],
+            rows(&path)
+        );
+    }
+
+    #[test]
+    fn append_to_existing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("smt100.csv");
+        let mut sink = CsvSink::open(&path, Default::default()).unwrap();
+        sink.write_row_at(started(), None, &Ok(measurements(started()))).unwrap();
+        drop(sink);
+        let mut sink = CsvSink::open(&path, Default::default()).unwrap();
+        sink.write_row_at(started(), None, &Ok(measurements(started()))).unwrap();
+        assert_eq!(3, rows(&path).len());
+    }
+
+    #[test]
+    fn rotate_by_size_but_never_a_file_without_rows() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("smt100.csv");
+        let rotation = RotationPolicy {
+            max_size: Some(10),
+            max_age: None,
+        };
+        let mut sink = CsvSink::open(&path, rotation).unwrap();
+        // The first row exceeds the maximum size, but the file only
+        // contains the header
+        sink.write_row_at(started(), Some(1), &Ok(measurements(started()))).unwrap();
+        assert_eq!(vec!["smt100.csv"], files(dir.path()));
+        let next = started() + SECOND;
+        sink.write_row_at(next, Some(1), &Ok(measurements(next))).unwrap();
+        assert_eq!(vec!["smt100-20191016T080001Z.csv", "smt100.csv"], files(dir.path()));
+        assert_eq!(2, rows(&dir.path().join("smt100-20191016T080001Z.csv")).len());
+        assert_eq!(vec![HEADER, "2019-10-16T08:00:01.000Z,1,21.5,,,,ok"], rows(&path));
+    }
+
+    #[test]
+    fn rotate_by_age_of_first_row() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("smt100.csv");
+        let rotation = RotationPolicy {
+            max_size: None,
+            max_age: Some(60 * SECOND),
+        };
+        let mut sink = CsvSink::open(&path, rotation).unwrap();
+        for secs in &[0, 30, 59, 60, 119, 120] {
+            let ts = started() + *secs * SECOND;
+            sink.write_row_at(ts, None, &Ok(measurements(ts))).unwrap();
+        }
+        assert_eq!(
+            vec!["smt100-20191016T080100Z.csv", "smt100-20191016T080200Z.csv", "smt100.csv"],
+            files(dir.path())
+        );
+        assert_eq!(4, rows(&dir.path().join("smt100-20191016T080100Z.csv")).len());
+        assert_eq!(3, rows(&dir.path().join("smt100-20191016T080200Z.csv")).len());
+        assert_eq!(2, rows(&path).len());
+    }
+}
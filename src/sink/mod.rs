@@ -0,0 +1,154 @@
+//! Consumers of measurements.
+//!
+//! Each `MeasurementSink` receives the measurements of a sensor after
+//! every poll cycle. Measurements are fanned out to multiple
+//! destinations by collecting sinks into a `Vec`.
+
+use super::*;
+
+use crate::poller::TimestampedMeasurements;
+
+use futures::{
+    future::{self, BoxFuture},
+    FutureExt,
+};
+use std::io;
+use tokio::sync::mpsc;
+
+pub mod buffered;
+pub mod csv;
+
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+pub use self::buffered::{buffered, BufferedSink};
+
+/// A destination for measurements.
+pub trait MeasurementSink: Send {
+    /// Record the measurements of a sensor, optionally identified
+    /// by its Modbus slave address.
+    fn record(&mut self, slave: Option<u8>, measurements: TimestampedMeasurements) -> BoxFuture<'_, io::Result<()>>;
+}
+
+/// Records all sinks one after another.
+///
+/// All sinks are recorded even if some fail. Only the first error
+/// is returned.
+impl MeasurementSink for Vec<Box<dyn MeasurementSink>> {
+    fn record(&mut self, slave: Option<u8>, measurements: TimestampedMeasurements) -> BoxFuture<'_, io::Result<()>> {
+        async move {
+            let mut res = Ok(());
+            for sink in self.iter_mut() {
+                if let Err(err) = sink.record(slave, measurements).await {
+                    if res.is_ok() {
+                        res = Err(err);
+                    }
+                }
+            }
+            res
+        }
+        .boxed()
+    }
+}
+
+/// Writes measurements into the log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LogSink {
+    pub level: log::Level,
+}
+
+impl Default for LogSink {
+    fn default() -> Self {
+        Self {
+            level: log::Level::Info,
+        }
+    }
+}
+
+impl MeasurementSink for LogSink {
+    fn record(&mut self, slave: Option<u8>, measurements: TimestampedMeasurements) -> BoxFuture<'_, io::Result<()>> {
+        log::log!(self.level, "Slave {:?}: {:?}", slave, measurements);
+        future::ok(()).boxed()
+    }
+}
+
+/// Forwards measurements to a receiver, e.g. another task.
+///
+/// Waits until the channel has capacity. Fails if the receiver has
+/// been dropped.
+impl MeasurementSink for mpsc::Sender<(Option<u8>, TimestampedMeasurements)> {
+    fn record(&mut self, slave: Option<u8>, measurements: TimestampedMeasurements) -> BoxFuture<'_, io::Result<()>> {
+        async move {
+            self.send((slave, measurements))
+                .await
+                .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "receiver dropped"))
+        }
+        .boxed()
+    }
+}
+
+/// Appends measurements as rows.
+impl MeasurementSink for csv::CsvSink {
+    fn record(&mut self, slave: Option<u8>, measurements: TimestampedMeasurements) -> BoxFuture<'_, io::Result<()>> {
+        future::ready(self.write_row(slave, &Ok(measurements))).boxed()
+    }
+}
+
+#[cfg(all(test, feature = "tokio-mock"))]
+mod tests {
+    use super::*;
+
+    use crate::{
+        mock,
+        poller::{PollConfig, Poller},
+    };
+
+    type Receiver = mpsc::Receiver<(Option<u8>, TimestampedMeasurements)>;
+
+    fn channel() -> (Box<dyn MeasurementSink>, Receiver) {
+        let (sender, receiver) = mpsc::channel(1);
+        (Box::new(sender), receiver)
+    }
+
+    #[tokio::test]
+    async fn record_all_sinks_after_each_poll_cycle() {
+        let config = PollConfig {
+            slave: Some(3),
+            ..Default::default()
+        };
+        let mut poller = Poller::new(mock::Proxy::default(), config);
+        let (first, mut first_rx) = channel();
+        let (second, mut second_rx) = channel();
+        poller.add_sink(first);
+        poller.add_sink(second);
+        poller.poll_once().await;
+        let expected = (Some(3), *poller.measurements());
+        assert_eq!(Some(mock::Proxy::default_temperature()), expected.1.temperature.map(|m| m.val));
+        assert_eq!(expected, first_rx.try_recv().unwrap());
+        assert_eq!(expected, second_rx.try_recv().unwrap());
+    }
+
+    #[tokio::test]
+    async fn record_remaining_sinks_after_failure() {
+        let (failing, failing_rx) = channel();
+        drop(failing_rx);
+        let (working, mut working_rx) = channel();
+        let mut sinks = vec![failing, Box::new(LogSink::default()), working];
+        let measurements = TimestampedMeasurements::default();
+        let err = sinks.record(None, measurements).await.unwrap_err();
+        assert_eq!(io::ErrorKind::BrokenPipe, err.kind());
+        assert_eq!((None, measurements), working_rx.try_recv().unwrap());
+    }
+
+    #[tokio::test]
+    async fn continue_polling_after_failed_sink() {
+        let mut poller = Poller::new(mock::Proxy::default(), Default::default());
+        let (failing, failing_rx) = channel();
+        drop(failing_rx);
+        poller.add_sink(failing);
+        let mut subscriber = poller.subscribe();
+        poller.poll_once().await;
+        assert!(subscriber.has_changed().unwrap());
+        assert!(subscriber.borrow_and_update().temperature.is_some());
+    }
+}
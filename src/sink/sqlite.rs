@@ -0,0 +1,413 @@
+//! Local storage of measurements in an SQLite database.
+//!
+//! All values are stored in a single table with a row per quantity:
+//!
+//! ```text
+//! CREATE TABLE measurements (
+//!     sensor   INTEGER,         -- the Modbus slave address or NULL
+//!     quantity TEXT NOT NULL,   -- e.g. 'temperature'
+//!     value    REAL NOT NULL,   -- in the units of the accessors, e.g. °C
+//!     ts       INTEGER NOT NULL -- milliseconds since the Unix epoch
+//! )
+//! ```
+//!
+//! Rows are buffered and inserted in batches within a single transaction
+//! to limit the wear of flash storage. Rows that are older than the
+//! retention period are deleted after each batch.
+//!
+//! The last known good values of a `Poller` are recorded repeatedly.
+//! Only samples that are newer than the last stored sample of the same
+//! sensor and quantity are inserted.
+
+use super::*;
+
+use crate::{
+    history::{QuantityValues, Sample},
+    poller::TimestampedMeasurements,
+    runtime::{self, Clock},
+};
+
+use rusqlite::{params, Connection};
+use std::{
+    collections::HashMap,
+    fmt, io,
+    path::Path,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const INSERT_ROW: &str = "INSERT INTO measurements (sensor, quantity, value, ts) VALUES (?1, ?2, ?3, ?4)";
+
+const SELECT_ROWS_SINCE: &str =
+    "SELECT sensor, quantity, value, ts FROM measurements WHERE ts >= ?1 ORDER BY ts, rowid";
+
+const CREATE_SCHEMA: &str = "CREATE TABLE IF NOT EXISTS measurements (
+    sensor INTEGER,
+    quantity TEXT NOT NULL,
+    value REAL NOT NULL,
+    ts INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS measurements_ts ON measurements (ts);";
+
+fn io_error(err: rusqlite::Error) -> io::Error {
+    io::Error::other(err)
+}
+
+fn parse_quantity(name: &str) -> Option<Quantity> {
+    Quantity::ALL.iter().copied().find(|quantity| quantity.name() == name)
+}
+
+fn unix_millis(ts: SystemTime) -> i64 {
+    ts.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as i64
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SqliteConfig {
+    /// The number of rows that are inserted together.
+    pub batch_size: usize,
+
+    /// Delete rows that are older, or keep all rows if `None`.
+    pub retention: Option<Duration>,
+}
+
+impl Default for SqliteConfig {
+    fn default() -> Self {
+        Self {
+            batch_size: 64,
+            retention: None,
+        }
+    }
+}
+
+/// Inserts measurements into an SQLite database.
+///
+/// Pending rows are inserted when dropped.
+pub struct SqliteSink {
+    connection: Connection,
+    config: SqliteConfig,
+    pending: Vec<Sample>,
+    last_ts: HashMap<(Option<u8>, Quantity), SystemTime>,
+    clock: Arc<dyn Clock>,
+}
+
+impl fmt::Debug for SqliteSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SqliteSink")
+            .field("connection", &self.connection)
+            .field("config", &self.config)
+            .field("pending", &self.pending)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SqliteSink {
+    /// Open or create the database file and its schema.
+    pub fn open(path: impl AsRef<Path>, config: SqliteConfig) -> io::Result<Self> {
+        let connection = Connection::open(path).map_err(io_error)?;
+        Self::with_connection(connection, config)
+    }
+
+    /// Use an existing connection, e.g. of an in-memory database.
+    pub fn with_connection(connection: Connection, config: SqliteConfig) -> io::Result<Self> {
+        connection.execute_batch(CREATE_SCHEMA).map_err(io_error)?;
+        Ok(Self {
+            connection,
+            pending: Vec::with_capacity(config.batch_size),
+            config,
+            last_ts: HashMap::new(),
+            clock: Arc::new(runtime::Tokio),
+        })
+    }
+
+    /// Determine expired rows by a different clock than _tokio_.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn config(&self) -> &SqliteConfig {
+        &self.config
+    }
+
+    pub fn connection(&self) -> &Connection {
+        &self.connection
+    }
+
+    /// Buffer a row for each measured quantity unless it has already
+    /// been stored.
+    ///
+    /// All pending rows are inserted when the batch is full.
+    pub fn write(&mut self, sensor: Option<u8>, measurements: &TimestampedMeasurements) -> io::Result<()> {
+        for quantity in MeasurementSelection::all().quantities() {
+            let ts = match quantity {
+                Quantity::Temperature => measurements.temperature.map(|m| m.ts),
+                Quantity::WaterContent => measurements.water_content.map(|m| m.ts),
+                Quantity::Permittivity => measurements.permittivity.map(|m| m.ts),
+                Quantity::RawCounts => measurements.raw_counts.map(|m| m.ts),
+            };
+            if let (Some(value), Some(ts)) = (measurements.value(quantity), ts) {
+                let key = (sensor, quantity);
+                if matches!(self.last_ts.get(&key), Some(last_ts) if *last_ts >= ts) {
+                    continue;
+                }
+                self.last_ts.insert(key, ts);
+                self.pending.push(Sample {
+                    sensor,
+                    quantity,
+                    value,
+                    ts,
+                });
+            }
+        }
+        if self.pending.len() >= self.config.batch_size {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Insert all pending rows in a single transaction and delete
+    /// expired rows.
+    pub fn flush(&mut self) -> io::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let tx = self.connection.transaction().map_err(io_error)?;
+        {
+            let mut insert = tx.prepare_cached(INSERT_ROW).map_err(io_error)?;
+            for sample in &self.pending {
+                insert
+                    .execute(params![
+                        sample.sensor,
+                        sample.quantity.name(),
+                        sample.value,
+                        unix_millis(sample.ts)
+                    ])
+                    .map_err(io_error)?;
+            }
+        }
+        tx.commit().map_err(io_error)?;
+        self.pending.clear();
+        // Nothing has expired if the retention exceeds the clock
+        let expired = self
+            .config
+            .retention
+            .and_then(|retention| self.clock.system_time().checked_sub(retention));
+        if let Some(expired) = expired {
+            let deleted = self.delete_before(expired)?;
+            if deleted > 0 {
+                log::debug!("Deleted {} expired rows", deleted);
+            }
+        }
+        Ok(())
+    }
+
+    /// Delete all rows that are older than `ts` and return their number.
+    pub fn delete_before(&self, ts: SystemTime) -> io::Result<usize> {
+        self.connection
+            .execute("DELETE FROM measurements WHERE ts < ?1", params![unix_millis(ts)])
+            .map_err(io_error)
+    }
+
+    /// The samples of all inserted rows since `ts` in chronological
+    /// order.
+    ///
+    /// Rows of unknown quantities are skipped.
+    pub fn samples_since(&self, ts: SystemTime) -> io::Result<Vec<Sample>> {
+        let mut select = self
+            .connection
+            .prepare(SELECT_ROWS_SINCE)
+            .map_err(io_error)?;
+        let rows = select
+            .query_map(params![unix_millis(ts)], |row| {
+                let sensor = row.get(0)?;
+                let quantity: String = row.get(1)?;
+                let value = row.get(2)?;
+                let millis: i64 = row.get(3)?;
+                Ok(parse_quantity(&quantity).map(|quantity| Sample {
+                    sensor,
+                    quantity,
+                    value,
+                    ts: UNIX_EPOCH + Duration::from_millis(millis.max(0) as u64),
+                }))
+            })
+            .map_err(io_error)?;
+        let mut samples = Vec::new();
+        for sample in rows {
+            if let Some(sample) = sample.map_err(io_error)? {
+                samples.push(sample);
+            }
+        }
+        Ok(samples)
+    }
+}
+
+impl Drop for SqliteSink {
+    fn drop(&mut self) {
+        if let Err(err) = self.flush() {
+            log::warn!("Failed to insert {} pending rows: {}", self.pending.len(), err);
+        }
+    }
+}
+
+/// Buffers and inserts measurements in batches.
+impl MeasurementSink for SqliteSink {
+    fn record(&mut self, slave: Option<u8>, measurements: TimestampedMeasurements) -> BoxFuture<'_, io::Result<()>> {
+        future::ready(self.write(slave, &measurements)).boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{poller::Measurement, runtime::ManualClock};
+
+    fn ts() -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(1_571_212_800_123)
+    }
+
+    fn measurements() -> TimestampedMeasurements {
+        TimestampedMeasurements {
+            temperature: Some(Measurement::at(ts(), Temperature::from_degree_celsius(21.5))),
+            raw_counts: Some(Measurement::at(ts(), RawCounts::from(11_345))),
+            ..Default::default()
+        }
+    }
+
+    fn in_memory(config: SqliteConfig) -> SqliteSink {
+        SqliteSink::with_connection(Connection::open_in_memory().unwrap(), config).unwrap()
+    }
+
+    fn row_count(sink: &SqliteSink) -> i64 {
+        sink.connection()
+            .query_row("SELECT COUNT(*) FROM measurements", [], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn insert_rows_per_quantity() {
+        let mut sink = in_memory(Default::default());
+        sink.write(Some(1), &measurements()).unwrap();
+        sink.flush().unwrap();
+        let mut select = sink
+            .connection()
+            .prepare("SELECT sensor, quantity, value, ts FROM measurements ORDER BY rowid")
+            .unwrap();
+        let rows: Vec<(Option<u8>, String, f64, i64)> = select
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(
+            vec![
+                (Some(1), "temperature".to_owned(), 21.5, 1_571_212_800_123),
+                (Some(1), "raw_counts".to_owned(), 11_345.0, 1_571_212_800_123),
+            ],
+            rows
+        );
+        assert_eq!(
+            vec![
+                Sample {
+                    sensor: Some(1),
+                    quantity: Quantity::Temperature,
+                    value: 21.5,
+                    ts: ts(),
+                },
+                Sample {
+                    sensor: Some(1),
+                    quantity: Quantity::RawCounts,
+                    value: 11_345.0,
+                    ts: ts(),
+                },
+            ],
+            sink.samples_since(ts()).unwrap()
+        );
+        assert!(sink.samples_since(ts() + Duration::from_millis(1)).unwrap().is_empty());
+    }
+
+    fn measurements_at(ts: SystemTime) -> TimestampedMeasurements {
+        TimestampedMeasurements {
+            temperature: Some(Measurement::at(ts, Temperature::from_degree_celsius(22.0))),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn insert_rows_in_batches() {
+        let config = SqliteConfig {
+            batch_size: 3,
+            ..Default::default()
+        };
+        let mut sink = in_memory(config);
+        sink.record(None, measurements()).await.unwrap();
+        assert_eq!(0, row_count(&sink));
+        sink.record(None, measurements_at(ts() + Duration::from_secs(1))).await.unwrap();
+        assert_eq!(3, row_count(&sink));
+        sink.record(None, TimestampedMeasurements::default()).await.unwrap();
+        sink.flush().unwrap();
+        assert_eq!(3, row_count(&sink));
+    }
+
+    #[tokio::test]
+    async fn skip_samples_that_have_already_been_stored() {
+        let mut sink = in_memory(SqliteConfig {
+            batch_size: 1,
+            ..Default::default()
+        });
+        sink.record(Some(1), measurements()).await.unwrap();
+        // The last known good values of a poller
+        sink.record(Some(1), measurements()).await.unwrap();
+        assert_eq!(2, row_count(&sink));
+        // Older samples
+        sink.record(Some(1), measurements_at(ts() - Duration::from_secs(1))).await.unwrap();
+        assert_eq!(2, row_count(&sink));
+        // Other sensors and newer samples
+        sink.record(Some(2), measurements()).await.unwrap();
+        assert_eq!(4, row_count(&sink));
+        let mut newer = measurements();
+        newer.temperature = measurements_at(ts() + Duration::from_secs(1)).temperature;
+        sink.record(Some(1), newer).await.unwrap();
+        assert_eq!(5, row_count(&sink));
+    }
+
+    #[test]
+    fn insert_pending_rows_when_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("smt100.sqlite");
+        let mut sink = SqliteSink::open(&path, Default::default()).unwrap();
+        sink.write(Some(2), &measurements()).unwrap();
+        drop(sink);
+        let sink = SqliteSink::open(&path, Default::default()).unwrap();
+        assert_eq!(2, row_count(&sink));
+        assert_eq!(2, sink.delete_before(ts() + Duration::from_millis(1)).unwrap());
+        assert_eq!(0, row_count(&sink));
+    }
+
+    #[test]
+    fn delete_expired_rows() {
+        let config = SqliteConfig {
+            batch_size: 1,
+            retention: Some(Duration::from_secs(3_600)),
+        };
+        let clock = ManualClock::starting_at(ts() + Duration::from_secs(1_800));
+        let mut sink = in_memory(config).with_clock(Arc::new(clock.clone()));
+        sink.write(None, &measurements()).unwrap();
+        assert_eq!(2, row_count(&sink));
+        clock.advance(Duration::from_secs(3_600));
+        sink.write(None, &measurements_at(clock.system_time())).unwrap();
+        let samples = sink.samples_since(UNIX_EPOCH).unwrap();
+        assert_eq!(1, samples.len());
+        assert_eq!(22.0, samples[0].value);
+    }
+
+    #[test]
+    fn keep_all_rows_if_the_retention_exceeds_the_clock() {
+        let config = SqliteConfig {
+            batch_size: 1,
+            retention: Some(Duration::MAX),
+        };
+        let mut sink = in_memory(config);
+        sink.write(None, &measurements()).unwrap();
+        assert_eq!(2, row_count(&sink));
+    }
+}
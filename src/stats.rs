@@ -0,0 +1,159 @@
+//! Statistics of measurements over time windows, e.g. for daily
+//! summaries.
+
+use super::*;
+
+use crate::history::{History, QuantityValues};
+
+use std::time::{Duration, SystemTime};
+
+/// Descriptive statistics of a sequence of values.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stats {
+    pub count: usize,
+    pub mean: f64,
+    pub min: f64,
+    pub max: f64,
+
+    /// The population standard deviation.
+    pub stddev: f64,
+}
+
+impl Stats {
+    /// Calculate the statistics in a single pass.
+    ///
+    /// Returns `None` if there are no values.
+    pub fn from_values(values: impl IntoIterator<Item = f64>) -> Option<Self> {
+        let mut values = values.into_iter();
+        let first = values.next()?;
+        let mut count = 1;
+        let mut mean = first;
+        let mut min = first;
+        let mut max = first;
+        // Welford's online algorithm
+        let mut m2 = 0.0;
+        for value in values {
+            count += 1;
+            let delta = value - mean;
+            mean += delta / count as f64;
+            m2 += delta * (value - mean);
+            min = min.min(value);
+            max = max.max(value);
+        }
+        Some(Self {
+            count,
+            mean,
+            min,
+            max,
+            stddev: (m2 / count as f64).sqrt(),
+        })
+    }
+}
+
+/// Statistics of all quantities within a time window.
+///
+/// Quantities without any values in the window are `None`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WindowStats {
+    pub window: Duration,
+    pub temperature: Option<Stats>,
+    pub water_content: Option<Stats>,
+    pub permittivity: Option<Stats>,
+    pub raw_counts: Option<Stats>,
+}
+
+impl WindowStats {
+    /// Calculate the statistics of all entries of the history that
+    /// are not older than `window` at `now`.
+    ///
+    /// The window is limited by the capacity of the history.
+    pub fn new<T>(history: &History<T>, window: Duration, now: SystemTime) -> Self
+    where
+        T: QuantityValues,
+    {
+        let stats = |quantity| {
+            let values = history.window(window, now);
+            Stats::from_values(values.filter_map(|entry| entry.val.value(quantity)))
+        };
+        Self {
+            window,
+            temperature: stats(Quantity::Temperature),
+            water_content: stats(Quantity::WaterContent),
+            permittivity: stats(Quantity::Permittivity),
+            raw_counts: stats(Quantity::RawCounts),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::poller::{Measurement, TimestampedMeasurements};
+    use std::time::UNIX_EPOCH;
+
+    fn assert_close(expected: f64, actual: f64) {
+        assert!((expected - actual).abs() < 1e-9, "expected {}, actual {}", expected, actual);
+    }
+
+    #[test]
+    fn no_stats_without_values() {
+        assert_eq!(None, Stats::from_values(Vec::new()));
+    }
+
+    #[test]
+    fn stats_of_single_value() {
+        let stats = Stats::from_values(vec![3.5]).unwrap();
+        assert_eq!(
+            Stats {
+                count: 1,
+                mean: 3.5,
+                min: 3.5,
+                max: 3.5,
+                stddev: 0.0,
+            },
+            stats
+        );
+    }
+
+    #[test]
+    fn population_standard_deviation() {
+        let stats = Stats::from_values(vec![2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]).unwrap();
+        assert_eq!(8, stats.count);
+        assert_close(5.0, stats.mean);
+        assert_close(2.0, stats.stddev);
+        assert_eq!(2.0, stats.min);
+        assert_eq!(9.0, stats.max);
+    }
+
+    #[test]
+    fn stable_with_large_offset() {
+        let offset = 1e9;
+        let values = [4.0, 7.0, 13.0, 16.0].iter().map(|value| offset + value);
+        let stats = Stats::from_values(values).unwrap();
+        assert_close(offset + 10.0, stats.mean);
+        assert!((22.5f64.sqrt() - stats.stddev).abs() < 1e-6);
+    }
+
+    #[test]
+    fn stats_within_window() {
+        let mut history = History::new(10);
+        for secs in 1..=4 {
+            let ts = UNIX_EPOCH + Duration::from_secs(secs);
+            let val = TimestampedMeasurements {
+                temperature: Some(Measurement::at(ts, Temperature::from_degree_celsius(secs as f64))),
+                ..Default::default()
+            };
+            history.push(Measurement::at(ts, val));
+        }
+        let window = Duration::from_secs(2);
+        let stats = WindowStats::new(&history, window, UNIX_EPOCH + Duration::from_secs(4));
+        assert_eq!(window, stats.window);
+        let temperature = stats.temperature.unwrap();
+        assert_eq!(3, temperature.count);
+        assert_close(3.0, temperature.mean);
+        assert_eq!(None, stats.water_content);
+        let stats = WindowStats::new(&history, window, UNIX_EPOCH + Duration::from_secs(10));
+        assert_eq!(None, stats.temperature);
+    }
+}
@@ -0,0 +1,305 @@
+//! Modbus RTU client that is not bound to a particular async runtime.
+//!
+//! In contrast to `modbus` the client doesn't depend on _tokio_.
+//! Frames are transferred over any byte stream that implements the
+//! I/O traits of _futures_, e.g. a serial port of _async-std_ or
+//! _smol_, and timeouts are measured by a `runtime::Runtime`:
+//!
+//! ```ignore
+//! use smol::Async;
+//! use std::{sync::Arc, time::Duration};
+//! use truebner_smt100::{runtime::Runtime, transport::Proxy};
+//!
+//! struct Smol;
+//!
+//! impl Runtime for Smol {
+//!     fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+//!         async_io::Timer::after(duration).map(|_| ()).boxed()
+//!     }
+//! }
+//!
+//! smol::block_on(async {
+//!     let port = serialport::new("/dev/ttyUSB0", 9600)
+//!         .parity(serialport::Parity::Even)
+//!         .open_native()?;
+//!     let proxy = Proxy::new(Async::new(port)?, 1, Arc::new(Smol));
+//!     let temperature = proxy.read_temperature(Some(Duration::from_millis(500))).await?;
+//! })
+//! ```
+
+use super::*;
+
+use crate::{
+    core::modbus::{rtu::*, *},
+    runtime::{self, Runtime},
+};
+
+use futures::{
+    future::BoxFuture,
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    FutureExt,
+};
+use std::{
+    convert::TryFrom,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::Duration,
+};
+
+/// Proxy for a single sensor on a Modbus RTU bus.
+///
+/// The transport is consumed by a pending request and only returned
+/// after the response has been received. If a request fails or times
+/// out the transport is dropped and all subsequent requests fail until
+/// a new transport has been provided by `reconnect()`, because the
+/// remains of an incomplete frame would corrupt the next response.
+pub struct Proxy<T> {
+    transport: Mutex<Option<T>>,
+    slave: u8,
+    runtime: Arc<dyn Runtime>,
+}
+
+impl<T> Proxy<T>
+where
+    T: AsyncRead + AsyncWrite + Unpin + Send,
+{
+    /// Timeouts are measured by the timers of `runtime`.
+    pub fn new(transport: T, slave: u8, runtime: Arc<dyn Runtime>) -> Self {
+        Self {
+            transport: Mutex::new(Some(transport)),
+            slave,
+            runtime,
+        }
+    }
+
+    pub fn slave(&self) -> u8 {
+        self.slave
+    }
+
+    fn transport(&self) -> MutexGuard<'_, Option<T>> {
+        self.transport.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Check if the transport is available for the next request.
+    pub fn is_connected(&self) -> bool {
+        self.transport().is_some()
+    }
+
+    /// Replace the transport to recover from communication errors.
+    pub fn reconnect(&self, transport: T) {
+        *self.transport() = Some(transport);
+    }
+
+    async fn request(&self, start: u16, regs: &mut [u16]) -> Result<()> {
+        let mut buf = [0u8; MAX_FRAME_LEN];
+        let count = u16::try_from(regs.len()).map_err(|_| DecodeError::InvalidInput)?;
+        let len = encode_read_holding_registers(self.slave, start, count, &mut buf)?;
+        let taken = self.transport().take();
+        let mut transport = taken.ok_or(Error::NotConnected)?;
+        transport.write_all(&buf[..len]).await?;
+        transport.flush().await?;
+        transport.read_exact(&mut buf[..3]).await?;
+        let len = response_frame_len(&buf[..3])?;
+        transport.read_exact(&mut buf[3..len]).await?;
+        *self.transport() = Some(transport);
+        let (frame, _) = decode_response_frame(&buf[..len])?;
+        if frame.slave != self.slave || frame.function & !EXCEPTION_FLAG != FN_READ_HOLDING_REGISTERS {
+            return Err(DecodeError::InvalidInput.into());
+        }
+        if let Some(code) = frame.exception_code() {
+            return Err(ModbusException::new(code)
+                .with_request(FN_READ_HOLDING_REGISTERS, start)
+                .into());
+        }
+        Ok(frame.decode_registers(regs)?)
+    }
+
+    /// Read and decode a block of consecutive holding registers.
+    pub async fn read_block<B, const START: u16, const COUNT: u16>(&self, timeout: Option<Duration>) -> Result<B>
+    where
+        B: FromRegisterBlock<START, COUNT>,
+    {
+        let mut regs = [0u16; MAX_REG_COUNT as usize];
+        let regs = regs.get_mut(..usize::from(COUNT)).ok_or(DecodeError::InvalidInput)?;
+        runtime::timeout(&*self.runtime, timeout, self.request(START, regs))
+            .await
+            .map_err(|err| err.with_timeout_slave(self.slave))?;
+        Ok(B::from_registers(regs)?)
+    }
+
+    pub async fn read_temperature(&self, timeout: Option<Duration>) -> Result<Temperature> {
+        self.read_block(timeout)
+            .await
+            .map_err(|err| err.with_timeout_quantity(Quantity::Temperature))
+    }
+
+    pub async fn read_water_content(&self, timeout: Option<Duration>) -> Result<VolumetricWaterContent> {
+        self.read_block(timeout)
+            .await
+            .map_err(|err| err.with_timeout_quantity(Quantity::WaterContent))
+    }
+
+    pub async fn read_permittivity(&self, timeout: Option<Duration>) -> Result<RelativePermittivity> {
+        self.read_block(timeout)
+            .await
+            .map_err(|err| err.with_timeout_quantity(Quantity::Permittivity))
+    }
+
+    pub async fn read_raw_counts(&self, timeout: Option<Duration>) -> Result<RawCounts> {
+        self.read_block(timeout)
+            .await
+            .map_err(|err| err.with_timeout_quantity(Quantity::RawCounts))
+    }
+
+    /// Read all measurements with a single request.
+    pub async fn read_measurements(&self, timeout: Option<Duration>) -> Result<Measurements> {
+        self.read_block(timeout).await
+    }
+}
+
+impl<T> Capabilities for Proxy<T>
+where
+    T: AsyncRead + AsyncWrite + Unpin + Send,
+{
+    fn read_temperature(&self, timeout: Option<Duration>) -> BoxFuture<'_, Result<Temperature>> {
+        self.read_temperature(timeout).boxed()
+    }
+
+    fn read_water_content(&self, timeout: Option<Duration>) -> BoxFuture<'_, Result<VolumetricWaterContent>> {
+        self.read_water_content(timeout).boxed()
+    }
+
+    fn read_permittivity(&self, timeout: Option<Duration>) -> BoxFuture<'_, Result<RelativePermittivity>> {
+        self.read_permittivity(timeout).boxed()
+    }
+
+    fn read_raw_counts(&self, timeout: Option<Duration>) -> BoxFuture<'_, Result<RawCounts>> {
+        self.read_raw_counts(timeout).boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::runtime::ManualClock;
+    use futures::{executor::block_on, pin_mut, poll};
+    use std::{
+        io,
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    /// Answers with the given bytes and never responds afterwards.
+    struct Serial {
+        rx: Vec<u8>,
+        tx: Arc<Mutex<Vec<u8>>>,
+    }
+
+    impl AsyncRead for Serial {
+        fn poll_read(mut self: Pin<&mut Self>, _cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+            if self.rx.is_empty() {
+                return Poll::Pending;
+            }
+            let len = buf.len().min(self.rx.len());
+            buf[..len].copy_from_slice(&self.rx[..len]);
+            self.rx.drain(..len);
+            Poll::Ready(Ok(len))
+        }
+    }
+
+    impl AsyncWrite for Serial {
+        fn poll_write(self: Pin<&mut Self>, _cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+            self.tx.lock().unwrap().extend_from_slice(buf);
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    /// Append the checksum to a response frame.
+    fn frame(pdu: &[u8]) -> Vec<u8> {
+        let mut frame = pdu.to_vec();
+        frame.extend_from_slice(&crc16(pdu).to_le_bytes());
+        frame
+    }
+
+    fn proxy(rx: Vec<u8>) -> (ManualClock, Arc<Mutex<Vec<u8>>>, Proxy<Serial>) {
+        let clock = ManualClock::new();
+        let tx = Arc::new(Mutex::new(Vec::new()));
+        let serial = Serial {
+            rx,
+            tx: Arc::clone(&tx),
+        };
+        let proxy = Proxy::new(serial, 1, Arc::new(clock.clone()));
+        (clock, tx, proxy)
+    }
+
+    #[test]
+    fn read_temperature() {
+        let (_clock, tx, proxy) = proxy(frame(&[0x01, 0x03, 0x02, 0x31, 0xFD]));
+        let temperature = block_on(proxy.read_temperature(None)).unwrap();
+        assert_eq!(Temperature::from_degree_celsius(27.97), temperature);
+        assert_eq!(vec![0x01, 0x03, 0x00, 0x00, 0x00, 0x01, 0x84, 0x0A], *tx.lock().unwrap());
+        assert!(proxy.is_connected());
+    }
+
+    #[test]
+    fn read_exception() {
+        let (_clock, _tx, proxy) = proxy(frame(&[0x01, 0x83, 0x02]));
+        match block_on(proxy.read_raw_counts(None)) {
+            Err(Error::ModbusException(exception)) => {
+                assert_eq!(0x02, u8::from(exception.code));
+                assert_eq!(Some(Register::RawCounts.address()), exception.register);
+            }
+            res => panic!("Unexpected result: {:?}", res),
+        }
+        // The response has been received completely
+        assert!(proxy.is_connected());
+    }
+
+    #[test]
+    fn reject_response_of_other_slave() {
+        let (_clock, _tx, proxy) = proxy(frame(&[0x02, 0x03, 0x02, 0x31, 0xFD]));
+        let res = block_on(proxy.read_temperature(None));
+        assert!(matches!(res, Err(Error::Decode(DecodeError::InvalidInput))));
+    }
+
+    #[test]
+    fn timeout_by_runtime() {
+        let (clock, _tx, proxy) = proxy(Vec::new());
+        let timeout = Duration::from_millis(500);
+        block_on(async {
+            let read = proxy.read_water_content(Some(timeout));
+            pin_mut!(read);
+            assert!(poll!(read.as_mut()).is_pending());
+            clock.advance(timeout);
+            match read.await {
+                Err(Error::Timeout(err)) => assert_eq!(
+                    TimeoutError {
+                        quantity: Some(Quantity::WaterContent),
+                        slave: Some(1),
+                        timeout: Some(timeout),
+                    },
+                    err
+                ),
+                res => panic!("Unexpected result: {:?}", res),
+            }
+        });
+        // The incomplete response is discarded with the transport
+        assert!(!proxy.is_connected());
+        let res = block_on(proxy.read_water_content(None));
+        assert!(matches!(res, Err(Error::NotConnected)));
+        let tx = Arc::new(Mutex::new(Vec::new()));
+        proxy.reconnect(Serial {
+            rx: frame(&[0x01, 0x03, 0x02, 0x0D, 0x7A]),
+            tx,
+        });
+        assert!(block_on(proxy.read_water_content(None)).is_ok());
+    }
+}
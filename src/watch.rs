@@ -0,0 +1,152 @@
+//! Continuous measurements as newline-delimited JSON (ND-JSON).
+//!
+//! Each record is written as a single line, e.g.
+//!
+//! ```text
+//! {"ts":1571212800.123,"slave":1,"temperature_c":21.5,"vwc_percent":34.4,"permittivity":15.2,"raw":11345}
+//! ```
+//!
+//! The timestamp `ts` is given in seconds since the Unix epoch. The
+//! `slave` is `null` if the sensor is not connected via Modbus.
+
+use super::*;
+
+use crate::runtime::{self, Clock};
+
+use std::{
+    fmt,
+    io::{self, Write},
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Timestamped measurements of a single sensor.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Record {
+    pub ts: SystemTime,
+    pub slave: Option<u8>,
+    pub measurements: Measurements,
+}
+
+impl Record {
+    pub fn new(slave: Option<u8>, measurements: Measurements) -> Self {
+        Self::with_clock(slave, measurements, &runtime::Tokio)
+    }
+
+    /// Timestamped by the wall-clock time of `clock`.
+    pub fn with_clock(slave: Option<u8>, measurements: Measurements, clock: &dyn Clock) -> Self {
+        Self {
+            ts: clock.system_time(),
+            slave,
+            measurements,
+        }
+    }
+}
+
+/// Seconds since the Unix epoch with millisecond precision.
+pub(crate) struct UnixTimestamp(pub SystemTime);
+
+impl fmt::Display for UnixTimestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let millis = self.0.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+        write!(f, "{}.{:03}", millis / 1000, millis % 1000)
+    }
+}
+
+/// Formats the record as a single line of JSON without a line break.
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{\"ts\":{},\"slave\":", UnixTimestamp(self.ts))?;
+        if let Some(slave) = self.slave {
+            write!(f, "{}", slave)?;
+        } else {
+            f.write_str("null")?;
+        }
+        let Measurements {
+            temperature,
+            water_content,
+            permittivity,
+            raw_counts,
+        } = self.measurements;
+        write!(
+            f,
+            ",\"temperature_c\":{},\"vwc_percent\":{},\"permittivity\":{},\"raw\":{}}}",
+            temperature.to_degree_celsius(),
+            water_content.to_percent(),
+            permittivity.to_ratio(),
+            raw_counts,
+        )
+    }
+}
+
+/// Read all quantities one after another.
+pub async fn read_measurements<C>(capabilities: &C, timeout: Option<Duration>) -> Result<Measurements>
+where
+    C: Capabilities,
+{
+    let temperature = capabilities.read_temperature(timeout).await?;
+    let water_content = capabilities.read_water_content(timeout).await?;
+    let permittivity = capabilities.read_permittivity(timeout).await?;
+    let raw_counts = capabilities.read_raw_counts(timeout).await?;
+    Ok(Measurements {
+        temperature,
+        water_content,
+        permittivity,
+        raw_counts,
+    })
+}
+
+/// Periodically read all measurements and write them as records
+/// into `writer`, one line per cycle.
+///
+/// Failed reads are logged and skipped. Wrap the proxy into a
+/// `RetryingProxy` or `modbus::ReconnectingProxy` for recovering
+/// from communication errors. The returned future never finishes
+/// unless writing fails. A zero `cycle` is rejected with an
+/// `io::ErrorKind::InvalidInput` error.
+pub async fn watch<C, W>(
+    capabilities: &C,
+    slave: Option<u8>,
+    cycle: Duration,
+    timeout: Option<Duration>,
+    writer: W,
+) -> Result<()>
+where
+    C: Capabilities,
+    W: Write,
+{
+    watch_with_clock(capabilities, slave, cycle, timeout, writer, Arc::new(runtime::Tokio)).await
+}
+
+/// Schedules and timestamps the records by a different clock than
+/// _tokio_, e.g. a `runtime::ManualClock` in tests.
+pub async fn watch_with_clock<C, W>(
+    capabilities: &C,
+    slave: Option<u8>,
+    cycle: Duration,
+    timeout: Option<Duration>,
+    mut writer: W,
+    clock: Arc<dyn Clock>,
+) -> Result<()>
+where
+    C: Capabilities,
+    W: Write,
+{
+    if cycle == Duration::ZERO {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "zero cycle time").into());
+    }
+    let mut next_cycle = clock.now() + cycle;
+    loop {
+        clock.sleep_until(next_cycle).await;
+        next_cycle += cycle;
+        match read_measurements(capabilities, timeout).await {
+            Ok(measurements) => {
+                writeln!(writer, "{}", Record::with_clock(slave, measurements, &*clock))?;
+                writer.flush()?;
+            }
+            Err(err) => {
+                log::warn!("Failed to read measurements: {}", err);
+            }
+        }
+    }
+}
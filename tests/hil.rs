@@ -0,0 +1,135 @@
+//! Hardware-in-the-loop tests with a real sensor.
+//!
+//! The tests are ignored by default and need to be run explicitly
+//! one after another:
+//!
+//! ```sh
+//! SMT100_PORT=/dev/ttyUSB0 SMT100_SLAVE=1 \
+//!     cargo test --features hil-tests --test hil -- --ignored --test-threads 1
+//! ```
+//!
+//! Environment variables:
+//!
+//! - `SMT100_PORT`: The serial port (default: `/dev/ttyUSB0`)
+//! - `SMT100_BAUD`: The baud rate (default: 9600)
+//! - `SMT100_SLAVE`: The slave address of the sensor (default: 1)
+//! - `SMT100_TEMP_SLAVE`: An unused slave address for testing
+//!   address changes and timeouts (default: 247)
+//!
+//! The slave address of the sensor is restored after changing it.
+
+#![cfg(feature = "hil-tests")]
+
+use futures::{future::BoxFuture, FutureExt};
+use std::{env, io, str::FromStr, sync::Arc, time::Duration};
+use tokio_modbus::prelude::{client, Slave};
+use truebner_smt100::{
+    modbus::{self, rtu::SerialConfig, CommissioningSession, NewContext, SharedContext, SlaveProxy},
+    Error,
+};
+
+const TIMEOUT: Duration = Duration::from_millis(1000);
+
+fn env_var<T: FromStr>(key: &str, default: T) -> T {
+    match env::var(key) {
+        Ok(val) => val
+            .parse()
+            .unwrap_or_else(|_| panic!("Invalid value of {}: {}", key, val)),
+        Err(_) => default,
+    }
+}
+
+fn slave() -> Slave {
+    Slave(env_var("SMT100_SLAVE", 1))
+}
+
+fn temp_slave() -> Slave {
+    Slave(env_var("SMT100_TEMP_SLAVE", Slave::max_device().0))
+}
+
+struct SerialPort {
+    path: String,
+    config: SerialConfig,
+}
+
+impl NewContext for SerialPort {
+    fn new_context(&self) -> BoxFuture<'_, io::Result<client::Context>> {
+        async move { modbus::rtu::connect_path_with_config(&self.path, self.config) }.boxed()
+    }
+}
+
+async fn connect() -> Arc<SharedContext> {
+    let serial_port = SerialPort {
+        path: env_var("SMT100_PORT", "/dev/ttyUSB0".to_owned()),
+        config: SerialConfig::default().with_baud_rate(env_var("SMT100_BAUD", 9600)),
+    };
+    let shared_context = Arc::new(SharedContext::new(None, Box::new(serial_port)));
+    shared_context.reconnect().await.expect("connected");
+    shared_context
+}
+
+
+#[tokio::test]
+#[ignore]
+async fn read_registers() {
+    let proxy = SlaveProxy::new(slave(), connect().await);
+    let timeout = Some(TIMEOUT);
+
+    let temperature = proxy.read_temperature(timeout).await.unwrap();
+    assert!((-40.0..=80.0).contains(&temperature.to_degree_celsius()));
+
+    let water_content = proxy.read_water_content(timeout).await.unwrap();
+    assert!((0.0..=100.0).contains(&water_content.to_percent()));
+
+    let permittivity = proxy.read_permittivity(timeout).await.unwrap();
+    assert!(permittivity.to_ratio() >= 1.0);
+
+    proxy.read_raw_counts(timeout).await.unwrap();
+
+    let measurements = proxy.read_all(timeout).await.unwrap();
+    assert!((-40.0..=80.0).contains(&measurements.temperature.to_degree_celsius()));
+}
+
+#[tokio::test]
+#[ignore]
+async fn change_slave_address() {
+    let shared_context = connect().await;
+    let (slave, temp_slave) = (slave(), temp_slave());
+    assert_ne!(slave, temp_slave);
+
+    let session = CommissioningSession::enter(Arc::clone(&shared_context));
+    session.set_slave_address(slave, temp_slave).await.unwrap();
+    let res = SlaveProxy::new(temp_slave, Arc::clone(&shared_context))
+        .read_raw_counts(Some(TIMEOUT))
+        .await;
+    // Always try to restore the original address
+    let restored = session.set_slave_address(temp_slave, slave).await;
+    res.unwrap();
+    restored.unwrap();
+
+    SlaveProxy::new(slave, shared_context)
+        .read_raw_counts(Some(TIMEOUT))
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+#[ignore]
+async fn timeout() {
+    let shared_context = connect().await;
+    let absent = SlaveProxy::new(temp_slave(), Arc::clone(&shared_context));
+    match absent.read_temperature(Some(TIMEOUT)).await {
+        Err(Error::Timeout(err)) => {
+            assert_eq!(Some(temp_slave().0), err.slave);
+            assert_eq!(Some(TIMEOUT), err.timeout);
+        }
+        res => panic!("Unexpected result: {:?}", res),
+    }
+
+    // The sensor is reachable again after reconnecting
+    absent.reconnect().await.unwrap();
+    SlaveProxy::new(slave(), shared_context)
+        .read_temperature(Some(TIMEOUT))
+        .await
+        .unwrap();
+}
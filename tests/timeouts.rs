@@ -0,0 +1,275 @@
+//! Deterministic tests of delays, timeouts, and retries.
+//!
+//! Time is either driven manually by a `ManualClock` or by
+//! the paused _tokio_ clock. No test sleeps in real time.
+
+#![cfg(feature = "tokio-mock")]
+
+use futures::{future, poll, task::Poll, FutureExt, StreamExt};
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use truebner_smt100::{
+    mock::{self, Driver, Step},
+    poller::{measurement_stream_with_clock, PollConfig, Poller},
+    retry::{RetryPolicy, RetryingProxy},
+    watch::watch_with_clock,
+    runtime::{self, Clock, ManualClock},
+    Capabilities, Error, Measurements, RawCounts, RelativePermittivity, Temperature, TimeoutError,
+    VolumetricWaterContent,
+};
+
+fn manual_proxy() -> (ManualClock, mock::Proxy) {
+    let clock = ManualClock::new();
+    let proxy = mock::Proxy::with_clock(Arc::new(clock.clone()));
+    (clock, proxy)
+}
+
+fn measurements(degree_celsius: f64) -> Measurements {
+    Measurements {
+        temperature: Temperature::from_degree_celsius(degree_celsius),
+        water_content: VolumetricWaterContent::from_percent(25.0),
+        permittivity: RelativePermittivity::from_ratio(12.0),
+        raw_counts: RawCounts::from(4_000),
+    }
+}
+
+fn is_timeout_after(res: &Result<impl std::fmt::Debug, Error>, timeout: Duration) -> bool {
+    match res {
+        Err(Error::Timeout(TimeoutError {
+            timeout: Some(actual), ..
+        })) => *actual == timeout,
+        _ => false,
+    }
+}
+
+#[tokio::test]
+async fn mock_read_without_timeout_is_not_delayed() {
+    let (_clock, mut proxy) = manual_proxy();
+    proxy.set_delay(Duration::from_secs(1));
+    let res = proxy.read_temperature(None).now_or_never();
+    assert_eq!(Some(mock::Proxy::default_temperature()), res.and_then(Result::ok));
+}
+
+#[tokio::test]
+async fn mock_read_completes_after_delay() {
+    let (clock, mut proxy) = manual_proxy();
+    proxy.set_delay(Duration::from_millis(100));
+    let read = proxy.read_temperature(Some(Duration::from_secs(1)));
+    futures::pin_mut!(read);
+    assert!(poll!(read.as_mut()).is_pending());
+    clock.advance(Duration::from_millis(99));
+    assert!(poll!(read.as_mut()).is_pending());
+    clock.advance(Duration::from_millis(1));
+    match poll!(read.as_mut()) {
+        Poll::Ready(Ok(temperature)) => assert_eq!(mock::Proxy::default_temperature(), temperature),
+        res => panic!("Unexpected result: {:?}", res),
+    }
+}
+
+#[tokio::test]
+async fn mock_read_times_out_before_delay() {
+    let (clock, mut proxy) = manual_proxy();
+    proxy.set_delay(Duration::from_secs(2));
+    let timeout = Duration::from_millis(500);
+    let read = proxy.read_water_content(Some(timeout));
+    futures::pin_mut!(read);
+    assert!(poll!(read.as_mut()).is_pending());
+    clock.advance(Duration::from_millis(499));
+    assert!(poll!(read.as_mut()).is_pending());
+    clock.advance(Duration::from_millis(1));
+    match poll!(read.as_mut()) {
+        Poll::Ready(res) => assert!(is_timeout_after(&res, timeout)),
+        Poll::Pending => panic!("Not timed out"),
+    }
+}
+
+#[tokio::test]
+async fn mock_step_delay_overrides_default_delay() {
+    let (clock, mut proxy) = manual_proxy();
+    proxy.set_delay(Duration::from_secs(10));
+    proxy.set_sequence(vec![Step::reading(measurements(7.5)).with_delay(Duration::from_millis(10))]);
+    let read = proxy.read_temperature(Some(Duration::from_secs(1)));
+    futures::pin_mut!(read);
+    assert!(poll!(read.as_mut()).is_pending());
+    clock.advance(Duration::from_millis(10));
+    match poll!(read.as_mut()) {
+        Poll::Ready(Ok(temperature)) => assert_eq!(Temperature::from_degree_celsius(7.5), temperature),
+        res => panic!("Unexpected result: {:?}", res),
+    }
+}
+
+#[tokio::test]
+async fn runtime_timeout_with_manual_clock() {
+    let clock = ManualClock::new();
+    let timeout = Duration::from_millis(250);
+    let request = runtime::timeout(&clock, Some(timeout), future::pending::<Result<(), Error>>());
+    futures::pin_mut!(request);
+    assert!(poll!(request.as_mut()).is_pending());
+    clock.advance(Duration::from_millis(249));
+    assert!(poll!(request.as_mut()).is_pending());
+    clock.advance(Duration::from_millis(1));
+    match poll!(request.as_mut()) {
+        Poll::Ready(res) => assert!(is_timeout_after(&res, timeout)),
+        Poll::Pending => panic!("Not timed out"),
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn mock_read_with_paused_tokio_clock() {
+    let mut proxy = mock::Proxy::default();
+    proxy.set_delay(Duration::from_secs(3));
+    let started = tokio::time::Instant::now();
+    let res = proxy.read_permittivity(Some(Duration::from_secs(1))).await;
+    assert!(is_timeout_after(&res, Duration::from_secs(1)));
+    assert_eq!(Duration::from_secs(1), started.elapsed());
+}
+
+#[tokio::test(start_paused = true)]
+async fn retry_backoff_doubles_delay() {
+    let mut proxy = mock::Proxy::default();
+    proxy.push_error(TimeoutError::default().into());
+    proxy.push_error(TimeoutError::default().into());
+    proxy.push_reading(measurements(21.0));
+    let proxy = RetryingProxy::new(proxy, RetryPolicy::default());
+    let started = tokio::time::Instant::now();
+    let temperature = proxy.read_temperature(Some(Duration::from_secs(1))).await.unwrap();
+    assert_eq!(Temperature::from_degree_celsius(21.0), temperature);
+    // 100 ms + 200 ms
+    assert_eq!(Duration::from_millis(300), started.elapsed());
+}
+
+#[tokio::test(start_paused = true)]
+async fn retry_gives_up_after_max_retries() {
+    let mut proxy = mock::Proxy::default();
+    for _ in 0..4 {
+        proxy.push_error(TimeoutError::default().into());
+    }
+    let policy = RetryPolicy {
+        max_retries: 3,
+        backoff: Duration::from_millis(50),
+        ..Default::default()
+    };
+    let proxy = RetryingProxy::new(proxy, policy);
+    let started = tokio::time::Instant::now();
+    let res = proxy.read_raw_counts(Some(Duration::from_secs(1))).await;
+    assert!(matches!(res, Err(Error::Timeout(_))));
+    // 50 ms + 100 ms + 200 ms
+    assert_eq!(Duration::from_millis(350), started.elapsed());
+}
+
+#[tokio::test(start_paused = true)]
+async fn retry_does_not_repeat_permanent_errors() {
+    let mut proxy = mock::Proxy::default();
+    proxy.push_error(Error::NotConnected);
+    proxy.push_reading(measurements(21.0));
+    let proxy = RetryingProxy::new(proxy, RetryPolicy::default());
+    let started = tokio::time::Instant::now();
+    let res = proxy.read_temperature(Some(Duration::from_secs(1))).await;
+    assert!(matches!(res, Err(Error::NotConnected)));
+    assert_eq!(Duration::ZERO, started.elapsed());
+}
+
+#[tokio::test]
+async fn retry_backoff_with_manual_clock() {
+    let (clock, mut proxy) = manual_proxy();
+    proxy.push_error(TimeoutError::default().into());
+    proxy.push_reading(measurements(21.0));
+    let proxy = RetryingProxy::new(proxy, RetryPolicy::default()).with_runtime(Arc::new(clock.clone()));
+    let read = proxy.read_temperature(None);
+    futures::pin_mut!(read);
+    assert!(poll!(read.as_mut()).is_pending());
+    clock.advance(Duration::from_millis(99));
+    assert!(poll!(read.as_mut()).is_pending());
+    clock.advance(Duration::from_millis(1));
+    match poll!(read.as_mut()) {
+        Poll::Ready(res) => assert_eq!(Temperature::from_degree_celsius(21.0), res.unwrap()),
+        Poll::Pending => panic!("Not retried"),
+    }
+}
+
+#[tokio::test]
+async fn watch_records_by_manual_clock() {
+    let started = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+    let clock = ManualClock::starting_at(started);
+    let mut proxy = mock::Proxy::with_clock(Arc::new(clock.clone()));
+    proxy.push_reading(measurements(21.0));
+    proxy.push_reading(measurements(22.0));
+    let mut output = Vec::new();
+    {
+        let cycle = Duration::from_secs(10);
+        let watch = watch_with_clock(&proxy, Some(1), cycle, None, &mut output, Arc::new(clock.clone()));
+        futures::pin_mut!(watch);
+        assert!(poll!(watch.as_mut()).is_pending());
+        clock.advance(Duration::from_secs(9));
+        assert!(poll!(watch.as_mut()).is_pending());
+        clock.advance(Duration::from_secs(1));
+        assert!(poll!(watch.as_mut()).is_pending());
+        clock.advance(Duration::from_secs(10));
+        assert!(poll!(watch.as_mut()).is_pending());
+    }
+    let output = String::from_utf8(output).unwrap();
+    let lines: Vec<_> = output.lines().collect();
+    assert_eq!(2, lines.len());
+    assert!(lines[0].starts_with("{\"ts\":1600000010.000,\"slave\":1,\"temperature_c\":21,"));
+    assert!(lines[1].starts_with("{\"ts\":1600000020.000,\"slave\":1,\"temperature_c\":22,"));
+}
+
+#[tokio::test]
+async fn watch_rejects_zero_cycle() {
+    let (clock, proxy) = manual_proxy();
+    let res = watch_with_clock(&proxy, None, Duration::ZERO, None, Vec::new(), Arc::new(clock)).await;
+    match res {
+        Err(Error::Transport(err)) => assert_eq!(std::io::ErrorKind::InvalidInput, err.kind()),
+        res => panic!("Unexpected result: {:?}", res),
+    }
+}
+
+#[tokio::test]
+async fn poller_timestamps_by_manual_clock() {
+    let started = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+    let clock = ManualClock::starting_at(started);
+    let proxy = mock::Proxy::with_clock(Arc::new(clock.clone()));
+    let config = PollConfig {
+        history_capacity: 10,
+        ..Default::default()
+    };
+    let mut poller = Poller::new(proxy, config).with_clock(Arc::new(clock.clone()));
+    poller.poll_once().await;
+    clock.advance(Duration::from_secs(60));
+    poller.poll_once().await;
+    assert_eq!(Some(started + Duration::from_secs(60)), poller.measurements().temperature.map(|m| m.ts));
+    let timestamps: Vec<SystemTime> = poller.history().iter().map(|entry| entry.ts).collect();
+    assert_eq!(vec![started, started + Duration::from_secs(60)], timestamps);
+    let window = Duration::from_secs(30);
+    assert_eq!(1, poller.history().window(window, clock.system_time()).count());
+    assert_eq!(Some(1), poller.window_stats(window).temperature.map(|stats| stats.count));
+}
+
+#[tokio::test]
+async fn measurement_stream_with_manual_clock() {
+    let (clock, proxy) = manual_proxy();
+    let config = PollConfig {
+        cycle: Duration::from_secs(10),
+        ..Default::default()
+    };
+    let stream = measurement_stream_with_clock(proxy, config, Arc::new(clock.clone()));
+    futures::pin_mut!(stream);
+    assert!(poll!(stream.next()).is_pending());
+    clock.advance(Duration::from_secs(9));
+    assert!(poll!(stream.next()).is_pending());
+    clock.advance(Duration::from_secs(1));
+    match poll!(stream.next()) {
+        Poll::Ready(Some(Ok(measurements))) => {
+            let ts = measurements.temperature.map(|m| m.ts);
+            assert_eq!(Some(clock.system_time()), ts);
+        }
+        res => panic!("Unexpected result: {:?}", res),
+    }
+    // Delayed poll cycles are caught up
+    clock.advance(Duration::from_secs(25));
+    assert!(matches!(poll!(stream.next()), Poll::Ready(Some(Ok(_)))));
+    assert!(matches!(poll!(stream.next()), Poll::Ready(Some(Ok(_)))));
+    assert!(poll!(stream.next()).is_pending());
+}